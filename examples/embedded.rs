@@ -0,0 +1,115 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! A command and query exchange using only stack buffers
+//!
+//! Everything except `main` is written like microcontroller firmware: only `core` and the
+//! no-alloc parts of this crate are used, and every buffer has a fixed capacity. Build the crate
+//! with `default-features = false` to use it this way on a real target.
+
+use core::convert::TryFrom;
+
+use red_sculpin::{
+    buffer::{ArrayBuffer, ScpiString},
+    decode::{DecodeError, Decoder},
+    encode::{EncodeError, Encoder},
+    ieee::message::OperationCompleteQuery,
+    Command, Query,
+};
+
+/// Instrument display text, `:DISP:TEXT <string>`
+struct DisplayText<'a>(&'a ScpiString<32>);
+
+impl<'a> Command for DisplayText<'a> {
+    type ProgramData = &'a ScpiString<32>;
+    fn mnemonic(&self) -> &str {
+        ":DISP:TEXT"
+    }
+    fn program_data(&self) -> Self::ProgramData {
+        self.0
+    }
+}
+
+/// Channel label query, `:CHAN<n>:LAB?`, answered with string response data
+struct ChannelLabelQuery;
+
+impl Query for ChannelLabelQuery {
+    type ProgramData = ();
+    type ResponseData = ScpiString<16>;
+    fn mnemonic(&self) -> &str {
+        ":CHAN1:LAB?"
+    }
+    fn program_data(&self) -> Self::ProgramData {}
+}
+
+/// Calibration data query, `:CAL:DATA?`, answered with arbitrary block response data
+struct CalibrationDataQuery;
+
+impl Query for CalibrationDataQuery {
+    type ProgramData = ();
+    type ResponseData = ArrayBuffer<64>;
+    fn mnemonic(&self) -> &str {
+        ":CAL:DATA?"
+    }
+    fn program_data(&self) -> Self::ProgramData {}
+}
+
+/// Encodes a program message with a command and three queries into a stack buffer.
+fn encode_request(text: &ScpiString<32>) -> Result<ArrayBuffer<128>, EncodeError> {
+    let mut encoder = Encoder::new(ArrayBuffer::new());
+    DisplayText(text).encode(&mut encoder)?;
+    ChannelLabelQuery.encode(&mut encoder)?;
+    CalibrationDataQuery.encode(&mut encoder)?;
+    OperationCompleteQuery.encode(&mut encoder)?;
+    encoder.finish()
+}
+
+struct Response {
+    label: ScpiString<16>,
+    calibration: ArrayBuffer<64>,
+    complete: bool,
+}
+
+/// Decodes the response message to the queries, borrowing nothing from the input.
+fn decode_response(input: &[u8]) -> Result<Response, DecodeError> {
+    let mut decoder = Decoder::new(input);
+    let label = ChannelLabelQuery.decode(&mut decoder)?;
+    let calibration = CalibrationDataQuery.decode(&mut decoder)?;
+    let complete = OperationCompleteQuery.decode(&mut decoder)?;
+    decoder.finish()?;
+    Ok(Response {
+        label,
+        calibration,
+        complete,
+    })
+}
+
+/// Decodes a single block into a plain array, without going through [`ResponseData`].
+///
+/// [`ResponseData`]: red_sculpin::ResponseData
+fn decode_block(input: &[u8], target: &mut [u8; 8]) -> Result<usize, DecodeError> {
+    let mut decoder = Decoder::new(input);
+    decoder.begin_response_data()?;
+    let len = decoder.decode_arbitrary_block_array(target)?;
+    decoder.finish()?;
+    Ok(len)
+}
+
+fn main() {
+    let text = ScpiString::try_from("HELLO \"WORLD\"").expect("text fits");
+    let request = encode_request(&text).expect("request fits");
+    println!(
+        "request: {}",
+        request.as_str().expect("requests are ASCII").trim_end()
+    );
+
+    let response = decode_response(b"\"PROBE A\";#14\x01\x02\x03\x04;1\n").expect("valid response");
+    println!("label: {}", response.label);
+    println!("calibration: {:?}", response.calibration.as_slice());
+    println!("complete: {}", response.complete);
+
+    let mut block = [0; 8];
+    let len = decode_block(b"#0\xde\xad\xbe\xef\n", &mut block).expect("valid block");
+    println!("block: {:02x?}", &block[..len]);
+}
@@ -0,0 +1,34 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use red_sculpin::{buffer::ArrayBuffer, decode::slice};
+
+fuzz_target!(|data: &[u8]| {
+    let check = |consumed: usize| assert!(consumed <= data.len());
+    if let Ok((_, consumed)) = slice::parse_numeric_integer::<i64>(data) {
+        check(consumed);
+    }
+    if let Ok((_, consumed)) = slice::parse_numeric_integer::<u128>(data) {
+        check(consumed);
+    }
+    if let Ok((_, consumed)) = slice::parse_numeric_float::<f64>(data) {
+        check(consumed);
+    }
+    if let Ok((_, consumed)) = slice::parse_boolean(data) {
+        check(consumed);
+    }
+    if let Ok(consumed) = slice::parse_string(data, &mut ArrayBuffer::<256>::new()) {
+        check(consumed);
+    }
+    if let Ok(consumed) = slice::parse_characters(data, &mut ArrayBuffer::<256>::new()) {
+        check(consumed);
+    }
+    if let Ok((block, consumed)) = slice::parse_block(data) {
+        check(consumed);
+        assert!(block.len() < consumed);
+    }
+});
@@ -0,0 +1,665 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::{
+    buffer::ArrayBuffer,
+    decode::DecodeError,
+    encode::{EncodeError, EncodeSink, PROGRAM_MESSAGE_TERMINATOR},
+    ByteSink, ByteSource, ProtocolObserver,
+};
+
+/// A byte source that passes every byte read from the inner source to an observer.
+///
+/// Useful for capturing the raw bytes of response messages as they are consumed by a decoder.
+pub struct TeeSource<S, F> {
+    source: S,
+    observer: F,
+}
+
+impl<S, F> TeeSource<S, F>
+where
+    S: ByteSource,
+    F: FnMut(u8),
+{
+    pub fn new(source: S, observer: F) -> TeeSource<S, F> {
+        TeeSource { source, observer }
+    }
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S, F> ByteSource for TeeSource<S, F>
+where
+    S: ByteSource,
+    F: FnMut(u8),
+{
+    type Error = S::Error;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        let byte = self.source.read_byte()?;
+        (self.observer)(byte);
+        Ok(byte)
+    }
+    fn read_byte_with_end(&mut self) -> Result<(u8, bool), Self::Error> {
+        let (byte, end) = self.source.read_byte_with_end()?;
+        (self.observer)(byte);
+        Ok((byte, end))
+    }
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(usize, bool), Self::Error> {
+        let (len, end) = self.source.read_bytes(buf)?;
+        buf[..len].iter().for_each(|&byte| (self.observer)(byte));
+        Ok((len, end))
+    }
+}
+
+/// Trait for errors that can be classified as timeouts
+pub trait Timeout {
+    /// Returns true if the error means that the operation timed out, and can be retried.
+    fn is_timeout(&self) -> bool;
+}
+
+impl Timeout for DecodeError {
+    fn is_timeout(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(feature = "std")]
+impl Timeout for std::io::Error {
+    fn is_timeout(&self) -> bool {
+        matches!(
+            self.kind(),
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl Timeout for crate::Error {
+    fn is_timeout(&self) -> bool {
+        match self.without_context() {
+            crate::Error::Io(err) => err.is_timeout(),
+            _ => false,
+        }
+    }
+}
+
+/// A byte source that retries reads that time out for as long as the given closure says so.
+///
+/// Once the closure returns false, [`DecodeError::DeadlineExceeded`] is returned. Together with a
+/// read timeout in the transport, this can be used to bound the duration of a whole exchange:
+///
+/// ```
+/// # #[cfg(feature = "std")]
+/// # mod example {
+/// use red_sculpin::{adapters::DeadlineSource, decode::Decoder, encode::Encoder, scpi, Io, Query};
+/// use std::{
+///     net::TcpStream,
+///     time::{Duration, Instant},
+/// };
+///
+/// fn query_system_version(stream: &mut TcpStream) -> Result<f32, red_sculpin::Error> {
+///     let query = scpi::message::SystemVersionQuery;
+///     stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+///     let deadline = Instant::now() + Duration::from_secs(2);
+///
+///     let mut encoder = Encoder::new(Io(stream));
+///     query.encode(&mut encoder)?;
+///     encoder.finish()?;
+///
+///     let source = DeadlineSource::new(Io(stream), || Instant::now() < deadline);
+///     let mut decoder = Decoder::new(source);
+///     let result = query.decode(&mut decoder)?;
+///     decoder.finish()?;
+///     Ok(result)
+/// }
+/// # }
+/// ```
+pub struct DeadlineSource<S, C> {
+    source: S,
+    keep_waiting: C,
+}
+
+impl<S, C> DeadlineSource<S, C>
+where
+    S: ByteSource,
+    S::Error: Timeout,
+    C: FnMut() -> bool,
+{
+    pub fn new(source: S, keep_waiting: C) -> DeadlineSource<S, C> {
+        DeadlineSource {
+            source,
+            keep_waiting,
+        }
+    }
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S, C> ByteSource for DeadlineSource<S, C>
+where
+    S: ByteSource,
+    S::Error: Timeout,
+    C: FnMut() -> bool,
+{
+    type Error = S::Error;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        self.read_byte_with_end().map(|(byte, _)| byte)
+    }
+    fn read_byte_with_end(&mut self) -> Result<(u8, bool), Self::Error> {
+        loop {
+            match self.source.read_byte_with_end() {
+                Err(err) if err.is_timeout() => {
+                    if !(self.keep_waiting)() {
+                        return Err(DecodeError::DeadlineExceeded.into());
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(usize, bool), Self::Error> {
+        loop {
+            match self.source.read_bytes(buf) {
+                Err(err) if err.is_timeout() => {
+                    if !(self.keep_waiting)() {
+                        return Err(DecodeError::DeadlineExceeded.into());
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A byte sink that notifies an observer of bytes written and program messages sent
+///
+/// Created by [`Encoder::with_observer`](crate::encode::Encoder::with_observer). The bytes of
+/// the program message terminator are counted as reported by
+/// [`terminator_len`](EncodeSink::terminator_len).
+pub struct Observed<T, O> {
+    inner: T,
+    observer: O,
+}
+
+impl<T, O: ProtocolObserver> Observed<T, O> {
+    pub fn new(inner: T, observer: O) -> Observed<T, O> {
+        Observed { inner, observer }
+    }
+    pub fn get_observer(&self) -> &O {
+        &self.observer
+    }
+    pub fn into_inner(self) -> (T, O) {
+        (self.inner, self.observer)
+    }
+}
+
+impl<T, O> ByteSink for Observed<T, O>
+where
+    T: ByteSink,
+    O: ProtocolObserver,
+{
+    type Error = T::Error;
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), Self::Error> {
+        self.inner.write_byte(byte)?;
+        self.observer.on_bytes_written(1);
+        Ok(())
+    }
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.inner.write_bytes(bytes)?;
+        self.observer.on_bytes_written(bytes.len());
+        Ok(())
+    }
+    fn write_bytes_vectored(&mut self, parts: &[&[u8]]) -> Result<(), Self::Error> {
+        self.inner.write_bytes_vectored(parts)?;
+        self.observer
+            .on_bytes_written(parts.iter().map(|part| part.len()).sum());
+        Ok(())
+    }
+    fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional)
+    }
+}
+
+impl<T, O> EncodeSink for Observed<T, O>
+where
+    T: EncodeSink,
+    O: ProtocolObserver,
+{
+    fn terminate_message(&mut self) -> Result<(), Self::Error> {
+        self.inner.terminate_message()?;
+        self.observer.on_bytes_written(self.inner.terminator_len());
+        self.observer.on_message_end();
+        Ok(())
+    }
+}
+
+/// A protocol observer that counts events
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Counters {
+    pub bytes_written: u64,
+    pub bytes_read: u64,
+    pub messages_sent: u64,
+    /// Number of response data elements decoded
+    pub response_data: u64,
+    pub responses_received: u64,
+    pub decode_errors: u64,
+}
+
+impl ProtocolObserver for Counters {
+    fn on_bytes_written(&mut self, len: usize) {
+        self.bytes_written += len as u64;
+    }
+    fn on_message_end(&mut self) {
+        self.messages_sent += 1;
+    }
+    fn on_bytes_read(&mut self, len: usize) {
+        self.bytes_read += len as u64;
+    }
+    fn on_response_data(&mut self) {
+        self.response_data += 1;
+    }
+    fn on_response_end(&mut self) {
+        self.responses_received += 1;
+    }
+    fn on_decode_error(&mut self, _err: &DecodeError) {
+        self.decode_errors += 1;
+    }
+}
+
+/// A byte source that reads bytes from a sequence of byte chunks.
+///
+/// Useful for replaying captured traffic, or for transports that deliver data in frames. Returns
+/// [`DecodeError::UnexpectedEnd`] once all chunks have been consumed.
+pub struct ChunkedSource<I: Iterator> {
+    chunks: I,
+    current: Option<I::Item>,
+    position: usize,
+}
+
+impl<I> ChunkedSource<I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    pub fn new<C>(chunks: C) -> ChunkedSource<I>
+    where
+        C: IntoIterator<IntoIter = I>,
+    {
+        ChunkedSource {
+            chunks: chunks.into_iter(),
+            current: None,
+            position: 0,
+        }
+    }
+}
+
+impl<I> ByteSource for ChunkedSource<I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Error = DecodeError;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        loop {
+            if let Some(&byte) = self
+                .current
+                .as_ref()
+                .and_then(|chunk| chunk.as_ref().get(self.position))
+            {
+                self.position += 1;
+                return Ok(byte);
+            }
+            self.current = Some(self.chunks.next().ok_or(DecodeError::UnexpectedEnd)?);
+            self.position = 0;
+        }
+    }
+    /// Reads at most up to the end of the current chunk.
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(usize, bool), Self::Error> {
+        if buf.is_empty() {
+            return Ok((0, false));
+        }
+        loop {
+            let chunk = self
+                .current
+                .as_ref()
+                .map_or(&[][..], |chunk| chunk.as_ref());
+            let available = chunk.get(self.position..).unwrap_or_default();
+            if !available.is_empty() {
+                let len = buf.len().min(available.len());
+                buf[..len].copy_from_slice(&available[..len]);
+                self.position += len;
+                return Ok((len, false));
+            }
+            self.current = Some(self.chunks.next().ok_or(DecodeError::UnexpectedEnd)?);
+            self.position = 0;
+        }
+    }
+}
+
+/// A byte sink that delivers bytes to a callback.
+///
+/// Every write is passed to the callback as is, so separators are delivered as single bytes. See
+/// [`BufferedFnSink`] for a version that coalesces small writes.
+pub struct FnSink<F> {
+    callback: F,
+}
+
+impl<F, E> FnSink<F>
+where
+    F: FnMut(&[u8]) -> Result<(), E>,
+    E: From<EncodeError>,
+{
+    pub fn new(callback: F) -> FnSink<F> {
+        FnSink { callback }
+    }
+    pub fn into_inner(self) -> F {
+        self.callback
+    }
+}
+
+impl<F, E> ByteSink for FnSink<F>
+where
+    F: FnMut(&[u8]) -> Result<(), E>,
+    E: From<EncodeError>,
+{
+    type Error = E;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        (self.callback)(bytes)
+    }
+}
+
+impl<F, E> EncodeSink for FnSink<F>
+where
+    F: FnMut(&[u8]) -> Result<(), E>,
+    E: From<EncodeError>,
+{
+}
+
+/// A byte sink that delivers bytes to a callback through a buffer of `N` bytes.
+///
+/// Writes are collected into the buffer and delivered once the buffer is full or the program
+/// message is terminated. Writes that don't fit in the buffer are delivered directly.
+pub struct BufferedFnSink<F, const N: usize> {
+    callback: F,
+    buffer: ArrayBuffer<N>,
+}
+
+impl<F, E, const N: usize> BufferedFnSink<F, N>
+where
+    F: FnMut(&[u8]) -> Result<(), E>,
+    E: From<EncodeError>,
+{
+    pub fn new(callback: F) -> BufferedFnSink<F, N> {
+        BufferedFnSink {
+            callback,
+            buffer: ArrayBuffer::new(),
+        }
+    }
+    /// Delivers all buffered bytes to the callback.
+    pub fn flush(&mut self) -> Result<(), E> {
+        let bytes = self.buffer.finish();
+        if !bytes.is_empty() {
+            (self.callback)(bytes)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+    /// Returns the callback. Buffered bytes that haven't been flushed are discarded.
+    pub fn into_inner(self) -> F {
+        self.callback
+    }
+}
+
+impl<F, E, const N: usize> ByteSink for BufferedFnSink<F, N>
+where
+    F: FnMut(&[u8]) -> Result<(), E>,
+    E: From<EncodeError>,
+{
+    type Error = E;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        if bytes.len() > self.buffer.remaining() {
+            self.flush()?;
+        }
+        if self.buffer.push_all(bytes).is_err() {
+            (self.callback)(bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl<F, E, const N: usize> EncodeSink for BufferedFnSink<F, N>
+where
+    F: FnMut(&[u8]) -> Result<(), E>,
+    E: From<EncodeError>,
+{
+    fn terminate_message(&mut self) -> Result<(), Self::Error> {
+        self.write_byte(PROGRAM_MESSAGE_TERMINATOR)?;
+        self.flush()
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use alloc::{string::String, vec, vec::Vec};
+    use matches::assert_matches;
+
+    use super::{
+        BufferedFnSink, ChunkedSource, Counters, DeadlineSource, FnSink, TeeSource, Timeout,
+    };
+    use crate::{
+        decode::{DecodeError, Decoder},
+        encode::{EncodeError, Encoder},
+        scpi::message::StatusOperationEnable,
+        Command, ResponseData,
+    };
+
+    #[test]
+    fn tee_source_observes_all_consumed_bytes() {
+        let data = b"42,\"forty-two\",#13abc\n";
+        let mut captured = Vec::new();
+        let source = TeeSource::new(&data[..], |byte| captured.push(byte));
+        let mut decoder = Decoder::new(source);
+        let (number, text, block) =
+            <(u16, String, Vec<u8>)>::decode(&mut decoder).expect("decoding failed");
+        let source = decoder.finish().expect("finishing failed").into_inner();
+        assert_eq!(number, 42);
+        assert_eq!(text, "forty-two");
+        assert_eq!(block, b"abc");
+        assert!(source.is_empty());
+        assert_eq!(captured, data);
+    }
+
+    #[test]
+    fn chunked_source_reads_across_chunk_boundaries() {
+        let chunks: [&[u8]; 5] = [b"12", b"34,\"quo", b"", b"ted\",#", b"14data\n"];
+        let mut decoder = Decoder::new(ChunkedSource::new(chunks));
+        let (number, text, block) =
+            <(u16, String, Vec<u8>)>::decode(&mut decoder).expect("decoding failed");
+        assert_eq!(number, 1234);
+        assert_eq!(text, "quoted");
+        assert_eq!(block, b"data");
+        assert!(decoder.finish().is_ok());
+    }
+
+    #[test]
+    fn chunked_source_accepts_owned_chunks() {
+        let chunks = vec![b"#".to_vec(), b"1".to_vec(), b"3abc\n".to_vec()];
+        let mut decoder = Decoder::new(ChunkedSource::new(chunks));
+        assert_matches!(Vec::<u8>::decode(&mut decoder).as_deref(), Ok(b"abc"));
+    }
+
+    #[test]
+    fn chunked_source_ends_after_last_chunk() {
+        let chunks: [&[u8]; 2] = [b"4", b"2"];
+        let mut decoder = Decoder::new(ChunkedSource::new(chunks));
+        assert_matches!(u8::decode(&mut decoder), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[derive(Debug)]
+    enum SourceError {
+        TimedOut,
+        Decode(DecodeError),
+    }
+
+    impl From<DecodeError> for SourceError {
+        fn from(err: DecodeError) -> Self {
+            SourceError::Decode(err)
+        }
+    }
+
+    impl Timeout for SourceError {
+        fn is_timeout(&self) -> bool {
+            matches!(self, SourceError::TimedOut)
+        }
+    }
+
+    /// A byte source that times out twice before delivering each byte
+    struct SlowSource(&'static [u8], u32);
+
+    impl crate::ByteSource for SlowSource {
+        type Error = SourceError;
+
+        fn read_byte(&mut self) -> Result<u8, Self::Error> {
+            self.1 += 1;
+            if self.1 % 3 != 0 {
+                return Err(SourceError::TimedOut);
+            }
+            let (&byte, rest) = self.0.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+            self.0 = rest;
+            Ok(byte)
+        }
+    }
+
+    #[test]
+    fn deadline_source_retries_timeouts() {
+        let mut polls = 0;
+        let source = DeadlineSource::new(SlowSource(b"42\n", 0), || {
+            polls += 1;
+            true
+        });
+        let mut decoder = Decoder::new(source);
+        assert_matches!(u8::decode(&mut decoder), Ok(42));
+        assert!(decoder.finish().is_ok());
+        assert_eq!(polls, 6);
+    }
+
+    #[test]
+    fn deadline_source_gives_up_when_told_to() {
+        let mut polls = 0;
+        let source = DeadlineSource::new(SlowSource(b"42\n", 0), || {
+            polls += 1;
+            polls < 4
+        });
+        let mut decoder = Decoder::new(source);
+        assert_matches!(
+            u8::decode(&mut decoder),
+            Err(SourceError::Decode(DecodeError::DeadlineExceeded))
+        );
+    }
+
+    #[test]
+    fn deadline_source_passes_other_errors_through() {
+        let source = DeadlineSource::new(SlowSource(b"", 0), || true);
+        let mut decoder = Decoder::new(source);
+        assert_matches!(
+            u8::decode(&mut decoder),
+            Err(SourceError::Decode(DecodeError::UnexpectedEnd))
+        );
+    }
+
+    #[test]
+    fn observed_encoder_and_decoder_count_events() {
+        let mut counters = Counters::default();
+        let mut encoder = Encoder::with_observer(Vec::new(), &mut counters);
+        StatusOperationEnable(42).encode(&mut encoder).unwrap();
+        StatusOperationEnable(1).encode(&mut encoder).unwrap();
+        let (sink, _) = encoder.finish().unwrap().into_inner();
+        assert_eq!(sink, b":STAT:OPER:ENAB 42;:STAT:OPER:ENAB 1\n");
+        assert_eq!(counters.bytes_written, sink.len() as u64);
+        assert_eq!(counters.messages_sent, 1);
+
+        let mut decoder = Decoder::with_observer(&b"1,2,x\n"[..], &mut counters);
+        assert_matches!(<(u8, u8)>::decode(&mut decoder), Ok((1, 2)));
+        assert_matches!(u8::decode(&mut decoder), Err(DecodeError::Parse));
+        assert_eq!(
+            counters,
+            Counters {
+                bytes_written: 37,
+                bytes_read: 5,
+                messages_sent: 1,
+                response_data: 3,
+                responses_received: 0,
+                decode_errors: 1,
+            }
+        );
+    }
+
+    #[derive(Debug)]
+    enum CallbackError {
+        Encode,
+        Failed,
+    }
+
+    impl From<EncodeError> for CallbackError {
+        fn from(_: EncodeError) -> Self {
+            CallbackError::Encode
+        }
+    }
+
+    #[test]
+    fn fn_sink_delivers_all_bytes() {
+        let mut calls: Vec<Vec<u8>> = Vec::new();
+        let mut encoder = Encoder::new(FnSink::new(|bytes: &[u8]| {
+            calls.push(bytes.to_vec());
+            Ok::<_, CallbackError>(())
+        }));
+        StatusOperationEnable(42).encode(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+        assert!(calls.len() > 1);
+        assert_eq!(calls.concat(), b":STAT:OPER:ENAB 42\n");
+    }
+
+    #[test]
+    fn buffered_fn_sink_coalesces_writes() {
+        let mut calls: Vec<Vec<u8>> = Vec::new();
+        let mut encoder = Encoder::new(BufferedFnSink::<_, 8>::new(|bytes: &[u8]| {
+            calls.push(bytes.to_vec());
+            Ok::<_, CallbackError>(())
+        }));
+        StatusOperationEnable(42).encode(&mut encoder).unwrap();
+        StatusOperationEnable(1).encode(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(
+            calls,
+            [
+                &b":STAT:OPER:ENAB"[..],
+                b" 42;",
+                b":STAT:OPER:ENAB",
+                b" 1\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn fn_sink_callback_error_aborts_encoding() {
+        let mut calls = 0;
+        let mut encoder = Encoder::new(FnSink::new(|_: &[u8]| {
+            calls += 1;
+            Err(CallbackError::Failed)
+        }));
+        assert_matches!(
+            StatusOperationEnable(42).encode(&mut encoder),
+            Err(CallbackError::Failed)
+        );
+        assert_eq!(calls, 1);
+    }
+}
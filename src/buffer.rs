@@ -0,0 +1,552 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::{
+    convert::TryFrom,
+    fmt,
+    ops::Deref,
+    str::{self, Utf8Error},
+};
+
+use crate::{
+    decode::Decoder,
+    encode::{EncodeError, EncodeSink, Encoder},
+    ByteSink, ByteSource, ProgramData, ProtocolObserver, ResponseData, ResponseDataInto,
+};
+
+/// A fixed-capacity byte buffer of `LEN` bytes
+///
+/// Useful as a decoding target and an encoding sink when heap allocation is not available. It
+/// implements `fmt::Write` for [`Decoder::decode_characters`] and [`Decoder::decode_string`], and
+/// [`ByteSink`] for [`Decoder::decode_arbitrary_block`]. Writes that don't fit are rejected as a
+/// whole, and decoding fails with [`DecodeError::BufferOverflow`].
+///
+/// ```
+/// use red_sculpin::{buffer::ArrayBuffer, decode::Decoder};
+///
+/// let mut decoder = Decoder::new(&b"\"MY DEVICE\"\n"[..]);
+/// let mut name = ArrayBuffer::<16>::new();
+/// decoder.begin_response_data().unwrap();
+/// decoder.decode_string(&mut name).unwrap();
+/// assert_eq!(name.as_str(), Ok("MY DEVICE"));
+/// ```
+///
+/// [`Decoder::decode_characters`]: crate::decode::Decoder::decode_characters
+/// [`Decoder::decode_string`]: crate::decode::Decoder::decode_string
+/// [`Decoder::decode_arbitrary_block`]: crate::decode::Decoder::decode_arbitrary_block
+/// [`DecodeError::BufferOverflow`]: crate::decode::DecodeError::BufferOverflow
+#[derive(Clone)]
+pub struct ArrayBuffer<const LEN: usize> {
+    buffer: [u8; LEN],
+    written: usize,
+}
+
+/// Error returned when an [`ArrayBuffer`] is full
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ArrayBufferFull;
+
+impl fmt::Display for ArrayBufferFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "array buffer full")
+    }
+}
+
+impl core::error::Error for ArrayBufferFull {}
+
+impl<const LEN: usize> ArrayBuffer<LEN> {
+    pub fn new() -> ArrayBuffer<LEN> {
+        ArrayBuffer {
+            buffer: [0; LEN],
+            written: 0,
+        }
+    }
+    pub fn push(&mut self, byte: u8) -> Result<(), ArrayBufferFull> {
+        if self.written < self.buffer.len() {
+            self.buffer[self.written] = byte;
+            self.written += 1;
+            Ok(())
+        } else {
+            Err(ArrayBufferFull)
+        }
+    }
+    pub fn push_all(&mut self, bytes: &[u8]) -> Result<(), ArrayBufferFull> {
+        if self.written + bytes.len() <= self.buffer.len() {
+            self.buffer[self.written..(self.written + bytes.len())].copy_from_slice(bytes);
+            self.written += bytes.len();
+            Ok(())
+        } else {
+            Err(ArrayBufferFull)
+        }
+    }
+    pub fn finish(&mut self) -> &mut [u8] {
+        &mut self.buffer[0..self.written]
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buffer[0..self.written]
+    }
+    pub fn as_str(&self) -> Result<&str, Utf8Error> {
+        str::from_utf8(self.as_slice())
+    }
+    pub fn len(&self) -> usize {
+        self.written
+    }
+    pub fn is_empty(&self) -> bool {
+        self.written == 0
+    }
+    pub fn remaining(&self) -> usize {
+        self.buffer.len() - self.written
+    }
+    pub fn clear(&mut self) {
+        self.written = 0;
+    }
+}
+
+impl<const LEN: usize> Default for ArrayBuffer<LEN> {
+    fn default() -> Self {
+        ArrayBuffer::new()
+    }
+}
+
+impl<const LEN: usize> fmt::Debug for ArrayBuffer<LEN> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ArrayBuffer")
+            .field(&self.as_slice())
+            .finish()
+    }
+}
+
+impl<const LEN: usize> PartialEq for ArrayBuffer<LEN> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<const LEN: usize> Eq for ArrayBuffer<LEN> {}
+
+impl<const LEN: usize> fmt::Write for ArrayBuffer<LEN> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let bytes = s.as_bytes();
+        self.push_all(bytes).map_err(|_| fmt::Error)
+    }
+}
+
+impl<const LEN: usize> ByteSink for ArrayBuffer<LEN> {
+    type Error = EncodeError;
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), Self::Error> {
+        self.push(byte).map_err(|_| EncodeError::BufferOverflow)
+    }
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.push_all(bytes)
+            .map_err(|_| EncodeError::BufferOverflow)
+    }
+}
+
+impl<const LEN: usize> EncodeSink for ArrayBuffer<LEN> {}
+
+impl<const LEN: usize> ResponseData for ArrayBuffer<LEN> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        let mut result = ArrayBuffer::new();
+        ArrayBuffer::decode_into(decoder, &mut result)?;
+        Ok(result)
+    }
+}
+
+impl<const LEN: usize> ResponseDataInto for ArrayBuffer<LEN> {
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        target: &mut Self,
+    ) -> Result<(), S::Error> {
+        target.clear();
+        decoder.begin_response_data()?;
+        decoder.decode_arbitrary_block(target)
+    }
+}
+
+/// A byte sink that writes into a borrowed slice
+///
+/// Like [`ArrayBuffer`], but the storage is provided by the caller. Writes that don't fit in the
+/// rest of the slice are rejected as a whole with [`EncodeError::BufferOverflow`].
+///
+/// ```
+/// use red_sculpin::{buffer::SliceSink, encode::Encoder, ieee::message::ClearStatus, Command};
+///
+/// let mut buf = [0; 16];
+/// let mut encoder = Encoder::new(SliceSink::new(&mut buf));
+/// ClearStatus.encode(&mut encoder).unwrap();
+/// let sink = encoder.finish().unwrap();
+/// assert_eq!(sink.as_slice(), b"*CLS\n");
+/// ```
+pub struct SliceSink<'a> {
+    buffer: &'a mut [u8],
+    written: usize,
+}
+
+impl<'a> SliceSink<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> SliceSink<'a> {
+        SliceSink { buffer, written: 0 }
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buffer[0..self.written]
+    }
+    pub fn len(&self) -> usize {
+        self.written
+    }
+    pub fn is_empty(&self) -> bool {
+        self.written == 0
+    }
+    pub fn remaining(&self) -> usize {
+        self.buffer.len() - self.written
+    }
+}
+
+impl<'a> fmt::Debug for SliceSink<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SliceSink").field(&self.as_slice()).finish()
+    }
+}
+
+impl<'a> ByteSink for SliceSink<'a> {
+    type Error = EncodeError;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        if bytes.len() > self.remaining() {
+            return Err(EncodeError::BufferOverflow);
+        }
+        self.buffer[self.written..(self.written + bytes.len())].copy_from_slice(bytes);
+        self.written += bytes.len();
+        Ok(())
+    }
+}
+
+impl<'a> EncodeSink for SliceSink<'a> {}
+
+/// A fixed-capacity ASCII string of at most `N` bytes
+///
+/// Encoded as string program data, and decoded from string response data, so it can be used on
+/// both sides of a message exchange without heap allocation. It also implements `fmt::Write`, so it
+/// can be used as the target of [`Decoder::decode_characters`] and
+/// [`Decoder::decode_arbitrary_ascii`]. Without the `alloc` feature, typed character response data
+/// is collected into a `ScpiString<64>` before it's parsed with
+/// [`CharacterResponseData`](crate::CharacterResponseData), so it can be at most 64 bytes long.
+///
+/// ```
+/// use core::convert::TryFrom;
+/// use red_sculpin::{buffer::ScpiString, decode::Decoder, ResponseData};
+///
+/// let name = ScpiString::<8>::try_from("CH1").unwrap();
+/// assert_eq!(name, "CH1");
+///
+/// let mut decoder = Decoder::new(&b"\"CH2\"\n"[..]);
+/// let name = ScpiString::<8>::decode(&mut decoder).unwrap();
+/// assert_eq!(name.len(), 3);
+/// ```
+#[derive(Clone, Default)]
+pub struct ScpiString<const N: usize>(ArrayBuffer<N>);
+
+impl<const N: usize> ScpiString<N> {
+    pub fn new() -> ScpiString<N> {
+        ScpiString(ArrayBuffer::new())
+    }
+    /// Appends an ASCII string.
+    ///
+    /// Nothing is appended if the string is not ASCII or doesn't fit.
+    pub fn push_str(&mut self, text: &str) -> Result<(), EncodeError> {
+        if !text.is_ascii() {
+            return Err(EncodeError::NonAsciiString);
+        }
+        self.0
+            .push_all(text.as_bytes())
+            .map_err(|_| EncodeError::BufferOverflow)
+    }
+    pub fn as_str(&self) -> &str {
+        // Only ASCII is ever written to the buffer, so it's always valid UTF-8
+        self.0.as_str().unwrap_or_default()
+    }
+    pub fn capacity(&self) -> usize {
+        N
+    }
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+impl<const N: usize> TryFrom<&str> for ScpiString<N> {
+    type Error = EncodeError;
+
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        let mut result = ScpiString::new();
+        result.push_str(text)?;
+        Ok(result)
+    }
+}
+
+impl<const N: usize> Deref for ScpiString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> fmt::Debug for ScpiString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> fmt::Display for ScpiString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const N: usize> fmt::Write for ScpiString<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s).map_err(|_| fmt::Error)
+    }
+}
+
+impl<const N: usize, const M: usize> PartialEq<ScpiString<M>> for ScpiString<N> {
+    fn eq(&self, other: &ScpiString<M>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<const N: usize> Eq for ScpiString<N> {}
+
+impl<const N: usize> PartialEq<str> for ScpiString<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<const N: usize> PartialEq<&str> for ScpiString<N> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl<const N: usize> ProgramData for ScpiString<N> {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        encoder.begin_program_data()?;
+        encoder.encode_string(self.as_str())
+    }
+}
+
+impl<const N: usize> ResponseData for ScpiString<N> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        let mut text = ScpiString::new();
+        ScpiString::decode_into(decoder, &mut text)?;
+        Ok(text)
+    }
+}
+
+impl<const N: usize> ResponseDataInto for ScpiString<N> {
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        target: &mut Self,
+    ) -> Result<(), S::Error> {
+        target.clear();
+        decoder.begin_response_data()?;
+        decoder.decode_string(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use core::convert::TryFrom;
+
+    use super::{ArrayBuffer, ArrayBufferFull, ScpiString, SliceSink};
+    use crate::{
+        decode::{DecodeError, Decoder},
+        encode::{EncodeError, Encoder},
+        ieee::message::OperationCompleteQuery,
+        scpi::message::{StatusOperationEnable, SystemVersionQuery},
+        ByteSink, Command, ProgramData, Query, ResponseData,
+    };
+
+    #[test]
+    fn byte_sink_rejects_writes_that_dont_fit() {
+        let mut buffer = ArrayBuffer::<4>::new();
+        buffer.write_bytes(b"abc").unwrap();
+        assert_matches!(buffer.write_bytes(b"de"), Err(EncodeError::BufferOverflow));
+        buffer.write_byte(b'd').unwrap();
+        assert_matches!(buffer.write_byte(b'e'), Err(EncodeError::BufferOverflow));
+        assert_eq!(buffer.push(b'e'), Err(ArrayBufferFull));
+        assert_eq!(buffer.as_slice(), b"abcd");
+        assert_eq!(buffer.len(), 4);
+        buffer.clear();
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn encode_sink() {
+        let mut encoder = Encoder::new(ArrayBuffer::<19>::new());
+        StatusOperationEnable(42).encode(&mut encoder).unwrap();
+        let buffer = encoder.finish().unwrap();
+        assert_eq!(buffer.as_str(), Ok(":STAT:OPER:ENAB 42\n"));
+
+        let mut encoder = Encoder::new(ArrayBuffer::<18>::new());
+        StatusOperationEnable(42).encode(&mut encoder).unwrap();
+        assert_matches!(encoder.finish(), Err(EncodeError::BufferOverflow));
+    }
+
+    #[test]
+    fn slice_sink() {
+        let mut buf = [0; 4];
+        let mut sink = SliceSink::new(&mut buf);
+        sink.write_bytes(b"abc").unwrap();
+        assert_matches!(sink.write_bytes(b"de"), Err(EncodeError::BufferOverflow));
+        sink.write_byte(b'd').unwrap();
+        assert_matches!(sink.write_byte(b'e'), Err(EncodeError::BufferOverflow));
+        assert_eq!(sink.as_slice(), b"abcd");
+        assert_eq!(sink.remaining(), 0);
+    }
+
+    #[test]
+    fn encode_to_slice() {
+        let mut buf = [0; 19];
+        assert_matches!(StatusOperationEnable(42).encode_to_slice(&mut buf), Ok(19));
+        assert_eq!(&buf, b":STAT:OPER:ENAB 42\n");
+        assert_matches!(
+            StatusOperationEnable(42).encode_to_slice(&mut buf[..18]),
+            Err(EncodeError::BufferOverflow)
+        );
+
+        let mut buf = [0; 32];
+        let len = SystemVersionQuery.encode_to_slice(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b":SYST:VERS?\n");
+        let len = OperationCompleteQuery.encode_to_slice(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"*OPC?\n");
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn encode_to_vec() {
+        use alloc::vec::Vec;
+
+        let mut encoder = Encoder::new(Vec::new());
+        StatusOperationEnable(42).encode(&mut encoder).unwrap();
+        assert_eq!(
+            StatusOperationEnable(42).encode_to_vec().unwrap(),
+            encoder.finish().unwrap()
+        );
+        let mut encoder = Encoder::new(Vec::new());
+        SystemVersionQuery.encode(&mut encoder).unwrap();
+        assert_eq!(
+            SystemVersionQuery.encode_to_vec().unwrap(),
+            encoder.finish().unwrap()
+        );
+    }
+
+    #[test]
+    fn decode_targets() {
+        let mut decoder = Decoder::new(&b"ABCD,\"abcd\",#14abcd,ABCDE\n"[..]);
+        let mut buffer = ArrayBuffer::<4>::new();
+        decoder.begin_response_data().unwrap();
+        decoder.decode_characters(&mut buffer).unwrap();
+        assert_eq!(buffer.as_str(), Ok("ABCD"));
+
+        buffer.clear();
+        decoder.begin_response_data().unwrap();
+        decoder.decode_string(&mut buffer).unwrap();
+        assert_eq!(buffer.as_str(), Ok("abcd"));
+
+        buffer.clear();
+        decoder.begin_response_data().unwrap();
+        decoder.decode_arbitrary_block(&mut buffer).unwrap();
+        assert_eq!(buffer.as_slice(), b"abcd");
+
+        buffer.clear();
+        decoder.begin_response_data().unwrap();
+        assert_matches!(
+            decoder.decode_characters(&mut buffer),
+            Err(DecodeError::BufferOverflow)
+        );
+    }
+
+    #[test]
+    fn block_response_data() {
+        let mut decoder = Decoder::new(&b"#14abcd,#0ef\n"[..]);
+        let (definite, indefinite) =
+            <(ArrayBuffer<4>, ArrayBuffer<4>)>::decode(&mut decoder).unwrap();
+        assert_eq!(definite.as_slice(), b"abcd");
+        assert_eq!(indefinite.as_slice(), b"ef");
+
+        let mut decoder = Decoder::new(&b"#15abcde\n"[..]);
+        assert_matches!(
+            ArrayBuffer::<4>::decode(&mut decoder),
+            Err(DecodeError::BufferOverflow)
+        );
+    }
+
+    #[test]
+    fn non_utf8_contents() {
+        let mut buffer = ArrayBuffer::<2>::new();
+        buffer.push_all(&[0xc3, 0x28]).unwrap();
+        assert!(buffer.as_str().is_err());
+    }
+
+    #[test]
+    fn scpi_string_construction() {
+        assert_eq!(ScpiString::<4>::try_from("abcd").unwrap(), "abcd");
+        assert_matches!(
+            ScpiString::<4>::try_from("abcde"),
+            Err(EncodeError::BufferOverflow)
+        );
+        assert_matches!(
+            ScpiString::<4>::try_from("\u{e4}"),
+            Err(EncodeError::NonAsciiString)
+        );
+        let mut text = ScpiString::<4>::new();
+        text.push_str("ab").unwrap();
+        assert_matches!(text.push_str("cde"), Err(EncodeError::BufferOverflow));
+        assert_eq!(&*text, "ab");
+    }
+
+    #[test]
+    fn scpi_string_round_trip_at_capacity() {
+        let original = ScpiString::<7>::try_from("a\"b\"cde").unwrap();
+        let mut encoder = Encoder::new(ArrayBuffer::<16>::new());
+        encoder.begin_message_unit().unwrap();
+        original.encode(&mut encoder).unwrap();
+        let output = encoder.finish().unwrap();
+        assert_eq!(output.as_slice(), b" \"a\"\"b\"\"cde\"\n");
+
+        let mut decoder = Decoder::new(&output.as_slice()[1..]);
+        let decoded = ScpiString::<7>::decode(&mut decoder).unwrap();
+        assert_eq!(decoded, original);
+        assert_eq!(decoded.len(), decoded.capacity());
+
+        let mut decoder = Decoder::new(&output.as_slice()[1..]);
+        assert_matches!(
+            ScpiString::<6>::decode(&mut decoder),
+            Err(DecodeError::BufferOverflow)
+        );
+    }
+
+    #[test]
+    fn scpi_string_as_character_target() {
+        let mut decoder = Decoder::new(&b"VOLTAGE\n"[..]);
+        let mut text = ScpiString::<4>::new();
+        decoder.begin_response_data().unwrap();
+        assert_matches!(
+            decoder.decode_characters(&mut text),
+            Err(DecodeError::BufferOverflow)
+        );
+
+        let mut decoder = Decoder::new(&b"VOLT\n"[..]);
+        text.clear();
+        decoder.begin_response_data().unwrap();
+        decoder.decode_characters(&mut text).unwrap();
+        assert_eq!(text, "VOLT");
+    }
+}
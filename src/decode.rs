@@ -2,23 +2,29 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 use core::fmt;
 
-pub use self::arbitrary_ascii::*;
-pub use self::arbitrary_block::*;
-pub use self::boolean::*;
-pub use self::characters::*;
-pub use self::numeric_float::*;
-pub use self::numeric_integer::*;
-pub use self::string::*;
-use crate::ByteSource;
+pub use self::arbitrary_block::BlockHeader;
+#[cfg(feature = "std")]
+pub use self::block_reader::BlockReader;
+pub use self::feed::{Boundary, FeedDecoder, FeedResult};
+use crate::{buffer::ArrayBuffer, ByteSource, ProtocolObserver};
 
 mod arbitrary_ascii;
 mod arbitrary_block;
+#[cfg(feature = "std")]
+mod block_reader;
 mod boolean;
+mod borrowed;
 mod characters;
+mod echoed_header;
+mod feed;
 mod numeric_float;
 mod numeric_integer;
+/// Decoding of single response data elements from byte slices, e.g. for fuzzing
+pub mod slice;
 mod string;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -27,7 +33,23 @@ pub enum DecodeError {
     UnexpectedEnd,
     BufferOverflow,
     InvalidDecodeState(DecodeState),
-    InvalidDataTerminator { byte: u8 },
+    InvalidDataTerminator {
+        byte: u8,
+    },
+    DeadlineExceeded,
+    /// An echoed response header doesn't match the query
+    HeaderMismatch,
+    /// A response message ended before the expected number of list elements
+    ListLengthMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// Character response data that isn't one of the expected values
+    UnknownCharacters(CharacterParseError),
+    /// A definite length arbitrary block is too large to be decoded into memory
+    BlockTooLarge {
+        declared: u64,
+    },
 }
 
 impl fmt::Display for DecodeError {
@@ -42,78 +64,385 @@ impl fmt::Display for DecodeError {
             DecodeError::InvalidDataTerminator { byte } => {
                 write!(f, "invalid data terminator byte (0x{byte:02x})",)
             }
+            DecodeError::DeadlineExceeded => write!(f, "deadline exceeded"),
+            DecodeError::HeaderMismatch => write!(f, "echoed header doesn't match the query"),
+            DecodeError::ListLengthMismatch { expected, actual } => {
+                write!(f, "expected {} list elements, got {}", expected, actual)
+            }
+            DecodeError::UnknownCharacters(err) => err.fmt(f),
+            DecodeError::BlockTooLarge { declared } => {
+                write!(f, "arbitrary block of {} bytes is too large", declared)
+            }
         }
     }
 }
 
+impl core::error::Error for DecodeError {}
+
+/// Maximum number of bytes of unknown character data kept in a [`CharacterParseError`]
+const CHARACTER_PARSE_ERROR_LEN: usize = 32;
+
+/// Error for character response data that isn't one of the expected values
+///
+/// The error keeps a copy of the text, so the value can be reported, or added to the expected
+/// values. Long text is truncated to its first 32 bytes.
+///
+/// Reference: IEEE 488.2: 8.7.1 - \<CHARACTER RESPONSE DATA\>
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CharacterParseError {
+    text: ArrayBuffer<CHARACTER_PARSE_ERROR_LEN>,
+    truncated: bool,
+}
+
+impl CharacterParseError {
+    pub fn new(text: &str) -> CharacterParseError {
+        let mut len = text.len().min(CHARACTER_PARSE_ERROR_LEN);
+        while !text.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut buffer = ArrayBuffer::new();
+        // The copy is never longer than the buffer
+        let _ = buffer.push_all(&text.as_bytes()[..len]);
+        CharacterParseError {
+            text: buffer,
+            truncated: len < text.len(),
+        }
+    }
+    /// Returns the text, or its beginning if it was truncated.
+    pub fn text(&self) -> &str {
+        self.text.as_str().unwrap_or_default()
+    }
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl fmt::Display for CharacterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ellipsis = if self.truncated { "..." } else { "" };
+        write!(f, "unknown character data \"{}{}\"", self.text(), ellipsis)
+    }
+}
+
+impl core::error::Error for CharacterParseError {}
+
 #[cfg(feature = "std")]
-impl std::error::Error for DecodeError {}
+impl From<DecodeError> for std::io::Error {
+    fn from(err: DecodeError) -> Self {
+        let kind = match err {
+            DecodeError::UnexpectedEnd => std::io::ErrorKind::UnexpectedEof,
+            DecodeError::DeadlineExceeded => std::io::ErrorKind::TimedOut,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub enum DecodeState {
+    #[default]
     Initial,
     Data,
     DataExpected,
     MessageUnitExpected,
+    /// An arbitrary block reader was dropped before the block was read completely
+    IncompleteBlock,
     End,
 }
 
-impl Default for DecodeState {
-    fn default() -> Self {
-        DecodeState::Initial
+/// Maximum number of bytes that can be peeked ahead, which limits the length of echoed headers
+/// without a leading `:` or `*`
+const LOOKAHEAD_LEN: usize = 64;
+
+/// Bytes that have been read from the byte source, but not consumed yet
+struct Lookahead {
+    buf: [u8; LOOKAHEAD_LEN],
+    start: usize,
+    len: usize,
+}
+
+impl Lookahead {
+    fn new() -> Lookahead {
+        Lookahead {
+            buf: [0; LOOKAHEAD_LEN],
+            start: 0,
+            len: 0,
+        }
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    fn get(&self, idx: usize) -> Option<u8> {
+        (idx < self.len).then(|| self.buf[self.start + idx])
+    }
+    fn push(&mut self, byte: u8) {
+        debug_assert!(self.len < LOOKAHEAD_LEN);
+        if self.start + self.len == LOOKAHEAD_LEN {
+            self.buf.copy_within(self.start.., 0);
+            self.start = 0;
+        }
+        self.buf[self.start + self.len] = byte;
+        self.len += 1;
+    }
+    fn pop(&mut self) -> Option<u8> {
+        let byte = self.get(0)?;
+        self.len -= 1;
+        self.start = if self.len == 0 { 0 } else { self.start + 1 };
+        Some(byte)
     }
 }
 
 #[must_use]
-pub struct Decoder<S: ByteSource> {
+pub struct Decoder<S: ByteSource, O = ()> {
     source: S,
+    observer: O,
     state: DecodeState,
-    peeked: Option<u8>,
+    lookahead: Lookahead,
+    /// END was received with the last byte read from the source, which wasn't a terminator
+    end_pending: bool,
+    last: u8,
+    /// Number of bytes of the current response message consumed so far
+    offset: usize,
+    echoed_headers: bool,
+    header_checked: bool,
+    /// The opening quote of string response data has been read, but the closing quote hasn't
+    in_string: bool,
+    /// Payload left of the arbitrary block begun with `begin_arbitrary_block`
+    block: Option<BlockHeader>,
+    max_block_len: Option<u64>,
+    #[cfg(feature = "alloc")]
+    journal: Vec<u8>,
+    #[cfg(feature = "alloc")]
+    replay: Option<usize>,
+    #[cfg(feature = "alloc")]
+    interrupted: bool,
+    /// Characters written to the target before character decoding was interrupted
+    #[cfg(feature = "alloc")]
+    resumed_chars: usize,
+    #[cfg(feature = "alloc")]
+    capture: Option<Vec<u8>>,
 }
 
 impl<S: ByteSource> Decoder<S> {
     pub fn new(source: S) -> Decoder<S> {
+        Decoder::with_observer(source, ())
+    }
+}
+
+impl<S: ByteSource, O: ProtocolObserver> Decoder<S, O> {
+    /// Creates a decoder that notifies the given observer of decoder events and bytes read.
+    pub fn with_observer(source: S, observer: O) -> Decoder<S, O> {
         Decoder {
             source,
+            observer,
             state: DecodeState::default(),
-            peeked: None,
+            lookahead: Lookahead::new(),
+            end_pending: false,
+            last: 0,
+            offset: 0,
+            echoed_headers: false,
+            header_checked: false,
+            in_string: false,
+            block: None,
+            max_block_len: None,
+            #[cfg(feature = "alloc")]
+            journal: Vec::new(),
+            #[cfg(feature = "alloc")]
+            replay: None,
+            #[cfg(feature = "alloc")]
+            interrupted: false,
+            #[cfg(feature = "alloc")]
+            resumed_chars: 0,
+            #[cfg(feature = "alloc")]
+            capture: None,
         }
     }
+    /// Reports a decode error to the observer, and converts it into the byte source error type.
+    pub(crate) fn error(&mut self, err: DecodeError) -> S::Error {
+        self.observer.on_decode_error(&err);
+        err.into()
+    }
     pub fn read_byte(&mut self) -> Result<u8, S::Error> {
-        if let Some(byte) = self.peeked.take() {
-            Ok(byte)
-        } else {
-            let byte = self.source.read_byte()?;
-            Ok(byte)
+        #[cfg(feature = "alloc")]
+        if let Some(ref mut pos) = self.replay {
+            if let Some(&byte) = self.journal.get(*pos) {
+                *pos += 1;
+                return Ok(self.consume(byte));
+            }
+        }
+        let byte = match self.lookahead.pop() {
+            Some(byte) => byte,
+            None => self.read_source_byte()?,
+        };
+        self.offset += 1;
+        #[cfg(feature = "alloc")]
+        if let Some(ref mut pos) = self.replay {
+            self.journal.push(byte);
+            *pos += 1;
         }
+        Ok(self.consume(byte))
+    }
+    /// Reads bytes into the given buffer, and returns the number of bytes read.
+    ///
+    /// Bytes that are replayed, peeked, or pending after END are read one at a time, and otherwise
+    /// as many bytes as the source provides in one call.
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, S::Error> {
+        #[cfg(feature = "alloc")]
+        let replaying = self.replay.is_some_and(|pos| pos < self.journal.len());
+        #[cfg(not(feature = "alloc"))]
+        let replaying = false;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if replaying || !self.lookahead.is_empty() || self.end_pending {
+            buf[0] = self.read_byte()?;
+            return Ok(1);
+        }
+        let (len, end) = self.source.read_bytes(buf).inspect_err(|_| {
+            #[cfg(feature = "alloc")]
+            {
+                self.interrupted = true;
+            }
+        })?;
+        self.observer.on_bytes_read(len);
+        let bytes = &buf[..len];
+        if let Some(&last) = bytes.last() {
+            self.end_pending = end && last != b'\n';
+            self.offset += len;
+            #[cfg(feature = "alloc")]
+            if let Some(ref mut pos) = self.replay {
+                self.journal.extend_from_slice(bytes);
+                *pos += len;
+            }
+            #[cfg(feature = "alloc")]
+            if let Some(ref mut capture) = self.capture {
+                capture.extend_from_slice(bytes);
+            }
+            self.last = last;
+        }
+        Ok(len)
     }
     pub fn peek_byte(&mut self) -> Result<u8, S::Error> {
-        if let Some(byte) = self.peeked {
-            Ok(byte)
-        } else {
-            let byte = self.source.read_byte()?;
-            self.peeked = Some(byte);
-            Ok(byte)
+        #[cfg(feature = "alloc")]
+        if let Some(pos) = self.replay {
+            if let Some(&byte) = self.journal.get(pos) {
+                return Ok(byte);
+            }
+        }
+        match self.lookahead.get(0) {
+            Some(byte) => Ok(byte),
+            None => {
+                let byte = self.read_source_byte()?;
+                self.lookahead.push(byte);
+                Ok(byte)
+            }
+        }
+    }
+    /// Peeks the byte at the given offset from the next byte to be read.
+    ///
+    /// Returns `None` if the offset is beyond the lookahead capacity.
+    fn peek_byte_at(&mut self, idx: usize) -> Result<Option<u8>, S::Error> {
+        while self.lookahead.len() <= idx && self.lookahead.len() < LOOKAHEAD_LEN {
+            let byte = self.read_source_byte()?;
+            self.lookahead.push(byte);
+        }
+        Ok(self.lookahead.get(idx))
+    }
+    /// Reads a byte from the source, and a response message terminator after a byte that was
+    /// received with END.
+    ///
+    /// A failed read marks resumable decoding as interrupted, so the bytes consumed so far are
+    /// replayed when decoding is retried.
+    fn read_source_byte(&mut self) -> Result<u8, S::Error> {
+        if self.end_pending {
+            self.end_pending = false;
+            return Ok(b'\n');
+        }
+        let (byte, end) = self.source.read_byte_with_end().inspect_err(|_| {
+            #[cfg(feature = "alloc")]
+            {
+                self.interrupted = true;
+            }
+        })?;
+        self.observer.on_bytes_read(1);
+        self.end_pending = end && byte != b'\n';
+        Ok(byte)
+    }
+    fn consume(&mut self, byte: u8) -> u8 {
+        #[cfg(feature = "alloc")]
+        if let Some(ref mut capture) = self.capture {
+            capture.push(byte);
+        }
+        self.last = byte;
+        byte
+    }
+    /// Runs a decoding function, and returns its result together with the bytes it consumed.
+    ///
+    /// Bytes that have only been peeked are not included.
+    #[cfg(feature = "alloc")]
+    pub fn capture<T, F>(&mut self, f: F) -> Result<(T, Vec<u8>), S::Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, S::Error>,
+    {
+        let outer = self.capture.replace(Vec::new());
+        let result = f(self);
+        let captured = core::mem::replace(&mut self.capture, outer).unwrap_or_default();
+        if let Some(ref mut outer) = self.capture {
+            outer.extend_from_slice(&captured);
         }
+        result.map(|value| (value, captured))
+    }
+    /// Runs a decoding function so that the bytes it has consumed are kept if reading from the
+    /// source fails, and replayed when the decoding function is called again.
+    #[cfg(feature = "alloc")]
+    fn resumable<T, F>(&mut self, f: F) -> Result<T, S::Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, S::Error>,
+    {
+        self.replay = Some(0);
+        self.interrupted = false;
+        let result = f(self);
+        self.replay = None;
+        if result.is_ok() || !self.interrupted {
+            self.journal.clear();
+        }
+        result
+    }
+    /// Runs a decoding function. Without the `alloc` feature consumed bytes can't be kept, so
+    /// decoding can't be resumed.
+    #[cfg(not(feature = "alloc"))]
+    fn resumable<T, F>(&mut self, f: F) -> Result<T, S::Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, S::Error>,
+    {
+        f(self)
     }
     fn skip_whitespace(&mut self) -> Result<(), S::Error> {
-        self.peeked = Some(loop {
-            match self.read_byte()? {
+        loop {
+            match self.peek_byte()? {
                 // Reference: IEEE 488.2 7.4.1.2 - Encoding Syntax
-                0x00..=0x09 | 0x0b..=0x20 => (),
-                byte => break byte,
-            }
-        });
-        Ok(())
+                0x00..=0x09 | 0x0b..=0x20 => self.read_byte()?,
+                _ => break Ok(()),
+            };
+        }
     }
     pub fn begin_response_data(&mut self) -> Result<(), S::Error> {
         match self.state {
             DecodeState::Initial | DecodeState::DataExpected | DecodeState::MessageUnitExpected => {
+                #[cfg(feature = "alloc")]
+                self.journal.clear();
                 self.skip_whitespace()?;
+                if self.echoed_headers && !self.header_checked {
+                    self.skip_header(None)?;
+                }
                 self.state = DecodeState::Data;
+                self.observer.on_response_data();
                 Ok(())
             }
-            _ => Err(DecodeError::InvalidDecodeState(self.state).into()),
+            _ => Err(self.error(DecodeError::InvalidDecodeState(self.state))),
         }
     }
     pub fn end_with(&mut self, byte: u8) -> Result<(), S::Error> {
@@ -122,32 +451,204 @@ impl<S: ByteSource> Decoder<S> {
                 // Reference: IEEE 488.2: 8.5 - \<RESPONSE MESSAGE TERMINATOR\>
                 b'\n' => DecodeState::End,
                 // Reference: IEEE 488.2: 8.4.1 - \<RESPONSE MESSAGE UNIT SEPARATOR\>
-                b';' => DecodeState::MessageUnitExpected,
+                b';' => {
+                    self.header_checked = false;
+                    DecodeState::MessageUnitExpected
+                }
                 // Reference: IEEE 488.2: 8.4.2 - \<RESPONSE DATA SEPARATOR\>
                 b',' => DecodeState::DataExpected,
-                _ => return Err(DecodeError::InvalidDataTerminator { byte })?,
+                _ => return Err(self.error(DecodeError::InvalidDataTerminator { byte })),
             },
-            _ => return Err(DecodeError::InvalidDecodeState(self.state))?,
+            _ => return Err(self.error(DecodeError::InvalidDecodeState(self.state))),
         };
+        if self.state == DecodeState::End {
+            self.observer.on_response_end();
+        }
         Ok(())
     }
     pub fn is_at_end(&self) -> bool {
         self.state == DecodeState::End
     }
-    pub fn finish(self) -> Result<S, S::Error> {
+    pub fn state(&self) -> DecodeState {
+        self.state
+    }
+    /// Returns the number of bytes of the current response message consumed so far, which is the
+    /// offset of the next byte in the message.
+    ///
+    /// Bytes that have only been peeked are not included.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+    /// Discards input until the end of the current response message.
+    ///
+    /// This can be used to keep the byte source usable after a decoding error. If the response
+    /// message terminator has already been consumed, no input is read. String and definite length
+    /// arbitrary block response data are skipped as a whole, also when the error happened in the
+    /// middle of them, so a newline inside them is not mistaken for a terminator.
+    pub fn resynchronize(&mut self) -> Result<(), S::Error> {
+        #[cfg(feature = "alloc")]
+        self.journal.clear();
+        if self.state != DecodeState::End {
+            self.discard_message()?;
+        }
+        self.state = DecodeState::End;
+        self.header_checked = false;
+        self.in_string = false;
+        self.block = None;
+        Ok(())
+    }
+    fn discard_message(&mut self) -> Result<(), S::Error> {
+        if self.block.is_some() {
+            self.skip_block_payload()?;
+        } else if !self.in_string && self.lookahead.is_empty() && self.last == b'\n' {
+            return Ok(());
+        }
+        let mut in_string = self.in_string;
+        loop {
+            match self.read_byte()? {
+                // Doubled quotes inside a string toggle twice
+                b'"' => in_string = !in_string,
+                _ if in_string => (),
+                // Reference: IEEE 488.2: 8.5 - \<RESPONSE MESSAGE TERMINATOR\>
+                b'\n' => return Ok(()),
+                b'#' if matches!(self.peek_byte()?, b'1'..=b'9') => {
+                    let digits = self.read_byte()? - b'0';
+                    let mut block_size = Some(0);
+                    for _ in 0..digits {
+                        match self.read_byte()? {
+                            byte @ b'0'..=b'9' => {
+                                block_size =
+                                    block_size.map(|size| size * 10 + u64::from(byte - b'0'))
+                            }
+                            b'\n' => return Ok(()),
+                            _ => block_size = None,
+                        }
+                    }
+                    if let Some(block_size) = block_size {
+                        self.block = Some(BlockHeader::Definite(block_size));
+                        self.skip_block_payload()?;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    /// Skips a single response data element of any type, and the separator or terminator
+    /// following it.
+    ///
+    /// String and arbitrary block response data are skipped as a whole, so they may contain
+    /// separators. Other response data ends at the first separator or terminator.
+    pub fn skip_response_data(&mut self) -> Result<(), S::Error> {
+        self.begin_response_data()?;
+        match self.peek_byte()? {
+            b'"' => self.decode_string(&mut Discard),
+            b'#' if self
+                .peek_byte_at(1)?
+                .is_some_and(|byte| byte.is_ascii_digit()) =>
+            {
+                self.skip_arbitrary_block()
+            }
+            _ => loop {
+                let byte = self.read_byte()?;
+                if matches!(byte, b',' | b';' | b'\n') {
+                    break self.end_with(byte);
+                }
+            },
+        }
+    }
+    /// Skips a response data element if it's empty, and the separator or terminator following it.
+    ///
+    /// An element is empty if a separator or terminator follows immediately, or if it's an empty
+    /// string (`""`). Whitespace is skipped first like in
+    /// [`begin_response_data`](Decoder::begin_response_data), so an element of only whitespace is
+    /// also empty. Returns false and leaves other elements to be decoded normally.
+    pub fn skip_empty_response_data(&mut self) -> Result<bool, S::Error> {
+        match self.state {
+            DecodeState::Initial | DecodeState::DataExpected | DecodeState::MessageUnitExpected => {
+                self.skip_whitespace()?;
+                if self.echoed_headers && !self.header_checked {
+                    self.skip_header(None)?;
+                }
+            }
+            _ => return Err(self.error(DecodeError::InvalidDecodeState(self.state))),
+        }
+        let empty = match self.peek_byte()? {
+            b',' | b';' | b'\n' => 0,
+            b'"' if self.peek_byte_at(1)? == Some(b'"') && self.peek_byte_at(2)? != Some(b'"') => 2,
+            _ => return Ok(false),
+        };
+        self.begin_response_data()?;
+        for _ in 0..empty {
+            self.read_byte()?;
+        }
+        let byte = self.read_byte()?;
+        self.end_with(byte)?;
+        Ok(true)
+    }
+    /// Prepares the decoder for the next response message from the same byte source.
+    ///
+    /// The current response message must have been decoded up to and including its terminator,
+    /// so a missing terminator or an unread response data element is reported as
+    /// [`DecodeError::InvalidDecodeState`] instead of mixing two responses together. Decoding a
+    /// response never starts a new one implicitly, so this must be called between responses.
+    pub fn reset_for_next_response(&mut self) -> Result<(), S::Error> {
+        match self.state {
+            DecodeState::End => {
+                self.state = DecodeState::Initial;
+                self.header_checked = false;
+                self.in_string = false;
+                self.offset = 0;
+                Ok(())
+            }
+            _ => Err(self.error(DecodeError::InvalidDecodeState(self.state))),
+        }
+    }
+    pub fn finish(mut self) -> Result<S, S::Error> {
         match self.state {
             DecodeState::End => Ok(self.source),
-            _ => Err(DecodeError::InvalidDecodeState(self.state).into()),
+            _ => Err(self.error(DecodeError::InvalidDecodeState(self.state))),
         }
     }
 }
 
-impl<S: ByteSource> Decoder<S> {
-    #[inline]
-    fn sign(&mut self) -> Result<u8, S::Error> {
-        match self.read_byte()? {
-            byte @ b'-' | byte @ b'+' => Ok(byte),
-            _ => Err(DecodeError::Parse.into()),
+/// A target that discards everything written to it
+struct Discard;
+
+impl fmt::Write for Discard {
+    fn write_str(&mut self, _: &str) -> fmt::Result {
+        Ok(())
+    }
+}
+
+/// Result of feeding a byte to a [`Scanner`]
+enum Step {
+    /// The byte was accepted, and more bytes are expected
+    More,
+    /// The byte was not part of the data, and should end it
+    End,
+    Invalid,
+    Overflow,
+}
+
+/// A byte-level state machine that validates and collects response data bytes
+///
+/// Scanners are used through `dyn Scanner`, so the scanning loop is compiled once per byte source
+/// type, and not once per decoded type.
+trait Scanner {
+    fn push(&mut self, byte: u8) -> Step;
+}
+
+impl<S: ByteSource, O: ProtocolObserver> Decoder<S, O> {
+    /// Feeds bytes to the scanner until the data ends with a terminator or separator.
+    fn scan(&mut self, scanner: &mut dyn Scanner) -> Result<(), S::Error> {
+        loop {
+            let byte = self.read_byte()?;
+            match scanner.push(byte) {
+                Step::More => (),
+                Step::End => break self.end_with(byte),
+                Step::Invalid => break Err(self.error(DecodeError::Parse)),
+                Step::Overflow => break Err(self.error(DecodeError::BufferOverflow)),
+            }
         }
     }
 
@@ -155,48 +656,323 @@ impl<S: ByteSource> Decoder<S> {
     fn digit(&mut self) -> Result<u8, S::Error> {
         match self.read_byte()? {
             byte @ b'0'..=b'9' => Ok(byte),
-            _ => Err(DecodeError::Parse.into()),
+            _ => Err(self.error(DecodeError::Parse)),
         }
     }
 
     #[inline]
-    fn hex_digit(&mut self) -> Result<u8, S::Error> {
+    fn upper(&mut self) -> Result<u8, S::Error> {
         match self.read_byte()? {
-            byte @ b'A'..=b'F' => Ok(byte),
-            byte @ b'0'..=b'9' => Ok(byte),
-            _ => Err(DecodeError::Parse.into()),
+            byte @ b'A'..=b'Z' => Ok(byte),
+            _ => Err(self.error(DecodeError::Parse)),
         }
     }
 
     #[inline]
-    fn octal_digit(&mut self) -> Result<u8, S::Error> {
+    fn quote(&mut self) -> Result<u8, S::Error> {
         match self.read_byte()? {
-            byte @ b'0'..=b'7' => Ok(byte),
-            _ => Err(DecodeError::Parse.into()),
+            byte @ b'"' => {
+                self.in_string = true;
+                Ok(byte)
+            }
+            _ => Err(self.error(DecodeError::Parse)),
         }
     }
+}
 
-    #[inline]
-    fn binary_digit(&mut self) -> Result<u8, S::Error> {
-        match self.read_byte()? {
-            byte @ b'0'..=b'1' => Ok(byte),
-            _ => Err(DecodeError::Parse.into()),
-        }
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::{DecodeError, DecodeState, Decoder};
+    use crate::ByteSource;
+
+    #[test]
+    fn resynchronize_discards_rest_of_message() {
+        let mut decoder = Decoder::new(&b"1,2,3\n4\n"[..]);
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(1));
+        assert_eq!(decoder.state(), DecodeState::DataExpected);
+        decoder.resynchronize().unwrap();
+        assert!(decoder.is_at_end());
+        assert_eq!(decoder.finish().unwrap(), b"4\n");
     }
 
-    #[inline]
-    fn upper(&mut self) -> Result<u8, S::Error> {
-        match self.read_byte()? {
-            byte @ b'A'..=b'Z' => Ok(byte),
-            _ => Err(DecodeError::Parse.into()),
+    #[test]
+    fn resynchronize_after_consumed_terminator_reads_nothing() {
+        let mut decoder = Decoder::new(&b"\n4\n"[..]);
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_boolean(), Err(DecodeError::Parse));
+        decoder.resynchronize().unwrap();
+        assert_eq!(decoder.finish().unwrap(), b"4\n");
+    }
+
+    #[test]
+    fn resynchronize_after_whitespace_skip_reads_peeked_terminator() {
+        let mut decoder = Decoder::new(&b"  \n4\n"[..]);
+        decoder.begin_response_data().unwrap();
+        decoder.resynchronize().unwrap();
+        assert_eq!(decoder.finish().unwrap(), b"4\n");
+    }
+
+    #[test]
+    fn resynchronize_skips_strings_and_blocks() {
+        let mut decoder = Decoder::new(&b"1,\"a\n\"\"\n\",#13\n\n\n,#0\n4\n"[..]);
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(1));
+        decoder.resynchronize().unwrap();
+        assert_eq!(decoder.finish().unwrap(), b"4\n");
+    }
+
+    #[test]
+    fn resynchronize_in_the_middle_of_a_string() {
+        let mut decoder = Decoder::new(&b"\"a\xff\n\"\n4\n"[..]);
+        decoder.begin_response_data().unwrap();
+        assert_matches!(
+            decoder.decode_string(&mut super::Discard),
+            Err(DecodeError::Parse)
+        );
+        decoder.resynchronize().unwrap();
+        assert_eq!(decoder.finish().unwrap(), b"4\n");
+    }
+
+    #[test]
+    fn resynchronize_in_the_middle_of_a_block() {
+        let mut decoder = Decoder::new(&b"#14\nab\n\n4\n"[..]);
+        decoder.begin_response_data().unwrap();
+        decoder.begin_arbitrary_block().unwrap();
+        assert_eq!(decoder.read_block_payload(&mut [0; 1]), Ok(1));
+        decoder.resynchronize().unwrap();
+        assert_eq!(decoder.finish().unwrap(), b"4\n");
+    }
+
+    #[test]
+    fn consecutive_responses() {
+        let mut decoder = Decoder::new(&b"1,2\n  -3;4\n5\n"[..]);
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(1));
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(2));
+        assert!(decoder.is_at_end());
+        assert_matches!(
+            decoder.begin_response_data(),
+            Err(DecodeError::InvalidDecodeState(DecodeState::End))
+        );
+
+        decoder.reset_for_next_response().unwrap();
+        assert_eq!(decoder.state(), DecodeState::Initial);
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<i8>(), Ok(-3));
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(4));
+        decoder.reset_for_next_response().unwrap();
+        assert_eq!(
+            decoder.finish().unwrap_err(),
+            DecodeError::InvalidDecodeState(DecodeState::Initial)
+        );
+    }
+
+    #[test]
+    fn next_response_requires_end() {
+        let mut decoder = Decoder::new(&b"1,2\n3\n"[..]);
+        assert_matches!(
+            decoder.reset_for_next_response(),
+            Err(DecodeError::InvalidDecodeState(DecodeState::Initial))
+        );
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(1));
+        assert_matches!(
+            decoder.reset_for_next_response(),
+            Err(DecodeError::InvalidDecodeState(DecodeState::DataExpected))
+        );
+        decoder.resynchronize().unwrap();
+        decoder.reset_for_next_response().unwrap();
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(3));
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+
+    /// A byte source that reports END with the last byte of each message
+    struct EndSource<'a> {
+        messages: &'a [&'a [u8]],
+        pos: usize,
+    }
+
+    impl<'a> ByteSource for EndSource<'a> {
+        type Error = DecodeError;
+
+        fn read_byte(&mut self) -> Result<u8, Self::Error> {
+            self.read_byte_with_end().map(|(byte, _)| byte)
+        }
+        fn read_byte_with_end(&mut self) -> Result<(u8, bool), Self::Error> {
+            let (message, rest) = self
+                .messages
+                .split_first()
+                .ok_or(DecodeError::UnexpectedEnd)?;
+            let byte = message[self.pos];
+            self.pos += 1;
+            let end = self.pos == message.len();
+            if end {
+                self.messages = rest;
+                self.pos = 0;
+            }
+            Ok((byte, end))
         }
     }
 
-    #[inline]
-    fn quote(&mut self) -> Result<u8, S::Error> {
-        match self.read_byte()? {
-            byte @ b'"' => Ok(byte),
-            _ => Err(DecodeError::Parse.into()),
+    #[test]
+    fn end_is_treated_as_terminator() {
+        let mut decoder = Decoder::new(EndSource {
+            messages: &[b"1,2", b"3\n", b"1"],
+            pos: 0,
+        });
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(1));
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(2));
+        assert!(decoder.is_at_end());
+
+        // END with a terminator doesn't add another one
+        decoder.reset_for_next_response().unwrap();
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(3));
+        decoder.reset_for_next_response().unwrap();
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_boolean(), Ok(true));
+        assert!(decoder.is_at_end());
+        assert!(decoder.finish().unwrap().messages.is_empty());
+    }
+
+    #[test]
+    fn resynchronize_stops_at_end() {
+        let mut decoder = Decoder::new(EndSource {
+            messages: &[b"1,2", b"3"],
+            pos: 0,
+        });
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(1));
+        decoder.resynchronize().unwrap();
+        decoder.reset_for_next_response().unwrap();
+        decoder.begin_response_data().unwrap();
+        assert_matches!(decoder.decode_numeric_integer::<u8>(), Ok(3));
+        assert!(decoder.is_at_end());
+    }
+
+    #[cfg(feature = "alloc")]
+    mod resumable {
+        use alloc::string::String;
+        use matches::assert_matches;
+
+        use crate::{
+            decode::{DecodeError, Decoder},
+            ByteSource,
+        };
+
+        #[derive(Debug)]
+        enum StutterError {
+            WouldBlock,
+            Decode(DecodeError),
+        }
+
+        impl From<DecodeError> for StutterError {
+            fn from(err: DecodeError) -> Self {
+                StutterError::Decode(err)
+            }
+        }
+
+        /// A byte source that fails every other read
+        struct Stutter<'a> {
+            data: &'a [u8],
+            stutter: bool,
+        }
+
+        impl<'a> ByteSource for Stutter<'a> {
+            type Error = StutterError;
+
+            fn read_byte(&mut self) -> Result<u8, Self::Error> {
+                self.stutter = !self.stutter;
+                if self.stutter {
+                    return Err(StutterError::WouldBlock);
+                }
+                let (&byte, rest) = self.data.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+                self.data = rest;
+                Ok(byte)
+            }
+        }
+
+        fn retry<T>(
+            decoder: &mut Decoder<Stutter>,
+            mut f: impl FnMut(&mut Decoder<Stutter>) -> Result<T, StutterError>,
+        ) -> Result<T, StutterError> {
+            loop {
+                match f(decoder) {
+                    Err(StutterError::WouldBlock) => continue,
+                    result => break result,
+                }
+            }
+        }
+
+        #[test]
+        fn decoding_resumes_after_source_errors() {
+            let data = b"-1234,#HBEEF,1.25E+2,1,SOME_CHARS\n";
+            let mut decoder = Decoder::new(Stutter {
+                data,
+                stutter: false,
+            });
+            let begin = |decoder: &mut Decoder<Stutter>| {
+                retry(decoder, |decoder| decoder.begin_response_data()).unwrap()
+            };
+            begin(&mut decoder);
+            assert_matches!(
+                retry(&mut decoder, |d| d.decode_numeric_integer::<i16>()),
+                Ok(-1234)
+            );
+            begin(&mut decoder);
+            assert_matches!(
+                retry(&mut decoder, |d| d.decode_numeric_integer::<u16>()),
+                Ok(0xBEEF)
+            );
+            begin(&mut decoder);
+            assert_matches!(retry(&mut decoder, |d| d.decode_numeric_float::<f32>()), Ok(v) if v == 125.0);
+            begin(&mut decoder);
+            assert_matches!(retry(&mut decoder, |d| d.decode_boolean()), Ok(true));
+            begin(&mut decoder);
+            let mut text = String::new();
+            assert_matches!(
+                retry(&mut decoder, |d| d.decode_characters(&mut text)),
+                Ok(())
+            );
+            assert_eq!(text, "SOME_CHARS");
+            assert!(decoder.is_at_end());
+        }
+
+        #[test]
+        fn decoding_resumes_after_peek_errors() {
+            let mut decoder = Decoder::new(Stutter {
+                data: b"ab\n",
+                stutter: true,
+            });
+            let read_and_peek = |decoder: &mut Decoder<Stutter>| {
+                decoder.resumable(|decoder| Ok((decoder.read_byte()?, decoder.peek_byte()?)))
+            };
+            assert_matches!(read_and_peek(&mut decoder), Err(StutterError::WouldBlock));
+            assert_matches!(read_and_peek(&mut decoder), Ok((b'a', b'b')));
+        }
+
+        #[test]
+        fn decode_errors_are_not_resumed() {
+            let mut decoder = Decoder::new(Stutter {
+                data: b"12x,3\n",
+                stutter: true,
+            });
+            decoder.begin_response_data().unwrap();
+            assert_matches!(
+                retry(&mut decoder, |d| d.decode_numeric_integer::<u8>()),
+                Err(StutterError::Decode(DecodeError::InvalidDataTerminator {
+                    byte: b'x'
+                }))
+            );
+            assert!(decoder.journal.is_empty());
         }
     }
 }
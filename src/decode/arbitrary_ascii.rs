@@ -5,12 +5,15 @@
 use core::fmt;
 
 use super::Decoder;
-use crate::{decode::DecodeError, ByteSource};
+use crate::{decode::DecodeError, ByteSource, ProtocolObserver};
 
 /// Decodes arbitrary ASCII response data into the given target buffer.
 ///
 /// Reference: IEEE 488.2: 8.7.11 - \<ARBITRARY ASCII RESPONSE DATA\>
-impl<S: ByteSource> Decoder<S> {
+///
+/// Not resumable: if reading from the byte source fails, the bytes consumed so far are lost, and
+/// the response message should be discarded with [`resynchronize`](Decoder::resynchronize).
+impl<S: ByteSource, O: ProtocolObserver> Decoder<S, O> {
     pub fn decode_arbitrary_ascii<T: fmt::Write>(
         &mut self,
         target: &mut T,
@@ -20,14 +23,14 @@ impl<S: ByteSource> Decoder<S> {
                 byte @ b'\n' => break self.end_with(byte),
                 byte if byte.is_ascii() => target
                     .write_char(byte as char)
-                    .map_err(|_| DecodeError::BufferOverflow)?,
-                _ => break Err(DecodeError::Parse.into()),
+                    .map_err(|_| self.error(DecodeError::BufferOverflow))?,
+                _ => break Err(self.error(DecodeError::Parse)),
             }
         }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
     use alloc::string::String;
     use matches::assert_matches;
@@ -2,22 +2,206 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use core::str;
+use super::{DecodeState, Decoder};
+use crate::{buffer::ArrayBuffer, decode::DecodeError, ByteSink, ByteSource, ProtocolObserver};
 
-use super::Decoder;
-use crate::{decode::DecodeError, internal::ArrayBuffer, ByteSink, ByteSource};
+const CHUNK_SIZE: usize = 512;
+/// Maximum number of bytes reserved up front for a definite length block. The declared length
+/// comes from the device, so larger blocks grow the target as the payload arrives.
+const MAX_RESERVE: usize = 128 * CHUNK_SIZE;
 
-/// Decodes arbitrary block response data into the given target buffer.
+/// Header of arbitrary block response data
+///
+/// Returned by [`Decoder::begin_arbitrary_block`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BlockHeader {
+    /// Definite length format with the given payload length
+    ///
+    /// Reference: IEEE 488.2: 8.7.9 - \<DEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA\>
+    Definite(u64),
+    /// Indefinite length format, where the payload ends at the response message terminator
+    ///
+    /// Reference: IEEE 488.2: 8.7.10 - \<INDEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA\>
+    Indefinite,
+}
+
+/// Decodes arbitrary block response data.
 ///
 /// References:
 ///
 /// - IEEE 488.2: 8.7.9 - \<DEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA\>
 /// - IEEE 488.2: 8.7.10 - \<INDEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA\>
-impl<S: ByteSource> Decoder<S> {
+///
+/// A block can be decoded in one go into a target buffer, or incrementally: the header is decoded
+/// with [`begin_arbitrary_block`](Decoder::begin_arbitrary_block), the payload is read in parts
+/// with [`read_block_payload`](Decoder::read_block_payload), and the data terminator following the
+/// block is consumed with [`finish_block`](Decoder::finish_block).
+///
+/// ```
+/// use red_sculpin::decode::{BlockHeader, Decoder};
+///
+/// let mut decoder = Decoder::new(&b"#15hello\n"[..]);
+/// decoder.begin_response_data().unwrap();
+/// assert_eq!(decoder.begin_arbitrary_block(), Ok(BlockHeader::Definite(5)));
+/// let mut buf = [0; 3];
+/// assert_eq!(decoder.read_block_payload(&mut buf), Ok(3));
+/// assert_eq!(&buf, b"hel");
+/// assert_eq!(decoder.read_block_payload(&mut buf), Ok(2));
+/// assert_eq!(decoder.read_block_payload(&mut buf), Ok(0));
+/// decoder.finish_block().unwrap();
+/// assert!(decoder.is_at_end());
+/// ```
+///
+/// Not resumable: if reading from the byte source fails, the bytes consumed so far are lost, and
+/// the response message should be discarded with [`resynchronize`](Decoder::resynchronize).
+///
+/// Definite length blocks that are decoded into memory are rejected with
+/// [`DecodeError::BlockTooLarge`] if the declared length doesn't fit in `usize`, or exceeds the
+/// limit set with [`set_max_block_len`](Decoder::set_max_block_len). Blocks read incrementally
+/// can have any length.
+impl<S: ByteSource, O: ProtocolObserver> Decoder<S, O> {
+    /// Sets the maximum payload length of definite length blocks decoded into memory, or removes
+    /// the limit with `None`.
+    pub fn set_max_block_len(&mut self, max_len: Option<u64>) {
+        self.max_block_len = max_len;
+    }
+    pub fn max_block_len(&self) -> Option<u64> {
+        self.max_block_len
+    }
     pub fn decode_arbitrary_block<T: ByteSink>(&mut self, target: &mut T) -> Result<(), S::Error> {
+        if let BlockHeader::Definite(block_size) = self.begin_arbitrary_block()? {
+            target.reserve(self.check_block_len(block_size)?.min(MAX_RESERVE));
+        }
+        let mut buf = [0; CHUNK_SIZE];
+        loop {
+            let len = self.read_block_payload(&mut buf)?;
+            if len == 0 {
+                break self.finish_block();
+            }
+            target
+                .write_bytes(&buf[..len])
+                .map_err(|_| self.error(DecodeError::BufferOverflow))?;
+        }
+    }
+    /// Decodes arbitrary block response data into a fixed-size array, and returns the number of
+    /// bytes written.
+    ///
+    /// Blocks longer than `N` bytes are rejected with [`DecodeError::BufferOverflow`]. The
+    /// decoder can then be resynchronized with [`resynchronize`](Decoder::resynchronize): for the
+    /// definite length format, the rest of the block is discarded before the error is returned,
+    /// so block data is never mistaken for a terminator.
+    pub fn decode_arbitrary_block_array<const N: usize>(
+        &mut self,
+        target: &mut [u8; N],
+    ) -> Result<usize, S::Error> {
+        match self.begin_arbitrary_block()? {
+            BlockHeader::Definite(block_size) if block_size > N as u64 => {
+                self.skip_block_payload()?;
+                Err(self.error(DecodeError::BufferOverflow))
+            }
+            _ => {
+                let len = self.read_block_payload(target)?;
+                if self.block == Some(BlockHeader::Indefinite) && self.peek_byte()? != b'\n' {
+                    return Err(self.error(DecodeError::BufferOverflow));
+                }
+                self.finish_block()?;
+                Ok(len)
+            }
+        }
+    }
+    /// Decodes an arbitrary block header, and leaves the decoder at the first payload byte.
+    ///
+    /// The payload must then be read with [`read_block_payload`](Decoder::read_block_payload)
+    /// until it returns 0, and the block finished with [`finish_block`](Decoder::finish_block).
+    pub fn begin_arbitrary_block(&mut self) -> Result<BlockHeader, S::Error> {
+        let header = self.decode_block_header()?;
+        self.block = Some(header);
+        Ok(header)
+    }
+    /// Reads payload bytes of the current arbitrary block into the given buffer, and returns the
+    /// number of bytes read.
+    ///
+    /// Returns 0 once the whole payload has been read, or if the buffer is empty. The data
+    /// terminator following the block is not consumed.
+    pub fn read_block_payload(&mut self, buf: &mut [u8]) -> Result<usize, S::Error> {
+        match self.block {
+            Some(BlockHeader::Definite(remaining)) => {
+                let len = remaining.min(buf.len() as u64) as usize;
+                let mut pos = 0;
+                while pos < len {
+                    pos += self.read_bytes(&mut buf[pos..len])?;
+                    self.block = Some(BlockHeader::Definite(remaining - pos as u64));
+                }
+                Ok(len)
+            }
+            Some(BlockHeader::Indefinite) => {
+                let mut len = 0;
+                while len < buf.len() && self.peek_byte()? != b'\n' {
+                    buf[len] = self.read_byte()?;
+                    len += 1;
+                }
+                Ok(len)
+            }
+            None => Err(self.error(DecodeError::InvalidDecodeState(self.state))),
+        }
+    }
+    /// Ends the current arbitrary block, and consumes the data terminator following it.
+    ///
+    /// The whole payload must have been read, so that payload bytes are never mistaken for a
+    /// terminator. Otherwise, [`DecodeError::InvalidDecodeState`] is returned.
+    pub fn finish_block(&mut self) -> Result<(), S::Error> {
+        let finished = match self.block {
+            Some(BlockHeader::Definite(remaining)) => remaining == 0,
+            Some(BlockHeader::Indefinite) => self.peek_byte()? == b'\n',
+            None => false,
+        };
+        if !finished {
+            return Err(self.error(DecodeError::InvalidDecodeState(DecodeState::Data)));
+        }
+        self.block = None;
+        let byte = self.read_byte()?;
+        self.end_with(byte)
+    }
+    /// Skips arbitrary block response data, and the data terminator following it.
+    pub(super) fn skip_arbitrary_block(&mut self) -> Result<(), S::Error> {
+        self.begin_arbitrary_block()?;
+        self.skip_block_payload()?;
+        self.finish_block()
+    }
+    pub(super) fn skip_block_payload(&mut self) -> Result<(), S::Error> {
+        let mut buf = [0; CHUNK_SIZE];
+        while self.read_block_payload(&mut buf)? > 0 {}
+        Ok(())
+    }
+    /// Returns the declared length of a definite length block that is decoded into memory, or
+    /// [`DecodeError::BlockTooLarge`] if it's too large.
+    pub(super) fn check_block_len(&mut self, declared: u64) -> Result<usize, S::Error> {
+        match usize::try_from(declared) {
+            Ok(len)
+                if self
+                    .max_block_len
+                    .map_or(true, |max_len| declared <= max_len) =>
+            {
+                Ok(len)
+            }
+            _ => Err(self.error(DecodeError::BlockTooLarge { declared })),
+        }
+    }
+    /// Decodes an arbitrary block header, and returns the block size for the definite length
+    /// format, or `None` for the indefinite length format.
+    ///
+    /// Unlike [`begin_arbitrary_block`](Decoder::begin_arbitrary_block), the payload is left for
+    /// the caller to read directly.
+    pub(crate) fn decode_arbitrary_block_header(&mut self) -> Result<Option<u64>, S::Error> {
+        match self.decode_block_header()? {
+            BlockHeader::Definite(block_size) => Ok(Some(block_size)),
+            BlockHeader::Indefinite => Ok(None),
+        }
+    }
+    fn decode_block_header(&mut self) -> Result<BlockHeader, S::Error> {
         match self.read_byte()? {
             b'#' => (),
-            _ => return Err(DecodeError::Parse.into()),
+            _ => return Err(self.error(DecodeError::Parse)),
         }
         match self.read_byte()? {
             byte @ b'1'..=b'9' => {
@@ -26,40 +210,32 @@ impl<S: ByteSource> Decoder<S> {
                 let mut buf = ArrayBuffer::<9>::new();
                 for _ in 0..digits {
                     buf.push(self.digit()?)
-                        .map_err(|_| DecodeError::BufferOverflow)?;
+                        .map_err(|_| self.error(DecodeError::BufferOverflow))?;
                 }
-                let block_size = str::from_utf8(buf.finish())
+                let block_size = buf
+                    .as_str()
                     .ok()
                     .and_then(|text| text.parse().ok())
-                    .ok_or(DecodeError::Parse)?;
-                for _ in 0..block_size {
-                    target
-                        .write_byte(self.read_byte()?)
-                        .map_err(|_| DecodeError::BufferOverflow)?;
-                }
-                let byte = self.read_byte()?;
-                self.end_with(byte)
+                    .ok_or_else(|| self.error(DecodeError::Parse))?;
+                Ok(BlockHeader::Definite(block_size))
             }
-            b'0' => loop {
-                // indefinite length format
-                match self.read_byte()? {
-                    byte @ b'\n' => break self.end_with(byte),
-                    byte => target
-                        .write_byte(byte)
-                        .map_err(|_| DecodeError::BufferOverflow)?,
-                }
-            },
-            _ => Err(DecodeError::Parse.into()),
+            // indefinite length format
+            b'0' => Ok(BlockHeader::Indefinite),
+            _ => Err(self.error(DecodeError::Parse)),
         }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
-    use alloc::vec::Vec;
+    use alloc::{collections::VecDeque, vec::Vec};
     use matches::assert_matches;
 
-    use crate::decode::{DecodeError, Decoder};
+    use crate::{
+        decode::{DecodeError, Decoder},
+        encode::EncodeError,
+        ByteSink,
+    };
 
     #[test]
     fn header_must_exist() {
@@ -67,10 +243,15 @@ mod tests {
     }
 
     mod definite_format {
+        use alloc::vec::Vec;
         use matches::assert_matches;
 
         use super::decode;
-        use crate::decode::DecodeError;
+        use crate::{
+            adapters::ChunkedSource,
+            decode::{DecodeError, Decoder},
+            ByteSource,
+        };
 
         #[test]
         fn data_can_be_empty() {
@@ -90,10 +271,184 @@ mod tests {
             );
         }
 
+        #[test]
+        fn header_length_can_be_9() {
+            assert_matches!(decode(b"#9000000005short\n").as_deref(), Ok(b"short"));
+        }
+
+        #[test]
+        fn reservation_is_capped() {
+            let mut block = Vec::new();
+            let mut decoder = Decoder::new(&b"#9999999999truncated\n"[..]);
+            decoder.begin_response_data().unwrap();
+            assert_matches!(
+                decoder.decode_arbitrary_block(&mut block),
+                Err(DecodeError::UnexpectedEnd)
+            );
+            assert!(block.capacity() <= super::super::MAX_RESERVE);
+
+            let mut block = Vec::new();
+            let mut decoder = Decoder::new(&b"#15short\n"[..]);
+            decoder.begin_response_data().unwrap();
+            decoder.decode_arbitrary_block(&mut block).unwrap();
+            assert_eq!(block.capacity(), 5);
+        }
+
+        #[test]
+        fn length_above_limit_is_rejected() {
+            let mut decoder = Decoder::new(&b"#9999999999truncated\n"[..]);
+            decoder.set_max_block_len(Some(1 << 20));
+            decoder.begin_response_data().unwrap();
+            assert_matches!(
+                decoder.decode_arbitrary_block(&mut Vec::new()),
+                Err(DecodeError::BlockTooLarge {
+                    declared: 999_999_999
+                })
+            );
+
+            let mut decoder = Decoder::new(&b"#15short\n"[..]);
+            decoder.set_max_block_len(Some(4));
+            decoder.begin_response_data().unwrap();
+            assert_matches!(
+                decoder.decode_block_borrowed(),
+                Err(DecodeError::BlockTooLarge { declared: 5 })
+            );
+        }
+
         #[test]
         fn having_too_few_bytes_leads_to_error() {
             assert_matches!(decode(b"#210truncated\n"), Err(DecodeError::UnexpectedEnd));
         }
+
+        #[test]
+        fn large_block() {
+            let payload = (0..300_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+            let mut data = b"#6300000".to_vec();
+            data.extend_from_slice(&payload);
+            data.push(b'\n');
+            let mut decoder = Decoder::new(&data[..]);
+            decoder.begin_response_data().unwrap();
+            let mut result = Vec::new();
+            decoder.decode_arbitrary_block(&mut result).unwrap();
+            assert!(result == payload);
+            assert!(decoder.finish().unwrap().is_empty());
+        }
+
+        #[test]
+        fn payload_is_read_in_bulk() {
+            let payload = (0..8192u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+            let mut data = b"#48192".to_vec();
+            data.extend_from_slice(&payload);
+            data.push(b'\n');
+
+            let mut source = CountingSource {
+                data: &data,
+                byte_reads: 0,
+                bulk_reads: 0,
+            };
+            let mut decoder = Decoder::new(&mut source);
+            decoder.begin_response_data().unwrap();
+            let mut result = Vec::new();
+            decoder.decode_arbitrary_block(&mut result).unwrap();
+            assert!(result == payload);
+            decoder.finish().unwrap();
+            assert!(source.data.is_empty());
+            assert_eq!(source.bulk_reads, 16);
+            // only the header and the terminator are read one byte at a time
+            assert_eq!(source.byte_reads, 7);
+
+            let mut decoder = Decoder::new(ChunkedSource::new(data.chunks(1000)));
+            decoder.begin_response_data().unwrap();
+            let mut result = Vec::new();
+            decoder.decode_arbitrary_block(&mut result).unwrap();
+            assert!(result == payload);
+            assert!(decoder.finish().is_ok());
+        }
+
+        /// A byte source that counts single byte and bulk reads
+        struct CountingSource<'a> {
+            data: &'a [u8],
+            byte_reads: usize,
+            bulk_reads: usize,
+        }
+
+        impl ByteSource for CountingSource<'_> {
+            type Error = DecodeError;
+
+            fn read_byte(&mut self) -> Result<u8, Self::Error> {
+                self.byte_reads += 1;
+                self.data.read_byte()
+            }
+            fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(usize, bool), Self::Error> {
+                self.bulk_reads += 1;
+                self.data.read_bytes(buf)
+            }
+        }
+    }
+
+    /// A byte sink that ignores the reserve hint, and counts calls
+    #[derive(Default)]
+    struct CountingSink {
+        data: Vec<u8>,
+        reserved: Vec<usize>,
+        writes: usize,
+    }
+
+    impl ByteSink for CountingSink {
+        type Error = EncodeError;
+
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+            self.data.extend_from_slice(bytes);
+            self.writes += 1;
+            Ok(())
+        }
+        fn reserve(&mut self, additional: usize) {
+            self.reserved.push(additional);
+        }
+    }
+
+    #[test]
+    fn reserve_hint_is_given_for_definite_format() {
+        let mut decoder = Decoder::new(&b"#41200ABCDEFGHIJKLMNOPQRSTUVWXYZ..."[..]);
+        decoder.begin_response_data().unwrap();
+        let mut sink = CountingSink::default();
+        assert_matches!(
+            decoder.decode_arbitrary_block(&mut sink),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(sink.reserved, [1200]);
+
+        let mut data = b"#41200".to_vec();
+        data.extend((0..1200).map(|i| i as u8));
+        data.extend_from_slice(b",#03\n");
+        let mut decoder = Decoder::new(&data[..]);
+        let mut sink = CountingSink::default();
+        decoder.begin_response_data().unwrap();
+        decoder.decode_arbitrary_block(&mut sink).unwrap();
+        decoder.begin_response_data().unwrap();
+        decoder.decode_arbitrary_block(&mut sink).unwrap();
+        assert_eq!(sink.reserved, [1200]);
+        assert_eq!(sink.data.len(), 1201);
+        assert_eq!(sink.writes, 4);
+        assert!(sink.data[..1200]
+            .iter()
+            .enumerate()
+            .all(|(i, &b)| b == i as u8));
+        assert_eq!(sink.data[1200], b'3');
+    }
+
+    #[test]
+    fn ring_buffer_target() {
+        let mut ring = VecDeque::from(*b"old");
+        ring.pop_front();
+        let mut decoder = Decoder::new(&b"#15abcde,#0fg\n"[..]);
+        decoder.begin_response_data().unwrap();
+        decoder.decode_arbitrary_block(&mut ring).unwrap();
+        decoder.begin_response_data().unwrap();
+        decoder.decode_arbitrary_block(&mut ring).unwrap();
+        assert!(decoder.finish().unwrap().is_empty());
+        assert_eq!(ring.drain(..4).collect::<Vec<_>>(), b"ldab");
+        assert_eq!(ring, b"cdefg");
     }
 
     mod indefinite_format {
@@ -128,3 +483,136 @@ mod tests {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod array_tests {
+    use matches::assert_matches;
+
+    use crate::decode::{DecodeError, Decoder};
+
+    #[test]
+    fn exact_fit() {
+        assert_eq!(decode::<4>(b"#14abcd\n"), Ok((4, *b"abcd")));
+        assert_eq!(decode::<4>(b"#0abcd\n"), Ok((4, *b"abcd")));
+    }
+
+    #[test]
+    fn under_fill() {
+        assert_eq!(decode::<4>(b"#12ab\n"), Ok((2, *b"ab\0\0")));
+        assert_eq!(decode::<4>(b"#0ab\n"), Ok((2, *b"ab\0\0")));
+        assert_eq!(decode::<4>(b"#10\n"), Ok((0, [0; 4])));
+    }
+
+    #[test]
+    fn definite_overflow_skips_the_block() {
+        let mut decoder = Decoder::new(&b"#15a\nb\nc;1\n2\n"[..]);
+        let mut target = [0; 4];
+        decoder.begin_response_data().unwrap();
+        assert_matches!(
+            decoder.decode_arbitrary_block_array(&mut target),
+            Err(DecodeError::BufferOverflow)
+        );
+        decoder.resynchronize().unwrap();
+        assert_eq!(decoder.finish().unwrap(), b"2\n");
+    }
+
+    #[test]
+    fn indefinite_overflow() {
+        let mut decoder = Decoder::new(&b"#0abcde\n2\n"[..]);
+        let mut target = [0; 4];
+        decoder.begin_response_data().unwrap();
+        assert_matches!(
+            decoder.decode_arbitrary_block_array(&mut target),
+            Err(DecodeError::BufferOverflow)
+        );
+        decoder.resynchronize().unwrap();
+        assert_eq!(decoder.finish().unwrap(), b"2\n");
+    }
+
+    fn decode<const N: usize>(bytes: &'static [u8]) -> Result<(usize, [u8; N]), DecodeError> {
+        let mut decoder = Decoder::new(bytes);
+        let mut target = [0; N];
+        decoder.begin_response_data()?;
+        let len = decoder.decode_arbitrary_block_array(&mut target)?;
+        assert!(decoder.is_at_end());
+        Ok((len, target))
+    }
+}
+
+#[cfg(test)]
+mod incremental_tests {
+    use matches::assert_matches;
+
+    use super::BlockHeader;
+    use crate::{
+        decode::{DecodeError, DecodeState, Decoder},
+        ResponseData,
+    };
+
+    #[test]
+    fn definite_payload_in_parts() {
+        let mut decoder = Decoder::new(&b"#2100123456789,42\n"[..]);
+        decoder.begin_response_data().unwrap();
+        assert_eq!(
+            decoder.begin_arbitrary_block(),
+            Ok(BlockHeader::Definite(10))
+        );
+        let mut buf = [0; 4];
+        assert_eq!(decoder.read_block_payload(&mut buf), Ok(4));
+        assert_eq!(&buf, b"0123");
+        assert_eq!(decoder.read_block_payload(&mut []), Ok(0));
+        assert_eq!(decoder.read_block_payload(&mut buf), Ok(4));
+        assert_eq!(&buf, b"4567");
+        assert_eq!(decoder.read_block_payload(&mut buf), Ok(2));
+        assert_eq!(&buf[..2], b"89");
+        assert_eq!(decoder.read_block_payload(&mut buf), Ok(0));
+        decoder.finish_block().unwrap();
+        assert_eq!(decoder.state(), DecodeState::DataExpected);
+        assert_matches!(u8::decode(&mut decoder), Ok(42));
+        assert!(decoder.finish().is_ok());
+    }
+
+    #[test]
+    fn indefinite_payload_in_parts() {
+        let mut decoder = Decoder::new(&b"#0a,b;c\n"[..]);
+        decoder.begin_response_data().unwrap();
+        assert_eq!(decoder.begin_arbitrary_block(), Ok(BlockHeader::Indefinite));
+        let mut buf = [0; 3];
+        assert_eq!(decoder.read_block_payload(&mut buf), Ok(3));
+        assert_eq!(&buf, b"a,b");
+        assert_eq!(decoder.read_block_payload(&mut buf), Ok(2));
+        assert_eq!(&buf[..2], b";c");
+        assert_eq!(decoder.read_block_payload(&mut buf), Ok(0));
+        decoder.finish_block().unwrap();
+        assert!(decoder.finish().is_ok());
+    }
+
+    #[test]
+    fn payload_must_be_read_before_finishing() {
+        for response in [&b"#13a,b\n"[..], b"#0ab\n"] {
+            let mut decoder = Decoder::new(response);
+            decoder.begin_response_data().unwrap();
+            decoder.begin_arbitrary_block().unwrap();
+            let mut buf = [0; 1];
+            assert_eq!(decoder.read_block_payload(&mut buf), Ok(1));
+            assert_matches!(
+                decoder.finish_block(),
+                Err(DecodeError::InvalidDecodeState(DecodeState::Data))
+            );
+        }
+    }
+
+    #[test]
+    fn payload_requires_a_block() {
+        let mut decoder = Decoder::new(&b"#13abc\n"[..]);
+        decoder.begin_response_data().unwrap();
+        assert_matches!(
+            decoder.read_block_payload(&mut [0; 4]),
+            Err(DecodeError::InvalidDecodeState(DecodeState::Data))
+        );
+        assert_matches!(
+            decoder.finish_block(),
+            Err(DecodeError::InvalidDecodeState(DecodeState::Data))
+        );
+    }
+}
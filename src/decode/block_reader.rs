@@ -0,0 +1,206 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::io;
+
+use super::{BlockHeader, DecodeState, Decoder};
+use crate::{ByteSource, ProtocolObserver};
+
+/// Reader for the payload of arbitrary block response data
+///
+/// Returned by [`Decoder::block_reader`]. The payload is read with
+/// [`Decoder::read_block_payload`], and once it has been read completely (`read` returns 0), the
+/// data terminator following the block has also been consumed with [`Decoder::finish_block`] and
+/// the decoder can be used normally.
+///
+/// If the reader is dropped before that, the rest of the payload is *not* consumed, and the
+/// decoder is left in the [`DecodeState::IncompleteBlock`] state, so any further attempt to
+/// begin response data or finish the decoder fails with an invalid decode state error. The rest of
+/// the block and its response message can be discarded with
+/// [`resynchronize`](Decoder::resynchronize).
+pub struct BlockReader<'a, S: ByteSource, O = ()> {
+    decoder: &'a mut Decoder<S, O>,
+    header: BlockHeader,
+}
+
+impl<'a, S: ByteSource, O: ProtocolObserver> BlockReader<'a, S, O> {
+    /// Returns the number of payload bytes left for definite length blocks, or `None` for
+    /// indefinite length blocks.
+    pub fn remaining(&self) -> Option<u64> {
+        match (self.header, self.decoder.block) {
+            (BlockHeader::Indefinite, _) => None,
+            (_, Some(BlockHeader::Definite(remaining))) => Some(remaining),
+            _ => Some(0),
+        }
+    }
+}
+
+impl<'a, S, O> io::Read for BlockReader<'a, S, O>
+where
+    S: ByteSource,
+    O: ProtocolObserver,
+    S::Error: Into<io::Error>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.decoder.block.is_none() {
+            return Ok(0);
+        }
+        let len = self.decoder.read_block_payload(buf).map_err(Into::into)?;
+        if len == 0 {
+            self.decoder.finish_block().map_err(Into::into)?;
+        }
+        Ok(len)
+    }
+}
+
+impl<'a, S: ByteSource, O> Drop for BlockReader<'a, S, O> {
+    fn drop(&mut self) {
+        if self.decoder.block.is_some() {
+            self.decoder.state = DecodeState::IncompleteBlock;
+        }
+    }
+}
+
+/// Returns a reader for arbitrary block response data.
+///
+/// The block header is decoded immediately, and the payload can then be consumed through the
+/// reader without buffering it. See [`BlockReader`] for details.
+///
+/// References:
+///
+/// - IEEE 488.2: 8.7.9 - \<DEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA\>
+/// - IEEE 488.2: 8.7.10 - \<INDEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA\>
+impl<S: ByteSource, O: ProtocolObserver> Decoder<S, O> {
+    pub fn block_reader(&mut self) -> Result<BlockReader<'_, S, O>, S::Error> {
+        let header = self.begin_arbitrary_block()?;
+        Ok(BlockReader {
+            decoder: self,
+            header,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use matches::assert_matches;
+    use std::io::{self, Read};
+
+    use crate::{
+        decode::{DecodeError, DecodeState, Decoder},
+        ByteSource, ResponseData,
+    };
+
+    /// A source of a block with the largest declared length, generated on the fly
+    struct LargeBlock {
+        pos: u64,
+    }
+
+    impl ByteSource for LargeBlock {
+        type Error = DecodeError;
+
+        fn read_byte(&mut self) -> Result<u8, Self::Error> {
+            const HEADER: &[u8] = b"#9999999999";
+            let byte = match HEADER.get(self.pos as usize) {
+                Some(&byte) => byte,
+                None => (self.pos % 251) as u8,
+            };
+            self.pos += 1;
+            Ok(byte)
+        }
+    }
+
+    #[test]
+    fn definite_block_can_be_copied() {
+        let mut decoder = Decoder::new(&b"#2170123456789\nabcdef,42\n"[..]);
+        decoder.begin_response_data().unwrap();
+        let mut data = Vec::new();
+        let mut reader = decoder.block_reader().unwrap();
+        assert_eq!(reader.remaining(), Some(17));
+        io::copy(&mut reader, &mut data).unwrap();
+        assert_eq!(reader.remaining(), Some(0));
+        drop(reader);
+        assert_eq!(data, b"0123456789\nabcdef");
+        assert_matches!(u8::decode(&mut decoder), Ok(42));
+        assert!(decoder.finish().is_ok());
+    }
+
+    #[test]
+    fn indefinite_block_can_be_copied() {
+        let mut decoder = Decoder::new(&b"#0abcdef\n"[..]);
+        decoder.begin_response_data().unwrap();
+        let mut data = Vec::new();
+        io::copy(&mut decoder.block_reader().unwrap(), &mut data).unwrap();
+        assert_eq!(data, b"abcdef");
+        assert!(decoder.finish().is_ok());
+    }
+
+    #[test]
+    fn invalid_terminator_is_an_error() {
+        let mut decoder = Decoder::new(&b"#13abcd\n"[..]);
+        decoder.begin_response_data().unwrap();
+        let mut data = Vec::new();
+        let err = io::copy(&mut decoder.block_reader().unwrap(), &mut data).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn dropping_reader_early_poisons_decoder() {
+        let mut decoder = Decoder::new(&b"#14abcd\n"[..]);
+        decoder.begin_response_data().unwrap();
+        let mut reader = decoder.block_reader().unwrap();
+        let mut buf = [0; 2];
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        drop(reader);
+        assert_matches!(
+            decoder.begin_response_data(),
+            Err(DecodeError::InvalidDecodeState(
+                DecodeState::IncompleteBlock
+            ))
+        );
+        assert_matches!(
+            decoder.finish(),
+            Err(DecodeError::InvalidDecodeState(
+                DecodeState::IncompleteBlock
+            ))
+        );
+    }
+
+    #[test]
+    fn resynchronize_skips_rest_of_dropped_block() {
+        let mut decoder = Decoder::new(&b"#16a\nb\nc\n\n42\n"[..]);
+        decoder.begin_response_data().unwrap();
+        let mut reader = decoder.block_reader().unwrap();
+        let mut buf = [0; 2];
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf, b"a\n");
+        drop(reader);
+        decoder.resynchronize().unwrap();
+        decoder.reset_for_next_response().unwrap();
+        assert_matches!(u8::decode(&mut decoder), Ok(42));
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn large_block_is_streamed_but_not_collected() {
+        let mut decoder = Decoder::new(LargeBlock { pos: 0 });
+        decoder.set_max_block_len(Some(1 << 20));
+        decoder.begin_response_data().unwrap();
+        assert_matches!(
+            decoder.decode_arbitrary_block(&mut Vec::new()),
+            Err(DecodeError::BlockTooLarge {
+                declared: 999_999_999
+            })
+        );
+
+        let mut decoder = Decoder::new(LargeBlock { pos: 0 });
+        decoder.set_max_block_len(Some(1 << 20));
+        decoder.begin_response_data().unwrap();
+        let mut reader = decoder.block_reader().unwrap();
+        assert_eq!(reader.remaining(), Some(999_999_999));
+        let len = io::copy(&mut reader.by_ref().take(2 << 20), &mut io::sink()).unwrap();
+        assert_eq!(len, 2 << 20);
+        assert_eq!(reader.remaining(), Some(999_999_999 - (2 << 20)));
+    }
+}
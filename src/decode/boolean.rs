@@ -3,7 +3,7 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use super::Decoder;
-use crate::{decode::DecodeError, ByteSource};
+use crate::{decode::DecodeError, ByteSource, ProtocolObserver};
 
 /// Decodes boolean response data.
 ///
@@ -11,8 +11,14 @@ use crate::{decode::DecodeError, ByteSource};
 /// responses tend to use NR1 numerical literals 0 and 1, which match the SCPI boolean format spec.
 ///
 /// Reference: SCPI 1999.0: 7.3 - Boolean Program Data
-impl<S: ByteSource> Decoder<S> {
+///
+/// Resumable with the `alloc` feature: if reading from the byte source fails, calling this method
+/// again continues where it left off.
+impl<S: ByteSource, O: ProtocolObserver> Decoder<S, O> {
     pub fn decode_boolean(&mut self) -> Result<bool, S::Error> {
+        self.resumable(Decoder::boolean)
+    }
+    fn boolean(&mut self) -> Result<bool, S::Error> {
         match self.read_byte()? {
             b'0' => {
                 let byte = self.read_byte()?;
@@ -24,7 +30,7 @@ impl<S: ByteSource> Decoder<S> {
                 self.end_with(byte)?;
                 Ok(true)
             }
-            _ => Err(DecodeError::Parse.into()),
+            _ => Err(self.error(DecodeError::Parse)),
         }
     }
 }
@@ -0,0 +1,281 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(feature = "alloc")]
+use alloc::{borrow::Cow, string::String};
+#[cfg(feature = "alloc")]
+use core::str;
+
+use super::Decoder;
+use crate::decode::DecodeError;
+
+/// Zero-copy decoding when the whole response is already in memory.
+///
+/// These methods return subslices of the input instead of copying response data into a target
+/// buffer. Like their generic counterparts, they must be preceded by
+/// [`begin_response_data`](Decoder::begin_response_data).
+///
+/// Bytes that the decoder has peeked, e.g. in
+/// [`skip_empty_response_data`](Decoder::skip_empty_response_data), have already been removed
+/// from the input, so they can't be borrowed. A string that has been peeked into is copied
+/// instead, and a block whose payload has been peeked into is rejected with
+/// [`DecodeError::InvalidDecodeState`].
+///
+/// ```
+/// use red_sculpin::decode::Decoder;
+///
+/// let mut decoder = Decoder::new(&b"#15\x00\x01\x02\x03\x04\n"[..]);
+/// decoder.begin_response_data().unwrap();
+/// assert_eq!(decoder.decode_block_borrowed(), Ok(&[0, 1, 2, 3, 4][..]));
+/// ```
+impl<'a> Decoder<&'a [u8]> {
+    /// Decodes string response data, borrowing it from the input.
+    ///
+    /// The string is copied only if it contains doubled quotes that need to be unescaped.
+    ///
+    /// Reference: IEEE 488.2: 8.7.8 - \<STRING RESPONSE DATA\>
+    #[cfg(feature = "alloc")]
+    pub fn decode_string_borrowed(&mut self) -> Result<Cow<'a, str>, DecodeError> {
+        self.quote()?;
+        if !self.lookahead.is_empty() {
+            let mut text = String::new();
+            self.decode_string_contents(&mut text)?;
+            return Ok(Cow::Owned(text));
+        }
+        let data = self.source;
+        let mut escaped = false;
+        let mut pos = 0;
+        let len = loop {
+            match data.get(pos..) {
+                Some([b'"', b'"', ..]) => {
+                    escaped = true;
+                    pos += 2;
+                }
+                Some([b'"', ..]) => break pos,
+                Some([byte, ..]) if byte.is_ascii() => pos += 1,
+                Some([_, ..]) => return Err(self.error(DecodeError::Parse)),
+                _ => return Err(self.error(DecodeError::UnexpectedEnd)),
+            }
+        };
+        let raw = self.take(len + 1)?;
+        self.in_string = false;
+        // Only ASCII bytes have been accepted, so this can't fail
+        let text = str::from_utf8(&raw[..len]).map_err(|_| self.error(DecodeError::Parse))?;
+        let byte = self.read_byte()?;
+        self.end_with(byte)?;
+        Ok(if escaped {
+            Cow::Owned(text.replace("\"\"", "\""))
+        } else {
+            Cow::Borrowed(text)
+        })
+    }
+    /// Decodes arbitrary block response data, borrowing it from the input.
+    ///
+    /// References:
+    ///
+    /// - IEEE 488.2: 8.7.9 - \<DEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA\>
+    /// - IEEE 488.2: 8.7.10 - \<INDEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA\>
+    pub fn decode_block_borrowed(&mut self) -> Result<&'a [u8], DecodeError> {
+        let header = self.decode_arbitrary_block_header()?;
+        self.check_no_lookahead()?;
+        let len = match header {
+            Some(block_size) => self.check_block_len(block_size)?,
+            None => self
+                .source
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .ok_or_else(|| self.error(DecodeError::UnexpectedEnd))?,
+        };
+        let data = self.take(len)?;
+        let byte = self.read_byte()?;
+        self.end_with(byte)?;
+        Ok(data)
+    }
+    /// Checks that no bytes have been peeked, so the rest of the input is still in the slice.
+    fn check_no_lookahead(&mut self) -> Result<(), DecodeError> {
+        if self.lookahead.is_empty() {
+            Ok(())
+        } else {
+            Err(self.error(DecodeError::InvalidDecodeState(self.state)))
+        }
+    }
+    /// Consumes `len` bytes directly from the input.
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        self.check_no_lookahead()?;
+        if len > self.source.len() {
+            return Err(self.error(DecodeError::UnexpectedEnd));
+        }
+        let (data, rest) = self.source.split_at(len);
+        self.source = rest;
+        self.offset += len;
+        #[cfg(feature = "alloc")]
+        if let Some(ref mut capture) = self.capture {
+            capture.extend_from_slice(data);
+        }
+        if let Some(&last) = data.last() {
+            self.last = last;
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use alloc::{borrow::Cow, string::String, vec::Vec};
+    use matches::assert_matches;
+
+    use crate::{
+        decode::{DecodeError, DecodeState, Decoder},
+        EmptyAsNone, ResponseData,
+    };
+
+    #[test]
+    fn borrowed_string_matches_owned() {
+        let data = b"\"Hello, world\",\"\"\n";
+        let mut decoder = Decoder::new(&data[..]);
+        let mut owned = Decoder::new(&data[..]);
+        for _ in 0..2 {
+            decoder.begin_response_data().unwrap();
+            let borrowed = decoder.decode_string_borrowed().unwrap();
+            assert_matches!(borrowed, Cow::Borrowed(_));
+
+            let mut text = String::new();
+            owned.begin_response_data().unwrap();
+            owned.decode_string(&mut text).unwrap();
+            assert_eq!(borrowed, text);
+            assert_eq!(decoder.state(), owned.state());
+        }
+        assert!(decoder.is_at_end());
+    }
+
+    #[test]
+    fn escaped_quotes_are_copied() {
+        let data = b"\"quote:\"\"\"\n";
+        let mut decoder = Decoder::new(&data[..]);
+        decoder.begin_response_data().unwrap();
+        let borrowed = decoder.decode_string_borrowed().unwrap();
+        assert_matches!(borrowed, Cow::Owned(_));
+
+        let mut text = String::new();
+        let mut owned = Decoder::new(&data[..]);
+        owned.begin_response_data().unwrap();
+        owned.decode_string(&mut text).unwrap();
+        assert_eq!(borrowed, text);
+        assert_eq!(borrowed, "quote:\"");
+        assert_eq!(decoder.finish().unwrap(), b"");
+    }
+
+    #[test]
+    fn invalid_strings() {
+        let decode = |data: &'static [u8]| {
+            let mut decoder = Decoder::new(data);
+            decoder.begin_response_data()?;
+            decoder.decode_string_borrowed()
+        };
+        assert_matches!(decode(b"notquoted\n"), Err(DecodeError::Parse));
+        assert_matches!(decode(b"\"Invalid\n"), Err(DecodeError::UnexpectedEnd));
+        assert_matches!(decode(b"\"\xff\"\n"), Err(DecodeError::Parse));
+        assert_matches!(
+            decode(b"\"text\"x\n"),
+            Err(DecodeError::InvalidDataTerminator { byte: b'x' })
+        );
+    }
+
+    #[test]
+    fn borrowed_blocks_match_owned() {
+        let data = b"#15\x00\x01\x02\x03\x04,#10;#0\x00\"\n";
+        let mut decoder = Decoder::new(&data[..]);
+        let mut owned = Decoder::new(&data[..]);
+        for _ in 0..3 {
+            decoder.begin_response_data().unwrap();
+            let borrowed = decoder.decode_block_borrowed().unwrap();
+
+            let mut block = Vec::new();
+            owned.begin_response_data().unwrap();
+            owned.decode_arbitrary_block(&mut block).unwrap();
+            assert_eq!(borrowed, block);
+            assert_eq!(decoder.state(), owned.state());
+        }
+        assert_eq!(decoder.finish().unwrap(), b"");
+    }
+
+    #[test]
+    fn truncated_block() {
+        let mut decoder = Decoder::new(&b"#15abc"[..]);
+        decoder.begin_response_data().unwrap();
+        assert_matches!(
+            decoder.decode_block_borrowed(),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn peeked_string_is_copied() {
+        let mut decoder = Decoder::new(&b"\"ab\",#14wxyz\n"[..]);
+        assert_eq!(decoder.skip_empty_response_data(), Ok(false));
+        decoder.begin_response_data().unwrap();
+        let text = decoder.decode_string_borrowed().unwrap();
+        assert_matches!(text, Cow::Owned(_));
+        assert_eq!(text, "ab");
+        assert_eq!(decoder.skip_empty_response_data(), Ok(false));
+        decoder.begin_response_data().unwrap();
+        assert_eq!(decoder.decode_block_borrowed(), Ok(&b"wxyz"[..]));
+        assert_eq!(decoder.finish().unwrap(), b"");
+    }
+
+    #[test]
+    fn empty_as_none_before_borrowed_data() {
+        let mut decoder = Decoder::new(&b"\"\",\"a\"\"b\",#0xy\n"[..]);
+        assert_eq!(
+            EmptyAsNone::<String>::decode(&mut decoder),
+            Ok(EmptyAsNone(None))
+        );
+        assert_eq!(decoder.skip_empty_response_data(), Ok(false));
+        decoder.begin_response_data().unwrap();
+        assert_eq!(decoder.decode_string_borrowed().unwrap(), "a\"b");
+        assert_eq!(decoder.skip_empty_response_data(), Ok(false));
+        decoder.begin_response_data().unwrap();
+        assert_eq!(decoder.decode_block_borrowed(), Ok(&b"xy"[..]));
+        assert!(decoder.is_at_end());
+    }
+
+    #[test]
+    fn echoed_header_before_borrowed_data() {
+        let mut decoder = Decoder::new(&b":DATA \"ab\";:BLOCK #12cd;DATA \"ef\"\n"[..]);
+        decoder.set_echoed_headers(true);
+        decoder.begin_response_data().unwrap();
+        let text = decoder.decode_string_borrowed().unwrap();
+        assert_matches!(text, Cow::Borrowed("ab"));
+        decoder.begin_response_data().unwrap();
+        assert_eq!(decoder.decode_block_borrowed(), Ok(&b"cd"[..]));
+        decoder.begin_response_data().unwrap();
+        assert_eq!(decoder.decode_string_borrowed().unwrap(), "ef");
+        assert!(decoder.is_at_end());
+    }
+
+    #[test]
+    fn peeked_block_payload_is_rejected() {
+        let mut decoder = Decoder::new(&b"#12ab\n"[..]);
+        decoder.begin_response_data().unwrap();
+        assert_eq!(decoder.peek_byte_at(3), Ok(Some(b'a')));
+        assert_matches!(
+            decoder.decode_block_borrowed(),
+            Err(DecodeError::InvalidDecodeState(DecodeState::Data))
+        );
+    }
+
+    #[test]
+    fn borrowed_bytes_are_captured() {
+        let mut decoder = Decoder::new(&b"\"ab\",#12cd\n"[..]);
+        let (_, captured) = decoder
+            .capture(|decoder| {
+                decoder.begin_response_data()?;
+                decoder.decode_string_borrowed()?;
+                decoder.begin_response_data()?;
+                decoder.decode_block_borrowed()
+            })
+            .unwrap();
+        assert_eq!(captured, b"\"ab\",#12cd\n");
+    }
+}
@@ -5,28 +5,85 @@
 use core::fmt;
 
 use super::Decoder;
-use crate::{decode::DecodeError, ByteSource};
+use crate::{decode::DecodeError, ByteSource, ProtocolObserver};
 
 /// Decodes character response data
 ///
 /// Reference: IEEE 488.2: 8.7.1 - \<CHARACTER RESPONSE DATA\>
-impl<S: ByteSource> Decoder<S> {
+///
+/// Characters are written to the target as they are decoded, so the length is only limited by the
+/// target.
+///
+/// Resumable with the `alloc` feature: if reading from the byte source fails, calling this method
+/// again continues where it left off, and characters that were already written to the target are
+/// not written again.
+impl<S: ByteSource, O: ProtocolObserver> Decoder<S, O> {
     pub fn decode_characters<T: fmt::Write>(&mut self, target: &mut T) -> Result<(), S::Error> {
-        target
-            .write_char(self.upper()? as char)
-            .map_err(|_| DecodeError::BufferOverflow)?;
+        #[cfg(feature = "alloc")]
+        let skip = if self.journal.is_empty() {
+            0
+        } else {
+            self.resumed_chars
+        };
+        #[cfg(not(feature = "alloc"))]
+        let skip = 0;
+        let mut target = Resumed {
+            target,
+            skip,
+            written: 0,
+        };
+        let result = self.resumable(|decoder| decoder.characters(&mut target));
+        #[cfg(feature = "alloc")]
+        {
+            self.resumed_chars = if self.journal.is_empty() {
+                0
+            } else {
+                target.written
+            };
+        }
+        result
+    }
+    fn characters<T: fmt::Write>(&mut self, target: &mut T) -> Result<(), S::Error> {
+        let byte = self.upper()?;
+        self.write_char(target, byte)?;
         loop {
             match self.read_byte()? {
-                byte @ b'A'..=b'Z' | byte @ b'0'..=b'9' | byte @ b'_' => target
-                    .write_char(byte as char)
-                    .map_err(|_| DecodeError::BufferOverflow)?,
+                byte @ b'A'..=b'Z' | byte @ b'0'..=b'9' | byte @ b'_' => {
+                    self.write_char(target, byte)?
+                }
                 byte => break self.end_with(byte),
             }
         }
     }
+    fn write_char<T: fmt::Write>(&mut self, target: &mut T, byte: u8) -> Result<(), S::Error> {
+        target
+            .write_char(byte as char)
+            .map_err(|_| self.error(DecodeError::BufferOverflow))
+    }
+}
+
+/// Skips the characters that were written before decoding was interrupted, when they are decoded
+/// again from the replayed bytes.
+struct Resumed<'a, T> {
+    target: &'a mut T,
+    skip: usize,
+    written: usize,
 }
 
-#[cfg(test)]
+impl<'a, T: fmt::Write> fmt::Write for Resumed<'a, T> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.chars().try_for_each(|c| self.write_char(c))
+    }
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        if self.written >= self.skip {
+            self.target.write_char(c)?;
+        }
+        self.written += 1;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
     use alloc::string::String;
     use matches::assert_matches;
@@ -60,7 +117,14 @@ mod tests {
         assert_matches!(decode(b"\n"), Err(DecodeError::Parse));
     }
 
-    fn decode(bytes: &'static [u8]) -> Result<String, DecodeError> {
+    #[test]
+    fn long_data_is_not_limited() {
+        let mut data = [b'A'; 101];
+        data[100] = b'\n';
+        assert_eq!(decode(&data).unwrap().len(), 100);
+    }
+
+    fn decode(bytes: &[u8]) -> Result<String, DecodeError> {
         let mut decoder = Decoder::new(bytes);
         decoder.begin_response_data()?;
         let mut buffer = String::new();
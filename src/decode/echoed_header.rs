@@ -0,0 +1,285 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::str;
+
+use super::{Decoder, LOOKAHEAD_LEN};
+use crate::{
+    decode::{DecodeError, DecodeState},
+    utils::mnemonic_matches,
+    ByteSource, ProtocolObserver,
+};
+
+/// Echoed response headers
+///
+/// Some instruments echo the query header in front of the response data, e.g. `:SYST:VERS 1999.0`
+/// instead of `1999.0`, usually after a `:SYSTem:HEADer ON` command. With echoed headers enabled,
+/// a header followed by a space at the start of a response message unit is skipped before the
+/// response data is decoded, and responses without a header are decoded as usual.
+///
+/// [`Query::decode`](crate::Query::decode) validates echoed headers against the mnemonic of the
+/// query, and rejects a mismatch with [`DecodeError::HeaderMismatch`]. Nodes are compared with
+/// [`mnemonic_matches`], so a mixed case query like `:SYSTem:VERSion?` matches only its short and
+/// long forms. A query in short form like `:SYST:VERS?` also matches the long forms it is the short
+/// form of, like `:SYSTEM:VERSION`. A missing numeric suffix matches `1`.
+///
+/// A header starting with `:` or `*` can't be mistaken for response data, but other headers look
+/// like arbitrary ASCII response data, e.g. `ACME Instruments,PSU1,0,1.0`. When the query is known,
+/// such headers are only skipped if they match it, and otherwise they are kept as response data.
+/// Headers without a leading `:` or `*` must also fit in 64 bytes.
+///
+/// ```
+/// use red_sculpin::{decode::Decoder, scpi::message::SystemVersionQuery, Query};
+///
+/// let mut decoder = Decoder::new(&b":SYSTEM:VERSION 1999.0\n"[..]);
+/// decoder.set_echoed_headers(true);
+/// assert_eq!(SystemVersionQuery.decode(&mut decoder), Ok(1999.0));
+/// ```
+///
+/// Reference: IEEE 488.2: 8.7.1 - \<RESPONSE HEADER\>
+impl<S: ByteSource, O: ProtocolObserver> Decoder<S, O> {
+    /// Enables or disables skipping of echoed response headers.
+    pub fn set_echoed_headers(&mut self, enabled: bool) {
+        self.echoed_headers = enabled;
+    }
+    pub fn echoed_headers(&self) -> bool {
+        self.echoed_headers
+    }
+    /// Skips an echoed header at the start of a response message unit, if there is one, and
+    /// validates it against the given query mnemonic.
+    ///
+    /// Does nothing if echoed headers are disabled, or if the header of the current response
+    /// message unit has already been checked.
+    pub fn skip_echoed_header(&mut self, mnemonic: &str) -> Result<(), S::Error> {
+        match self.state {
+            DecodeState::Initial | DecodeState::MessageUnitExpected
+                if self.echoed_headers && !self.header_checked =>
+            {
+                self.skip_whitespace()?;
+                self.skip_header(Some(mnemonic))
+            }
+            _ => Ok(()),
+        }
+    }
+    pub(super) fn skip_header(&mut self, mnemonic: Option<&str>) -> Result<(), S::Error> {
+        self.header_checked = true;
+        let mut len = 0;
+        while let Some(byte) = self.peek_byte_at(len)? {
+            if !is_header_byte(byte) {
+                break;
+            }
+            len += 1;
+        }
+        // Reference: IEEE 488.2: 8.7.1.2 - Encoding syntax
+        let is_header = len > 0
+            && self.peek_byte_at(len)? == Some(b' ')
+            && self
+                .lookahead
+                .get(0)
+                .is_some_and(|byte| byte.is_ascii_alphabetic() || matches!(byte, b':' | b'*'));
+        if !is_header {
+            return Ok(());
+        }
+        let mut header = [0; LOOKAHEAD_LEN];
+        for (idx, byte) in header[..len].iter_mut().enumerate() {
+            *byte = self.lookahead.get(idx).unwrap_or_default();
+        }
+        let header = &header[..len];
+        if let Some(mnemonic) = mnemonic {
+            if !header_matches(mnemonic.as_bytes(), header) {
+                return match header[0] {
+                    b':' | b'*' => Err(self.error(DecodeError::HeaderMismatch)),
+                    // Arbitrary ASCII response data, not a header
+                    _ => Ok(()),
+                };
+            }
+        }
+        for _ in 0..len {
+            self.read_byte()?;
+        }
+        self.skip_whitespace()
+    }
+}
+
+fn is_header_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b':' | b'*' | b'?')
+}
+
+/// Compares a query header like `:SYST:VERS?` with an echoed header like `:SYSTEM:VERSION`.
+fn header_matches(query: &[u8], echoed: &[u8]) -> bool {
+    let mut query = nodes(query);
+    let mut echoed = nodes(echoed);
+    loop {
+        match (query.next(), echoed.next()) {
+            (Some(query), Some(echoed)) if node_matches(query, echoed) => (),
+            (None, None) => break true,
+            _ => break false,
+        }
+    }
+}
+
+fn nodes(header: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let header = header.strip_suffix(b"?").unwrap_or(header);
+    let header = header.strip_prefix(b":").unwrap_or(header);
+    header.split(|&byte| byte == b':')
+}
+
+/// Compares a query node like `VERS` or `VERSion` with an echoed node like `VERSION`.
+fn node_matches(query: &[u8], echoed: &[u8]) -> bool {
+    let (query, query_suffix) = split_suffix(query);
+    let (echoed, echoed_suffix) = split_suffix(echoed);
+    let (Ok(query), Ok(echoed)) = (str::from_utf8(query), str::from_utf8(echoed)) else {
+        return false;
+    };
+    query_suffix.is_some()
+        && query_suffix == echoed_suffix
+        && (mnemonic_matches(query, echoed) || is_short_form_of(query, echoed))
+}
+
+/// Returns true if the query node is in short form only, and it's the short form of the echoed
+/// long form: its first four characters, or three if the fourth one is a vowel.
+///
+/// Reference: SCPI 1999.0: 6.2.1 - Keywords
+fn is_short_form_of(query: &str, echoed: &str) -> bool {
+    let len = match echoed.as_bytes() {
+        long if long.len() <= 4 => long.len(),
+        [_, _, _, fourth, ..] if b"AEIOU".contains(&fourth.to_ascii_uppercase()) => 3,
+        _ => 4,
+    };
+    !query.bytes().any(|byte| byte.is_ascii_lowercase())
+        && query.len() == len
+        && echoed[..len].eq_ignore_ascii_case(query)
+}
+
+/// Splits a node into its mnemonic and numeric suffix, which defaults to 1.
+fn split_suffix(node: &[u8]) -> (&[u8], Option<u32>) {
+    let digits = node
+        .iter()
+        .rev()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    let (mnemonic, suffix) = node.split_at(node.len() - digits);
+    let suffix = match suffix {
+        [] => Some(1),
+        _ => str::from_utf8(suffix)
+            .ok()
+            .and_then(|text| text.parse().ok()),
+    };
+    (mnemonic, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::header_matches;
+    use crate::{
+        decode::{DecodeError, Decoder},
+        ieee::message::OperationCompleteQuery,
+        scpi::message::SystemVersionQuery,
+        Query, ResponseData,
+    };
+
+    fn version(response: &[u8]) -> Result<f32, DecodeError> {
+        let mut decoder = Decoder::new(response);
+        decoder.set_echoed_headers(true);
+        let version = SystemVersionQuery.decode(&mut decoder)?;
+        decoder.finish()?;
+        Ok(version)
+    }
+
+    #[test]
+    fn headered_and_headerless_responses() {
+        assert_eq!(version(b"1999.0\n"), Ok(1999.0));
+        assert_eq!(version(b":SYST:VERS 1999.0\n"), Ok(1999.0));
+        assert_eq!(version(b":SYSTEM:VERSION 1999.0\n"), Ok(1999.0));
+        assert_eq!(version(b"syst:vers  1999.0\n"), Ok(1999.0));
+        assert_eq!(version(b":SYST:VERS? 1999.0\n"), Ok(1999.0));
+    }
+
+    #[test]
+    fn mismatched_header() {
+        assert_matches!(
+            version(b":SYST:ERR 1999.0\n"),
+            Err(DecodeError::HeaderMismatch)
+        );
+        assert_matches!(version(b":SYST 1999.0\n"), Err(DecodeError::HeaderMismatch));
+        assert_matches!(version(b"*OPC 1999.0\n"), Err(DecodeError::HeaderMismatch));
+        // Without a leading `:`, a mismatched header is response data
+        assert_matches!(version(b"SYST:ERR 1999.0\n"), Err(DecodeError::Parse));
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let mut decoder = Decoder::new(&b":SYST:VERS 1999.0\n"[..]);
+        assert!(!decoder.echoed_headers());
+        assert_matches!(
+            SystemVersionQuery.decode(&mut decoder),
+            Err(DecodeError::Parse)
+        );
+    }
+
+    #[test]
+    fn compound_response() {
+        let mut decoder = Decoder::new(&b":SYST:VERS 1999.0;*OPC 1\n"[..]);
+        decoder.set_echoed_headers(true);
+        assert_eq!(SystemVersionQuery.decode(&mut decoder), Ok(1999.0));
+        assert_eq!(OperationCompleteQuery.decode(&mut decoder), Ok(true));
+        assert!(decoder.is_at_end());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arbitrary_ascii_response() {
+        use crate::ieee::message::IdentificationQuery;
+
+        for response in [
+            &b"*IDN ACME Instruments,PSU1,0,1.0\n"[..],
+            b"ACME Instruments,PSU1,0,1.0\n",
+        ] {
+            let mut decoder = Decoder::new(response);
+            decoder.set_echoed_headers(true);
+            let identification = IdentificationQuery.decode(&mut decoder).unwrap();
+            assert_eq!(identification.manufacturer, "ACME Instruments");
+            assert_eq!(identification.model, "PSU1");
+        }
+    }
+
+    #[test]
+    fn responses_without_query() {
+        let mut decoder = Decoder::new(&b"VOLT 1.5,2.0;CURR 0.5\n"[..]);
+        decoder.set_echoed_headers(true);
+        assert_eq!(f32::decode(&mut decoder), Ok(1.5));
+        assert_eq!(f32::decode(&mut decoder), Ok(2.0));
+        assert_eq!(f32::decode(&mut decoder), Ok(0.5));
+
+        let mut decoder = Decoder::new(&b"1;10 \n"[..]);
+        decoder.set_echoed_headers(true);
+        assert_eq!(bool::decode(&mut decoder), Ok(true));
+        assert_matches!(
+            u8::decode(&mut decoder),
+            Err(DecodeError::InvalidDataTerminator { byte: b' ' })
+        );
+    }
+
+    #[test]
+    fn header_matching() {
+        assert!(header_matches(b":SYST:VERS?", b":SYSTEM:VERSION"));
+        assert!(header_matches(b"SYSTem:VERSion?", b"SYST:VERS"));
+        assert!(header_matches(b":OUTP?", b":OUTPUT1"));
+        assert!(header_matches(b":CHAN2:SCAL?", b":CHANNEL2:SCALE"));
+        assert!(header_matches(b"*IDN?", b"*idn"));
+        assert!(!header_matches(b":CHAN2:SCAL?", b":CHANNEL1:SCALE"));
+        assert!(!header_matches(b":SYST:VERS?", b":SYST:VERS:NEXT"));
+        assert!(!header_matches(b"*IDN?", b"IDN"));
+        assert!(!header_matches(b":OUTP?", b":OUTP99999999999"));
+        assert!(header_matches(b":VOLTage?", b":VOLTAGE"));
+        assert!(!header_matches(b":VOLTage?", b":VOLTA"));
+        assert!(!header_matches(b":VOLT?", b":VO"));
+        assert!(header_matches(b":DISP?", b":DISPLAY"));
+        assert!(header_matches(b":MEAS?", b":MEASURE"));
+        assert!(header_matches(b":CAL?", b":CALIBRATION"));
+        assert!(!header_matches(b":CALI?", b":CALIBRATION"));
+    }
+}
@@ -0,0 +1,364 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use super::{DecodeError, DecodeState, Decoder};
+use crate::ResponseData;
+
+/// A response message boundary reached while feeding bytes
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Boundary {
+    /// Reference: IEEE 488.2: 8.4.2 - \<RESPONSE DATA SEPARATOR\>
+    Data,
+    /// Reference: IEEE 488.2: 8.4.1 - \<RESPONSE MESSAGE UNIT SEPARATOR\>
+    MessageUnit,
+    /// Reference: IEEE 488.2: 8.5 - \<RESPONSE MESSAGE TERMINATOR\>
+    End,
+}
+
+/// Result of feeding bytes to a [`FeedDecoder`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FeedResult {
+    /// Number of bytes consumed from the fed slice. This is less than the length of the slice if
+    /// a boundary was reached, or the buffer became full.
+    pub consumed: usize,
+    /// The boundary that was reached, if any. Feeding stops right after a boundary, so any bytes
+    /// after it must be fed again.
+    pub boundary: Option<Boundary>,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+enum Scan {
+    #[default]
+    Data,
+    String,
+    StringQuote,
+    BlockStart,
+    BlockDigits {
+        digits: u8,
+        len: usize,
+    },
+    BlockPayload(usize),
+    IndefiniteBlock,
+}
+
+/// A push-based response message decoder for non-blocking I/O
+///
+/// Bytes are fed to the decoder as they arrive, and the decoder reports when a response data
+/// separator, message unit separator, or message terminator has been reached. Strings and
+/// arbitrary blocks are tracked, so separator bytes inside them are not reported. Complete
+/// response data can then be decoded with the same grammar as the pull-based [`Decoder`].
+///
+/// Character and arbitrary ASCII response data that are decoded until the message terminator
+/// require feeding until [`Boundary::End`].
+///
+/// Fed bytes are kept in a caller-provided buffer until they are decoded, so the buffer must be
+/// large enough for the longest response data element, including its separator. Once the buffer
+/// is full, no more bytes are consumed until complete data is decoded, and if there is no complete
+/// data, [`is_full`](FeedDecoder::is_full) reports that the element can't fit.
+///
+/// ```
+/// use red_sculpin::decode::{Boundary, FeedDecoder};
+///
+/// let mut buf = [0; 16];
+/// let mut decoder = FeedDecoder::new(&mut buf);
+/// assert_eq!(decoder.feed(b"42,1").boundary, Some(Boundary::Data));
+/// assert_eq!(decoder.decode::<u8>(), Ok(42));
+/// ```
+#[derive(Debug)]
+pub struct FeedDecoder<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+    complete: usize,
+    scan: Scan,
+    state: DecodeState,
+}
+
+impl<'a> FeedDecoder<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> FeedDecoder<'a> {
+        FeedDecoder {
+            buffer,
+            len: 0,
+            complete: 0,
+            scan: Scan::default(),
+            state: DecodeState::default(),
+        }
+    }
+    /// Feeds bytes to the decoder, stopping after the first boundary, or when the buffer is full.
+    pub fn feed(&mut self, bytes: &[u8]) -> FeedResult {
+        let free = self.buffer.len() - self.len;
+        for (idx, &byte) in bytes.iter().take(free).enumerate() {
+            self.buffer[self.len] = byte;
+            self.len += 1;
+            if let Some(boundary) = self.scan_byte(byte) {
+                self.complete = self.len;
+                return FeedResult {
+                    consumed: idx + 1,
+                    boundary: Some(boundary),
+                };
+            }
+        }
+        FeedResult {
+            consumed: bytes.len().min(free),
+            boundary: None,
+        }
+    }
+    /// Returns true if the buffer is full and has no complete response data, so the response data
+    /// element being fed doesn't fit in it.
+    pub fn is_full(&self) -> bool {
+        self.len == self.buffer.len() && self.complete == 0
+    }
+    /// Returns the capacity of the buffer.
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+    /// Returns true if there's buffered response data followed by a boundary.
+    pub fn has_complete_data(&self) -> bool {
+        self.complete > 0
+    }
+    /// Decodes response data from the buffered bytes that precede the last reached boundary.
+    ///
+    /// If decoding fails, the buffered bytes are left untouched.
+    pub fn decode<T: ResponseData>(&mut self) -> Result<T, DecodeError> {
+        self.decode_with(|decoder| T::decode(decoder))
+    }
+    /// Decodes response data from the buffered bytes that precede the last reached boundary using
+    /// the given function.
+    ///
+    /// If decoding fails, the buffered bytes are left untouched.
+    pub fn decode_with<T, F>(&mut self, f: F) -> Result<T, DecodeError>
+    where
+        F: FnOnce(&mut Decoder<&[u8]>) -> Result<T, DecodeError>,
+    {
+        let complete = &self.buffer[..self.complete];
+        let mut decoder = Decoder::new(complete);
+        decoder.state = match self.state {
+            DecodeState::End => DecodeState::Initial,
+            state => state,
+        };
+        let value = f(&mut decoder)?;
+        let consumed = complete.len() - decoder.source.len() - decoder.lookahead.len();
+        self.state = decoder.state;
+        self.buffer.copy_within(consumed..self.len, 0);
+        self.len -= consumed;
+        self.complete -= consumed;
+        Ok(value)
+    }
+    pub fn state(&self) -> DecodeState {
+        self.state
+    }
+    pub fn is_at_end(&self) -> bool {
+        self.state == DecodeState::End
+    }
+    /// Discards all buffered bytes and resets the decoder to its initial state.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.complete = 0;
+        self.scan = Scan::default();
+        self.state = DecodeState::default();
+    }
+    fn scan_byte(&mut self, byte: u8) -> Option<Boundary> {
+        loop {
+            self.scan = match (self.scan, byte) {
+                (Scan::Data, b',') => return Some(Boundary::Data),
+                (Scan::Data, b';') => return Some(Boundary::MessageUnit),
+                (Scan::Data, b'\n') => return Some(Boundary::End),
+                (Scan::Data, b'"') => Scan::String,
+                (Scan::Data, b'#') => Scan::BlockStart,
+                (Scan::Data, _) => Scan::Data,
+                (Scan::String, b'"') => Scan::StringQuote,
+                (Scan::String, _) => Scan::String,
+                (Scan::StringQuote, b'"') => Scan::String,
+                (Scan::BlockStart, b'0') => Scan::IndefiniteBlock,
+                (Scan::BlockStart, b'1'..=b'9') => Scan::BlockDigits {
+                    digits: byte - b'0',
+                    len: 0,
+                },
+                (Scan::BlockDigits { digits, len }, b'0'..=b'9') => {
+                    let len = len
+                        .saturating_mul(10)
+                        .saturating_add((byte - b'0') as usize);
+                    match digits - 1 {
+                        0 if len == 0 => Scan::Data,
+                        0 => Scan::BlockPayload(len),
+                        digits => Scan::BlockDigits { digits, len },
+                    }
+                }
+                (Scan::BlockPayload(1), _) => Scan::Data,
+                (Scan::BlockPayload(len), _) => Scan::BlockPayload(len - 1),
+                (Scan::IndefiniteBlock, b'\n') => {
+                    self.scan = Scan::Data;
+                    return Some(Boundary::End);
+                }
+                (Scan::IndefiniteBlock, _) => Scan::IndefiniteBlock,
+                // Not a string, a block, or a well-formed block header, so the byte is scanned
+                // again as normal data, and any errors are reported when decoding
+                (Scan::StringQuote, _) | (Scan::BlockStart, _) | (Scan::BlockDigits { .. }, _) => {
+                    self.scan = Scan::Data;
+                    continue;
+                }
+            };
+            return None;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use alloc::{string::String, vec::Vec};
+    use matches::assert_matches;
+
+    use super::{Boundary, FeedDecoder, FeedResult};
+    use crate::{
+        decode::{DecodeError, DecodeState, Decoder},
+        ResponseData,
+    };
+
+    type Response = (i32, f64, String, Vec<u8>);
+
+    const MESSAGE: &[u8] = b"-42,+1.5E+3,\"a,b;\"\"c\n\",#213x,y;z\n\"\"#1234\n";
+
+    fn feed_all(decoder: &mut FeedDecoder, mut bytes: &[u8]) -> Vec<Boundary> {
+        let mut boundaries = Vec::new();
+        while !bytes.is_empty() {
+            let FeedResult { consumed, boundary } = decoder.feed(bytes);
+            boundaries.extend(boundary);
+            bytes = &bytes[consumed..];
+        }
+        boundaries
+    }
+
+    fn pull_decode() -> Response {
+        let mut decoder = Decoder::new(MESSAGE);
+        let response = Response::decode(&mut decoder).unwrap();
+        assert!(decoder.finish().unwrap().is_empty());
+        response
+    }
+
+    #[test]
+    fn one_byte_at_a_time() {
+        let mut buf = [0; 64];
+        let mut decoder = FeedDecoder::new(&mut buf);
+        let mut boundaries = Vec::new();
+        for byte in MESSAGE.chunks(1) {
+            let result = decoder.feed(byte);
+            assert_eq!(result.consumed, 1);
+            boundaries.extend(result.boundary);
+        }
+        assert_eq!(
+            boundaries,
+            [
+                Boundary::Data,
+                Boundary::Data,
+                Boundary::Data,
+                Boundary::End
+            ]
+        );
+        assert!(decoder.has_complete_data());
+        assert_eq!(decoder.decode::<Response>().unwrap(), pull_decode());
+        assert!(decoder.is_at_end());
+        assert!(!decoder.has_complete_data());
+    }
+
+    #[test]
+    fn random_sized_chunks() {
+        let mut seed = 0x1234_5678u32;
+        for _ in 0..32 {
+            let mut buf = [0; 64];
+            let mut decoder = FeedDecoder::new(&mut buf);
+            let mut boundaries = Vec::new();
+            let mut remaining = MESSAGE;
+            while !remaining.is_empty() {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let len = ((seed >> 16) as usize % 8 + 1).min(remaining.len());
+                boundaries.extend(feed_all(&mut decoder, &remaining[..len]));
+                remaining = &remaining[len..];
+            }
+            assert_eq!(
+                boundaries,
+                [
+                    Boundary::Data,
+                    Boundary::Data,
+                    Boundary::Data,
+                    Boundary::End
+                ]
+            );
+            assert_eq!(decoder.decode::<Response>().unwrap(), pull_decode());
+        }
+    }
+
+    #[test]
+    fn values_are_decoded_as_they_arrive() {
+        let mut buf = [0; 64];
+        let mut decoder = FeedDecoder::new(&mut buf);
+        let result = decoder.feed(b"12,");
+        assert_eq!(result.boundary, Some(Boundary::Data));
+        assert_eq!(decoder.decode::<u8>(), Ok(12));
+        assert_eq!(decoder.state(), DecodeState::DataExpected);
+
+        assert_eq!(decoder.feed(b"\"x").boundary, None);
+        assert_matches!(decoder.decode::<String>(), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decoder.feed(b"\";").boundary, Some(Boundary::MessageUnit));
+        assert_eq!(decoder.decode::<String>().as_deref(), Ok("x"));
+        assert_eq!(decoder.state(), DecodeState::MessageUnitExpected);
+
+        let result = decoder.feed(b"ABC\n1\n");
+        assert_eq!(result.consumed, 4);
+        assert_eq!(result.boundary, Some(Boundary::End));
+        let mut text = String::new();
+        decoder
+            .decode_with(|decoder| {
+                decoder.begin_response_data()?;
+                decoder.decode_characters(&mut text)
+            })
+            .unwrap();
+        assert_eq!(text, "ABC");
+        assert!(decoder.is_at_end());
+
+        assert_eq!(decoder.feed(b"1\n").boundary, Some(Boundary::End));
+        assert_eq!(decoder.decode::<bool>(), Ok(true));
+        assert!(decoder.is_at_end());
+    }
+
+    #[test]
+    fn block_payload_is_not_scanned_for_separators() {
+        let mut buf = [0; 64];
+        let mut decoder = FeedDecoder::new(&mut buf);
+        let result = decoder.feed(b"#14,;\n\n\n");
+        assert_eq!(result.consumed, 8);
+        assert_eq!(result.boundary, Some(Boundary::End));
+        assert_eq!(decoder.decode::<Vec<u8>>().as_deref(), Ok(&b",;\n\n"[..]));
+    }
+
+    #[test]
+    fn feeding_stops_when_the_buffer_is_full() {
+        let mut buf = [0; 4];
+        let mut decoder = FeedDecoder::new(&mut buf);
+        let result = decoder.feed(b"1,23,456\n");
+        assert_eq!(result.consumed, 2);
+        assert_eq!(result.boundary, Some(Boundary::Data));
+        let result = decoder.feed(b"23,456\n");
+        assert_eq!(result.consumed, 2);
+        assert_eq!(result.boundary, None);
+        assert!(!decoder.is_full());
+        assert_eq!(decoder.decode::<u8>(), Ok(1));
+        let result = decoder.feed(b",456\n");
+        assert_eq!(result.consumed, 1);
+        assert_eq!(result.boundary, Some(Boundary::Data));
+        assert_eq!(decoder.decode::<u8>(), Ok(23));
+        assert_eq!(decoder.feed(b"456\n").consumed, 4);
+        assert_eq!(decoder.decode::<u16>(), Ok(456));
+
+        decoder.reset();
+        assert_eq!(decoder.feed(b"12345").consumed, 4);
+        assert!(decoder.is_full());
+    }
+
+    #[test]
+    fn hexadecimal_numbers_are_not_blocks() {
+        let mut buf = [0; 64];
+        let mut decoder = FeedDecoder::new(&mut buf);
+        assert_eq!(decoder.feed(b"#HFF,").boundary, Some(Boundary::Data));
+        assert_eq!(decoder.decode::<u8>(), Ok(255));
+    }
+}
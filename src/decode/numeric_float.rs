@@ -2,59 +2,116 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use alloc::string::String;
+use super::{Decoder, Scanner, Step};
+use crate::{
+    buffer::ArrayBuffer, decode::DecodeError, internal::Float, ByteSource, ProtocolObserver,
+};
 
-use super::Decoder;
-use crate::{decode::DecodeError, internal::Float, ByteSource};
+const BUFFER_LEN: usize = 128;
 
-/// Decodes numeric float response data in plain (NR2) or exponential (NR3) format.
+/// Decodes numeric float response data in plain (NR2) or exponential (NR3) format, or with
+/// [`decode_numeric_decimal`](Decoder::decode_numeric_decimal), in any decimal format.
 ///
 /// References:
 ///
 /// - IEEE 488.2: 8.7.3 - \<NR2 NUMERIC RESPONSE DATA\>
 /// - IEEE 488.2: 8.7.4 - \<NR3 NUMERIC RESPONSE DATA\>
-impl<S: ByteSource> Decoder<S> {
+///
+/// Values longer than 128 bytes are rejected with [`DecodeError::BufferOverflow`].
+///
+/// Resumable with the `alloc` feature: if reading from the byte source fails, calling this method
+/// again continues where it left off.
+impl<S: ByteSource, O: ProtocolObserver> Decoder<S, O> {
     pub fn decode_numeric_float<T: Float>(&mut self) -> Result<T, S::Error> {
-        let mut buf = String::new();
-        match self.read_byte()? {
-            byte @ b'+' | byte @ b'-' => {
-                buf.push(byte as char);
-                buf.push(self.digit()? as char);
-            }
-            byte @ b'0'..=b'9' => buf.push(byte as char),
-            _ => return Err(DecodeError::Parse.into()),
-        };
-        loop {
-            match self.read_byte()? {
-                byte @ b'0'..=b'9' => buf.push(byte as char),
-                byte @ b'.' => break buf.push(byte as char),
-                _ => return Err(DecodeError::Parse.into()),
-            }
-        }
-        match self.read_byte()? {
-            byte @ b'0'..=b'9' => buf.push(byte as char),
-            _ => return Err(DecodeError::Parse.into()),
+        self.decode_float_with(FloatScanner::new(false))
+    }
+    /// Decodes numeric response data in any of the plain integer (NR1), plain (NR2) or exponential
+    /// (NR3) formats.
+    ///
+    /// This is meant for fields that instruments send in varying formats. The exponent marker may
+    /// also be a lowercase `e`, and the exponent sign may be omitted, which some instruments do
+    /// even though IEEE 488.2 doesn't allow it.
+    ///
+    /// ```
+    /// use red_sculpin::decode::Decoder;
+    ///
+    /// let mut decoder = Decoder::new(&b"+128,4.132813e-02\n"[..]);
+    /// for expected in [128.0, 4.132813e-2] {
+    ///     decoder.begin_response_data().unwrap();
+    ///     assert_eq!(decoder.decode_numeric_decimal::<f64>().unwrap(), expected);
+    /// }
+    /// ```
+    pub fn decode_numeric_decimal<T: Float>(&mut self) -> Result<T, S::Error> {
+        self.decode_float_with(FloatScanner::new(true))
+    }
+    fn decode_float_with<T: Float>(&mut self, scanner: FloatScanner) -> Result<T, S::Error> {
+        let scanner = self.resumable(|decoder| {
+            let mut scanner = scanner;
+            decoder.scan(&mut scanner)?;
+            Ok(scanner)
+        })?;
+        scanner
+            .text
+            .as_str()
+            .ok()
+            .and_then(|text| T::from_str(text).ok())
+            .ok_or_else(|| self.error(DecodeError::Parse))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum FloatState {
+    Start,
+    Sign,
+    Integer,
+    FirstFraction,
+    Fraction,
+    ExponentSign,
+    FirstExponent,
+    Exponent,
+}
+
+/// Collects the bytes of NR2/NR3 numeric response data
+struct FloatScanner {
+    state: FloatState,
+    /// Accept NR1, a lowercase exponent marker, and an exponent without a sign
+    lenient: bool,
+    text: ArrayBuffer<BUFFER_LEN>,
+}
+
+impl FloatScanner {
+    fn new(lenient: bool) -> FloatScanner {
+        FloatScanner {
+            state: FloatState::Start,
+            lenient,
+            text: ArrayBuffer::new(),
         }
-        loop {
-            match self.read_byte()? {
-                byte @ b'0'..=b'9' => buf.push(byte as char),
-                byte @ b'E' => break buf.push(byte as char),
-                byte => {
-                    self.end_with(byte)?;
-                    return T::from_str(&buf).map_err(|_| DecodeError::Parse.into());
-                }
+    }
+}
+
+impl Scanner for FloatScanner {
+    fn push(&mut self, byte: u8) -> Step {
+        self.state = match (self.state, byte) {
+            (FloatState::Start, b'+' | b'-') => FloatState::Sign,
+            (FloatState::Start | FloatState::Sign | FloatState::Integer, b'0'..=b'9') => {
+                FloatState::Integer
             }
-        }
-        buf.push(self.sign()? as char);
-        buf.push(self.digit()? as char);
-        loop {
-            match self.read_byte()? {
-                byte @ b'0'..=b'9' => buf.push(byte as char),
-                byte => {
-                    self.end_with(byte)?;
-                    break T::from_str(&buf).map_err(|_| DecodeError::Parse.into());
-                }
+            (FloatState::Integer, b'.') => FloatState::FirstFraction,
+            (FloatState::FirstFraction | FloatState::Fraction, b'0'..=b'9') => FloatState::Fraction,
+            (FloatState::Fraction, b'E') => FloatState::ExponentSign,
+            (FloatState::Integer | FloatState::Fraction, b'E' | b'e') if self.lenient => {
+                FloatState::ExponentSign
             }
+            (FloatState::ExponentSign, b'+' | b'-') => FloatState::FirstExponent,
+            (FloatState::ExponentSign, b'0'..=b'9') if self.lenient => FloatState::Exponent,
+            (FloatState::FirstExponent | FloatState::Exponent, b'0'..=b'9') => FloatState::Exponent,
+            (FloatState::Fraction | FloatState::Exponent, _) => return Step::End,
+            (FloatState::Integer, _) if self.lenient => return Step::End,
+            _ => return Step::Invalid,
+        };
+        match self.text.push(byte) {
+            Ok(()) => Step::More,
+            Err(_) => Step::Overflow,
         }
     }
 }
@@ -80,6 +137,7 @@ mod tests {
         }
 
         #[test]
+        #[allow(clippy::excessive_precision)]
         fn negative_value() {
             let data = b"-5.123456789\n";
             assert_matches!(decode::<f32>(data), Ok(value) if value == -5.123456789);
@@ -132,6 +190,34 @@ mod tests {
         }
     }
 
+    mod any_format {
+        use matches::assert_matches;
+
+        use crate::decode::{DecodeError, Decoder};
+
+        fn decode(bytes: &'static [u8]) -> Result<f64, DecodeError> {
+            let mut decoder = Decoder::new(bytes);
+            decoder.begin_response_data()?;
+            decoder.decode_numeric_decimal()
+        }
+
+        #[test]
+        fn accepted_formats() {
+            assert_matches!(decode(b"+128\n"), Ok(value) if value == 128.0);
+            assert_matches!(decode(b"-5.25\n"), Ok(value) if value == -5.25);
+            assert_matches!(decode(b"-99.123E-1\n"), Ok(value) if value == -99.123E-1);
+            assert_matches!(decode(b"1.000000e-09\n"), Ok(value) if value == 1e-9);
+            assert_matches!(decode(b"2E3\n"), Ok(value) if value == 2e3);
+        }
+
+        #[test]
+        fn rejected_formats() {
+            for data in [&b".5\n"[..], b"5.\n", b"5E\n", b"1.0E+\n", b"INF\n"] {
+                assert_matches!(decode(data), Err(DecodeError::Parse));
+            }
+        }
+    }
+
     fn decode<T: Float>(bytes: &'static [u8]) -> Result<T, DecodeError> {
         let mut decoder = Decoder::new(bytes);
         decoder.begin_response_data()?;
@@ -2,10 +2,13 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use alloc::string::String;
+use super::{Decoder, Scanner, Step};
+use crate::{
+    buffer::ArrayBuffer, decode::DecodeError, internal::Integer, ByteSource, ProtocolObserver,
+};
 
-use super::Decoder;
-use crate::{decode::DecodeError, internal::Integer, ByteSource};
+// Enough for a binary u128 without leading zeros
+const BUFFER_LEN: usize = 128;
 
 /// Decodes numeric integer response data in plain (NR1), hexadecimal, octal, or binary format.
 ///
@@ -15,69 +18,90 @@ use crate::{decode::DecodeError, internal::Integer, ByteSource};
 /// - IEEE 488.2: 8.7.5 - \<HEXADECIMAL NUMERIC RESPONSE DATA\>
 /// - IEEE 488.2: 8.7.6 - \<OCTAL NUMERIC RESPONSE DATA\>
 /// - IEEE 488.2: 8.7.7 - \<BINARY NUMERIC RESPONSE DATA\>
-impl<S: ByteSource> Decoder<S> {
+///
+/// Values longer than 128 bytes are rejected with [`DecodeError::BufferOverflow`].
+///
+/// Resumable with the `alloc` feature: if reading from the byte source fails, calling this method
+/// again continues where it left off.
+impl<S: ByteSource, O: ProtocolObserver> Decoder<S, O> {
     pub fn decode_numeric_integer<T: Integer>(&mut self) -> Result<T, S::Error> {
-        let mut buf = String::new();
-        match self.read_byte()? {
-            byte @ b'+' | byte @ b'-' => {
-                buf.push(byte as char);
-                buf.push(self.digit()? as char);
-            }
-            b'#' => match self.read_byte()? {
-                b'H' => {
-                    buf.push(self.hex_digit()? as char);
-                    return loop {
-                        match self.read_byte()? {
-                            byte @ b'A'..=b'F' => buf.push(byte as char),
-                            byte @ b'0'..=b'9' => buf.push(byte as char),
-                            byte => {
-                                self.end_with(byte)?;
-                                break T::from_str_radix(&buf, 16)
-                                    .map_err(|_| DecodeError::Parse.into());
-                            }
-                        }
-                    };
-                }
-                b'Q' => {
-                    buf.push(self.octal_digit()? as char);
-                    return loop {
-                        match self.read_byte()? {
-                            byte @ b'0'..=b'7' => buf.push(byte as char),
-                            byte => {
-                                self.end_with(byte)?;
-                                break T::from_str_radix(&buf, 8)
-                                    .map_err(|_| DecodeError::Parse.into());
-                            }
-                        }
-                    };
-                }
-                b'B' => {
-                    buf.push(self.binary_digit()? as char);
-                    return loop {
-                        match self.read_byte()? {
-                            byte @ b'0' | byte @ b'1' => buf.push(byte as char),
-                            byte => {
-                                self.end_with(byte)?;
-                                break T::from_str_radix(&buf, 2)
-                                    .map_err(|_| DecodeError::Parse.into());
-                            }
-                        }
-                    };
-                }
-                _ => return Err(DecodeError::Parse)?,
-            },
-            byte @ b'0'..=b'9' => buf.push(byte as char),
-            _ => return Err(DecodeError::Parse)?,
+        let scanner = self.resumable(|decoder| {
+            let mut scanner = IntegerScanner::new();
+            decoder.scan(&mut scanner)?;
+            Ok(scanner)
+        })?;
+        scanner
+            .digits
+            .as_str()
+            .ok()
+            .and_then(|text| T::from_str_radix(text, scanner.radix()).ok())
+            .ok_or_else(|| self.error(DecodeError::Parse))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum IntegerState {
+    Start,
+    Sign,
+    Prefix,
+    FirstDigit(u32),
+    Digits(u32),
+}
+
+/// Collects the digits of numeric integer response data, without the sign and radix prefix
+struct IntegerScanner {
+    state: IntegerState,
+    digits: ArrayBuffer<BUFFER_LEN>,
+}
+
+impl IntegerScanner {
+    fn new() -> IntegerScanner {
+        IntegerScanner {
+            state: IntegerState::Start,
+            digits: ArrayBuffer::new(),
+        }
+    }
+    fn radix(&self) -> u32 {
+        match self.state {
+            IntegerState::FirstDigit(radix) | IntegerState::Digits(radix) => radix,
+            _ => 10,
         }
-        loop {
-            match self.read_byte()? {
-                byte @ b'0'..=b'9' => buf.push(byte as char),
-                byte => {
-                    self.end_with(byte)?;
-                    break T::from_str_radix(&buf, 10).map_err(|_| DecodeError::Parse.into());
-                }
+    }
+}
+
+fn is_digit(byte: u8, radix: u32) -> bool {
+    match radix {
+        16 => matches!(byte, b'A'..=b'F' | b'0'..=b'9'),
+        8 => matches!(byte, b'0'..=b'7'),
+        2 => matches!(byte, b'0' | b'1'),
+        _ => byte.is_ascii_digit(),
+    }
+}
+
+impl Scanner for IntegerScanner {
+    fn push(&mut self, byte: u8) -> Step {
+        let (state, is_digit) = match (self.state, byte) {
+            (IntegerState::Start, b'+' | b'-') => (IntegerState::Sign, true),
+            (IntegerState::Start, b'#') => (IntegerState::Prefix, false),
+            (IntegerState::Start | IntegerState::Sign, b'0'..=b'9') => {
+                (IntegerState::Digits(10), true)
             }
+            (IntegerState::Prefix, b'H') => (IntegerState::FirstDigit(16), false),
+            (IntegerState::Prefix, b'Q') => (IntegerState::FirstDigit(8), false),
+            (IntegerState::Prefix, b'B') => (IntegerState::FirstDigit(2), false),
+            (IntegerState::FirstDigit(radix) | IntegerState::Digits(radix), _)
+                if is_digit(byte, radix) =>
+            {
+                (IntegerState::Digits(radix), true)
+            }
+            (IntegerState::Digits(_), _) => return Step::End,
+            _ => return Step::Invalid,
+        };
+        self.state = state;
+        if is_digit && self.digits.push(byte).is_err() {
+            return Step::Overflow;
         }
+        Step::More
     }
 }
 
@@ -206,6 +230,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn too_long_value_is_a_buffer_overflow() {
+        let mut data = [b'0'; 200];
+        data[198] = b'1';
+        data[199] = b'\n';
+        let mut decoder = Decoder::new(&data[..]);
+        decoder.begin_response_data().unwrap();
+        assert_matches!(
+            decoder.decode_numeric_integer::<u8>(),
+            Err(DecodeError::BufferOverflow)
+        );
+    }
+
     #[test]
     fn format_switch_in_middle_is_invalid() {
         assert_matches!(
@@ -0,0 +1,142 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Each function decodes one response data element from the start of a byte slice, and returns it
+//! together with the number of bytes consumed, including the separator or terminator that ends
+//! the element. The functions never block or panic, and malformed or incomplete input is
+//! rejected with a [`DecodeError`].
+//!
+//! ```
+//! use red_sculpin::decode::slice;
+//!
+//! let data = b"1,-2.5E+0\n";
+//! let (first, consumed) = slice::parse_numeric_integer::<u8>(data).unwrap();
+//! let (second, _) = slice::parse_numeric_float::<f32>(&data[consumed..]).unwrap();
+//! assert_eq!((first, consumed, second), (1, 2, -2.5));
+//! ```
+
+use core::fmt;
+
+use super::{DecodeError, Decoder};
+use crate::internal::{Float, Integer};
+
+/// Decodes an element with the given function, and counts the consumed bytes.
+fn parse<'a, T>(
+    bytes: &'a [u8],
+    decode: impl FnOnce(&mut Decoder<&'a [u8]>) -> Result<T, DecodeError>,
+) -> Result<(T, usize), DecodeError> {
+    let mut decoder = Decoder::new(bytes);
+    decoder.begin_response_data()?;
+    let value = decode(&mut decoder)?;
+    let consumed = bytes.len() - decoder.source.len() - decoder.lookahead.len();
+    Ok((value, consumed))
+}
+
+/// Decodes numeric integer response data, see [`Decoder::decode_numeric_integer`].
+pub fn parse_numeric_integer<T: Integer>(bytes: &[u8]) -> Result<(T, usize), DecodeError> {
+    parse(bytes, |decoder| decoder.decode_numeric_integer())
+}
+
+/// Decodes numeric float response data, see [`Decoder::decode_numeric_float`].
+pub fn parse_numeric_float<T: Float>(bytes: &[u8]) -> Result<(T, usize), DecodeError> {
+    parse(bytes, |decoder| decoder.decode_numeric_float())
+}
+
+/// Decodes boolean response data, see [`Decoder::decode_boolean`].
+pub fn parse_boolean(bytes: &[u8]) -> Result<(bool, usize), DecodeError> {
+    parse(bytes, |decoder| decoder.decode_boolean())
+}
+
+/// Decodes string response data into the target, and returns the number of bytes consumed.
+///
+/// See [`Decoder::decode_string`].
+pub fn parse_string<T: fmt::Write>(bytes: &[u8], target: &mut T) -> Result<usize, DecodeError> {
+    parse(bytes, |decoder| decoder.decode_string(target)).map(|((), consumed)| consumed)
+}
+
+/// Decodes character response data into the target, and returns the number of bytes consumed.
+///
+/// See [`Decoder::decode_characters`].
+pub fn parse_characters<T: fmt::Write>(bytes: &[u8], target: &mut T) -> Result<usize, DecodeError> {
+    parse(bytes, |decoder| decoder.decode_characters(target)).map(|((), consumed)| consumed)
+}
+
+/// Decodes arbitrary block response data, borrowing it from the input.
+///
+/// See [`Decoder::decode_block_borrowed`].
+pub fn parse_block(bytes: &[u8]) -> Result<(&[u8], usize), DecodeError> {
+    parse(bytes, |decoder| decoder.decode_block_borrowed())
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::{
+        parse_block, parse_boolean, parse_characters, parse_numeric_float, parse_numeric_integer,
+        parse_string,
+    };
+    use crate::{buffer::ArrayBuffer, decode::DecodeError};
+
+    #[test]
+    fn consumed_lengths() {
+        let data = b"-12,1.5E+0;1,\"a\"\"b\",CHARS,#13xyz\n";
+        let (integer, consumed) = parse_numeric_integer::<i8>(data).unwrap();
+        assert_eq!((integer, consumed), (-12, 4));
+        let data = &data[consumed..];
+        let (float, consumed) = parse_numeric_float::<f32>(data).unwrap();
+        assert_eq!((float, consumed), (1.5, 7));
+        let data = &data[consumed..];
+        assert_eq!(parse_boolean(data), Ok((true, 2)));
+        let data = &data[2..];
+        let mut text = ArrayBuffer::<8>::new();
+        assert_eq!(parse_string(data, &mut text), Ok(7));
+        assert_eq!(text.as_str(), Ok("a\"b"));
+        let data = &data[7..];
+        let mut text = ArrayBuffer::<8>::new();
+        assert_eq!(parse_characters(data, &mut text), Ok(6));
+        assert_eq!(text.as_str(), Ok("CHARS"));
+        let data = &data[6..];
+        assert_eq!(parse_block(data), Ok((&b"xyz"[..], 7)));
+    }
+
+    #[test]
+    fn leading_whitespace_is_consumed() {
+        assert_eq!(parse_numeric_integer::<u8>(b"  7\n"), Ok((7, 4)));
+    }
+
+    #[test]
+    fn incomplete_input() {
+        assert_matches!(
+            parse_numeric_integer::<u8>(b""),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_matches!(
+            parse_numeric_integer::<u8>(b"12"),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_matches!(parse_boolean(b"1"), Err(DecodeError::UnexpectedEnd));
+        assert_matches!(
+            parse_string(b"\"abc", &mut ArrayBuffer::<8>::new()),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_matches!(parse_block(b"#0abc"), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn pathological_block_headers() {
+        for data in [
+            &b"#"[..],
+            b"#1",
+            b"#A1x\n",
+            b"#19",
+            b"#2\n",
+            b"#99999999999\n",
+            b"#9999999999\n",
+            b"#218x\n",
+        ] {
+            assert!(parse_block(data).is_err(), "{:?}", data);
+        }
+    }
+}
@@ -5,34 +5,50 @@
 use core::fmt;
 
 use super::Decoder;
-use crate::{decode::DecodeError, ByteSource};
+use crate::{decode::DecodeError, ByteSource, ProtocolObserver};
 
 /// Decodes string response data into the given target buffer.
 ///
 /// As per IEEE 488.2, only ASCII is supported.
 ///
 /// Reference: IEEE 488.2: 8.7.8 - \<STRING RESPONSE DATA\>
-impl<S: ByteSource> Decoder<S> {
+///
+/// Not resumable: if reading from the byte source fails, the bytes consumed so far are lost, and
+/// the response message should be discarded with [`resynchronize`](Decoder::resynchronize).
+impl<S: ByteSource, O: ProtocolObserver> Decoder<S, O> {
     pub fn decode_string<T: fmt::Write>(&mut self, target: &mut T) -> Result<(), S::Error> {
         self.quote()?;
+        self.decode_string_contents(target)
+    }
+    /// Decodes the rest of string response data after the opening quote.
+    pub(super) fn decode_string_contents<T: fmt::Write>(
+        &mut self,
+        target: &mut T,
+    ) -> Result<(), S::Error> {
         loop {
             match self.read_byte()? {
-                b'"' => match self.read_byte()? {
-                    b'"' => target
-                        .write_char('"')
-                        .map_err(|_| DecodeError::BufferOverflow)?,
-                    byte => break self.end_with(byte),
-                },
+                b'"' => {
+                    self.in_string = false;
+                    match self.read_byte()? {
+                        b'"' => {
+                            self.in_string = true;
+                            target
+                                .write_char('"')
+                                .map_err(|_| self.error(DecodeError::BufferOverflow))?
+                        }
+                        byte => break self.end_with(byte),
+                    }
+                }
                 byte if byte.is_ascii() => target
                     .write_char(byte as char)
-                    .map_err(|_| DecodeError::BufferOverflow)?,
-                _ => break Err(DecodeError::Parse.into()),
+                    .map_err(|_| self.error(DecodeError::BufferOverflow))?,
+                _ => break Err(self.error(DecodeError::Parse)),
             }
         }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
     use alloc::string::String;
     use matches::assert_matches;
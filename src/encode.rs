@@ -4,17 +4,44 @@
 
 use core::fmt::{self, Write};
 
+pub use self::block_writer::BlockWriter;
 use crate::{
-    internal::{ArrayBuffer, Float, Integer},
-    is_program_mnemonic, ByteSink,
+    adapters::Observed,
+    buffer::ArrayBuffer,
+    internal::{Float, Integer, Unsigned},
+    is_program_mnemonic,
+    scpi::types::{Multiplier, Suffix, Unit},
+    ByteSink, ProtocolObserver,
 };
 
+mod block_writer;
+
 #[derive(Debug)]
 pub enum EncodeError {
     NonAsciiString,
     InvalidCharacterData,
+    InvalidArbitraryAscii,
     BlockSizeOverflow(usize),
+    BlockLengthExceeded(usize),
+    IncompleteBlock(usize),
     InvalidEncodeState(EncodeState),
+    BufferOverflow,
+    /// A byte that would end the program header was written into it
+    InvalidHeader(u8),
+    /// A finite numeric value is outside the range allowed by SCPI
+    ValueOutOfScpiRange,
+    /// A channel number can't be used as a numeric suffix
+    InvalidChannel(u8),
+    /// The program header has no segment with the given index for a numeric suffix
+    MissingHeaderSegment(usize),
+    /// Suffix program data that can't be encoded unambiguously
+    InvalidSuffix,
+    /// Expression program data with a forbidden character or unbalanced parentheses
+    InvalidExpression,
+    /// A channel list that is empty, or has a channel or range without dimensions
+    InvalidChannelList,
+    /// A numeric list without items
+    EmptyNumericList,
 }
 
 impl fmt::Display for EncodeError {
@@ -22,38 +49,106 @@ impl fmt::Display for EncodeError {
         match self {
             EncodeError::InvalidCharacterData => write!(f, "invalid character data"),
             EncodeError::NonAsciiString => write!(f, "invalid non-ascii string"),
+            EncodeError::InvalidArbitraryAscii => write!(f, "invalid arbitrary ascii data"),
             EncodeError::BlockSizeOverflow(size) => {
                 write!(f, "block size {} overflows protocol limit", size)
             }
+            EncodeError::BlockLengthExceeded(len) => {
+                write!(f, "block data exceeds declared length {}", len)
+            }
+            EncodeError::IncompleteBlock(missing) => {
+                write!(f, "incomplete block data ({} bytes missing)", missing)
+            }
             EncodeError::InvalidEncodeState(state) => {
                 write!(f, "invalid encode state ({:?})", state)
             }
+            EncodeError::BufferOverflow => write!(f, "buffer overflow"),
+            EncodeError::InvalidHeader(byte) => {
+                write!(f, "invalid byte in program header (0x{:02x})", byte)
+            }
+            EncodeError::ValueOutOfScpiRange => write!(f, "value out of SCPI numeric range"),
+            EncodeError::InvalidChannel(channel) => write!(f, "invalid channel {}", channel),
+            EncodeError::MissingHeaderSegment(segment) => {
+                write!(f, "program header has no segment {}", segment)
+            }
+            EncodeError::InvalidSuffix => write!(f, "invalid suffix"),
+            EncodeError::InvalidExpression => write!(f, "invalid expression"),
+            EncodeError::InvalidChannelList => write!(f, "invalid channel list"),
+            EncodeError::EmptyNumericList => write!(f, "empty numeric list"),
         }
     }
 }
 
+impl core::error::Error for EncodeError {}
+
 #[cfg(feature = "std")]
-impl std::error::Error for EncodeError {}
+impl From<EncodeError> for std::io::Error {
+    fn from(err: EncodeError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, err)
+    }
+}
 
 /// A sink for encoded bytes
 pub trait EncodeSink: ByteSink {
+    /// Ends the program message.
+    ///
+    /// Called once per program message after all of its bytes have been written. By default, the
+    /// [`PROGRAM_MESSAGE_TERMINATOR`] byte is written. Transports with an out-of-band END or EOM
+    /// indicator, such as GPIB and USBTMC, override this to assert it with the last byte of the
+    /// message. Because END alone is also a valid program message terminator, an override may
+    /// leave out the terminator byte, but instruments commonly expect it even with END.
+    ///
+    /// Reference: IEEE 488.2: 7.5 - \<PROGRAM MESSAGE TERMINATOR\>
     fn terminate_message(&mut self) -> Result<(), Self::Error> {
         self.write_byte(PROGRAM_MESSAGE_TERMINATOR)
     }
+    /// Returns the number of bytes written by [`terminate_message`](EncodeSink::terminate_message).
+    ///
+    /// By default, 1 for the [`PROGRAM_MESSAGE_TERMINATOR`] byte. Overrides of
+    /// `terminate_message` that leave out the terminator byte return 0.
+    fn terminator_len(&self) -> usize {
+        1
+    }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// State of an [`Encoder`]
+///
+/// A program message starts with [`begin_message_unit`](Encoder::begin_message_unit), which moves
+/// the encoder from `Initial` to `Header`, and ends with [`end_message`](Encoder::end_message),
+/// which writes the terminator and moves the encoder to `End`. A new message can then be started on
+/// the same encoder with `begin_message_unit`, so one encoder can be used for any number of
+/// messages before [`finish`](Encoder::finish).
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub enum EncodeState {
+    #[default]
     Initial,
     Header,
     Data,
+    /// The payload of an arbitrary block is being written with a [`BlockWriter`]
+    Block,
+    /// An arbitrary block writer was dropped before the block was written completely
+    IncompleteBlock,
     End,
 }
 
-impl Default for EncodeState {
-    fn default() -> Self {
-        EncodeState::Initial
-    }
+/// Validation of floating point values encoded by an [`Encoder`]
+///
+/// SCPI limits decimal numeric program data to the range ±9.9E+37. Values outside it are usually
+/// rejected by the device with a -123 "Exponent too large" error, which is easier to trace when
+/// the encoder catches it instead. Infinities and NaN are not affected, because they are encoded
+/// as character data. The other SCPI limit of 255 mantissa digits can't be exceeded, because
+/// floats are encoded with the shortest exact representation.
+///
+/// Reference: SCPI 1999.0: 7.2.1 - Syntax
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum NumericValidation {
+    /// All values are encoded as-is
+    #[default]
+    Permissive,
+    /// Values outside the SCPI range are rejected with [`EncodeError::ValueOutOfScpiRange`]
+    Strict,
+    /// Values outside the SCPI range are clamped to ±9.9E+37
+    Clamp,
 }
 
 #[must_use]
@@ -61,6 +156,8 @@ impl Default for EncodeState {
 pub struct Encoder<S: EncodeSink> {
     sink: S,
     state: EncodeState,
+    numeric_validation: NumericValidation,
+    float_precision: Option<usize>,
 }
 
 /// Reference: IEEE 488.2: 7.4.1 - \<PROGRAM MESSAGE UNIT SEPARATOR\>
@@ -75,26 +172,162 @@ pub const PROGRAM_HEADER_SEPARATOR: u8 = b' ';
 /// Reference: IEEE 488.2: 7.5 - \<PROGRAM MESSAGE TERMINATOR\>
 pub const PROGRAM_MESSAGE_TERMINATOR: u8 = b'\n';
 
+// The formatting helpers below take trait objects, so they're compiled once instead of once per
+// sink and value type.
+
+// 40 bytes is enough for i128::MIN
+pub(crate) fn format_integer(value: &dyn fmt::Display) -> ArrayBuffer<40> {
+    let mut fmt = ArrayBuffer::new();
+    let res = write!(&mut fmt, "{}", value);
+    debug_assert_eq!(res, Ok(()));
+    fmt
+}
+
+// 130 bytes is enough for u128::MAX in binary with the prefix
+fn format_nondecimal(args: fmt::Arguments) -> ArrayBuffer<130> {
+    let mut fmt = ArrayBuffer::new();
+    let res = fmt.write_fmt(args);
+    debug_assert_eq!(res, Ok(()));
+    fmt
+}
+
+pub(crate) fn format_float(value: &dyn fmt::UpperExp) -> ArrayBuffer<64> {
+    let mut fmt = ArrayBuffer::new();
+    let res = write!(&mut fmt, "{:E}", value);
+    debug_assert_eq!(res, Ok(()));
+    fmt
+}
+
+fn format_float_precision(value: &dyn fmt::UpperExp, digits: usize) -> ArrayBuffer<64> {
+    let shortest = format_float(value);
+    let text = shortest.as_str().unwrap_or_default();
+    let mantissa_digits = text
+        .bytes()
+        .take_while(|&byte| byte != b'E')
+        .filter(u8::is_ascii_digit)
+        .count();
+    if mantissa_digits <= digits {
+        return shortest;
+    }
+    let mut rounded = ArrayBuffer::<64>::new();
+    let res = write!(&mut rounded, "{:.*E}", digits.max(1) - 1, value);
+    debug_assert_eq!(res, Ok(()));
+    // Trailing zeros of the mantissa are left out like in the shortest representation
+    let text = rounded.as_str().unwrap_or_default();
+    let (mantissa, exponent) = text.split_at(text.find('E').unwrap_or(text.len()));
+    let mantissa = match mantissa.contains('.') {
+        true => mantissa.trim_end_matches('0').trim_end_matches('.'),
+        false => mantissa,
+    };
+    let mut fmt = ArrayBuffer::new();
+    let res = fmt
+        .push_all(mantissa.as_bytes())
+        .and_then(|_| fmt.push_all(exponent.as_bytes()));
+    debug_assert_eq!(res, Ok(()));
+    fmt
+}
+
+/// Writes a formatted float directly to a sink, because a plain float can be hundreds of digits
+/// long
+struct PlainFloatWriter<'a, S: ByteSink> {
+    sink: &'a mut S,
+    error: Option<S::Error>,
+    decimal_point: bool,
+}
+
+impl<'a, S: ByteSink> fmt::Write for PlainFloatWriter<'a, S> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.decimal_point |= s.contains('.');
+        self.sink.write_bytes(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
+
+pub(crate) fn definite_block_header(len: usize) -> Result<ArrayBuffer<11>, EncodeError> {
+    let mut fmt: ArrayBuffer<11> = ArrayBuffer::new();
+
+    // IEEE 488.2: 7.7.6.2 - Encoding syntax
+    write!(&mut fmt, "#0{}", len).map_err(|_| EncodeError::BlockSizeOverflow(len))?;
+    let header = fmt.finish();
+    let digits = header[2..].len();
+    header[1] = b'0' + (digits as u8);
+    Ok(fmt)
+}
+
 impl<S: EncodeSink> Encoder<S> {
     pub fn new(sink: S) -> Encoder<S> {
         Encoder {
             sink,
             state: EncodeState::default(),
+            numeric_validation: NumericValidation::default(),
+            float_precision: None,
         }
     }
+    /// Creates an encoder that notifies the given observer of bytes written and terminated
+    /// program messages.
+    pub fn with_observer<O: ProtocolObserver>(sink: S, observer: O) -> Encoder<Observed<S, O>> {
+        Encoder::new(Observed::new(sink, observer))
+    }
+    /// Writes a raw byte.
+    ///
+    /// In the header state, bytes that would end the program header or the program message unit
+    /// are rejected with [`EncodeError::InvalidHeader`]. These are whitespace, control bytes like
+    /// the terminator, and the program message unit separator. Program data is written as-is.
     pub fn write_byte(&mut self, byte: u8) -> Result<(), S::Error> {
+        self.check_header(&[byte])?;
+        self.write_byte_raw(byte)
+    }
+    /// Writes raw bytes, validated like in [`write_byte`](Encoder::write_byte).
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), S::Error> {
+        self.check_header(bytes)?;
+        self.write_bytes_raw(bytes)
+    }
+    /// Writes raw bytes in parts, validated like in [`write_byte`](Encoder::write_byte).
+    pub fn write_bytes_vectored(&mut self, parts: &[&[u8]]) -> Result<(), S::Error> {
+        for part in parts {
+            self.check_header(part)?;
+        }
+        self.write_bytes_vectored_raw(parts)
+    }
+    fn check_header(&self, bytes: &[u8]) -> Result<(), EncodeError> {
+        if self.state != EncodeState::Header {
+            return Ok(());
+        }
+        // Reference: IEEE 488.2: 7.6.1 - \<COMMAND PROGRAM HEADER\>
+        match bytes.iter().find(|&&byte| {
+            byte.is_ascii_whitespace()
+                || byte.is_ascii_control()
+                || byte == PROGRAM_MESSAGE_UNIT_SEPARATOR
+        }) {
+            Some(&byte) => Err(EncodeError::InvalidHeader(byte)),
+            None => Ok(()),
+        }
+    }
+    /// Writes a byte of encoded program data, which may be any byte.
+    fn write_byte_raw(&mut self, byte: u8) -> Result<(), S::Error> {
         debug_assert!(self.state == EncodeState::Header || self.state == EncodeState::Data);
         self.sink.write_byte(byte)?;
         Ok(())
     }
-    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), S::Error> {
+    /// Writes encoded program data, which may contain any bytes.
+    fn write_bytes_raw(&mut self, bytes: &[u8]) -> Result<(), S::Error> {
         debug_assert!(self.state == EncodeState::Header || self.state == EncodeState::Data);
         self.sink.write_bytes(bytes)?;
         Ok(())
     }
+    fn write_bytes_vectored_raw(&mut self, parts: &[&[u8]]) -> Result<(), S::Error> {
+        debug_assert!(self.state == EncodeState::Header || self.state == EncodeState::Data);
+        self.sink.write_bytes_vectored(parts)?;
+        Ok(())
+    }
+    /// Begins a program message unit, writing a separator if the message already has one.
+    ///
+    /// After [`end_message`](Encoder::end_message), this begins a new program message.
     pub fn begin_message_unit(&mut self) -> Result<(), S::Error> {
         self.state = match self.state {
-            EncodeState::Initial => EncodeState::Header,
+            EncodeState::Initial | EncodeState::End => EncodeState::Header,
             EncodeState::Header | EncodeState::Data => {
                 self.sink.write_byte(PROGRAM_MESSAGE_UNIT_SEPARATOR)?;
                 EncodeState::Header
@@ -128,6 +361,27 @@ impl<S: EncodeSink> Encoder<S> {
         };
         Ok(())
     }
+    pub fn state(&self) -> EncodeState {
+        self.state
+    }
+    /// Sets the validation of floating point values. The default is
+    /// [`NumericValidation::Permissive`].
+    pub fn set_numeric_validation(&mut self, validation: NumericValidation) {
+        self.numeric_validation = validation;
+    }
+    pub fn numeric_validation(&self) -> NumericValidation {
+        self.numeric_validation
+    }
+    /// Sets the maximum number of significant digits of floating point values, which are rounded
+    /// to it. The default `None` uses the shortest representation that converts back to the same
+    /// value. At least one digit is always used.
+    pub fn set_float_precision(&mut self, digits: Option<usize>) {
+        self.float_precision = digits;
+    }
+    pub fn float_precision(&self) -> Option<usize> {
+        self.float_precision
+    }
+    /// Ends the current program message if it hasn't been ended yet, and returns the sink.
     pub fn finish(mut self) -> Result<S, S::Error> {
         self.end_message()?;
         Ok(self.sink)
@@ -136,7 +390,7 @@ impl<S: EncodeSink> Encoder<S> {
     ///
     /// Reference: SCPI 1999.0: 7.3 - Boolean Program Data
     pub fn encode_boolean(&mut self, value: bool) -> Result<(), S::Error> {
-        self.write_byte(match value {
+        self.write_byte_raw(match value {
             true => b'1',
             false => b'0',
         })
@@ -146,59 +400,172 @@ impl<S: EncodeSink> Encoder<S> {
     /// Reference: IEEE 488.2: 7.7.1 - \<CHARACTER PROGRAM DATA\>
     pub fn encode_characters(&mut self, value: &str) -> Result<(), S::Error> {
         if is_program_mnemonic(value.as_bytes()) {
-            self.write_bytes(value.as_bytes())
+            self.write_bytes_raw(value.as_bytes())
         } else {
             Err(EncodeError::InvalidCharacterData.into())
         }
     }
+    /// Encodes an expression into expression program data bytes, wrapping it in parentheses.
+    ///
+    /// The expression may contain printable ASCII characters except `"`, `#`, `'` and `;`, and
+    /// its parentheses must be balanced.
+    ///
+    /// Reference: IEEE 488.2: 7.7.7 - \<EXPRESSION PROGRAM DATA\>
+    pub fn encode_expression(&mut self, value: &str) -> Result<(), S::Error> {
+        let mut depth = 0usize;
+        for &byte in value.as_bytes() {
+            depth = match byte {
+                b'(' => depth + 1,
+                b')' => depth.checked_sub(1).ok_or(EncodeError::InvalidExpression)?,
+                b'"' | b'#' | b'\'' | b';' => return Err(EncodeError::InvalidExpression.into()),
+                b' '..=b'~' => depth,
+                _ => return Err(EncodeError::InvalidExpression.into()),
+            };
+        }
+        if depth != 0 {
+            return Err(EncodeError::InvalidExpression.into());
+        }
+        self.write_bytes_vectored_raw(&[b"(", value.as_bytes(), b")"])
+    }
     /// Encodes an integer value into decimal numeric program data bytes.
     ///
     /// Reference: IEEE 488.2: 7.7.2 - \<DECIMAL NUMERIC PROGRAM DATA\>
     pub fn encode_numeric_integer<T: Integer>(&mut self, value: T) -> Result<(), S::Error> {
-        let mut fmt: ArrayBuffer<32> = ArrayBuffer::new();
-        let res = write!(&mut fmt, "{}", value);
-        debug_assert_eq!(res, Ok(()));
-        self.write_bytes(fmt.finish())
+        self.write_bytes_raw(format_integer(&value).as_slice())
+    }
+    /// Encodes an unsigned integer value into hexadecimal numeric program data bytes, e.g. `#H2A`.
+    ///
+    /// Reference: IEEE 488.2: 7.7.4 - \<NON-DECIMAL NUMERIC PROGRAM DATA\>
+    pub fn encode_hexadecimal_integer<T: Unsigned>(&mut self, value: T) -> Result<(), S::Error> {
+        self.write_bytes_raw(format_nondecimal(format_args!("#H{:X}", value)).as_slice())
+    }
+    /// Encodes an unsigned integer value into octal numeric program data bytes, e.g. `#Q52`.
+    ///
+    /// Reference: IEEE 488.2: 7.7.4 - \<NON-DECIMAL NUMERIC PROGRAM DATA\>
+    pub fn encode_octal_integer<T: Unsigned>(&mut self, value: T) -> Result<(), S::Error> {
+        self.write_bytes_raw(format_nondecimal(format_args!("#Q{:o}", value)).as_slice())
+    }
+    /// Encodes an unsigned integer value into binary numeric program data bytes, e.g. `#B101010`.
+    ///
+    /// Reference: IEEE 488.2: 7.7.4 - \<NON-DECIMAL NUMERIC PROGRAM DATA\>
+    pub fn encode_binary_integer<T: Unsigned>(&mut self, value: T) -> Result<(), S::Error> {
+        self.write_bytes_raw(format_nondecimal(format_args!("#B{:b}", value)).as_slice())
     }
     /// Encodes a floating point value into decimal numeric program data bytes.
     ///
+    /// Finite values are validated according to the configured [`NumericValidation`].
+    ///
     /// References:
     ///   - IEEE 488.2: 7.7.2 - \<DECIMAL NUMERIC PROGRAM DATA\>
     ///   - SCPI 1999.0: 7.2 - Decimal Numeric Program Data
     pub fn encode_numeric_float<T: Float>(&mut self, value: T) -> Result<(), S::Error> {
-        // TODO: consider validating the range?
         if value.is_finite() {
-            let mut fmt: ArrayBuffer<64> = ArrayBuffer::new();
-            let res = write!(&mut fmt, "{:E}", value);
-            debug_assert_eq!(res, Ok(()));
-            self.write_bytes(fmt.finish())
-        } else if value.is_nan() {
+            let value = self.validate_float(value)?;
+            let text = match self.float_precision {
+                Some(digits) => format_float_precision(&value, digits),
+                None => format_float(&value),
+            };
+            self.write_bytes_raw(text.as_slice())
+        } else {
+            self.encode_nonfinite_float(value)
+        }
+    }
+    /// Encodes a floating point value into decimal numeric program data bytes without an exponent,
+    /// e.g. `0.0012`.
+    ///
+    /// The value always has a decimal point, and is written out in full, so the smallest values
+    /// take hundreds of digits. Finite values are validated according to the configured
+    /// [`NumericValidation`], which limits large values. The precision set with
+    /// [`set_float_precision`](Encoder::set_float_precision) is not used.
+    ///
+    /// References:
+    ///   - IEEE 488.2: 7.7.2 - \<DECIMAL NUMERIC PROGRAM DATA\>
+    ///   - SCPI 1999.0: 7.2 - Decimal Numeric Program Data
+    pub fn encode_numeric_float_plain<T: Float>(&mut self, value: T) -> Result<(), S::Error> {
+        if !value.is_finite() {
+            return self.encode_nonfinite_float(value);
+        }
+        let value = self.validate_float(value)?;
+        debug_assert!(self.state == EncodeState::Data);
+        let mut writer = PlainFloatWriter {
+            sink: &mut self.sink,
+            error: None,
+            decimal_point: false,
+        };
+        if write!(writer, "{}", value).is_err() {
+            return Err(writer
+                .error
+                .unwrap_or_else(|| EncodeError::BufferOverflow.into()));
+        }
+        if !writer.decimal_point {
+            self.write_bytes_raw(b".0")?;
+        }
+        Ok(())
+    }
+    fn validate_float<T: Float>(&self, value: T) -> Result<T, EncodeError> {
+        match self.numeric_validation {
+            NumericValidation::Permissive => Ok(value),
+            _ if -T::SCPI_MAX <= value && value <= T::SCPI_MAX => Ok(value),
+            NumericValidation::Strict => Err(EncodeError::ValueOutOfScpiRange),
+            NumericValidation::Clamp if value.is_sign_positive() => Ok(T::SCPI_MAX),
+            NumericValidation::Clamp => Ok(-T::SCPI_MAX),
+        }
+    }
+    fn encode_nonfinite_float<T: Float>(&mut self, value: T) -> Result<(), S::Error> {
+        if value.is_nan() {
             // SCPI 1999.0: 7.2.1.5 - Not A Number (NAN)
-            self.write_bytes(b"NAN")
+            self.write_bytes_raw(b"NAN")
         } else {
             // SCPI 1999.0: 7.2.1.4 - INFinity and Negative INFinity (NINF)
             if value.is_sign_positive() {
-                self.write_bytes(b"INF")
+                self.write_bytes_raw(b"INF")
             } else {
-                self.write_bytes(b"NINF")
+                self.write_bytes_raw(b"NINF")
             }
         }
     }
+    /// Encodes suffix program data, separated from the preceding numeric value by a space.
+    ///
+    /// Mega hertz and mega ohm are written `MHZ` and `MOHM`, so milli can't be combined with
+    /// them. Such suffixes, and unit exponents outside -9 to 9 or 0, are rejected with
+    /// [`EncodeError::InvalidSuffix`].
+    ///
+    /// Reference: IEEE 488.2: 7.7.3 - \<SUFFIX PROGRAM DATA\>
+    pub fn encode_suffix(&mut self, suffix: &Suffix) -> Result<(), S::Error> {
+        let multiplier = match (suffix.multiplier, suffix.unit) {
+            (Some(Multiplier::Milli), Unit::Hertz | Unit::Ohm) => {
+                return Err(EncodeError::InvalidSuffix.into())
+            }
+            (Some(Multiplier::Mega), Unit::Hertz | Unit::Ohm) => "M",
+            (multiplier, _) => multiplier.map_or("", |multiplier| multiplier.mnemonic()),
+        };
+        let exponent = match suffix.exponent {
+            1 => ArrayBuffer::new(),
+            -9..=-1 | 2..=9 => format_integer(&suffix.exponent),
+            _ => return Err(EncodeError::InvalidSuffix.into()),
+        };
+        self.write_bytes_vectored_raw(&[
+            b" ",
+            multiplier.as_bytes(),
+            suffix.unit.mnemonic().as_bytes(),
+            exponent.as_slice(),
+        ])
+    }
     /// Encodes an ASCII string into IEEE 488.2 string program data bytes.
     ///
     /// Reference: IEEE 488.2: 7.7.5 - \<STRING PROGRAM DATA\>
     pub fn encode_string(&mut self, data: &str) -> Result<(), S::Error> {
         if data.as_bytes().iter().all(|ch| ch.is_ascii()) {
             // IEEE 488.2: 7.7.5.2 - Encoding syntax
-            self.write_byte(b'"')?;
+            self.write_byte_raw(b'"')?;
             let mut chunk_iter = data.as_bytes().split(|&ch| ch == b'"').peekable();
             while let Some(chunk) = chunk_iter.next() {
-                self.write_bytes(chunk)?;
+                self.write_bytes_raw(chunk)?;
                 if chunk_iter.peek().is_some() {
-                    self.write_bytes(b"\"\"")?;
+                    self.write_bytes_raw(b"\"\"")?;
                 }
             }
-            self.write_byte(b'"')
+            self.write_byte_raw(b'"')
         } else {
             Err(EncodeError::NonAsciiString.into())
         }
@@ -207,20 +574,304 @@ impl<S: EncodeSink> Encoder<S> {
     ///
     /// Reference: IEEE 488.2: 7.7.6 - \<ARBITRARY BLOCK PROGRAM DATA\>
     pub fn encode_definite_block_header(&mut self, len: usize) -> Result<(), S::Error> {
-        let mut fmt: ArrayBuffer<11> = ArrayBuffer::new();
-
-        // IEEE 488.2: 7.7.6.2 - Encoding syntax
-        write!(&mut fmt, "#0{}", len).map_err(|_| EncodeError::BlockSizeOverflow(len))?;
-        let header = fmt.finish();
-        let digits = header[2..].len();
-        header[1] = b'0' + (digits as u8);
-        self.write_bytes(header)
+        let mut header = definite_block_header(len)?;
+        self.write_bytes_raw(header.finish())
     }
     /// Encodes a slice of bytes into IEEE 488.2 definite length arbitrary block bytes.
     ///
+    /// The header and the data are written to the sink with a single vectored write.
+    ///
     /// Reference: IEEE 488.2: 7.7.6 - \<ARBITRARY BLOCK PROGRAM DATA\>
     pub fn encode_definite_block(&mut self, data: &[u8]) -> Result<(), S::Error> {
-        self.encode_definite_block_header(data.len())?;
-        self.write_bytes(data)
+        let mut header = definite_block_header(data.len())?;
+        self.write_bytes_vectored_raw(&[header.finish(), data])
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use alloc::vec::Vec;
+    use matches::assert_matches;
+
+    use super::{EncodeError, EncodeSink, EncodeState, Encoder, NumericValidation};
+    use crate::{
+        adapters::Counters,
+        ieee::message::{ClearStatus, IdentificationQuery, OperationCompleteQuery},
+        scpi::message::StatusOperationEnable,
+        ByteSink, Command, Query,
+    };
+
+    fn first(encoder: &mut Encoder<Vec<u8>>) {
+        StatusOperationEnable(42).encode(encoder).unwrap();
+        IdentificationQuery.encode(encoder).unwrap();
+    }
+
+    fn second(encoder: &mut Encoder<Vec<u8>>) {
+        ClearStatus.encode(encoder).unwrap();
+    }
+
+    fn third(encoder: &mut Encoder<Vec<u8>>) {
+        OperationCompleteQuery.encode(encoder).unwrap();
+        StatusOperationEnable(0).encode(encoder).unwrap();
+    }
+
+    #[test]
+    fn messages_back_to_back() {
+        let mut encoder = Encoder::new(Vec::new());
+        for message in [first, second, third] {
+            message(&mut encoder);
+            encoder.end_message().unwrap();
+            assert_eq!(encoder.state(), EncodeState::End);
+        }
+        let data = encoder.finish().unwrap();
+
+        let mut expected = Vec::new();
+        for message in [first, second, third] {
+            let mut encoder = Encoder::new(Vec::new());
+            message(&mut encoder);
+            expected.extend(encoder.finish().unwrap());
+        }
+        assert_eq!(data, expected);
+        assert_eq!(
+            data,
+            b":STAT:OPER:ENAB 42;*IDN?\n*CLS\n*OPC?;:STAT:OPER:ENAB 0\n"
+        );
+    }
+
+    #[test]
+    fn finish_ends_the_last_message() {
+        let mut encoder = Encoder::new(Vec::new());
+        first(&mut encoder);
+        encoder.end_message().unwrap();
+        second(&mut encoder);
+        assert_eq!(
+            encoder.finish().unwrap(),
+            b":STAT:OPER:ENAB 42;*IDN?\n*CLS\n"
+        );
+    }
+
+    #[test]
+    fn ended_message_has_no_program_data() {
+        let mut encoder = Encoder::new(Vec::new());
+        second(&mut encoder);
+        encoder.end_message().unwrap();
+        assert_matches!(
+            encoder.begin_program_data(),
+            Err(EncodeError::InvalidEncodeState(EncodeState::End))
+        );
+        assert_matches!(
+            Encoder::new(Vec::new()).finish(),
+            Err(EncodeError::InvalidEncodeState(EncodeState::Initial))
+        );
+    }
+
+    /// A sink that records transfers, and sets an EOM flag on the last one of each message
+    /// instead of writing a terminator byte
+    #[derive(Default)]
+    struct EomSink {
+        transfers: Vec<(Vec<u8>, bool)>,
+    }
+
+    impl ByteSink for EomSink {
+        type Error = EncodeError;
+
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+            self.transfers.push((bytes.to_vec(), false));
+            Ok(())
+        }
+    }
+
+    impl EncodeSink for EomSink {
+        fn terminate_message(&mut self) -> Result<(), Self::Error> {
+            match self.transfers.last_mut() {
+                Some((_, eom)) => *eom = true,
+                None => self.transfers.push((Vec::new(), true)),
+            }
+            Ok(())
+        }
+        fn terminator_len(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn terminate_message_can_set_eom() {
+        let mut encoder = Encoder::new(EomSink::default());
+        ClearStatus.encode(&mut encoder).unwrap();
+        encoder.end_message().unwrap();
+        OperationCompleteQuery.encode(&mut encoder).unwrap();
+        let sink = encoder.finish().unwrap();
+        let eom = sink.transfers.iter().map(|&(_, eom)| eom);
+        assert_eq!(eom.filter(|&eom| eom).count(), 2);
+        let (last, eom) = sink.transfers.last().unwrap();
+        assert_eq!(last, b"*OPC?");
+        assert!(eom);
+        let data = sink.transfers.into_iter().flat_map(|(bytes, _)| bytes);
+        assert_eq!(data.collect::<Vec<_>>(), b"*CLS*OPC?");
+    }
+
+    #[test]
+    fn observer_counts_terminator_bytes_written() {
+        let mut counters = Counters::default();
+        let mut encoder = Encoder::with_observer(EomSink::default(), &mut counters);
+        ClearStatus.encode(&mut encoder).unwrap();
+        encoder.end_message().unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(counters.bytes_written, 4);
+        assert_eq!(counters.messages_sent, 1);
+
+        let mut encoder = Encoder::with_observer(Vec::new(), &mut counters);
+        ClearStatus.encode(&mut encoder).unwrap();
+        encoder.end_message().unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(counters.bytes_written, 9);
+        assert_eq!(counters.messages_sent, 2);
+    }
+
+    #[test]
+    fn header_bytes_are_validated() {
+        struct BadMnemonic(&'static str);
+
+        impl Command for BadMnemonic {
+            type ProgramData = ();
+            fn mnemonic(&self) -> &str {
+                self.0
+            }
+            fn program_data(&self) -> Self::ProgramData {}
+        }
+
+        for (mnemonic, byte) in [
+            ("*CLS\n*RST", b'\n'),
+            ("*CLS;*RST", b';'),
+            ("OUTP ON", b' '),
+            (" OUTP", b' '),
+            ("OUTP\r", b'\r'),
+        ] {
+            let mut encoder = Encoder::new(Vec::new());
+            assert_matches!(
+                BadMnemonic(mnemonic).encode(&mut encoder),
+                Err(EncodeError::InvalidHeader(b)) if b == byte
+            );
+        }
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.begin_message_unit().unwrap();
+        assert_matches!(
+            encoder.write_bytes_vectored(&[b"MMEM:DATA", b"\n"]),
+            Err(EncodeError::InvalidHeader(b'\n'))
+        );
+    }
+
+    #[test]
+    fn program_data_may_contain_any_bytes() {
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.begin_message_unit().unwrap();
+        encoder.write_bytes(b"MMEM:DATA").unwrap();
+        encoder.begin_program_data().unwrap();
+        encoder.encode_string("a;b c").unwrap();
+        encoder.begin_program_data().unwrap();
+        encoder.encode_definite_block(b"\n;\r ").unwrap();
+        assert_eq!(
+            encoder.finish().unwrap(),
+            b"MMEM:DATA \"a;b c\",#14\n;\r \n"
+        );
+    }
+
+    #[test]
+    fn float_precision() {
+        fn encode(precision: Option<usize>, value: f64) -> Vec<u8> {
+            let mut encoder = Encoder::new(Vec::new());
+            encoder.set_float_precision(precision);
+            encoder.begin_message_unit().unwrap();
+            encoder.write_bytes(b"VOLT").unwrap();
+            encoder.begin_program_data().unwrap();
+            encoder.encode_numeric_float(value).unwrap();
+            encoder.finish().unwrap()
+        }
+
+        assert_eq!(Encoder::new(Vec::new()).float_precision(), None);
+        assert_eq!(encode(None, 1.0 / 3.0), b"VOLT 3.333333333333333E-1\n");
+        assert_eq!(encode(Some(12), 1.0 / 3.0), b"VOLT 3.33333333333E-1\n");
+        assert_eq!(encode(Some(3), 9.9999999), b"VOLT 1E1\n");
+        assert_eq!(encode(Some(3), -0.099999), b"VOLT -1E-1\n");
+        assert_eq!(encode(Some(3), 1.2345e-7), b"VOLT 1.23E-7\n");
+        assert_eq!(encode(Some(3), 1.5), b"VOLT 1.5E0\n");
+        assert_eq!(encode(Some(3), 0.0), b"VOLT 0E0\n");
+        assert_eq!(encode(Some(0), 2.5e10), b"VOLT 2E10\n");
+        assert_eq!(encode(Some(100), 0.1), b"VOLT 1E-1\n");
+        assert_eq!(encode(Some(100), 1.0 / 3.0), encode(None, 1.0 / 3.0));
+        assert_eq!(encode(Some(3), f64::INFINITY), b"VOLT INF\n");
+        assert_eq!(encode(Some(3), f64::NEG_INFINITY), b"VOLT NINF\n");
+        assert_eq!(encode(Some(3), f64::NAN), b"VOLT NAN\n");
+    }
+
+    #[test]
+    fn plain_float() {
+        fn encode<T: crate::internal::Float>(value: T) -> Result<Vec<u8>, EncodeError> {
+            let mut encoder = Encoder::new(Vec::new());
+            encoder.set_numeric_validation(NumericValidation::Strict);
+            encoder.begin_message_unit()?;
+            encoder.write_bytes(b"VOLT")?;
+            encoder.begin_program_data()?;
+            encoder.encode_numeric_float_plain(value)?;
+            encoder.finish()
+        }
+
+        assert_eq!(encode(1.2e-3f64).unwrap(), b"VOLT 0.0012\n");
+        assert_eq!(encode(-42.0f32).unwrap(), b"VOLT -42.0\n");
+        assert_eq!(encode(0.0f64).unwrap(), b"VOLT 0.0\n");
+        assert_eq!(encode(1.5e10f64).unwrap(), b"VOLT 15000000000.0\n");
+        assert_eq!(encode(0.1f32).unwrap(), b"VOLT 0.1\n");
+        let tiny = encode(f64::from_bits(1)).unwrap();
+        assert_eq!(tiny.len(), "VOLT 0.\n".len() + 324);
+        assert!(tiny.starts_with(b"VOLT 0.000") && tiny.ends_with(b"0005\n"));
+        assert_eq!(
+            encode(9.9e37f64).unwrap(),
+            b"VOLT 99000000000000000000000000000000000000.0\n"
+        );
+        assert_matches!(encode(1e38f64), Err(EncodeError::ValueOutOfScpiRange));
+        assert_eq!(encode(f64::INFINITY).unwrap(), b"VOLT INF\n");
+        assert_eq!(encode(f32::NEG_INFINITY).unwrap(), b"VOLT NINF\n");
+        assert_eq!(encode(f64::NAN).unwrap(), b"VOLT NAN\n");
+    }
+
+    #[test]
+    fn numeric_validation() {
+        fn encode(validation: NumericValidation, value: f64) -> Result<Vec<u8>, EncodeError> {
+            let mut encoder = Encoder::new(Vec::new());
+            encoder.set_numeric_validation(validation);
+            encoder.begin_message_unit()?;
+            encoder.write_bytes(b"VOLT")?;
+            encoder.begin_program_data()?;
+            encoder.encode_numeric_float(value)?;
+            encoder.finish()
+        }
+
+        use NumericValidation::{Clamp, Permissive, Strict};
+        assert_eq!(Encoder::new(Vec::new()).numeric_validation(), Permissive);
+        for validation in [Permissive, Strict, Clamp] {
+            assert_eq!(encode(validation, 9.9e37).unwrap(), b"VOLT 9.9E37\n");
+            assert_eq!(encode(validation, -9.9e37).unwrap(), b"VOLT -9.9E37\n");
+            assert_eq!(encode(validation, f64::INFINITY).unwrap(), b"VOLT INF\n");
+            assert_eq!(encode(validation, f64::NAN).unwrap(), b"VOLT NAN\n");
+        }
+        assert_eq!(encode(Permissive, 1e38).unwrap(), b"VOLT 1E38\n");
+        assert_matches!(encode(Strict, 1e38), Err(EncodeError::ValueOutOfScpiRange));
+        assert_matches!(
+            encode(Strict, -1e200),
+            Err(EncodeError::ValueOutOfScpiRange)
+        );
+        assert_eq!(encode(Clamp, 1e38).unwrap(), b"VOLT 9.9E37\n");
+        assert_eq!(encode(Clamp, -1e200).unwrap(), b"VOLT -9.9E37\n");
+
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.set_numeric_validation(Strict);
+        encoder.begin_message_unit().unwrap();
+        encoder.write_bytes(b"VOLT").unwrap();
+        encoder.begin_program_data().unwrap();
+        encoder.encode_numeric_float(9.9e37f32).unwrap();
+        assert_matches!(
+            encoder.encode_numeric_float(f32::MAX),
+            Err(EncodeError::ValueOutOfScpiRange)
+        );
     }
 }
@@ -0,0 +1,268 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(feature = "std")]
+use std::io;
+
+use super::{EncodeError, EncodeSink, EncodeState, Encoder};
+use crate::ByteSink;
+
+/// Writer for the payload of definite length arbitrary block program data
+///
+/// Returned by [`Encoder::begin_definite_block`]. The payload is written through the [`ByteSink`]
+/// implementation, or `io::Write` with the `std` feature. Writes that would exceed the declared
+/// length fail without writing anything, and [`finish`](BlockWriter::finish) checks that the whole
+/// payload has been written, after which more program data can follow.
+///
+/// While the writer exists, the encoder is in the [`EncodeState::Block`] state. If the writer is
+/// dropped before the payload has been written completely, the encoder is left in the
+/// [`EncodeState::IncompleteBlock`] state, so the truncated message can't be terminated.
+pub struct BlockWriter<'a, S: EncodeSink> {
+    encoder: &'a mut Encoder<S>,
+    len: usize,
+    remaining: usize,
+}
+
+impl<'a, S: EncodeSink> BlockWriter<'a, S> {
+    /// Returns the number of payload bytes left to write.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+    /// Finishes the block, checking that exactly the declared number of bytes has been written.
+    pub fn finish(self) -> Result<(), S::Error> {
+        match self.remaining {
+            0 => Ok(()),
+            missing => Err(EncodeError::IncompleteBlock(missing).into()),
+        }
+    }
+}
+
+impl<'a, S: EncodeSink> ByteSink for BlockWriter<'a, S> {
+    type Error = S::Error;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        if bytes.len() > self.remaining {
+            return Err(EncodeError::BlockLengthExceeded(self.len).into());
+        }
+        self.encoder.sink.write_bytes(bytes)?;
+        self.remaining -= bytes.len();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, S> io::Write for BlockWriter<'a, S>
+where
+    S: EncodeSink,
+    S::Error: Into<io::Error>,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_bytes(buf).map_err(Into::into)?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, S: EncodeSink> Drop for BlockWriter<'a, S> {
+    fn drop(&mut self) {
+        self.encoder.state = if self.remaining > 0 {
+            EncodeState::IncompleteBlock
+        } else {
+            EncodeState::Data
+        };
+    }
+}
+
+impl<S: EncodeSink> Encoder<S> {
+    /// Encodes a definite length arbitrary block header declaring the given length, and returns a
+    /// writer for the payload. See [`BlockWriter`] for details.
+    ///
+    /// Reference: IEEE 488.2: 7.7.6 - \<ARBITRARY BLOCK PROGRAM DATA\>
+    pub fn begin_definite_block(&mut self, len: usize) -> Result<BlockWriter<'_, S>, S::Error> {
+        self.encode_definite_block_header(len)?;
+        self.state = EncodeState::Block;
+        Ok(BlockWriter {
+            encoder: self,
+            len,
+            remaining: len,
+        })
+    }
+    /// Encodes definite length arbitrary block program data, copying exactly `len` payload bytes
+    /// from the reader in chunks.
+    ///
+    /// If the reader ends early, [`EncodeError::IncompleteBlock`] is returned with the number of
+    /// missing bytes, and the truncated message can't be terminated, like with a [`BlockWriter`]
+    /// that wasn't finished.
+    ///
+    /// Reference: IEEE 488.2: 7.7.6 - \<ARBITRARY BLOCK PROGRAM DATA\>
+    #[cfg(feature = "std")]
+    pub fn encode_definite_block_from<R: io::Read>(
+        &mut self,
+        reader: &mut R,
+        len: usize,
+    ) -> io::Result<()>
+    where
+        S::Error: Into<io::Error>,
+    {
+        let mut writer = self.begin_definite_block(len).map_err(Into::into)?;
+        io::copy(&mut io::Read::take(reader, len as u64), &mut writer)?;
+        writer.finish().map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "std")]
+    use alloc::vec::Vec;
+    use core::mem;
+    use matches::assert_matches;
+    #[cfg(feature = "std")]
+    use std::io::{self, Write};
+
+    use crate::{
+        buffer::ArrayBuffer,
+        encode::{EncodeError, EncodeState, Encoder},
+        ByteSink,
+    };
+
+    fn encoder() -> Encoder<ArrayBuffer<32>> {
+        let mut encoder = Encoder::new(ArrayBuffer::new());
+        encoder.begin_message_unit().unwrap();
+        encoder.write_bytes(b"DATA").unwrap();
+        encoder.begin_program_data().unwrap();
+        encoder
+    }
+
+    #[test]
+    fn length_overflows_header() {
+        let mut encoder = encoder();
+        assert_matches!(
+            encoder.begin_definite_block(usize::MAX).err(),
+            Some(EncodeError::BlockSizeOverflow(_))
+        );
+    }
+
+    #[test]
+    fn written_in_parts() {
+        let mut encoder = encoder();
+        let mut writer = encoder.begin_definite_block(6).unwrap();
+        for sample in [0x0102u16, 0x0304, 0x0506] {
+            writer.write_bytes(&sample.to_be_bytes()).unwrap();
+        }
+        assert_eq!(writer.remaining(), 0);
+        assert_matches!(
+            writer.write_byte(7),
+            Err(EncodeError::BlockLengthExceeded(6))
+        );
+        writer.finish().unwrap();
+        assert_eq!(encoder.state(), EncodeState::Data);
+        encoder.begin_program_data().unwrap();
+        encoder.encode_numeric_integer(1).unwrap();
+        assert_eq!(
+            encoder.finish().unwrap().as_slice(),
+            b"DATA #16\x01\x02\x03\x04\x05\x06,1\n"
+        );
+    }
+
+    #[test]
+    fn open_block_rejects_other_data() {
+        let mut encoder = encoder();
+        let mut writer = encoder.begin_definite_block(4).unwrap();
+        writer.write_bytes(b"ab").unwrap();
+        mem::forget(writer);
+        assert_eq!(encoder.state(), EncodeState::Block);
+        assert_matches!(
+            encoder.begin_program_data(),
+            Err(EncodeError::InvalidEncodeState(EncodeState::Block))
+        );
+        assert_matches!(
+            encoder.finish(),
+            Err(EncodeError::InvalidEncodeState(EncodeState::Block))
+        );
+    }
+
+    #[test]
+    fn unfinished_block_poisons_encoder() {
+        let mut encoder = encoder();
+        let mut writer = encoder.begin_definite_block(4).unwrap();
+        writer.write_bytes(b"abc").unwrap();
+        assert_matches!(writer.finish(), Err(EncodeError::IncompleteBlock(1)));
+        assert_matches!(
+            encoder.begin_message_unit(),
+            Err(EncodeError::InvalidEncodeState(
+                EncodeState::IncompleteBlock
+            ))
+        );
+        assert_matches!(
+            encoder.finish(),
+            Err(EncodeError::InvalidEncodeState(
+                EncodeState::IncompleteBlock
+            ))
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn io_write_over_length() {
+        let mut encoder = encoder();
+        let mut writer = encoder.begin_definite_block(4).unwrap();
+        writer.write_all(b"ab").unwrap();
+        let err = writer.write(b"cde").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(writer.remaining(), 2);
+        writer.write_all(b"cd").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(encoder.finish().unwrap().as_slice(), b"DATA #14abcd\n");
+    }
+
+    #[cfg(feature = "std")]
+    fn encode_from(payload: &[u8], len: usize) -> (io::Result<()>, Encoder<Vec<u8>>) {
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.begin_message_unit().unwrap();
+        encoder.write_bytes(b"DATA").unwrap();
+        encoder.begin_program_data().unwrap();
+        let result = encoder.encode_definite_block_from(&mut &payload[..], len);
+        (result, encoder)
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_reader() {
+        let payload = (0..20_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let (result, encoder) = encode_from(&payload, 20_000);
+        result.unwrap();
+        let mut expected = b"DATA #520000".to_vec();
+        expected.extend_from_slice(&payload);
+        expected.push(b'\n');
+        assert!(encoder.finish().unwrap() == expected);
+
+        let (result, encoder) = encode_from(b"abcdef", 4);
+        result.unwrap();
+        assert_eq!(encoder.finish().unwrap(), b"DATA #14abcd\n");
+
+        let (result, encoder) = encode_from(b"", 0);
+        result.unwrap();
+        assert_eq!(encoder.finish().unwrap(), b"DATA #10\n");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_short_reader() {
+        let (result, encoder) = encode_from(b"abc", 5);
+        let err = result.unwrap_err();
+        assert_matches!(
+            err.get_ref()
+                .and_then(|err| err.downcast_ref::<EncodeError>()),
+            Some(EncodeError::IncompleteBlock(2))
+        );
+        assert_matches!(
+            encoder.finish(),
+            Err(EncodeError::InvalidEncodeState(
+                EncodeState::IncompleteBlock
+            ))
+        );
+    }
+}
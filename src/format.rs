@@ -0,0 +1,168 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(feature = "alloc")]
+use alloc::string::{String, ToString};
+use core::fmt::{self, Write};
+
+/// Number of payload bytes shown for an arbitrary block
+const BLOCK_PREVIEW_LEN: usize = 16;
+
+/// Renders program or response message bytes in a readable form, for logging
+///
+/// Printable ASCII is rendered as-is, and other bytes are escaped as `\n`, `\r`, `\t`, `\\` or
+/// `\xNN`. Arbitrary block payloads are summarized with their first 16 bytes in hex and their
+/// length, e.g. `#3100[00010203… 100 bytes]`, so large transfers don't flood the log. Partial
+/// messages, such as a single chunk read from a transport, are rendered as far as they go.
+///
+/// ```
+/// use red_sculpin::format::Escaped;
+///
+/// let message = b"MMEM:DATA \"a.bin\",#14\x00\x01\x02\x03\n";
+/// assert_eq!(
+///     Escaped(message).to_string(),
+///     "MMEM:DATA \"a.bin\",#14[00010203 4 bytes]\\n"
+/// );
+/// ```
+///
+/// Reference: IEEE 488.2: 7.7.6 - \<ARBITRARY BLOCK PROGRAM DATA\>
+#[derive(Copy, Clone, Debug)]
+pub struct Escaped<'a>(pub &'a [u8]);
+
+impl<'a> fmt::Display for Escaped<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut bytes = self.0;
+        let mut quote = None;
+        while let Some((&byte, rest)) = bytes.split_first() {
+            bytes = rest;
+            match quote {
+                Some(delimiter) if byte == delimiter => quote = None,
+                Some(_) => (),
+                None if matches!(byte, b'"' | b'\'') => quote = Some(byte),
+                None if byte == b'#' => {
+                    if let Some((header, payload, len, rest)) = split_block(bytes) {
+                        f.write_char('#')?;
+                        f.write_str(header)?;
+                        write_block_summary(f, payload, len)?;
+                        bytes = rest;
+                        continue;
+                    }
+                }
+                None => (),
+            }
+            write_escaped(f, byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders program or response message bytes in a readable form, for logging.
+///
+/// See [`Escaped`].
+#[cfg(feature = "alloc")]
+pub fn escape_program_message(bytes: &[u8]) -> String {
+    Escaped(bytes).to_string()
+}
+
+/// Splits an arbitrary block that follows a `#` into its header, payload, payload length and the
+/// rest of the input, or returns `None` if the bytes don't start a block. The payload is truncated
+/// if the input ends early.
+fn split_block(bytes: &[u8]) -> Option<(&str, &[u8], usize, &[u8])> {
+    let (&digit, rest) = bytes.split_first()?;
+    let len_digits = match digit {
+        b'0' => {
+            // Indefinite length format, terminated by the final newline
+            let len = match rest {
+                [payload @ .., b'\n'] => payload.len(),
+                _ => rest.len(),
+            };
+            let (payload, rest) = rest.split_at(len);
+            return Some(("0", payload, len, rest));
+        }
+        b'1'..=b'9' => usize::from(digit - b'0'),
+        _ => return None,
+    };
+    let digits = rest.get(..len_digits)?;
+    let len = digits.iter().try_fold(0usize, |acc, &byte| match byte {
+        b'0'..=b'9' => acc.checked_mul(10)?.checked_add(usize::from(byte - b'0')),
+        _ => None,
+    })?;
+    let rest = &rest[len_digits..];
+    let (payload, rest) = rest.split_at(len.min(rest.len()));
+    // The header is ASCII digits
+    let header = core::str::from_utf8(&bytes[..=len_digits]).ok()?;
+    Some((header, payload, len, rest))
+}
+
+fn write_block_summary(f: &mut fmt::Formatter, payload: &[u8], len: usize) -> fmt::Result {
+    let preview = &payload[..payload.len().min(BLOCK_PREVIEW_LEN)];
+    f.write_char('[')?;
+    for byte in preview {
+        write!(f, "{:02x}", byte)?;
+    }
+    if preview.len() < len {
+        f.write_char('…')?;
+    }
+    write!(f, " {} bytes]", len)
+}
+
+/// Writes a byte, escaped unless it's printable ASCII.
+pub(crate) fn write_escaped(f: &mut impl Write, byte: u8) -> fmt::Result {
+    match byte {
+        b'\n' => f.write_str("\\n"),
+        b'\r' => f.write_str("\\r"),
+        b'\t' => f.write_str("\\t"),
+        b'\\' => f.write_str("\\\\"),
+        0x20..=0x7e => f.write_char(char::from(byte)),
+        _ => write!(f, "\\x{:02x}", byte),
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::escape_program_message;
+
+    #[test]
+    fn strings_and_control_characters() {
+        assert_eq!(
+            escape_program_message(b":DISP:TEXT \"say \"\"hi\"\"\";*RST\r\n"),
+            ":DISP:TEXT \"say \"\"hi\"\"\";*RST\\r\\n"
+        );
+        assert_eq!(
+            escape_program_message(b"\x00\x1b[2J\x7f\xff\\"),
+            "\\x00\\x1b[2J\\x7f\\xff\\\\"
+        );
+        // A `#` inside a string doesn't start a block
+        assert_eq!(escape_program_message(b"'#14abcd'\n"), "'#14abcd'\\n");
+    }
+
+    #[test]
+    fn blocks_are_summarized() {
+        let mut message = Vec::from(&b"MMEM:DATA \"a.bin\",#3100"[..]);
+        message.extend(0..100);
+        message.extend(b";*OPC\n");
+        assert_eq!(
+            escape_program_message(&message),
+            "MMEM:DATA \"a.bin\",#3100[000102030405060708090a0b0c0d0e0f… 100 bytes];*OPC\\n"
+        );
+        assert_eq!(
+            escape_program_message(b"#0\n\x01\x02\n"),
+            "#0[0a0102 3 bytes]\\n"
+        );
+        assert_eq!(escape_program_message(b"#10;\n"), "#10[ 0 bytes];\\n");
+    }
+
+    #[test]
+    fn partial_messages() {
+        assert_eq!(
+            escape_program_message(b"DATA #210\x01\x02"),
+            "DATA #210[0102… 10 bytes]"
+        );
+        assert_eq!(escape_program_message(b"DATA #21"), "DATA #21");
+        assert_eq!(escape_program_message(b"DATA #"), "DATA #");
+        assert_eq!(escape_program_message(b"#H1F,#B0"), "#H1F,#B0");
+    }
+}
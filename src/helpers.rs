@@ -0,0 +1,670 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::{string::String, vec::Vec};
+use core::ops::ControlFlow;
+use std::io;
+
+use crate::{
+    decode::Decoder,
+    encode::Encoder,
+    ieee::{
+        message::{
+            encode_with_opc, ClearStatus, IdentificationQuery, OperationCompleteQuery,
+            OptionIdentificationQuery,
+        },
+        types::DeviceIdentification,
+    },
+    scpi::{
+        message::{StatusRegisterSnapshotQuery, SystemErrorQuery, SystemVersionQuery},
+        types::{ErrorCode, StatusRegister, StatusRegisterSnapshot, SystemErrorResponse},
+    },
+    Command, Error, Io, Phase, Query, Transport,
+};
+
+/// Default maximum number of queries issued by [`drain_error_queue`]
+pub const DEFAULT_ERROR_QUEUE_LIMIT: usize = 64;
+
+/// Encodes the given command as a complete program message and writes it to the transport.
+pub fn send<T, C>(io: &mut T, command: &C) -> Result<(), Error>
+where
+    T: Transport,
+    C: Command,
+{
+    let mut encoder = Encoder::new(Io(io));
+    command
+        .encode(&mut encoder)
+        .and_then(|_| encoder.finish())
+        .map_err(|err| err.with_context(command.mnemonic(), Phase::Encode, None))?;
+    Ok(())
+}
+
+/// Encodes the given query as a complete program message, writes it to the transport, and decodes
+/// the response message.
+pub fn query<T, Q>(io: &mut T, query: &Q) -> Result<Q::ResponseData, Error>
+where
+    T: Transport,
+    Q: Query,
+{
+    let mut encoder = Encoder::new(Io(io));
+    query
+        .encode(&mut encoder)
+        .and_then(|_| encoder.finish())
+        .map_err(|err| err.with_context(query.mnemonic(), Phase::Encode, None))?;
+
+    let mut decoder = Decoder::new(Io(io));
+    let response = query
+        .decode(&mut decoder)
+        .map_err(|err| err.with_context(query.mnemonic(), Phase::Decode, Some(decoder.offset())))?;
+    let offset = decoder.offset();
+    decoder
+        .finish()
+        .map_err(|err| err.with_context(query.mnemonic(), Phase::Finish, Some(offset)))?;
+    Ok(response)
+}
+
+/// Sends the given command followed by an Operation Complete Query (*OPC?) in the same program
+/// message, and waits for the device to report that the operation has been completed.
+///
+/// Returns [`Error::OperationIncomplete`] if the device responds with anything else than `1`.
+pub fn send_and_wait_opc<T, C>(io: &mut T, command: &C) -> Result<(), Error>
+where
+    T: Transport,
+    C: Command,
+{
+    let mut encoder = Encoder::new(Io(io));
+    encode_with_opc(&mut encoder, command)
+        .and_then(|_| encoder.finish())
+        .map_err(|err| err.with_context(command.mnemonic(), Phase::Encode, None))?;
+
+    let opc = OperationCompleteQuery;
+    let mut decoder = Decoder::new(Io(io));
+    let complete = opc
+        .decode(&mut decoder)
+        .map_err(|err| err.with_context(opc.mnemonic(), Phase::Decode, Some(decoder.offset())))?;
+    let offset = decoder.offset();
+    decoder
+        .finish()
+        .map_err(|err| err.with_context(opc.mnemonic(), Phase::Finish, Some(offset)))?;
+    if complete {
+        Ok(())
+    } else {
+        Err(Error::OperationIncomplete)
+    }
+}
+
+/// Repeatedly sends the given query until the response satisfies the predicate, and returns the
+/// first such response.
+///
+/// The `between_polls` callback is called with the number of polls done so far after every
+/// response that doesn't satisfy the predicate. It can be used to sleep, yield, or give up polling
+/// by returning `ControlFlow::Break`, which leads to [`Error::PollAborted`].
+pub fn poll_until<T, Q, F, W>(
+    io: &mut T,
+    query: &Q,
+    mut predicate: F,
+    mut between_polls: W,
+) -> Result<Q::ResponseData, Error>
+where
+    T: Transport,
+    Q: Query,
+    F: FnMut(&Q::ResponseData) -> bool,
+    W: FnMut(u32) -> ControlFlow<()>,
+{
+    let mut polls = 0;
+    loop {
+        let response = self::query(io, query)?;
+        polls += 1;
+        if predicate(&response) {
+            break Ok(response);
+        }
+        if let ControlFlow::Break(()) = between_polls(polls) {
+            break Err(Error::PollAborted);
+        }
+    }
+}
+
+/// Reads the device error/event queue until it reports no error, and returns all the errors that
+/// were found.
+///
+/// At most [`DEFAULT_ERROR_QUEUE_LIMIT`] queries are issued. See [`drain_error_queue_with_limit`].
+///
+/// Reference: SCPI 1999.0: 21.8 - :ERRor Subsystem
+pub fn drain_error_queue<T>(io: &mut T) -> Result<Vec<SystemErrorResponse>, Error>
+where
+    T: Transport,
+{
+    drain_error_queue_with_limit(io, DEFAULT_ERROR_QUEUE_LIMIT)
+}
+
+/// Reads the device error/event queue until it reports no error, and returns all the errors that
+/// were found.
+///
+/// If the queue is still not empty after `limit` queries, [`Error::ErrorQueueLimit`] is returned.
+/// This protects against devices that never report an empty queue.
+///
+/// Reference: SCPI 1999.0: 21.8 - :ERRor Subsystem
+pub fn drain_error_queue_with_limit<T>(
+    io: &mut T,
+    limit: usize,
+) -> Result<Vec<SystemErrorResponse>, Error>
+where
+    T: Transport,
+{
+    let mut errors = Vec::new();
+    for _ in 0..limit {
+        let response = query(io, &SystemErrorQuery)?;
+        if response.code == ErrorCode::NoError {
+            return Ok(errors);
+        }
+        errors.push(response);
+    }
+    Err(Error::ErrorQueueLimit(limit))
+}
+
+/// Reads the condition, event and enable registers of the given status register with one
+/// compound query.
+///
+/// Reading the event register also clears it. See [`StatusRegisterSnapshotQuery`].
+pub fn status_snapshot<T>(
+    io: &mut T,
+    register: StatusRegister,
+) -> Result<StatusRegisterSnapshot, Error>
+where
+    T: Transport,
+{
+    query(io, &StatusRegisterSnapshotQuery(register))
+}
+
+/// Options for [`recover`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RecoverOptions {
+    /// Read the device error/event queue until it reports no error
+    pub drain_errors: bool,
+    /// Send a Clear Status command (*CLS)
+    pub clear_status: bool,
+}
+
+impl Default for RecoverOptions {
+    fn default() -> Self {
+        RecoverOptions {
+            drain_errors: true,
+            clear_status: true,
+        }
+    }
+}
+
+/// Recovers the transport to a known state after a protocol desynchronization (e.g. a failed
+/// response decode).
+///
+/// Input is first discarded until a response message terminator or a read error (e.g. a read
+/// timeout if there was nothing left to discard). The error queue is then optionally drained, and
+/// finally the status data structures are optionally cleared with *CLS. Draining is done before
+/// clearing, because *CLS also clears the error queue.
+///
+/// Returns the errors found in the error queue.
+pub fn recover<T>(io: &mut T, options: RecoverOptions) -> Result<Vec<SystemErrorResponse>, Error>
+where
+    T: Transport,
+{
+    let mut decoder = Decoder::new(Io(io));
+    let _ = decoder.resynchronize();
+
+    let errors = if options.drain_errors {
+        drain_error_queue(io)?
+    } else {
+        Vec::new()
+    };
+    if options.clear_status {
+        send(io, &ClearStatus)?;
+    }
+    Ok(errors)
+}
+
+/// Basic information about an instrument
+///
+/// Returned by [`probe`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstrumentInfo {
+    pub identification: DeviceIdentification,
+    /// SCPI version, or `None` if the instrument doesn't support :SYST:VERS?
+    pub scpi_version: Option<f32>,
+    /// Installed options, or `None` if the instrument doesn't support *OPT?
+    pub options: Option<String>,
+}
+
+/// Probes basic information about an instrument.
+///
+/// The identification (*IDN?) is mandatory, but SCPI version (:SYST:VERS?) and options (*OPT?) are
+/// not supported by all instruments. If one of those queries fails due to a decoding error or a
+/// read timeout, the result is `None` and the transport is recovered so that subsequent exchanges
+/// are not affected: the error queue is drained if the instrument supports SCPI, and the status
+/// data structures are cleared with *CLS.
+pub fn probe<T>(io: &mut T) -> Result<InstrumentInfo, Error>
+where
+    T: Transport,
+{
+    let identification = query(io, &IdentificationQuery)?;
+    let scpi_version = query_optional(io, &SystemVersionQuery, false)?;
+    let options = query_optional(io, &OptionIdentificationQuery, scpi_version.is_some())?;
+    Ok(InstrumentInfo {
+        identification,
+        scpi_version,
+        options: options.map(String::from),
+    })
+}
+
+fn query_optional<T, Q>(io: &mut T, query: &Q, scpi: bool) -> Result<Option<Q::ResponseData>, Error>
+where
+    T: Transport,
+    Q: Query,
+{
+    let mut encoder = Encoder::new(Io(io));
+    query
+        .encode(&mut encoder)
+        .and_then(|_| encoder.finish())
+        .map_err(|err| err.with_context(query.mnemonic(), Phase::Encode, None))?;
+
+    let mut decoder = Decoder::new(Io(io));
+    let result = query.decode(&mut decoder);
+    let offset = decoder.offset();
+    let result = result.and_then(|response| decoder.finish().map(|_| response));
+    match result {
+        Ok(response) => Ok(Some(response)),
+        Err(Error::Decode(_)) => {
+            recover_unsupported(io, scpi)?;
+            Ok(None)
+        }
+        Err(Error::Io(err))
+            if matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            ) =>
+        {
+            recover_unsupported(io, scpi)?;
+            Ok(None)
+        }
+        Err(err) => Err(err.with_context(query.mnemonic(), Phase::Decode, Some(offset))),
+    }
+}
+
+fn recover_unsupported<T>(io: &mut T, scpi: bool) -> Result<(), Error>
+where
+    T: Transport,
+{
+    let options = RecoverOptions {
+        drain_errors: scpi,
+        clear_status: true,
+    };
+    recover(io, options)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec::Vec};
+    use core::ops::ControlFlow;
+    use matches::assert_matches;
+
+    use super::{
+        drain_error_queue, drain_error_queue_with_limit, poll_until, probe, query, recover, send,
+        send_and_wait_opc, status_snapshot, RecoverOptions, DEFAULT_ERROR_QUEUE_LIMIT,
+    };
+    use crate::{
+        decode::DecodeError,
+        ieee::message::{IdentificationQuery, OpcTerminated, Reset, StatusByteQuery},
+        mock::{Expect, MockTransport},
+        scpi::{
+            message::{StatusOperationConditionQuery, StatusOperationEnable},
+            types::{StatusRegister, StatusRegisterSnapshot},
+        },
+        Error, ErrorCode, IoPair, OwnedIoPair, Phase, StandardErrorCode,
+    };
+
+    #[test]
+    fn query_over_separate_reader_and_writer() {
+        let mut reader = &b"ACME,M1,1234,1.0\n"[..];
+        let mut writer = Vec::new();
+        let mut io = IoPair(&mut reader, &mut writer);
+        send(&mut io, &Reset).unwrap();
+        let idn = query(&mut io, &IdentificationQuery).unwrap();
+        assert_eq!(idn.manufacturer, "ACME");
+        assert_eq!(idn.serial_number, "1234");
+        assert!(reader.is_empty());
+        assert_eq!(writer, b"*RST\n*IDN?\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn query_over_socket_pair() {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            os::unix::net::UnixStream,
+            thread,
+        };
+
+        let (client, instrument) = UnixStream::pair().unwrap();
+        let handle = thread::spawn(move || {
+            let mut writer = instrument.try_clone().unwrap();
+            let mut lines = BufReader::new(instrument).lines();
+            assert_eq!(lines.next().unwrap().unwrap(), "*RST");
+            assert_eq!(lines.next().unwrap().unwrap(), "*IDN?");
+            writer.write_all(b"ACME,M1,1234,1.0\n").unwrap();
+        });
+        let mut reader = client.try_clone().unwrap();
+        let mut writer = client;
+        let mut io = IoPair(&mut reader, &mut writer);
+        send(&mut io, &Reset).unwrap();
+        let idn = query(&mut io, &IdentificationQuery).unwrap();
+        assert_eq!(idn.model, "M1");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn errors_name_the_failing_query() {
+        let mut transport = MockTransport::new([
+            Expect::write(b":SYST:ERR?\n").then_respond(b"-100,\"Command error\"\n"),
+            Expect::write(b":SYST:ERR?\n").then_respond(b"x\n"),
+        ]);
+        let err = drain_error_queue_with_limit(&mut transport, 2).unwrap_err();
+        let context = err.context().expect("no context");
+        assert_eq!(context.mnemonic, ":SYST:ERR?");
+        assert_eq!(context.phase, Phase::Decode);
+        assert_eq!(context.offset, Some(1));
+        assert_eq!(
+            err.to_string(),
+            "while decoding response to \":SYST:ERR?\" at response offset 1: parse error"
+        );
+
+        let source = std::error::Error::source(&err).expect("no source");
+        assert_matches!(
+            source.downcast_ref::<Error>(),
+            Some(Error::Decode(DecodeError::Parse))
+        );
+        let options = RecoverOptions {
+            drain_errors: false,
+            clear_status: false,
+        };
+        assert_matches!(recover(&mut transport, options), Ok(_));
+    }
+
+    #[test]
+    fn errors_name_the_phase() {
+        let mut io = OwnedIoPair(&b""[..], &mut [0u8; 4][..]);
+        let err = send(&mut io, &Reset).unwrap_err();
+        let context = err.context().expect("no context");
+        assert_eq!((context.phase, context.offset), (Phase::Encode, None));
+        assert_matches!(context.source, Error::Io(_));
+        assert_eq!(
+            err.to_string(),
+            "while encoding \"*RST\": failed to write whole buffer"
+        );
+
+        let mut io = OwnedIoPair(&b"+1"[..], Vec::new());
+        let err = query(&mut io, &StatusOperationConditionQuery).unwrap_err();
+        let context = err.context().expect("no context");
+        assert_eq!((context.phase, context.offset), (Phase::Decode, Some(2)));
+        assert_matches!(context.source, Error::Io(_));
+
+        let mut io = OwnedIoPair(&b"+1,2\n"[..], Vec::new());
+        let err = query(&mut io, &StatusOperationConditionQuery).unwrap_err();
+        let context = err.context().expect("no context");
+        assert_eq!((context.phase, context.offset), (Phase::Finish, Some(3)));
+        assert_matches!(context.source, Error::Decode(_));
+    }
+
+    #[test]
+    fn query_over_owned_reader_and_writer() {
+        let mut io = OwnedIoPair(&b"+1\n"[..], Vec::new());
+        assert_matches!(query(&mut io, &StatusOperationConditionQuery), Ok(1));
+        assert_matches!(
+            query(&mut io, &StatusOperationConditionQuery)
+                .as_ref()
+                .map_err(Error::without_context),
+            Err(Error::Io(_))
+        );
+        assert_eq!(io.1, b":STAT:OPER:COND?\n:STAT:OPER:COND?\n");
+    }
+
+    #[test]
+    fn opc_query_is_appended_to_the_command() {
+        let mut io = MockTransport::new([Expect::write(b"*RST;*OPC?\n").then_respond(b"1\n")]);
+        assert_matches!(send_and_wait_opc(&mut io, &Reset), Ok(()));
+
+        let mut io =
+            MockTransport::new([Expect::write(b":STAT:OPER:ENAB 42;*OPC?\n").then_respond(b"1\n")]);
+        assert_matches!(
+            send_and_wait_opc(&mut io, &StatusOperationEnable(42)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn opc_terminated_query() {
+        let mut io = MockTransport::new([
+            Expect::write(b"*RST;:STAT:OPER:ENAB 1;*OPC?\n").then_respond(b"1\n")
+        ]);
+        assert_matches!(
+            query(&mut io, &OpcTerminated((Reset, StatusOperationEnable(1)))),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn opc_response_other_than_one_is_an_error() {
+        let mut io = MockTransport::new([Expect::write(b"*RST;*OPC?\n").then_respond(b"0\n")]);
+        assert_matches!(
+            send_and_wait_opc(&mut io, &Reset),
+            Err(Error::OperationIncomplete)
+        );
+    }
+
+    #[test]
+    fn polling_returns_first_matching_response() {
+        let mut io = MockTransport::new([
+            Expect::write(b":STAT:OPER:COND?\n").then_respond(b"16\n"),
+            Expect::write(b":STAT:OPER:COND?\n").then_respond(b"16\n"),
+            Expect::write(b":STAT:OPER:COND?\n").then_respond(b"24\n"),
+            Expect::write(b":STAT:OPER:COND?\n").then_respond(b"8\n"),
+        ]);
+        let mut attempts = Vec::new();
+        let result = poll_until(
+            &mut io,
+            &StatusOperationConditionQuery,
+            |&condition| condition & 0x10 == 0,
+            |attempt| {
+                attempts.push(attempt);
+                ControlFlow::Continue(())
+            },
+        );
+        assert_matches!(result, Ok(8));
+        assert_eq!(attempts, [1, 2, 3]);
+    }
+
+    #[test]
+    fn polling_can_be_aborted() {
+        let mut io = MockTransport::new([
+            Expect::write(b":STAT:OPER:COND?\n").then_respond(b"16\n"),
+            Expect::write(b":STAT:OPER:COND?\n").then_respond(b"16\n"),
+        ]);
+        let result = poll_until(
+            &mut io,
+            &StatusOperationConditionQuery,
+            |&condition| condition & 0x10 == 0,
+            |attempt| {
+                if attempt < 2 {
+                    ControlFlow::Continue(())
+                } else {
+                    ControlFlow::Break(())
+                }
+            },
+        );
+        assert_matches!(result, Err(Error::PollAborted));
+    }
+
+    #[test]
+    fn empty_queue_returns_no_errors() {
+        let mut io =
+            MockTransport::new([Expect::write(b":SYST:ERR?\n").then_respond(b"0,\"No error\"\n")]);
+        assert_matches!(drain_error_queue(&mut io).as_deref(), Ok([]));
+    }
+
+    #[test]
+    fn errors_are_collected_until_no_error() {
+        let mut io = MockTransport::new([
+            Expect::write(b":SYST:ERR?\n").then_respond(b"-113,\"Undefined header\"\n"),
+            Expect::write(b":SYST:ERR?\n").then_respond(b"-222,\"Data out of range\"\n"),
+            Expect::write(b":SYST:ERR?\n").then_respond(b"0,\"No error\"\n"),
+        ]);
+        let errors = drain_error_queue(&mut io).unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].code,
+            ErrorCode::Standard(StandardErrorCode::UndefinedHeader)
+        );
+        assert_eq!(errors[0].message, "Undefined header");
+        assert_eq!(
+            errors[1].code,
+            ErrorCode::Standard(StandardErrorCode::DataOutOfRange)
+        );
+        assert_eq!(errors[1].message, "Data out of range");
+    }
+
+    #[test]
+    fn never_ending_queue_hits_the_limit() {
+        let mut io = MockTransport::new(
+            (0..DEFAULT_ERROR_QUEUE_LIMIT)
+                .map(|_| Expect::write(b":SYST:ERR?\n").then_respond(b"-100,\"Command error\"\n")),
+        );
+        assert_matches!(
+            drain_error_queue(&mut io),
+            Err(Error::ErrorQueueLimit(DEFAULT_ERROR_QUEUE_LIMIT))
+        );
+    }
+
+    #[test]
+    fn recover_discards_leftover_response() {
+        let mut io = MockTransport::new([
+            Expect::respond(b"#15ga"),
+            Expect::respond(b"rbage\n"),
+            Expect::write(b":SYST:ERR?\n").then_respond(b"-410,\"Query INTERRUPTED\"\n"),
+            Expect::write(b":SYST:ERR?\n").then_respond(b"0,\"No error\"\n"),
+            Expect::write(b"*CLS\n"),
+            Expect::write(b"*STB?\n").then_respond(b"0\n"),
+        ]);
+        let errors = recover(&mut io, RecoverOptions::default()).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].code,
+            ErrorCode::Standard(StandardErrorCode::QueryInterrupted)
+        );
+        assert_matches!(super::query(&mut io, &StatusByteQuery), Ok(0));
+    }
+
+    #[test]
+    fn recover_tolerates_nothing_to_discard() {
+        let mut io = MockTransport::new([
+            Expect::write(b":SYST:ERR?\n").then_respond(b"0,\"No error\"\n"),
+            Expect::write(b"*CLS\n"),
+        ]);
+        assert_matches!(
+            recover(&mut io, RecoverOptions::default()).as_deref(),
+            Ok([])
+        );
+    }
+
+    #[test]
+    fn recover_steps_are_optional() {
+        let mut io = MockTransport::new([Expect::respond(b"garbage\n")]);
+        let options = RecoverOptions {
+            drain_errors: false,
+            clear_status: false,
+        };
+        assert_matches!(recover(&mut io, options).as_deref(), Ok([]));
+    }
+
+    #[test]
+    fn status_snapshot_is_one_compound_query() {
+        let mut transport = MockTransport::new([
+            Expect::write(b":STAT:OPER:COND?;:STAT:OPER?;:STAT:OPER:ENAB?\n")
+                .then_respond(b"16;+272;256\n"),
+            Expect::write(
+                b":STAT:QUES:INST:ISUM2:COND?;:STAT:QUES:INST:ISUM2?;:STAT:QUES:INST:ISUM2:ENAB?\n",
+            )
+            .then_respond(b"0;0;32767\n"),
+        ]);
+        assert_eq!(
+            status_snapshot(&mut transport, StatusRegister::Operation).unwrap(),
+            StatusRegisterSnapshot {
+                condition: 16,
+                event: 272,
+                enable: 256,
+            }
+        );
+        let snapshot = status_snapshot(
+            &mut transport,
+            StatusRegister::Path(":STAT:QUES:INST:ISUM2"),
+        )
+        .unwrap();
+        assert_eq!(snapshot.enable, 32767);
+        assert_eq!(snapshot.summarize().enabled_not_set, 32767);
+    }
+
+    #[test]
+    fn status_snapshot_requires_three_responses() {
+        let mut transport =
+            MockTransport::new([
+                Expect::write(b":STAT:QUES:COND?;:STAT:QUES?;:STAT:QUES:ENAB?\n")
+                    .then_respond(b"0;1\n"),
+            ]);
+        let err = status_snapshot(&mut transport, StatusRegister::Questionable).unwrap_err();
+        assert_eq!(err.context().expect("no context").mnemonic, ":STAT:QUES");
+    }
+
+    #[test]
+    fn probe_scpi_instrument() {
+        let mut io = MockTransport::new([
+            Expect::write(b"*IDN?\n").then_respond(b"ACME,M1,1234,1.0\n"),
+            Expect::write(b":SYST:VERS?\n").then_respond(b"1999.0\n"),
+            Expect::write(b"*OPT?\n").then_respond(b"OPT1,OPT2\n"),
+        ]);
+        let info = probe(&mut io).unwrap();
+        assert_eq!(info.identification.manufacturer, "ACME");
+        assert_eq!(info.identification.serial_number, "1234");
+        assert_eq!(info.scpi_version, Some(1999.0));
+        assert_eq!(info.options.as_deref(), Some("OPT1,OPT2"));
+    }
+
+    #[test]
+    fn probe_scpi_instrument_without_options() {
+        let mut io = MockTransport::new([
+            Expect::write(b"*IDN?\n").then_respond(b"ACME,M1,1234,1.0\n"),
+            Expect::write(b":SYST:VERS?\n").then_respond(b"1999.0\n"),
+            Expect::write(b"*OPT?\n"),
+            Expect::write(b":SYST:ERR?\n").then_respond(b"-113,\"Undefined header\"\n"),
+            Expect::write(b":SYST:ERR?\n").then_respond(b"0,\"No error\"\n"),
+            Expect::write(b"*CLS\n"),
+        ]);
+        let info = probe(&mut io).unwrap();
+        assert_eq!(info.scpi_version, Some(1999.0));
+        assert_eq!(info.options, None);
+    }
+
+    #[test]
+    fn probe_ieee_488_2_only_instrument() {
+        let mut io = MockTransport::new([
+            Expect::write(b"*IDN?\n").then_respond(b"ACME,M1,0,1.0\n"),
+            Expect::write(b":SYST:VERS?\n"),
+            Expect::write(b"*CLS\n"),
+            Expect::write(b"*OPT?\n").then_respond(b"\xff\n"),
+            Expect::write(b"*CLS\n"),
+            Expect::write(b"*STB?\n").then_respond(b"0\n"),
+        ]);
+        let info = probe(&mut io).unwrap();
+        assert_eq!(info.identification.model, "M1");
+        assert_eq!(info.scpi_version, None);
+        assert_eq!(info.options, None);
+        assert_matches!(super::query(&mut io, &StatusByteQuery), Ok(0));
+    }
+}
@@ -0,0 +1,483 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+use std::io::{self, Read};
+
+use crate::{
+    encode::{EncodeSink, PROGRAM_MESSAGE_TERMINATOR},
+    ByteSink, ByteSource, Error,
+};
+
+/// HiSLIP protocol version 1.0, major version in the high byte
+pub const PROTOCOL_VERSION: u16 = 0x0100;
+/// Vendor ID sent by the client in the Initialize message
+pub const CLIENT_VENDOR_ID: [u8; 2] = *b"RS";
+
+const PROLOGUE: [u8; 2] = *b"HS";
+const HEADER_LEN: usize = 16;
+/// The first MessageID used by a client
+const INITIAL_MESSAGE_ID: u32 = 0xffff_ff00;
+
+const INITIALIZE: u8 = 0;
+const INITIALIZE_RESPONSE: u8 = 1;
+const FATAL_ERROR: u8 = 2;
+const ERROR: u8 = 3;
+const DATA: u8 = 6;
+const DATA_END: u8 = 7;
+const INTERRUPTED: u8 = 13;
+
+/// Error returned for HiSLIP protocol errors
+///
+/// Wrapped in an `io::Error` with kind `InvalidData`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HislipError {
+    /// The server sent a FatalError message, and closed the connection
+    Fatal { code: u8, message: String },
+    /// The server sent an Error message
+    Error { code: u8, message: String },
+    /// A message doesn't start with the `HS` prologue
+    InvalidPrologue,
+    /// The server sent a message that is not valid on the synchronous channel at this point
+    UnexpectedMessage { message_type: u8 },
+}
+
+impl fmt::Display for HislipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HislipError::Fatal { code, message } => {
+                write!(f, "HiSLIP fatal error {}: {}", code, message)
+            }
+            HislipError::Error { code, message } => {
+                write!(f, "HiSLIP error {}: {}", code, message)
+            }
+            HislipError::InvalidPrologue => write!(f, "invalid HiSLIP message prologue"),
+            HislipError::UnexpectedMessage { message_type } => {
+                write!(f, "unexpected HiSLIP message type {}", message_type)
+            }
+        }
+    }
+}
+
+impl core::error::Error for HislipError {}
+
+impl From<HislipError> for io::Error {
+    fn from(err: HislipError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Header {
+    message_type: u8,
+    control_code: u8,
+    parameter: u32,
+    payload_len: u64,
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0..2].copy_from_slice(&PROLOGUE);
+        bytes[2] = self.message_type;
+        bytes[3] = self.control_code;
+        bytes[4..8].copy_from_slice(&self.parameter.to_be_bytes());
+        bytes[8..16].copy_from_slice(&self.payload_len.to_be_bytes());
+        bytes
+    }
+    fn from_bytes(bytes: [u8; HEADER_LEN]) -> Result<Header, HislipError> {
+        if bytes[0..2] != PROLOGUE {
+            return Err(HislipError::InvalidPrologue);
+        }
+        let mut parameter = [0; 4];
+        parameter.copy_from_slice(&bytes[4..8]);
+        let mut payload_len = [0; 8];
+        payload_len.copy_from_slice(&bytes[8..16]);
+        Ok(Header {
+            message_type: bytes[2],
+            control_code: bytes[3],
+            parameter: u32::from_be_bytes(parameter),
+            payload_len: u64::from_be_bytes(payload_len),
+        })
+    }
+}
+
+fn write_message(
+    writer: &mut impl io::Write,
+    message_type: u8,
+    control_code: u8,
+    parameter: u32,
+    payload: &[u8],
+) -> Result<(), Error> {
+    let header = Header {
+        message_type,
+        control_code,
+        parameter,
+        payload_len: payload.len() as u64,
+    };
+    writer.write_all(&header.to_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// The synchronous channel of a HiSLIP connection
+///
+/// A program message is collected into a buffer and sent as one DataEnd message once its
+/// terminator has been encoded. Response bytes are read from the payloads of Data and DataEnd
+/// messages, and a DataEnd message ends the response message. If its payload doesn't end with a
+/// newline, one is added so the [`Decoder`](crate::decode::Decoder) sees a response message
+/// terminator.
+///
+/// Responses with a MessageID that doesn't match the last sent program message are stale, for
+/// example after a device clear, and are discarded. Error and FatalError messages from the server
+/// are returned as a [`HislipError`] wrapped in an `io::Error`.
+///
+/// Only the synchronous channel is implemented. The asynchronous channel, locking, and service
+/// requests are not supported, so servers that require both channels to be established before
+/// data transfer can't be used.
+///
+/// Reference: IVI-6.1: IVI High-Speed LAN Instrument Protocol (HiSLIP)
+pub struct HislipClient<T> {
+    transport: T,
+    session_id: u16,
+    server_protocol_version: u16,
+    overlap_mode: bool,
+    message_id: u32,
+    last_sent: Option<u32>,
+    rmt_delivered: bool,
+    write_buffer: Vec<u8>,
+    read_buffer: Vec<u8>,
+    read_pos: usize,
+    read_ended: bool,
+}
+
+impl<T> HislipClient<T>
+where
+    T: io::Read + io::Write,
+{
+    /// Initializes the synchronous channel with the Initialize / InitializeResponse handshake.
+    ///
+    /// The sub-address selects the device, e.g. `hislip0`.
+    pub fn connect(transport: T, sub_address: &str) -> Result<HislipClient<T>, Error> {
+        let mut client = HislipClient {
+            transport,
+            session_id: 0,
+            server_protocol_version: 0,
+            overlap_mode: false,
+            message_id: INITIAL_MESSAGE_ID,
+            last_sent: None,
+            rmt_delivered: false,
+            write_buffer: Vec::new(),
+            read_buffer: Vec::new(),
+            read_pos: 0,
+            read_ended: false,
+        };
+        let parameter =
+            u32::from(PROTOCOL_VERSION) << 16 | u32::from(u16::from_be_bytes(CLIENT_VENDOR_ID));
+        write_message(
+            &mut client.transport,
+            INITIALIZE,
+            0,
+            parameter,
+            sub_address.as_bytes(),
+        )?;
+        let header = client.receive_header()?;
+        match header.message_type {
+            INITIALIZE_RESPONSE => {
+                client.skip_payload(header.payload_len)?;
+                client.overlap_mode = header.control_code & 1 != 0;
+                client.server_protocol_version = (header.parameter >> 16) as u16;
+                client.session_id = header.parameter as u16;
+                Ok(client)
+            }
+            _ => Err(client.unexpected(header)?),
+        }
+    }
+    pub fn session_id(&self) -> u16 {
+        self.session_id
+    }
+    pub fn server_protocol_version(&self) -> u16 {
+        self.server_protocol_version
+    }
+    /// Returns true if the server uses overlapped mode.
+    pub fn overlap_mode(&self) -> bool {
+        self.overlap_mode
+    }
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+    fn receive_header(&mut self) -> Result<Header, Error> {
+        let mut bytes = [0; HEADER_LEN];
+        self.transport.read_exact(&mut bytes)?;
+        Ok(Header::from_bytes(bytes).map_err(io::Error::from)?)
+    }
+    fn receive_payload(&mut self, len: u64) -> Result<Vec<u8>, Error> {
+        let mut payload = Vec::new();
+        (&mut self.transport).take(len).read_to_end(&mut payload)?;
+        if (payload.len() as u64) < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(payload)
+    }
+    fn skip_payload(&mut self, len: u64) -> Result<(), Error> {
+        let skipped = io::copy(&mut (&mut self.transport).take(len), &mut io::sink())?;
+        if skipped < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(())
+    }
+    /// Reads the payload of an unexpected message, and returns the matching error.
+    fn unexpected(&mut self, header: Header) -> Result<Error, Error> {
+        let payload = self.receive_payload(header.payload_len)?;
+        let message = String::from_utf8_lossy(&payload).into_owned();
+        let code = header.control_code;
+        let err = match header.message_type {
+            FATAL_ERROR => HislipError::Fatal { code, message },
+            ERROR => HislipError::Error { code, message },
+            message_type => HislipError::UnexpectedMessage { message_type },
+        };
+        Ok(io::Error::from(err).into())
+    }
+    fn receive_data(&mut self) -> Result<(), Error> {
+        loop {
+            let header = self.receive_header()?;
+            match header.message_type {
+                DATA | DATA_END => {
+                    let payload = self.receive_payload(header.payload_len)?;
+                    if self
+                        .last_sent
+                        .is_some_and(|message_id| header.parameter != message_id)
+                    {
+                        continue;
+                    }
+                    self.read_buffer = payload;
+                    self.read_pos = 0;
+                    self.read_ended = header.message_type == DATA_END;
+                    if self.read_ended {
+                        if self.read_buffer.is_empty() {
+                            // END without data can't be reported with a byte, so report it with a
+                            // terminator
+                            self.read_buffer.push(b'\n');
+                        }
+                        self.rmt_delivered = true;
+                    }
+                    return Ok(());
+                }
+                INTERRUPTED => self.skip_payload(header.payload_len)?,
+                _ => return Err(self.unexpected(header)?),
+            }
+        }
+    }
+}
+
+impl<T> ByteSource for HislipClient<T>
+where
+    T: io::Read + io::Write,
+{
+    type Error = Error;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        self.read_byte_with_end().map(|(byte, _)| byte)
+    }
+    /// Reports END with the last byte of a DataEnd message.
+    fn read_byte_with_end(&mut self) -> Result<(u8, bool), Self::Error> {
+        while self.read_pos == self.read_buffer.len() {
+            self.receive_data()?;
+        }
+        let byte = self.read_buffer[self.read_pos];
+        self.read_pos += 1;
+        Ok((
+            byte,
+            self.read_ended && self.read_pos == self.read_buffer.len(),
+        ))
+    }
+}
+
+impl<T> ByteSink for HislipClient<T>
+where
+    T: io::Read + io::Write,
+{
+    type Error = Error;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.write_buffer.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl<T> EncodeSink for HislipClient<T>
+where
+    T: io::Read + io::Write,
+{
+    /// Sends the buffered program message and its terminator as a DataEnd message.
+    fn terminate_message(&mut self) -> Result<(), Self::Error> {
+        self.write_buffer.push(PROGRAM_MESSAGE_TERMINATOR);
+        let message_id = self.message_id;
+        let result = write_message(
+            &mut self.transport,
+            DATA_END,
+            self.rmt_delivered.into(),
+            message_id,
+            &self.write_buffer,
+        );
+        self.write_buffer.clear();
+        result?;
+        self.last_sent = Some(message_id);
+        self.message_id = message_id.wrapping_add(2);
+        self.rmt_delivered = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec::Vec};
+    use matches::assert_matches;
+
+    use super::{Header, HislipClient, HislipError, DATA, DATA_END, ERROR, FATAL_ERROR};
+    use crate::{
+        decode::Decoder,
+        encode::Encoder,
+        ieee::message::{IdentificationQuery, OperationCompleteQuery},
+        mock::{Expect, MockTransport},
+        ByteSource, Error, Query,
+    };
+
+    fn message(message_type: u8, control_code: u8, parameter: u32, payload: &[u8]) -> Vec<u8> {
+        let header = Header {
+            message_type,
+            control_code,
+            parameter,
+            payload_len: payload.len() as u64,
+        };
+        [&header.to_bytes()[..], payload].concat()
+    }
+
+    fn handshake() -> Expect {
+        // Version 1.0 and vendor ID "RS", session ID 0x1234 and overlap mode
+        Expect::write(message(0, 0, 0x0100_5253, b"hislip0")).then_respond(message(
+            1,
+            1,
+            0x0100_1234,
+            b"",
+        ))
+    }
+
+    fn query<Q: Query>(
+        client: &mut HislipClient<&mut MockTransport>,
+        query: &Q,
+    ) -> Result<Q::ResponseData, Error> {
+        let mut encoder = Encoder::new(&mut *client);
+        query.encode(&mut encoder)?;
+        encoder.finish()?;
+        let mut decoder = Decoder::new(&mut *client);
+        let response = query.decode(&mut decoder)?;
+        decoder.finish()?;
+        Ok(response)
+    }
+
+    #[test]
+    fn initialize() {
+        let mut transport = MockTransport::new([handshake()]);
+        let client = HislipClient::connect(&mut transport, "hislip0").unwrap();
+        assert_eq!(client.session_id(), 0x1234);
+        assert_eq!(client.server_protocol_version(), 0x0100);
+        assert!(client.overlap_mode());
+    }
+
+    #[test]
+    fn query_split_across_data_messages() {
+        let mut transport = MockTransport::new([
+            handshake(),
+            Expect::write(message(DATA_END, 0, 0xffff_ff00, b"*IDN?\n"))
+                .then_respond(message(DATA, 0, 0xffff_ff00, b"ACME,PSU1"))
+                .then_respond(message(DATA_END, 0, 0xffff_ff00, b",0,1.0\n")),
+            // RMT delivered, and a response without a newline
+            Expect::write(message(DATA_END, 1, 0xffff_ff02, b"*OPC?\n")).then_respond(message(
+                DATA_END,
+                0,
+                0xffff_ff02,
+                b"1",
+            )),
+        ]);
+        let mut client = HislipClient::connect(&mut transport, "hislip0").unwrap();
+        let identification = query(&mut client, &IdentificationQuery).unwrap();
+        assert_eq!(identification.model, "PSU1");
+        assert_eq!(identification.firmware_level, "1.0");
+        assert!(query(&mut client, &OperationCompleteQuery).unwrap());
+    }
+
+    #[test]
+    fn end_is_reported_with_the_last_byte() {
+        let mut transport = MockTransport::new([
+            handshake(),
+            Expect::write(message(DATA_END, 0, 0xffff_ff00, b"*IDN?\n"))
+                .then_respond(message(DATA, 0, 0xffff_ff00, b"A\n"))
+                .then_respond(message(DATA_END, 0, 0xffff_ff00, b"B"))
+                .then_respond(message(DATA_END, 0, 0xffff_ff00, b"")),
+        ]);
+        let mut client = HislipClient::connect(&mut transport, "hislip0").unwrap();
+        let mut encoder = Encoder::new(&mut client);
+        IdentificationQuery.encode(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(client.read_byte_with_end().unwrap(), (b'A', false));
+        assert_eq!(client.read_byte_with_end().unwrap(), (b'\n', false));
+        assert_eq!(client.read_byte_with_end().unwrap(), (b'B', true));
+        assert_eq!(client.read_byte_with_end().unwrap(), (b'\n', true));
+    }
+
+    #[test]
+    fn stale_responses_are_discarded() {
+        let mut transport = MockTransport::new([
+            handshake(),
+            Expect::write(message(DATA_END, 0, 0xffff_ff00, b"*OPC?\n"))
+                .then_respond(message(DATA_END, 0, 0xffff_fe00, b"0\n"))
+                .then_respond(message(DATA_END, 0, 0xffff_ff00, b"1\n")),
+        ]);
+        let mut client = HislipClient::connect(&mut transport, "hislip0").unwrap();
+        assert!(query(&mut client, &OperationCompleteQuery).unwrap());
+    }
+
+    #[test]
+    fn error_messages() {
+        let mut transport = MockTransport::new([
+            handshake(),
+            Expect::write(message(DATA_END, 0, 0xffff_ff00, b"*OPC?\n")).then_respond(message(
+                ERROR,
+                4,
+                0,
+                b"Message too large",
+            )),
+        ]);
+        let mut client = HislipClient::connect(&mut transport, "hislip0").unwrap();
+        let err = query(&mut client, &OperationCompleteQuery).unwrap_err();
+        assert_eq!(err.to_string(), "HiSLIP error 4: Message too large");
+        assert_matches!(
+            err,
+            Error::Io(err) if err.get_ref().and_then(|err| err.downcast_ref()) == Some(&HislipError::Error {
+                code: 4,
+                message: "Message too large".into(),
+            })
+        );
+
+        let mut transport =
+            MockTransport::new([Expect::write(message(0, 0, 0x0100_5253, b"hislip9"))
+                .then_respond(message(FATAL_ERROR, 4, 0, b"Too many clients"))]);
+        let err = HislipClient::connect(&mut transport, "hislip9")
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "HiSLIP fatal error 4: Too many clients");
+
+        let mut transport =
+            MockTransport::new([Expect::write(message(0, 0, 0x0100_5253, b"hislip0"))
+                .then_respond(b"XX")
+                .then_respond([0; 14])]);
+        let err = HislipClient::connect(&mut transport, "hislip0")
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "invalid HiSLIP message prologue");
+    }
+}
@@ -2,12 +2,20 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+#[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 
 use crate::{
-    ieee::types::{DeviceIdentification, MacroList, StandardEventStatus, StatusByte},
+    decode::Decoder,
+    encode::{EncodeSink, Encoder},
+    ieee::types::{StandardEventStatus, StatusByte},
     internal::{declare_tuple_command, declare_tuple_query},
-    ArbitraryAscii, Command,
+    ByteSource, Command, CommandSequence, ProtocolObserver, Query,
+};
+#[cfg(feature = "alloc")]
+use crate::{
+    ieee::types::{DeviceIdentification, MacroList},
+    ArbitraryAscii,
 };
 
 // Mandatory IEEE 488.2 commands
@@ -44,6 +52,7 @@ declare_tuple_query! {
     pub struct StandardEventStatusRegisterQuery<"*ESR?", StandardEventStatus>;
 }
 
+#[cfg(feature = "alloc")]
 declare_tuple_query! {
     /// IEEE 488.2 Identification Query
     ///
@@ -142,6 +151,7 @@ declare_tuple_command! {
     pub struct DefineDeviceTrigger<'a, "DDT">(pub &'a [u8]);
 }
 
+#[cfg(feature = "alloc")]
 declare_tuple_query! {
     /// IEEE 488.2 Define Device Trigger Query
     ///
@@ -195,6 +205,7 @@ declare_tuple_query! {
     pub struct EnableMacrosQuery<"*EMC?", bool>;
 }
 
+#[cfg(feature = "alloc")]
 declare_tuple_query! {
     /// IEEE 488.2 Get Macro Contents Query
     ///
@@ -211,6 +222,7 @@ declare_tuple_query! {
     pub struct IndividualStatusQuery<"*IST?", bool>;
 }
 
+#[cfg(feature = "alloc")]
 declare_tuple_query! {
     /// IEEE 488.2 Learn Macro Query
     ///
@@ -219,6 +231,7 @@ declare_tuple_query! {
     pub struct LearnMacroQuery<"*LMC?", MacroList>;
 }
 
+#[cfg(feature = "alloc")]
 declare_tuple_query! {
     /// IEEE 488.2 Option Identification Query
     ///
@@ -296,6 +309,7 @@ declare_tuple_command! {
     pub struct ProtectedUserData<'a, "*PUD">(pub &'a [u8]);
 }
 
+#[cfg(feature = "alloc")]
 declare_tuple_query! {
     /// IEEE 488.2 Protected User Data Query
     ///
@@ -320,6 +334,7 @@ declare_tuple_command! {
     pub struct ResourceDescriptionTransfer<'a, "*RDT">(pub &'a [u8]);
 }
 
+#[cfg(feature = "alloc")]
 declare_tuple_query! {
     /// IEEE 488.2 Resource Description Transfer Query
     ///
@@ -359,3 +374,139 @@ declare_tuple_command! {
     #[derive(Copy, Clone, Debug)]
     pub struct SaveDefaultDeviceSettings<"*SDS">(pub u32);
 }
+
+/// Encodes the given command followed by an Operation Complete Query (*OPC?) as two message units
+/// of the same program message.
+///
+/// The device responds with a single boolean once the command has been completed, which can be
+/// decoded with [`OperationCompleteQuery`].
+///
+/// Reference: IEEE 488.2: 12.5.3 - Operation Complete Query
+pub fn encode_with_opc<S: EncodeSink, C: Command>(
+    encoder: &mut Encoder<S>,
+    command: &C,
+) -> Result<(), S::Error> {
+    command.encode(encoder)?;
+    OperationCompleteQuery.encode(encoder)
+}
+
+/// One or more commands followed by an Operation Complete Query (*OPC?) in the same program
+/// message
+///
+/// The commands are encoded as message units before `*OPC?`, and the device responds with a single
+/// boolean once all of them have been completed. Unlike [`encode_with_opc`], this is a [`Query`],
+/// so it works with anything that sends queries.
+///
+/// ```
+/// use red_sculpin::{
+///     ieee::message::{OpcTerminated, Reset},
+///     scpi::message::StatusOperationEnable,
+///     Query,
+/// };
+///
+/// let mut buf = [0; 64];
+/// let len = OpcTerminated(Reset).encode_to_slice(&mut buf).unwrap();
+/// assert_eq!(&buf[..len], b"*RST;*OPC?\n");
+/// let len = OpcTerminated((Reset, StatusOperationEnable(1)))
+///     .encode_to_slice(&mut buf)
+///     .unwrap();
+/// assert_eq!(&buf[..len], b"*RST;:STAT:OPER:ENAB 1;*OPC?\n");
+/// assert_eq!(OpcTerminated(Reset).decode_from_slice(b"1\n"), Ok(true));
+/// ```
+///
+/// Reference: IEEE 488.2: 12.5.3 - Operation Complete Query
+#[derive(Copy, Clone, Debug)]
+pub struct OpcTerminated<C>(pub C);
+
+impl<C: CommandSequence> Query for OpcTerminated<C> {
+    type ProgramData = ();
+    type ResponseData = bool;
+    fn mnemonic(&self) -> &str {
+        OperationCompleteQuery.mnemonic()
+    }
+    fn program_data(&self) -> Self::ProgramData {}
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        self.0.encode_units(encoder)?;
+        OperationCompleteQuery.encode(encoder)
+    }
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        &self,
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<bool, S::Error> {
+        OperationCompleteQuery.decode(decoder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::{ClearStatus, OpcTerminated, Reset};
+    use crate::{
+        decode::{DecodeError, Decoder},
+        scpi::message::StatusOperationEnable,
+        Query,
+    };
+
+    fn encode<Q: Query>(query: Q) -> ([u8; 128], usize) {
+        let mut buf = [0; 128];
+        let len = query.encode_to_slice(&mut buf).unwrap();
+        (buf, len)
+    }
+
+    #[test]
+    fn opc_terminated_encode() {
+        let (buf, len) = encode(OpcTerminated(Reset));
+        assert_eq!(&buf[..len], b"*RST;*OPC?\n");
+        let (buf, len) = encode(OpcTerminated(StatusOperationEnable(42)));
+        assert_eq!(&buf[..len], b":STAT:OPER:ENAB 42;*OPC?\n");
+        let (buf, len) = encode(OpcTerminated((
+            ClearStatus,
+            StatusOperationEnable(1),
+            Reset,
+        )));
+        assert_eq!(&buf[..len], b"*CLS;:STAT:OPER:ENAB 1;*RST;*OPC?\n");
+        let (buf, len) = encode(OpcTerminated([
+            StatusOperationEnable(1),
+            StatusOperationEnable(2),
+        ]));
+        assert_eq!(&buf[..len], b":STAT:OPER:ENAB 1;:STAT:OPER:ENAB 2;*OPC?\n");
+        let (buf, len) = encode(OpcTerminated(&[ClearStatus][..]));
+        assert_eq!(&buf[..len], b"*CLS;*OPC?\n");
+        let (buf, len) = encode(OpcTerminated((
+            ClearStatus,
+            Reset,
+            ClearStatus,
+            Reset,
+            ClearStatus,
+            Reset,
+            ClearStatus,
+            Reset,
+            ClearStatus,
+            Reset,
+            ClearStatus,
+            Reset,
+        )));
+        assert_eq!(
+            &buf[..len],
+            b"*CLS;*RST;*CLS;*RST;*CLS;*RST;*CLS;*RST;*CLS;*RST;*CLS;*RST;*OPC?\n"
+        );
+    }
+
+    #[test]
+    fn opc_terminated_decode() {
+        let query = OpcTerminated(Reset);
+        assert_eq!(query.mnemonic(), "*OPC?");
+        assert_eq!(query.decode_from_slice(b"1\n"), Ok(true));
+        assert_eq!(query.decode_from_slice(b"0\n"), Ok(false));
+        assert_matches!(query.decode_from_slice(b"1;1\n"), Err(_));
+
+        let mut decoder = Decoder::new(&b"*OPC 1\n"[..]);
+        decoder.set_echoed_headers(true);
+        assert_eq!(query.decode(&mut decoder), Ok(true));
+        assert_matches!(
+            OpcTerminated(Reset).decode_from_slice(b"2\n"),
+            Err(DecodeError::Parse)
+        );
+    }
+}
@@ -2,15 +2,18 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+#[cfg(feature = "alloc")]
 use alloc::{borrow::ToOwned, string::String, vec::Vec};
 use bitflags::bitflags;
 
+#[cfg(feature = "alloc")]
+use crate::ArbitraryAscii;
 use crate::{
     decode::{DecodeError, Decoder},
     encode::{EncodeSink, Encoder},
     program_data::ProgramData,
     response_data::ResponseData,
-    ArbitraryAscii, ByteSource,
+    ByteSource, ProtocolObserver,
 };
 
 /// IEEE 488.2 Device identification response
@@ -18,6 +21,7 @@ use crate::{
 /// Returned by Identification Query (*IDN?).
 ///
 /// Reference: IEEE 488.2: 10.14 - *IDN?, Identification Query
+#[cfg(feature = "alloc")]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct DeviceIdentification {
     pub manufacturer: String,
@@ -26,6 +30,7 @@ pub struct DeviceIdentification {
     pub firmware_level: String,
 }
 
+#[cfg(feature = "alloc")]
 impl DeviceIdentification {
     pub fn from_response(text: &str) -> Option<Self> {
         let mut iter = text.split(',').map(|field| match field.trim() {
@@ -54,10 +59,13 @@ impl DeviceIdentification {
     }
 }
 
+#[cfg(feature = "alloc")]
 impl ResponseData for DeviceIdentification {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         let text: String = ArbitraryAscii::decode(decoder)?.into();
-        DeviceIdentification::from_response(&text).ok_or_else(|| DecodeError::Parse.into())
+        DeviceIdentification::from_response(&text).ok_or_else(|| decoder.error(DecodeError::Parse))
     }
 }
 
@@ -66,11 +74,15 @@ impl ResponseData for DeviceIdentification {
 // Returned by Learn Macro Query (*LMC?).
 //
 // Reference: IEEE 488.2: 10.16 - *LMC?, Learn Macro Query
+#[cfg(feature = "alloc")]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct MacroList(pub Vec<String>);
 
+#[cfg(feature = "alloc")]
 impl ResponseData for MacroList {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         let mut labels = Vec::new();
         let first = String::decode(decoder)?;
         if first.is_empty() {
@@ -134,9 +146,11 @@ impl ProgramData for StandardEventStatus {
 }
 
 impl ResponseData for StandardEventStatus {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         let value = u16::decode(decoder)?;
-        StandardEventStatus::from_bits(value).ok_or_else(|| DecodeError::Parse.into())
+        StandardEventStatus::from_bits(value).ok_or_else(|| decoder.error(DecodeError::Parse))
     }
 }
 
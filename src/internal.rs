@@ -5,7 +5,7 @@
 use core::{
     fmt,
     num::{ParseFloatError, ParseIntError},
-    str,
+    ops, str,
 };
 
 macro_rules! declare_tuple_command {
@@ -98,53 +98,27 @@ macro_rules! declare_tuple_query {
     }
 }
 
-pub(crate) use declare_tuple_command;
-pub(crate) use declare_tuple_query;
-
-pub struct ArrayBuffer<const LEN: usize> {
-    buffer: [u8; LEN],
-    written: usize,
-}
-
-#[derive(Debug)]
-pub struct ArrayBufferFull;
-
-impl<const LEN: usize> ArrayBuffer<LEN> {
-    pub fn new() -> ArrayBuffer<LEN> {
-        ArrayBuffer {
-            buffer: [0; LEN],
-            written: 0,
-        }
-    }
-    pub fn push(&mut self, byte: u8) -> Result<(), ArrayBufferFull> {
-        if self.written < self.buffer.len() {
-            self.buffer[self.written] = byte;
-            self.written += 1;
-            Ok(())
-        } else {
-            Err(ArrayBufferFull)
-        }
-    }
-    pub fn push_all(&mut self, bytes: &[u8]) -> Result<(), ArrayBufferFull> {
-        if self.written + bytes.len() <= self.buffer.len() {
-            self.buffer[self.written..(self.written + bytes.len())].copy_from_slice(bytes);
-            self.written += bytes.len();
-            Ok(())
-        } else {
-            Err(ArrayBufferFull)
-        }
-    }
-    pub fn finish(&mut self) -> &mut [u8] {
-        &mut self.buffer[0..self.written]
-    }
+/// Invokes the given macro for tuples of 2 to 12 elements, with the index and the type parameter
+/// of each element.
+macro_rules! impl_tuples {
+    ($mac:ident) => {
+        $mac!(0 A, 1 B);
+        $mac!(0 A, 1 B, 2 C);
+        $mac!(0 A, 1 B, 2 C, 3 D);
+        $mac!(0 A, 1 B, 2 C, 3 D, 4 E);
+        $mac!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F);
+        $mac!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G);
+        $mac!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H);
+        $mac!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I);
+        $mac!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J);
+        $mac!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J, 10 K);
+        $mac!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J, 10 K, 11 L);
+    };
 }
 
-impl<const LEN: usize> fmt::Write for ArrayBuffer<LEN> {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        let bytes = s.as_bytes();
-        self.push_all(bytes).map_err(|_| fmt::Error)
-    }
-}
+pub(crate) use declare_tuple_command;
+pub(crate) use declare_tuple_query;
+pub(crate) use impl_tuples;
 
 pub trait Integer: Sized + Copy + Default + fmt::Display {
     fn from_str_radix(s: &str, radix: u32) -> Result<Self, ParseIntError>;
@@ -222,7 +196,24 @@ impl Integer for isize {
     }
 }
 
-pub trait Float: Sized + Copy + Default + fmt::UpperExp {
+/// Integers that can be encoded in the unsigned hexadecimal, octal and binary formats
+pub trait Unsigned: Integer + fmt::UpperHex + fmt::Octal + fmt::Binary {}
+
+impl Unsigned for u8 {}
+impl Unsigned for u16 {}
+impl Unsigned for u32 {}
+impl Unsigned for u64 {}
+impl Unsigned for u128 {}
+impl Unsigned for usize {}
+
+pub trait Float:
+    Sized + Copy + Default + PartialOrd + ops::Neg<Output = Self> + fmt::UpperExp + fmt::Display
+{
+    /// Largest magnitude of SCPI decimal numeric program data
+    ///
+    /// Reference: SCPI 1999.0: 7.2.1 - Syntax
+    const SCPI_MAX: Self;
+
     fn from_str(s: &str) -> Result<Self, ParseFloatError>;
     fn from_str_radix(s: &str, radix: u32) -> Option<Self>;
 
@@ -232,6 +223,8 @@ pub trait Float: Sized + Copy + Default + fmt::UpperExp {
 }
 
 impl Float for f32 {
+    const SCPI_MAX: Self = 9.9E+37;
+
     #[allow(clippy::float_cmp)]
     fn from_str(s: &str) -> Result<Self, ParseFloatError> {
         let value = core::str::FromStr::from_str(s)?;
@@ -265,6 +258,8 @@ impl Float for f32 {
 }
 
 impl Float for f64 {
+    const SCPI_MAX: Self = 9.9E+37;
+
     #[allow(clippy::float_cmp)]
     fn from_str(s: &str) -> Result<Self, ParseFloatError> {
         let value = core::str::FromStr::from_str(s)?;
@@ -13,11 +13,24 @@
 //! * `i8`/`i16`/`i32`/`i64`/`i128`/`isize`: IEEE 488.2 decimal numeric program data, integer
 //! * `f32`/`f64`: IEEE 488.2 decimal numeric program data, exponential format. NaN/Inf/-Inf values
 //!   are encoded as character program data (as defined by SCPI)
-//! * `&[u8]`: IEEE 488.2 arbitrary block program data, definite length format
-//! * `&str`: IEEE 488.2 string program data
+//! * `PlainFloat`: like `f32`/`f64`, but without an exponent
+//! * [`scpi::types::Quantity`]: IEEE 488.2 decimal numeric program data followed by suffix program
+//!   data, e.g. `1.5E0 V`
+//! * `HexValue`/`OctalValue`/`BinaryValue`: IEEE 488.2 non-decimal numeric program data, for
+//!   unsigned integers only
+//! * `&[u8]`/`[u8; N]`/`Vec<u8>`: IEEE 488.2 arbitrary block program data, definite length format
+//! * `&str`/`String`: IEEE 488.2 string program data
 //! * `Option<T>`: `Some(value)`=contained value encoded normally, `None`=no value encoded
 //! * `CharacterProgramData`: IEEE 488.2 character program data
+//! * `Expression`: IEEE 488.2 expression program data
+//! * [`scpi::types::ChannelList`]: SCPI channel list, as expression program data
+//! * [`scpi::types::NumericList`]: SCPI numeric list, as expression program data
 //! * `ProgramList`: elements encoded as separate comma-delimited program data values
+//! * `ProgramIter`: like `ProgramList`, but the elements come from a cloneable iterator
+//! * `[T; N]`: encoded like `ProgramList`, for `f32`/`f64`/`bool`/`char`/`&str` elements
+//! * `&T`: encoded like `T`
+//! * `RawProgramData`: bytes written as-is, for parameter syntax not covered by other types
+//! * [`buffer::ScpiString`]: IEEE 488.2 string program data
 //!
 //! Decoding formats:
 //!
@@ -26,16 +39,46 @@
 //! * `i8`/`i16`/`i32`/`i64`/`i128`/`isize`: IEEE 488.2 numeric response data, all integer formats
 //!   are accepted (NR1, hex, oct, bin)
 //! * `f32`/`f64`: IEEE 488.2 numeric response data (NR2/NR3). NaN/Inf/-Inf are interpreted using
-//!    IEEE 488.2 recommendations
+//!   IEEE 488.2 recommendations
 //! * `Vec<u8>`: IEEE 488.2 arbitrary block response data, both definite and indefinite length
 //!   formats are accepted
 //! * `String`: IEEE 488.2 string response data
 //! * `ArbitraryAscii`: IEEE 488.2 arbitrary ascii response data
 //! * `ResponseList`: elements parsed from separate comma-delimited response data values
+//! * `CountedList`: a count followed by exactly that many response data values
+//! * `Captured<T>`: decoded as `T`, together with the raw response bytes
+//! * `IgnoreTrailing<T>`: decoded as `T`, skipping extra fields in the same response message unit
+//! * `EmptyAsNone<T>`: decoded as `T`, or `None` for an empty field or an empty string
+//! * `heapless::Vec<u8, N>`: IEEE 488.2 arbitrary block response data, like `Vec<u8>`
+//! * `heapless::String<N>`: IEEE 488.2 string response data, like `String`
+//! * [`buffer::ArrayBuffer`]: IEEE 488.2 arbitrary block response data, like `Vec<u8>`
+//! * [`buffer::ScpiString`]: IEEE 488.2 string response data, like `String`
+//!
+//! Cargo features:
+//!
+//! * `std` (default): std I/O adapters, helpers, sessions and transcripts. Enables `alloc`
+//! * `alloc`: the heap-allocated response data types above (`Vec<u8>`, `String`,
+//!   `ArbitraryAscii`, `ResponseList`, `CountedList`, `Captured<T>`), resumable decoding, and the
+//!   [`parse::ProgramDataParser`] tokenizer. Without it,
+//!   strings, character data and blocks can still be decoded into any `fmt::Write` or
+//!   `ByteSink`, such as [`buffer::ArrayBuffer`], with the low-level [`Decoder`] methods
+//! * `heapless`: the `heapless` response data types above. `heapless::Vec<u8, N>` can also be
+//!   used as an encoding target
+//! * `hislip`: a HiSLIP client for LXI instruments, synchronous channel only. Enables `std`
+//! * `prologix`: an adapter for Prologix GPIB-USB and GPIB-Ethernet controllers. Enables `std`
+//! * `serialport`: a serial port transport based on the `serialport` crate. Enables `std`
+//! * `visa`: a transport over a VISA session, for use with any VISA binding. Enables `std`
+//! * `test-util`: a scripted transport, a simulated instrument, and `proptest` strategies for
+//!   testing
+//!
+//! See `examples/embedded.rs` for a complete command and query exchange using only stack
+//! buffers.
 //!
 //! Examples:
 //!
 //! ```
+//! # #[cfg(feature = "std")]
+//! # mod example {
 //! use red_sculpin::{decode::Decoder, encode::Encoder, scpi, Query};
 //! use std::net::TcpStream;
 //!
@@ -51,45 +94,167 @@
 //!     decoder.finish()?;
 //!     Ok(result)
 //! }
+//! # }
 //! ```
 
+#[cfg(feature = "alloc")]
 extern crate alloc;
 
 #[cfg(feature = "std")]
 extern crate std;
 
-use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::{collections::VecDeque, vec::Vec};
 use core::str;
 
+pub use crate::response_data::CharacterResponseData;
+#[cfg(feature = "alloc")]
+pub use crate::response_data::{ArbitraryAscii, Captured, CountedList, ResponseList};
 use crate::{
+    buffer::SliceSink,
     decode::{DecodeError, Decoder},
     encode::{EncodeError, EncodeSink, Encoder},
 };
 pub use crate::{
     ieee::types::*,
-    program_data::{CharacterProgramData, ProgramData, ProgramList},
-    response_data::{ArbitraryAscii, CharacterResponseData, ResponseData, ResponseList},
+    mapped::{CommandExt, MappedCommand, MappedQuery, QueryExt},
+    program_data::{
+        BinaryValue, CharacterProgramData, Expression, HexValue, OctalValue, PlainFloat,
+        ProgramData, ProgramIter, ProgramList, RawProgramData,
+    },
+    response_data::{EmptyAsNone, IgnoreTrailing, ResponseData, ResponseDataInto},
     scpi::types::*,
-    utils::is_program_mnemonic,
+    utils::{is_program_mnemonic, mnemonic_matches, numeric_suffix, short_form},
 };
 
+/// Adapters for byte sources and sinks
+pub mod adapters;
+/// Fixed-capacity buffers
+pub mod buffer;
 /// Low-level IEEE/SCPI response message decoding
 pub mod decode;
 /// Low-level IEEE/SCPI program message encoding
 pub mod encode;
+/// Readable rendering of raw message bytes, for logging
+pub mod format;
+/// Convenience functions for common message exchanges over std I/O
+#[cfg(feature = "std")]
+pub mod helpers;
+/// HiSLIP client, synchronous channel only
+#[cfg(feature = "hislip")]
+pub mod hislip;
 /// IEEE 488.2 standard
 pub mod ieee;
 mod internal;
+mod mapped;
+/// Scripted transport for testing
+#[cfg(all(feature = "alloc", any(test, feature = "test-util")))]
+pub mod mock;
+/// Device-side program message parsing, for instrument emulators
+pub mod parse;
 mod program_data;
+/// Prologix GPIB controller support
+#[cfg(feature = "prologix")]
+pub mod prologix;
+/// IEEE/SCPI response message encoding, for instrument emulators
+pub mod respond;
 mod response_data;
 /// SCPI 1999.0 standard
 pub mod scpi;
+/// Serial port transport
+#[cfg(feature = "serialport")]
+pub mod serial;
+/// Device sessions over std I/O
+#[cfg(feature = "std")]
+pub mod session;
+/// Simulated instrument for testing
+#[cfg(all(feature = "alloc", any(test, feature = "test-util")))]
+pub mod simulator;
+#[doc(hidden)]
+pub mod status_register;
+/// `proptest` strategies and round-trip assertions for testing
+#[cfg(all(feature = "alloc", any(test, feature = "test-util")))]
+pub mod strategy;
+/// Recording and replaying of wire traffic
+#[cfg(feature = "std")]
+pub mod transcript;
 mod utils;
+/// VISA transport
+#[cfg(feature = "visa")]
+pub mod visa;
 
 /// A source of bytes
 pub trait ByteSource {
     type Error: From<DecodeError>;
     fn read_byte(&mut self) -> Result<u8, Self::Error>;
+    /// Reads a byte, and returns whether the END indicator was received with it.
+    ///
+    /// Transports such as GPIB and USBTMC signal the end of a message out of band, and the message
+    /// doesn't necessarily end with a newline. The [`Decoder`] treats a byte
+    /// received with END as if it was followed by the response message terminator. By default,
+    /// END is never reported.
+    fn read_byte_with_end(&mut self) -> Result<(u8, bool), Self::Error> {
+        Ok((self.read_byte()?, false))
+    }
+    /// Reads bytes into the given buffer, and returns the number of bytes read and whether the END
+    /// indicator was received with the last one.
+    ///
+    /// At least one byte is read if the buffer isn't empty, and reading stops after a byte received
+    /// with END. By default, a single byte is read with
+    /// [`read_byte_with_end`](ByteSource::read_byte_with_end). The [`Decoder`] uses this to read
+    /// arbitrary block payloads in bulk.
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(usize, bool), Self::Error> {
+        match buf.first_mut() {
+            Some(first) => {
+                let (byte, end) = self.read_byte_with_end()?;
+                *first = byte;
+                Ok((1, end))
+            }
+            None => Ok((0, false)),
+        }
+    }
+}
+
+/// Observer for protocol events, used for instrumentation
+///
+/// All methods do nothing by default, and `()` is the observer of decoders that are created
+/// without one. See [`Encoder::with_observer`] and [`Decoder::with_observer`].
+pub trait ProtocolObserver {
+    fn on_bytes_written(&mut self, _len: usize) {}
+    /// Called after a program message has been terminated.
+    fn on_message_end(&mut self) {}
+    fn on_bytes_read(&mut self, _len: usize) {}
+    /// Called when response data decoding begins.
+    fn on_response_data(&mut self) {}
+    /// Called after a response message terminator has been decoded.
+    fn on_response_end(&mut self) {}
+    fn on_decode_error(&mut self, _err: &DecodeError) {}
+}
+
+impl ProtocolObserver for () {}
+
+impl<T> ProtocolObserver for &mut T
+where
+    T: ProtocolObserver + ?Sized,
+{
+    fn on_bytes_written(&mut self, len: usize) {
+        (**self).on_bytes_written(len)
+    }
+    fn on_message_end(&mut self) {
+        (**self).on_message_end()
+    }
+    fn on_bytes_read(&mut self, len: usize) {
+        (**self).on_bytes_read(len)
+    }
+    fn on_response_data(&mut self) {
+        (**self).on_response_data()
+    }
+    fn on_response_end(&mut self) {
+        (**self).on_response_end()
+    }
+    fn on_decode_error(&mut self, err: &DecodeError) {
+        (**self).on_decode_error(err)
+    }
 }
 
 impl ByteSource for &[u8] {
@@ -104,6 +269,33 @@ impl ByteSource for &[u8] {
             [] => Err(DecodeError::UnexpectedEnd),
         }
     }
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(usize, bool), Self::Error> {
+        if self.is_empty() && !buf.is_empty() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let len = buf.len().min(self.len());
+        let (bytes, rest) = self.split_at(len);
+        buf[..len].copy_from_slice(bytes);
+        *self = rest;
+        Ok((len, false))
+    }
+}
+
+impl<T> ByteSource for &mut T
+where
+    T: ByteSource + ?Sized,
+{
+    type Error = T::Error;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        (**self).read_byte()
+    }
+    fn read_byte_with_end(&mut self) -> Result<(u8, bool), Self::Error> {
+        (**self).read_byte_with_end()
+    }
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(usize, bool), Self::Error> {
+        (**self).read_bytes(buf)
+    }
 }
 
 /// A sink for bytes
@@ -113,19 +305,105 @@ pub trait ByteSink {
         self.write_bytes(&[byte])
     }
     fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+    /// Writes all the given parts in order, in as few writes as the sink supports.
+    ///
+    /// Writes the parts one by one by default.
+    fn write_bytes_vectored(&mut self, parts: &[&[u8]]) -> Result<(), Self::Error> {
+        for part in parts {
+            self.write_bytes(part)?;
+        }
+        Ok(())
+    }
+    /// Hints that at least `additional` more bytes are about to be written.
+    ///
+    /// Does nothing by default.
+    fn reserve(&mut self, _additional: usize) {}
 }
 
+#[cfg(feature = "alloc")]
 impl ByteSink for Vec<u8> {
     type Error = EncodeError;
 
     fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
-        self.extend(bytes);
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+    fn write_bytes_vectored(&mut self, parts: &[&[u8]]) -> Result<(), Self::Error> {
+        self.reserve(parts.iter().map(|part| part.len()).sum());
+        for part in parts {
+            self.extend_from_slice(part);
+        }
         Ok(())
     }
+    fn reserve(&mut self, additional: usize) {
+        self.reserve_exact(additional);
+    }
 }
 
+#[cfg(feature = "alloc")]
 impl EncodeSink for Vec<u8> {}
 
+#[cfg(feature = "alloc")]
+impl ByteSink for VecDeque<u8> {
+    type Error = EncodeError;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.extend(bytes);
+        Ok(())
+    }
+    fn reserve(&mut self, additional: usize) {
+        self.reserve_exact(additional);
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl EncodeSink for VecDeque<u8> {}
+
+#[cfg(feature = "heapless")]
+impl<const N: usize> ByteSink for heapless::Vec<u8, N> {
+    type Error = EncodeError;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.extend_from_slice(bytes)
+            .map_err(|_| EncodeError::BufferOverflow)
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl<const N: usize> EncodeSink for heapless::Vec<u8, N> {}
+
+impl<T> ByteSink for &mut T
+where
+    T: ByteSink + ?Sized,
+{
+    type Error = T::Error;
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), Self::Error> {
+        (**self).write_byte(byte)
+    }
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        (**self).write_bytes(bytes)
+    }
+    fn write_bytes_vectored(&mut self, parts: &[&[u8]]) -> Result<(), Self::Error> {
+        (**self).write_bytes_vectored(parts)
+    }
+    fn reserve(&mut self, additional: usize) {
+        (**self).reserve(additional)
+    }
+}
+
+impl<T> EncodeSink for &mut T
+where
+    T: EncodeSink + ?Sized,
+{
+    fn terminate_message(&mut self) -> Result<(), Self::Error> {
+        (**self).terminate_message()
+    }
+    fn terminator_len(&self) -> usize {
+        (**self).terminator_len()
+    }
+}
+
 /// Trait for types that represent IEEE/SCPI commands
 pub trait Command {
     type ProgramData: ProgramData;
@@ -137,8 +415,96 @@ pub trait Command {
         self.program_data().encode(encoder)?;
         Ok(())
     }
+    /// Encodes the command as a complete program message, terminator included.
+    ///
+    /// ```
+    /// use red_sculpin::{scpi::message::StatusOperationEnable, Command};
+    ///
+    /// let bytes = StatusOperationEnable(42).encode_to_vec().unwrap();
+    /// assert_eq!(bytes, b":STAT:OPER:ENAB 42\n");
+    /// ```
+    #[cfg(feature = "alloc")]
+    fn encode_to_vec(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut encoder = Encoder::new(Vec::new());
+        self.encode(&mut encoder)?;
+        encoder.finish()
+    }
+    /// Encodes the command as a complete program message into a slice, terminator included, and
+    /// returns the number of bytes written.
+    ///
+    /// ```
+    /// use red_sculpin::{scpi::message::StatusOperationEnable, Command};
+    ///
+    /// let mut buf = [0; 32];
+    /// let len = StatusOperationEnable(42).encode_to_slice(&mut buf).unwrap();
+    /// assert_eq!(&buf[..len], b":STAT:OPER:ENAB 42\n");
+    /// ```
+    fn encode_to_slice(&self, buf: &mut [u8]) -> Result<usize, EncodeError> {
+        let mut encoder = Encoder::new(SliceSink::new(buf));
+        self.encode(&mut encoder)?;
+        Ok(encoder.finish()?.len())
+    }
+}
+
+/// Trait for one or more commands that are encoded as consecutive message units of the same
+/// program message
+///
+/// Every [`Command`] is a sequence of one, and tuples, arrays and slices of sequences are
+/// sequences of their elements in order.
+///
+/// ```
+/// use red_sculpin::{
+///     buffer::ArrayBuffer, encode::Encoder, ieee::message::ClearStatus,
+///     scpi::message::StatusOperationEnable, CommandSequence,
+/// };
+///
+/// let mut encoder = Encoder::new(ArrayBuffer::<32>::new());
+/// (ClearStatus, StatusOperationEnable(1)).encode_units(&mut encoder).unwrap();
+/// assert_eq!(encoder.finish().unwrap().as_slice(), b"*CLS;:STAT:OPER:ENAB 1\n");
+/// ```
+pub trait CommandSequence {
+    /// Encodes the commands as message units without terminating the program message.
+    fn encode_units<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error>;
+}
+
+impl<C: Command> CommandSequence for C {
+    fn encode_units<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        self.encode(encoder)
+    }
+}
+
+impl<C: CommandSequence> CommandSequence for &[C] {
+    fn encode_units<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        self.iter()
+            .try_for_each(|commands| commands.encode_units(encoder))
+    }
+}
+
+impl<C: CommandSequence, const N: usize> CommandSequence for [C; N] {
+    fn encode_units<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        self.as_slice().encode_units(encoder)
+    }
+}
+
+macro_rules! impl_command_sequence_tuple {
+    ($($idx:tt $name:ident),*) => {
+        impl<$($name),*> CommandSequence for ($($name,)*)
+        where
+            $($name: CommandSequence,)*
+        {
+            fn encode_units<S: EncodeSink>(
+                &self,
+                encoder: &mut Encoder<S>,
+            ) -> Result<(), S::Error> {
+                $(self.$idx.encode_units(encoder)?;)*
+                Ok(())
+            }
+        }
+    };
 }
 
+internal::impl_tuples!(impl_command_sequence_tuple);
+
 /// Trait for types that represent IEEE/SCPI queries
 pub trait Query {
     type ProgramData: ProgramData;
@@ -151,19 +517,134 @@ pub trait Query {
         self.program_data().encode(encoder)?;
         Ok(())
     }
-    fn decode<S: ByteSource>(
+    fn decode<S: ByteSource, O: ProtocolObserver>(
         &self,
-        decoder: &mut Decoder<S>,
+        decoder: &mut Decoder<S, O>,
     ) -> Result<Self::ResponseData, S::Error> {
+        decoder.skip_echoed_header(self.mnemonic())?;
         Self::ResponseData::decode(decoder)
     }
+    /// Decodes the response into an existing value, reusing its allocations.
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        &self,
+        decoder: &mut Decoder<S, O>,
+        target: &mut Self::ResponseData,
+    ) -> Result<(), S::Error>
+    where
+        Self::ResponseData: ResponseDataInto,
+    {
+        decoder.skip_echoed_header(self.mnemonic())?;
+        Self::ResponseData::decode_into(decoder, target)
+    }
+    /// Decodes a complete response message to the query from bytes.
+    ///
+    /// The response must end with a terminator, and nothing may follow it. See
+    /// [`ResponseData::from_response_bytes`].
+    ///
+    /// ```
+    /// use red_sculpin::{scpi::message::SystemVersionQuery, Query};
+    ///
+    /// assert_eq!(SystemVersionQuery.decode_from_slice(b"1999.0\n"), Ok(1999.0));
+    /// ```
+    fn decode_from_slice(&self, bytes: &[u8]) -> Result<Self::ResponseData, DecodeError> {
+        response_data::decode_complete(bytes, |decoder| self.decode(decoder))
+    }
+    /// Encodes the query as a complete program message, terminator included.
+    ///
+    /// ```
+    /// use red_sculpin::{ieee::message::IdentificationQuery, Query};
+    ///
+    /// assert_eq!(IdentificationQuery.encode_to_vec().unwrap(), b"*IDN?\n");
+    /// ```
+    #[cfg(feature = "alloc")]
+    fn encode_to_vec(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut encoder = Encoder::new(Vec::new());
+        self.encode(&mut encoder)?;
+        encoder.finish()
+    }
+    /// Encodes the query as a complete program message into a slice, terminator included, and
+    /// returns the number of bytes written.
+    ///
+    /// ```
+    /// use red_sculpin::{ieee::message::OperationCompleteQuery, Query};
+    ///
+    /// let mut buf = [0; 32];
+    /// let len = OperationCompleteQuery.encode_to_slice(&mut buf).unwrap();
+    /// assert_eq!(&buf[..len], b"*OPC?\n");
+    /// ```
+    fn encode_to_slice(&self, buf: &mut [u8]) -> Result<usize, EncodeError> {
+        let mut encoder = Encoder::new(SliceSink::new(buf));
+        self.encode(&mut encoder)?;
+        Ok(encoder.finish()?.len())
+    }
 }
 
 #[cfg(feature = "std")]
 pub use std_support::*;
 
+#[cfg(all(test, feature = "alloc", not(feature = "std")))]
+mod no_std_tests {
+    use alloc::{
+        string::{String, ToString},
+        vec::Vec,
+    };
+    use matches::assert_matches;
+
+    use crate::{
+        decode::{DecodeError, Decoder},
+        encode::{EncodeError, Encoder},
+        ieee::message::IdentificationQuery,
+        scpi::message::StatusOperationEnable,
+        Command, Query, ResponseData,
+    };
+
+    #[test]
+    fn errors_implement_core_error() {
+        let decode: &dyn core::error::Error = &DecodeError::Parse;
+        let encode: &dyn core::error::Error = &EncodeError::NonAsciiString;
+        assert!(decode.source().is_none());
+        assert_eq!(decode.to_string(), "parse error");
+        assert_eq!(encode.to_string(), "invalid non-ascii string");
+        assert_matches!(
+            decode.downcast_ref::<DecodeError>(),
+            Some(DecodeError::Parse)
+        );
+    }
+
+    #[test]
+    fn encode_into_vec() {
+        let mut encoder = Encoder::new(Vec::new());
+        StatusOperationEnable(42).encode(&mut encoder).unwrap();
+        IdentificationQuery.encode(&mut encoder).unwrap();
+        assert_eq!(encoder.finish().unwrap(), b":STAT:OPER:ENAB 42;*IDN?\n");
+    }
+
+    #[test]
+    fn decode_from_slice() {
+        let mut decoder = Decoder::new(&b"ACME,M1,0,1.0\n"[..]);
+        let idn = IdentificationQuery.decode(&mut decoder).unwrap();
+        assert_eq!(idn.manufacturer, "ACME");
+        assert!(decoder.finish().unwrap().is_empty());
+
+        let mut decoder = Decoder::new(&b"1.0\n"[..]);
+        assert_matches!(
+            u8::decode(&mut decoder),
+            Err(DecodeError::InvalidDataTerminator { .. })
+        );
+
+        let mut decoder = Decoder::new(&b"-1,\"x\",#13abc\n"[..]);
+        let (number, text, block) = <(i8, String, Vec<u8>)>::decode(&mut decoder).unwrap();
+        assert_eq!(
+            (number, text.as_str(), block.as_slice()),
+            (-1, "x", &b"abc"[..])
+        );
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+}
+
 #[cfg(feature = "std")]
 mod std_support {
+    use alloc::{boxed::Box, string::String, vec::Vec};
     use core::fmt;
     use std::io;
 
@@ -171,6 +652,7 @@ mod std_support {
     use crate::{
         decode::DecodeError,
         encode::{EncodeError, EncodeSink},
+        StandardEventStatus, SystemErrorResponse,
     };
 
     pub struct Io<'a, T>(pub &'a mut T);
@@ -186,6 +668,19 @@ mod std_support {
             self.0.read_exact(&mut buf)?;
             Ok(buf[0])
         }
+        fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(usize, bool), Self::Error> {
+            if buf.is_empty() {
+                return Ok((0, false));
+            }
+            loop {
+                match self.0.read(buf) {
+                    Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                    Ok(len) => return Ok((len, false)),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
     }
 
     impl<'a, T> ByteSink for Io<'a, T>
@@ -198,15 +693,284 @@ mod std_support {
             self.0.write_all(bytes)?;
             Ok(())
         }
+        /// Writes up to four parts with `io::Write::write_vectored`, and more parts one by one.
+        /// Writers that don't support vectored writes end up writing the parts one by one.
+        fn write_bytes_vectored(&mut self, parts: &[&[u8]]) -> Result<(), Self::Error> {
+            const MAX_PARTS: usize = 4;
+            if parts.len() > MAX_PARTS {
+                return parts.iter().try_for_each(|part| self.write_bytes(part));
+            }
+            let mut slices = [io::IoSlice::new(&[]); MAX_PARTS];
+            for (slice, part) in slices.iter_mut().zip(parts) {
+                *slice = io::IoSlice::new(part);
+            }
+            let mut slices = &mut slices[..parts.len()];
+            while !slices.is_empty() {
+                match self.0.write_vectored(slices) {
+                    Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+                    Ok(written) => io::IoSlice::advance_slices(&mut slices, written),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            Ok(())
+        }
     }
 
     impl<'a, T> EncodeSink for Io<'a, T> where T: io::Write {}
 
+    /// Writes at the cursor position, and fails with [`EncodeError::BufferOverflow`] without
+    /// writing anything if the bytes don't fit in the rest of the slice.
+    impl ByteSink for io::Cursor<&mut [u8]> {
+        type Error = EncodeError;
+
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+            let start = usize::try_from(self.position()).unwrap_or(usize::MAX);
+            let end = start
+                .checked_add(bytes.len())
+                .ok_or(EncodeError::BufferOverflow)?;
+            self.get_mut()
+                .get_mut(start..end)
+                .ok_or(EncodeError::BufferOverflow)?
+                .copy_from_slice(bytes);
+            self.set_position(end as u64);
+            Ok(())
+        }
+    }
+
+    impl EncodeSink for io::Cursor<&mut [u8]> {}
+
+    /// Writes at the cursor position, overwriting existing bytes and growing the vector as needed.
+    impl ByteSink for io::Cursor<Vec<u8>> {
+        type Error = EncodeError;
+
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+            io::Write::write_all(self, bytes).map_err(|_| EncodeError::BufferOverflow)
+        }
+        fn reserve(&mut self, additional: usize) {
+            self.get_mut().reserve(additional);
+        }
+    }
+
+    impl EncodeSink for io::Cursor<Vec<u8>> {}
+
+    /// A bidirectional transport, accepted by the convenience helpers and sessions
+    ///
+    /// Implemented for everything that implements both `io::Read` and `io::Write`, including
+    /// [`IoPair`] and [`OwnedIoPair`] for transports with separate reader and writer halves.
+    pub trait Transport: io::Read + io::Write {}
+
+    impl<T> Transport for T where T: io::Read + io::Write + ?Sized {}
+
+    /// A transport made of separate reader and writer halves
+    pub struct IoPair<'a, R, W>(pub &'a mut R, pub &'a mut W);
+
+    /// A transport made of separate owned reader and writer halves
+    pub struct OwnedIoPair<R, W>(pub R, pub W);
+
+    impl<'a, R, W> io::Read for IoPair<'a, R, W>
+    where
+        R: io::Read,
+    {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl<'a, R, W> io::Write for IoPair<'a, R, W>
+    where
+        W: io::Write,
+    {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.1.flush()
+        }
+    }
+
+    impl<'a, R, W> ByteSource for IoPair<'a, R, W>
+    where
+        R: io::Read,
+    {
+        type Error = Error;
+
+        fn read_byte(&mut self) -> Result<u8, Self::Error> {
+            Io(self.0).read_byte()
+        }
+        fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(usize, bool), Self::Error> {
+            Io(self.0).read_bytes(buf)
+        }
+    }
+
+    impl<'a, R, W> ByteSink for IoPair<'a, R, W>
+    where
+        W: io::Write,
+    {
+        type Error = Error;
+
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+            Io(self.1).write_bytes(bytes)
+        }
+        fn write_bytes_vectored(&mut self, parts: &[&[u8]]) -> Result<(), Self::Error> {
+            Io(self.1).write_bytes_vectored(parts)
+        }
+    }
+
+    impl<'a, R, W> EncodeSink for IoPair<'a, R, W> where W: io::Write {}
+
+    impl<R, W> OwnedIoPair<R, W> {
+        /// Returns a borrowed pair of the halves.
+        pub fn as_pair(&mut self) -> IoPair<'_, R, W> {
+            IoPair(&mut self.0, &mut self.1)
+        }
+    }
+
+    impl<R, W> io::Read for OwnedIoPair<R, W>
+    where
+        R: io::Read,
+    {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl<R, W> io::Write for OwnedIoPair<R, W>
+    where
+        W: io::Write,
+    {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.1.flush()
+        }
+    }
+
+    impl<R, W> ByteSource for OwnedIoPair<R, W>
+    where
+        R: io::Read,
+    {
+        type Error = Error;
+
+        fn read_byte(&mut self) -> Result<u8, Self::Error> {
+            Io(&mut self.0).read_byte()
+        }
+        fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(usize, bool), Self::Error> {
+            Io(&mut self.0).read_bytes(buf)
+        }
+    }
+
+    impl<R, W> ByteSink for OwnedIoPair<R, W>
+    where
+        W: io::Write,
+    {
+        type Error = Error;
+
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+            Io(&mut self.1).write_bytes(bytes)
+        }
+        fn write_bytes_vectored(&mut self, parts: &[&[u8]]) -> Result<(), Self::Error> {
+            Io(&mut self.1).write_bytes_vectored(parts)
+        }
+    }
+
+    impl<R, W> EncodeSink for OwnedIoPair<R, W> where W: io::Write {}
+
     #[derive(Debug)]
     pub enum Error {
         Encode(EncodeError),
         Decode(DecodeError),
         Io(io::Error),
+        ErrorQueueLimit(usize),
+        OperationIncomplete,
+        PollAborted,
+        TransferCancelled,
+        Device(SystemErrorResponse),
+        DeviceStatus(StandardEventStatus),
+        Context(Box<ContextError>),
+    }
+
+    impl Error {
+        /// Returns the context of the error, if there is one.
+        pub fn context(&self) -> Option<&ContextError> {
+            match self {
+                Error::Context(context) => Some(context),
+                _ => None,
+            }
+        }
+        /// Returns the error without its context.
+        pub fn without_context(&self) -> &Error {
+            match self {
+                Error::Context(context) => &context.source,
+                err => err,
+            }
+        }
+        /// Wraps an encode, decode or I/O error in [`Error::Context`]. Other errors are returned
+        /// as-is.
+        pub(crate) fn with_context(
+            self,
+            mnemonic: &str,
+            phase: Phase,
+            offset: Option<usize>,
+        ) -> Error {
+            match self {
+                source @ (Error::Encode(_) | Error::Decode(_) | Error::Io(_)) => {
+                    Error::Context(Box::new(ContextError {
+                        mnemonic: String::from(mnemonic),
+                        phase,
+                        offset,
+                        source,
+                    }))
+                }
+                err => err,
+            }
+        }
+    }
+
+    /// Phase of a message exchange
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    pub enum Phase {
+        /// Encoding and sending the program message
+        Encode,
+        /// Receiving and decoding the response message
+        Decode,
+        /// Checking that the response message was decoded completely
+        Finish,
+    }
+
+    /// An encode, decode or I/O error together with the command or query that was being sent
+    ///
+    /// Produced by the convenience helpers and sessions, and wrapped in [`Error::Context`]. Other
+    /// errors, such as device errors, are not wrapped.
+    #[derive(Debug)]
+    pub struct ContextError {
+        pub mnemonic: String,
+        pub phase: Phase,
+        /// Number of response message bytes consumed when decoding failed, or `None` for
+        /// [`Phase::Encode`]
+        pub offset: Option<usize>,
+        pub source: Error,
+    }
+
+    impl fmt::Display for ContextError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.phase {
+                Phase::Encode => write!(f, "while encoding \"{}\"", self.mnemonic)?,
+                Phase::Decode => write!(f, "while decoding response to \"{}\"", self.mnemonic)?,
+                Phase::Finish => write!(f, "while finishing response to \"{}\"", self.mnemonic)?,
+            }
+            if let Some(offset) = self.offset {
+                write!(f, " at response offset {}", offset)?;
+            }
+            write!(f, ": {}", self.source)
+        }
+    }
+
+    impl std::error::Error for ContextError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.source)
+        }
     }
 
     impl fmt::Display for Error {
@@ -215,6 +979,22 @@ mod std_support {
                 Error::Encode(err) => fmt::Display::fmt(err, f),
                 Error::Decode(err) => fmt::Display::fmt(err, f),
                 Error::Io(err) => fmt::Display::fmt(err, f),
+                Error::ErrorQueueLimit(limit) => {
+                    write!(f, "error queue not empty after {} queries", limit)
+                }
+                Error::OperationIncomplete => write!(f, "operation not complete"),
+                Error::PollAborted => write!(f, "polling aborted"),
+                Error::TransferCancelled => write!(f, "transfer cancelled"),
+                Error::Device(response) => write!(
+                    f,
+                    "device error {}: {}",
+                    i16::from(response.code),
+                    response.message
+                ),
+                Error::DeviceStatus(status) => {
+                    write!(f, "device error status ({:?})", status)
+                }
+                Error::Context(context) => fmt::Display::fmt(context, f),
             }
         }
     }
@@ -237,13 +1017,151 @@ mod std_support {
         }
     }
 
+    impl From<Error> for io::Error {
+        fn from(err: Error) -> Self {
+            match err {
+                Error::Io(err) => err,
+                Error::Encode(err) => err.into(),
+                Error::Decode(err) => err.into(),
+                err => io::Error::other(err),
+            }
+        }
+    }
+
     impl std::error::Error for Error {
         fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
             match self {
                 Error::Encode(err) => Some(err),
                 Error::Decode(err) => Some(err),
                 Error::Io(err) => Some(err),
+                Error::Context(context) => Some(&context.source),
+                Error::ErrorQueueLimit(_)
+                | Error::OperationIncomplete
+                | Error::PollAborted
+                | Error::TransferCancelled
+                | Error::Device(_)
+                | Error::DeviceStatus(_) => None,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use alloc::vec::Vec;
+        use std::io;
+
+        use matches::assert_matches;
+
+        use super::Io;
+        use crate::{
+            encode::{EncodeError, Encoder},
+            ieee::message::{DefineDeviceTrigger, OperationCompleteQuery},
+            ByteSink, Command, Query,
+        };
+
+        /// A writer that records vectored writes, and accepts at most `limit` bytes per write
+        struct Inspect {
+            data: Vec<u8>,
+            vectored_calls: Vec<usize>,
+            limit: usize,
+        }
+
+        impl Inspect {
+            fn new(limit: usize) -> Inspect {
+                Inspect {
+                    data: Vec::new(),
+                    vectored_calls: Vec::new(),
+                    limit,
+                }
             }
         }
+
+        impl io::Write for Inspect {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                let len = buf.len().min(self.limit);
+                self.data.extend_from_slice(&buf[..len]);
+                Ok(len)
+            }
+            fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+                self.vectored_calls.push(bufs.len());
+                let mut written = 0;
+                for buf in bufs {
+                    let len = buf.len().min(self.limit - written);
+                    self.data.extend_from_slice(&buf[..len]);
+                    written += len;
+                }
+                Ok(written)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        #[test]
+        fn block_is_written_with_single_vectored_call() {
+            let mut writer = Inspect::new(usize::MAX);
+            let mut encoder = Encoder::new(Io(&mut writer));
+            DefineDeviceTrigger(b"*TRG").encode(&mut encoder).unwrap();
+            encoder.finish().unwrap();
+            assert_eq!(writer.data, b"DDT #14*TRG\n");
+            assert_eq!(writer.vectored_calls, [2]);
+        }
+
+        #[test]
+        fn partial_vectored_writes_are_continued() {
+            let mut writer = Inspect::new(3);
+            let mut encoder = Encoder::new(Io(&mut writer));
+            encoder.begin_message_unit().unwrap();
+            encoder.write_bytes(b"DDT").unwrap();
+            encoder.begin_program_data().unwrap();
+            encoder.encode_definite_block(b"*TRG;*WAI").unwrap();
+            encoder.finish().unwrap();
+            assert_eq!(writer.data, b"DDT #19*TRG;*WAI\n");
+            assert_eq!(writer.vectored_calls, [2, 1, 1, 1]);
+        }
+
+        #[test]
+        fn many_parts_are_written_one_by_one() {
+            let mut writer = Inspect::new(usize::MAX);
+            Io(&mut writer)
+                .write_bytes_vectored(&[b"a", b"b", b"c", b"d", b"e"])
+                .unwrap();
+            assert_eq!(writer.data, b"abcde");
+            assert!(writer.vectored_calls.is_empty());
+        }
+
+        #[test]
+        fn slice_cursor_sink() {
+            let mut buf = [0; 13];
+            let mut cursor = io::Cursor::new(&mut buf[..]);
+            cursor.set_position(2);
+            let mut encoder = Encoder::new(&mut cursor);
+            DefineDeviceTrigger(b"*TRG").encode(&mut encoder).unwrap();
+            assert_matches!(encoder.finish(), Err(EncodeError::BufferOverflow));
+            assert_eq!(cursor.position(), 13);
+            assert_eq!(&buf, b"\0\0DDT #14*TRG");
+
+            let mut cursor = io::Cursor::new(&mut buf[..]);
+            let mut encoder = Encoder::new(&mut cursor);
+            OperationCompleteQuery.encode(&mut encoder).unwrap();
+            encoder.finish().unwrap();
+            assert_eq!(cursor.position(), 6);
+            assert_eq!(&buf[..6], b"*OPC?\n");
+            let mut cursor = io::Cursor::new(&mut buf[..]);
+            cursor.set_position(14);
+            assert_matches!(cursor.write_byte(b'x'), Err(EncodeError::BufferOverflow));
+            assert_matches!(cursor.write_bytes(b""), Err(EncodeError::BufferOverflow));
+        }
+
+        #[test]
+        fn vec_cursor_sink() {
+            let mut cursor = io::Cursor::new(b"xxxxxxxxx".to_vec());
+            cursor.set_position(1);
+            let mut encoder = Encoder::new(&mut cursor);
+            DefineDeviceTrigger(b"*TRG").encode(&mut encoder).unwrap();
+            encoder.finish().unwrap();
+            assert_eq!(cursor.position(), 13);
+            assert_eq!(cursor.into_inner(), b"xDDT #14*TRG\n");
+        }
     }
 }
@@ -0,0 +1,189 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::{
+    decode::Decoder,
+    encode::{EncodeSink, Encoder},
+    ByteSource, Command, ProgramData, ProtocolObserver, Query, ResponseData, ResponseDataInto,
+};
+
+/// A query whose response is converted with a function after decoding
+///
+/// Created with [`QueryExt::map_response`]. The query is encoded exactly like the inner query,
+/// and the response is decoded by the inner query before it is converted.
+#[derive(Copy, Clone)]
+pub struct MappedQuery<Q, F> {
+    query: Q,
+    map: F,
+}
+
+/// A command whose program data is converted with a function before encoding
+///
+/// Created with [`CommandExt::map_program_data`]. The header is encoded exactly like the header of
+/// the inner command.
+#[derive(Copy, Clone)]
+pub struct MappedCommand<C, F> {
+    command: C,
+    map: F,
+}
+
+/// Combinators for queries
+pub trait QueryExt: Query + Sized {
+    /// Converts the response of this query with the given function.
+    ///
+    /// The converted type must be response data itself, like the numeric types.
+    ///
+    /// ```
+    /// use red_sculpin::{scpi::message::StatusOperationConditionQuery, Query, QueryExt};
+    ///
+    /// let query = StatusOperationConditionQuery.map_response(|raw: u16| raw & 0x0100 != 0);
+    /// assert_eq!(query.decode_from_slice(b"256\n"), Ok(true));
+    /// ```
+    fn map_response<F, T>(self, map: F) -> MappedQuery<Self, F>
+    where
+        F: Fn(Self::ResponseData) -> T,
+        T: ResponseData,
+    {
+        MappedQuery { query: self, map }
+    }
+}
+
+impl<Q: Query> QueryExt for Q {}
+
+/// Combinators for commands
+pub trait CommandExt: Command + Sized {
+    /// Converts the program data of this command with the given function before encoding.
+    ///
+    /// This lets a command take its parameter in a convenient type, like volts, and encode it in
+    /// the type the instrument expects, like integer millivolts.
+    fn map_program_data<F, P>(self, map: F) -> MappedCommand<Self, F>
+    where
+        F: Fn(Self::ProgramData) -> P,
+        P: ProgramData,
+    {
+        MappedCommand { command: self, map }
+    }
+}
+
+impl<C: Command> CommandExt for C {}
+
+impl<Q, F, T> Query for MappedQuery<Q, F>
+where
+    Q: Query,
+    F: Fn(Q::ResponseData) -> T,
+    T: ResponseData,
+{
+    type ProgramData = Q::ProgramData;
+    type ResponseData = T;
+    fn mnemonic(&self) -> &str {
+        self.query.mnemonic()
+    }
+    fn program_data(&self) -> Self::ProgramData {
+        self.query.program_data()
+    }
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        self.query.encode(encoder)
+    }
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        &self,
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<T, S::Error> {
+        self.query.decode(decoder).map(&self.map)
+    }
+    /// Decodes the response with the inner query and replaces the target with the converted
+    /// value, so no allocations are reused.
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        &self,
+        decoder: &mut Decoder<S, O>,
+        target: &mut T,
+    ) -> Result<(), S::Error>
+    where
+        T: ResponseDataInto,
+    {
+        *target = self.decode(decoder)?;
+        Ok(())
+    }
+}
+
+impl<C, F, P> Command for MappedCommand<C, F>
+where
+    C: Command,
+    F: Fn(C::ProgramData) -> P,
+    P: ProgramData,
+{
+    type ProgramData = P;
+    fn mnemonic(&self) -> &str {
+        self.command.mnemonic()
+    }
+    fn program_data(&self) -> Self::ProgramData {
+        (self.map)(self.command.program_data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::{CommandExt, QueryExt};
+    use crate::{
+        decode::DecodeError,
+        internal::{declare_tuple_command, declare_tuple_query},
+        Command, Query,
+    };
+
+    declare_tuple_query! {
+        #[derive(Copy, Clone, Debug)]
+        pub struct RawVoltageQuery<":MEAS:VOLT:RAW?", u16>;
+    }
+
+    declare_tuple_command! {
+        #[derive(Copy, Clone, Debug)]
+        pub struct SourceVoltage<":SOUR:VOLT">(pub f64);
+    }
+
+    const VOLTS_PER_COUNT: f64 = 10.0 / 65535.0;
+
+    #[test]
+    fn mapped_query() {
+        let query = RawVoltageQuery.map_response(|raw| f64::from(raw) * VOLTS_PER_COUNT);
+        assert_eq!(query.mnemonic(), ":MEAS:VOLT:RAW?");
+
+        let mut raw = [0; 32];
+        let mut mapped = [0; 32];
+        let len = RawVoltageQuery.encode_to_slice(&mut raw).unwrap();
+        assert_eq!(query.encode_to_slice(&mut mapped).unwrap(), len);
+        assert_eq!(&raw[..len], &mapped[..len]);
+
+        assert_eq!(query.decode_from_slice(b"65535\n"), Ok(10.0));
+        assert_eq!(query.decode_from_slice(b"0\n"), Ok(0.0));
+        assert_matches!(query.decode_from_slice(b"-1\n"), Err(DecodeError::Parse));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn mapped_query_decodes_into_target() {
+        use alloc::vec::Vec;
+
+        use crate::{decode::Decoder, ieee::message::ProtectedUserDataQuery};
+
+        let query = ProtectedUserDataQuery.map_response(|mut data: Vec<u8>| {
+            data.reverse();
+            data
+        });
+        let mut target = b"old".to_vec();
+        let mut decoder = Decoder::new(&b"#13abc\n"[..]);
+        query.decode_into(&mut decoder, &mut target).unwrap();
+        assert_eq!(target, b"cba");
+    }
+
+    #[test]
+    fn mapped_command() {
+        let command = SourceVoltage(1.5).map_program_data(|volts| (volts * 1000.0) as i32);
+        assert_eq!(command.mnemonic(), ":SOUR:VOLT");
+        assert_eq!(command.program_data(), 1500);
+        let mut buf = [0; 32];
+        let len = command.encode_to_slice(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b":SOUR:VOLT 1500\n");
+    }
+}
@@ -0,0 +1,278 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::{collections::VecDeque, vec::Vec};
+
+use crate::{
+    decode::DecodeError,
+    encode::{EncodeError, EncodeSink},
+    ByteSink, ByteSource,
+};
+
+/// An expected exchange with a [`MockTransport`]: bytes the transport expects to be written,
+/// followed by bytes it responds with once the write is complete
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Expect {
+    write: Vec<u8>,
+    response: Vec<u8>,
+}
+
+impl Expect {
+    /// Expects the given bytes to be written. No response is given unless
+    /// [`then_respond`](Expect::then_respond) is also used.
+    pub fn write(bytes: impl AsRef<[u8]>) -> Expect {
+        Expect {
+            write: bytes.as_ref().to_vec(),
+            response: Vec::new(),
+        }
+    }
+    /// Makes the given bytes available for reading without expecting any writes first.
+    pub fn respond(bytes: impl AsRef<[u8]>) -> Expect {
+        Expect {
+            write: Vec::new(),
+            response: bytes.as_ref().to_vec(),
+        }
+    }
+    /// Responds with the given bytes once the expected write is complete.
+    pub fn then_respond(mut self, bytes: impl AsRef<[u8]>) -> Expect {
+        self.response.extend_from_slice(bytes.as_ref());
+        self
+    }
+}
+
+/// A scripted transport for testing code built on top of this crate.
+///
+/// Writes are compared byte-by-byte against the expectations in order, and a mismatching write
+/// panics. The response of an expectation becomes readable only after its expected write has
+/// been completed, so reading a response too early behaves like a transport with no data
+/// available: the [`ByteSource`] implementation returns [`DecodeError::UnexpectedEnd`], and the
+/// `io::Read` implementation returns an `io::ErrorKind::TimedOut` error.
+///
+/// Unconsumed expectations can be checked with [`verify`](MockTransport::verify). With the `std`
+/// feature, they are also checked when the transport is dropped.
+#[derive(Debug)]
+pub struct MockTransport {
+    expectations: VecDeque<Expect>,
+    written: usize,
+    readable: VecDeque<u8>,
+}
+
+impl MockTransport {
+    pub fn new(expectations: impl IntoIterator<Item = Expect>) -> MockTransport {
+        let mut transport = MockTransport {
+            expectations: expectations.into_iter().collect(),
+            written: 0,
+            readable: VecDeque::new(),
+        };
+        transport.advance();
+        transport
+    }
+    /// Returns true if all expected writes have been done and all responses have been read.
+    pub fn is_done(&self) -> bool {
+        self.expectations.is_empty() && self.readable.is_empty()
+    }
+    /// Panics if there are expected writes that haven't been done, or responses that haven't been
+    /// read.
+    pub fn verify(&self) {
+        if let Some(expect) = self.expectations.front() {
+            panic!(
+                "{} unconsumed expectation(s), next expected write: {:?}",
+                self.expectations.len(),
+                EscapedBytes(&expect.write[self.written..])
+            );
+        }
+        if !self.readable.is_empty() {
+            let (head, tail) = self.readable.as_slices();
+            panic!(
+                "unread response bytes: {:?}{:?}",
+                EscapedBytes(head),
+                EscapedBytes(tail)
+            );
+        }
+    }
+    fn advance(&mut self) {
+        while let Some(expect) = self.expectations.front() {
+            if self.written < expect.write.len() {
+                break;
+            }
+            self.readable.extend(&expect.response);
+            self.expectations.pop_front();
+            self.written = 0;
+        }
+    }
+    fn write_expected(&mut self, bytes: &[u8]) {
+        for (idx, &byte) in bytes.iter().enumerate() {
+            match self.expectations.front() {
+                Some(expect) if expect.write[self.written] == byte => {
+                    self.written += 1;
+                    self.advance();
+                }
+                Some(expect) => panic!(
+                    "unexpected write: {:?}, expected {:?} after {:?}",
+                    EscapedBytes(&bytes[idx..]),
+                    EscapedBytes(&expect.write[self.written..]),
+                    EscapedBytes(&expect.write[..self.written])
+                ),
+                None => panic!(
+                    "unexpected write: {:?}, no more writes expected",
+                    EscapedBytes(&bytes[idx..])
+                ),
+            }
+        }
+    }
+}
+
+impl ByteSource for MockTransport {
+    type Error = DecodeError;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        self.readable.pop_front().ok_or(DecodeError::UnexpectedEnd)
+    }
+}
+
+impl ByteSink for MockTransport {
+    type Error = EncodeError;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.write_expected(bytes);
+        Ok(())
+    }
+}
+
+impl EncodeSink for MockTransport {}
+
+#[cfg(feature = "std")]
+impl std::io::Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.readable.is_empty() && !buf.is_empty() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        let len = buf.len().min(self.readable.len());
+        for (target, byte) in buf.iter_mut().zip(self.readable.drain(..len)) {
+            *target = byte;
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_expected(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for MockTransport {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            self.verify();
+        }
+    }
+}
+
+struct EscapedBytes<'a>(&'a [u8]);
+
+impl<'a> core::fmt::Debug for EscapedBytes<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("b\"")?;
+        for &byte in self.0 {
+            core::fmt::Display::fmt(&core::ascii::escape_default(byte), f)?;
+        }
+        f.write_str("\"")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use matches::assert_matches;
+
+    use super::{Expect, MockTransport};
+    use crate::{
+        decode::{DecodeError, Decoder},
+        encode::Encoder,
+        ieee::message::{ClearStatus, IdentificationQuery},
+        Command, Query,
+    };
+
+    #[test]
+    fn query_round_trip() {
+        let mut transport =
+            MockTransport::new([Expect::write(b"*IDN?\n").then_respond(b"ACME,M1,0,1.0\n")]);
+
+        let mut encoder = Encoder::new(&mut transport);
+        IdentificationQuery.encode(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+
+        let mut decoder = Decoder::new(&mut transport);
+        let idn = IdentificationQuery.decode(&mut decoder).unwrap();
+        decoder.finish().unwrap();
+
+        assert_eq!(idn.manufacturer, "ACME");
+        assert_eq!(idn.model, "M1");
+        assert_eq!(idn.serial_number, "");
+        assert_eq!(idn.firmware_level, "1.0");
+        assert!(transport.is_done());
+    }
+
+    #[test]
+    fn response_is_not_readable_before_write() {
+        let mut transport = MockTransport::new([Expect::write(b"*OPC?\n").then_respond(b"1\n")]);
+        let mut decoder = Decoder::new(&mut transport);
+        assert_matches!(decoder.read_byte(), Err(DecodeError::UnexpectedEnd));
+        transport.write_expected(b"*OPC?\n");
+        assert_eq!(collect(&mut transport), b"1\n");
+    }
+
+    #[test]
+    fn response_without_write_is_readable_immediately() {
+        let mut transport = MockTransport::new([Expect::respond(b"garbage\n")]);
+        assert_eq!(collect(&mut transport), b"garbage\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected write")]
+    fn mismatched_write_panics() {
+        let mut transport = MockTransport::new([Expect::write(b"*RST\n")]);
+        let mut encoder = Encoder::new(&mut transport);
+        ClearStatus.encode(&mut encoder).unwrap();
+        let _ = encoder.finish();
+    }
+
+    #[test]
+    #[should_panic(expected = "no more writes expected")]
+    fn extra_write_panics() {
+        let mut transport = MockTransport::new([]);
+        let mut encoder = Encoder::new(&mut transport);
+        ClearStatus.encode(&mut encoder).unwrap();
+        let _ = encoder.finish();
+    }
+
+    #[test]
+    #[should_panic(expected = "unconsumed expectation")]
+    fn unconsumed_expectations_panic_on_verify() {
+        let transport = MockTransport::new([Expect::write(b"*CLS\n")]);
+        transport.verify();
+    }
+
+    #[test]
+    #[should_panic(expected = "unread response bytes")]
+    fn unread_response_panics_on_verify() {
+        let transport = MockTransport::new([Expect::respond(b"1\n")]);
+        transport.verify();
+    }
+
+    fn collect(transport: &mut MockTransport) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while let Ok(byte) = crate::ByteSource::read_byte(transport) {
+            bytes.push(byte);
+        }
+        bytes
+    }
+}
@@ -0,0 +1,26 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+pub use self::character::{parse_character_program_data, CharacterProgramData};
+pub use self::header::{HeaderError, HeaderKind, HeaderParser};
+#[cfg(all(feature = "alloc", any(test, feature = "test-util")))]
+pub(crate) use self::message::resolve_path;
+#[cfg(feature = "alloc")]
+pub use self::message::{HeaderNode, MessageUnit, MessageUnitSplitter, SplitError};
+pub use self::numeric::{parse_decimal_numeric, NumericError, ParsedNumeric};
+#[cfg(feature = "alloc")]
+pub use self::program_data::{
+    BooleanForm, BooleanProgramData, DecimalNumeric, ProgramDataElement, ProgramDataParser,
+    Separator,
+};
+pub use self::suffix::{parse_suffix, SuffixError};
+
+mod character;
+mod header;
+#[cfg(feature = "alloc")]
+mod message;
+mod numeric;
+#[cfg(feature = "alloc")]
+mod program_data;
+mod suffix;
@@ -0,0 +1,197 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::str;
+
+use crate::{
+    response_data::CharacterResponseData,
+    scpi::types::{Direction, Limit},
+    utils::{invalid_mnemonic_byte, mnemonic_matches},
+};
+
+/// Maximum length of character program data
+///
+/// Reference: IEEE 488.2: 7.7.1.4 - Rules
+const MAX_CHARACTER_DATA_LEN: usize = 12;
+
+/// Character program data received by an instrument, e.g. `MAX` in `VOLT MAX`
+///
+/// Comparisons ignore case, and accept both the short and long forms of the special SCPI values,
+/// so `max`, `MAX`, and `MAXimum` all map to [`Limit::Max`].
+///
+/// Reference: IEEE 488.2: 7.7.1 - \<CHARACTER PROGRAM DATA\>
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CharacterProgramData<'a>(&'a str);
+
+impl<'a> CharacterProgramData<'a> {
+    /// Returns the data as it was written.
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+    /// Returns true if the data matches the short or long form of a mnemonic spec like `IMMediate`.
+    ///
+    /// See [`mnemonic_matches`](crate::mnemonic_matches).
+    pub fn matches(&self, spec: &str) -> bool {
+        mnemonic_matches(spec, self.0)
+    }
+    /// Reference: SCPI 1999.0: 7.2.1.2 - MINimum|MAXimum
+    pub fn to_limit(&self) -> Option<Limit> {
+        if self.matches("MINimum") {
+            Some(Limit::Min)
+        } else if self.matches("MAXimum") {
+            Some(Limit::Max)
+        } else {
+            None
+        }
+    }
+    /// Reference: SCPI 1999.0: 7.2.1.1 - DEFault
+    pub fn to_default(&self) -> bool {
+        self.matches("DEFault")
+    }
+    /// Reference: SCPI 1999.0: 7.2.1.3 - UP|DOWN
+    pub fn to_direction(&self) -> Option<Direction> {
+        if self.matches("UP") {
+            Some(Direction::Up)
+        } else if self.matches("DOWN") {
+            Some(Direction::Down)
+        } else {
+            None
+        }
+    }
+    /// Parses the data with the same [`CharacterResponseData`] implementation that is used for
+    /// decoding responses.
+    pub fn parse<T: CharacterResponseData>(&self) -> Option<T> {
+        T::parse(self.0)
+    }
+}
+
+/// Extracts character program data, ignoring surrounding whitespace.
+///
+/// Returns `None` if the input is not valid character program data.
+///
+/// ```
+/// use red_sculpin::{parse::parse_character_program_data, scpi::types::Limit};
+///
+/// let data = parse_character_program_data(b" maximum ").unwrap();
+/// assert_eq!(data.as_str(), "maximum");
+/// assert_eq!(data.to_limit(), Some(Limit::Max));
+/// assert!(parse_character_program_data(b"1.5").is_none());
+/// ```
+///
+/// Reference: IEEE 488.2: 7.7.1 - \<CHARACTER PROGRAM DATA\>
+pub fn parse_character_program_data(input: &[u8]) -> Option<CharacterProgramData<'_>> {
+    // Reference: IEEE 488.2: 7.4.1.2 - Encoding syntax
+    let is_whitespace = |b: &u8| matches!(b, 0x00..=0x09 | 0x0b..=0x20);
+    let start = input.iter().position(|b| !is_whitespace(b))?;
+    let end = input.iter().rposition(|b| !is_whitespace(b))? + 1;
+    let data = &input[start..end];
+    if data.len() > MAX_CHARACTER_DATA_LEN || invalid_mnemonic_byte(data).is_some() {
+        return None;
+    }
+    // Validated data is ASCII
+    str::from_utf8(data).ok().map(CharacterProgramData)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_character_program_data;
+    use crate::scpi::types::{Direction, Limit};
+
+    #[test]
+    fn limits() {
+        for input in ["MIN", "min", "MINIMUM", "Minimum"] {
+            let data = parse_character_program_data(input.as_bytes()).unwrap();
+            assert_eq!(data.to_limit(), Some(Limit::Min));
+        }
+        for input in ["MAX", "max", "MAXIMUM", "maximum"] {
+            let data = parse_character_program_data(input.as_bytes()).unwrap();
+            assert_eq!(data.to_limit(), Some(Limit::Max));
+            assert!(!data.to_default());
+            assert_eq!(data.to_direction(), None);
+        }
+        let data = parse_character_program_data(b"MAXI").unwrap();
+        assert_eq!(data.to_limit(), None);
+    }
+
+    #[test]
+    fn default() {
+        for input in ["DEF", "def", "DEFAULT", "Default"] {
+            let data = parse_character_program_data(input.as_bytes()).unwrap();
+            assert!(data.to_default());
+            assert_eq!(data.to_limit(), None);
+        }
+        assert!(!parse_character_program_data(b"DEFA").unwrap().to_default());
+    }
+
+    #[test]
+    fn directions() {
+        let direction = |input: &[u8]| parse_character_program_data(input).unwrap().to_direction();
+        assert_eq!(direction(b"UP"), Some(Direction::Up));
+        assert_eq!(direction(b"up"), Some(Direction::Up));
+        assert_eq!(direction(b"DOWN"), Some(Direction::Down));
+        assert_eq!(direction(b"Down"), Some(Direction::Down));
+        assert_eq!(direction(b"DOW"), None);
+    }
+
+    #[test]
+    fn unknown_token() {
+        let data = parse_character_program_data(b"\tIMMEDIATE ").unwrap();
+        assert_eq!(data.as_str(), "IMMEDIATE");
+        assert_eq!(data.to_limit(), None);
+        assert!(!data.to_default());
+        assert_eq!(data.to_direction(), None);
+        assert!(data.matches("IMMediate"));
+        assert!(parse_character_program_data(b"imm")
+            .unwrap()
+            .matches("IMMediate"));
+    }
+
+    #[test]
+    fn invalid_data() {
+        for input in [
+            &b""[..],
+            b"  ",
+            b"1MAX",
+            b"MAX MIN",
+            b"\"MAX\"",
+            b"MAX-1",
+            b"MAX\n",
+            b"ABCDEFGHIJKLM",
+        ] {
+            assert_eq!(parse_character_program_data(input), None);
+        }
+        assert!(parse_character_program_data(b"ABCDEFGHIJKL").is_some());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn parse_with_character_response_data() {
+        use crate::CharacterResponseData;
+
+        #[derive(Debug, PartialEq)]
+        enum TriggerSource {
+            Immediate,
+            External,
+        }
+
+        impl CharacterResponseData for TriggerSource {
+            fn parse(text: &str) -> Option<Self> {
+                match text {
+                    "IMM" => Some(TriggerSource::Immediate),
+                    "EXT" => Some(TriggerSource::External),
+                    _ => None,
+                }
+            }
+        }
+
+        let parse = |input: &[u8]| {
+            parse_character_program_data(input)
+                .unwrap()
+                .parse::<TriggerSource>()
+        };
+        assert_eq!(parse(b"IMM"), Some(TriggerSource::Immediate));
+        assert_eq!(parse(b" EXT"), Some(TriggerSource::External));
+        assert_eq!(parse(b"BUS"), None);
+    }
+}
@@ -0,0 +1,317 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::{fmt, str};
+
+use crate::utils::invalid_mnemonic_byte;
+
+/// Maximum length of a program mnemonic
+///
+/// Reference: IEEE 488.2: 7.6.1.4 - Rules
+const MAX_MNEMONIC_LEN: usize = 12;
+
+/// The form of a program header
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HeaderKind {
+    /// A compound header starting with `:`, relative to the root
+    Absolute,
+    /// A compound header without a leading `:`, relative to the current path
+    Relative,
+    /// A common command header starting with `*`
+    Common,
+}
+
+/// Error returned for an invalid program header
+///
+/// Positions are byte offsets into the header.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HeaderError {
+    Empty,
+    EmptyMnemonic { position: usize },
+    InvalidCharacter { position: usize, byte: u8 },
+    MnemonicTooLong { position: usize },
+    SuffixOverflow { position: usize },
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::Empty => write!(f, "empty header"),
+            HeaderError::EmptyMnemonic { position } => {
+                write!(f, "empty mnemonic at position {}", position)
+            }
+            HeaderError::InvalidCharacter { position, byte } => {
+                write!(
+                    f,
+                    "invalid character (0x{:02x}) at position {}",
+                    byte, position
+                )
+            }
+            HeaderError::MnemonicTooLong { position } => {
+                write!(
+                    f,
+                    "mnemonic at position {} is longer than {} characters",
+                    position, MAX_MNEMONIC_LEN
+                )
+            }
+            HeaderError::SuffixOverflow { position } => {
+                write!(f, "numeric suffix at position {} is too large", position)
+            }
+        }
+    }
+}
+
+impl core::error::Error for HeaderError {}
+
+/// Splits a program header into mnemonics and their numeric suffixes
+///
+/// The whole header is validated when the parser is created, and iterating yields
+/// `(mnemonic, suffix)` pairs. For common command headers, the single mnemonic is yielded without
+/// the `*`, and digits are not split into a suffix.
+///
+/// ```
+/// use red_sculpin::parse::{HeaderKind, HeaderParser};
+///
+/// let header = HeaderParser::new(b":SENS2:VOLT:DC:RANG?").unwrap();
+/// assert_eq!(header.kind(), HeaderKind::Absolute);
+/// assert!(header.is_query());
+/// assert!(header.eq([("SENS", Some(2)), ("VOLT", None), ("DC", None), ("RANG", None)]));
+/// ```
+///
+/// References:
+///
+/// - IEEE 488.2: 7.6.1 - \<COMMAND PROGRAM HEADER\>
+/// - IEEE 488.2: 7.6.2 - \<QUERY PROGRAM HEADER\>
+/// - SCPI 1999.0: 6.2.5 - Numeric Suffixes
+#[derive(Clone, Debug)]
+pub struct HeaderParser<'a> {
+    kind: HeaderKind,
+    is_query: bool,
+    rest: &'a str,
+}
+
+impl<'a> HeaderParser<'a> {
+    pub fn new(header: &'a [u8]) -> Result<HeaderParser<'a>, HeaderError> {
+        let (header, is_query) = match header {
+            [head @ .., b'?'] => (head, true),
+            _ => (header, false),
+        };
+        let (kind, offset) = match header {
+            [] => return Err(HeaderError::Empty),
+            [b':', ..] => (HeaderKind::Absolute, 1),
+            [b'*', ..] => (HeaderKind::Common, 1),
+            _ => (HeaderKind::Relative, 0),
+        };
+        let mut position = offset;
+        for mnemonic in header[offset..].split(|&b| b == b':') {
+            validate(mnemonic, position)?;
+            if kind == HeaderKind::Common && position + mnemonic.len() != header.len() {
+                return Err(HeaderError::InvalidCharacter {
+                    position: position + mnemonic.len(),
+                    byte: b':',
+                });
+            }
+            if kind != HeaderKind::Common {
+                let digits = suffix_len(mnemonic);
+                let suffix = &mnemonic[mnemonic.len() - digits..];
+                if digits > 0 && parse_suffix(suffix).is_none() {
+                    return Err(HeaderError::SuffixOverflow {
+                        position: position + mnemonic.len() - digits,
+                    });
+                }
+            }
+            position += mnemonic.len() + 1;
+        }
+        // Validated mnemonics are ASCII, so this can't fail
+        let rest = str::from_utf8(&header[offset..]).unwrap_or_default();
+        Ok(HeaderParser {
+            kind,
+            is_query,
+            rest,
+        })
+    }
+    pub fn kind(&self) -> HeaderKind {
+        self.kind
+    }
+    /// Returns true if the header ends with `?`.
+    pub fn is_query(&self) -> bool {
+        self.is_query
+    }
+}
+
+impl<'a> Iterator for HeaderParser<'a> {
+    type Item = (&'a str, Option<u32>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let (mnemonic, rest) = self.rest.split_once(':').unwrap_or((self.rest, ""));
+        self.rest = rest;
+        if self.kind == HeaderKind::Common {
+            return Some((mnemonic, None));
+        }
+        let (mnemonic, suffix) =
+            mnemonic.split_at(mnemonic.len() - suffix_len(mnemonic.as_bytes()));
+        Some((mnemonic, parse_suffix(suffix.as_bytes())))
+    }
+}
+
+fn validate(mnemonic: &[u8], position: usize) -> Result<(), HeaderError> {
+    if mnemonic.is_empty() {
+        return Err(HeaderError::EmptyMnemonic { position });
+    }
+    if let Some(idx) = invalid_mnemonic_byte(mnemonic) {
+        return Err(HeaderError::InvalidCharacter {
+            position: position + idx,
+            byte: mnemonic[idx],
+        });
+    }
+    if mnemonic.len() > MAX_MNEMONIC_LEN {
+        return Err(HeaderError::MnemonicTooLong { position });
+    }
+    Ok(())
+}
+
+fn suffix_len(mnemonic: &[u8]) -> usize {
+    mnemonic
+        .iter()
+        .rev()
+        .take_while(|b| b.is_ascii_digit())
+        .count()
+}
+
+fn parse_suffix(digits: &[u8]) -> Option<u32> {
+    digits
+        .iter()
+        .try_fold(0u32, |value, &digit| {
+            value.checked_mul(10)?.checked_add(u32::from(digit - b'0'))
+        })
+        .filter(|_| !digits.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeaderError, HeaderKind, HeaderParser};
+
+    fn segments(header: &[u8]) -> ([(&str, Option<u32>); 4], usize) {
+        let mut result = [("", None); 4];
+        let mut count = 0;
+        for (slot, segment) in result.iter_mut().zip(HeaderParser::new(header).unwrap()) {
+            *slot = segment;
+            count += 1;
+        }
+        (result, count)
+    }
+
+    #[test]
+    fn absolute_header() {
+        let header = HeaderParser::new(b":SENS2:VOLT:DC:RANG?").unwrap();
+        assert_eq!(header.kind(), HeaderKind::Absolute);
+        assert!(header.is_query());
+        assert_eq!(
+            segments(b":SENS2:VOLT:DC:RANG?"),
+            (
+                [
+                    ("SENS", Some(2)),
+                    ("VOLT", None),
+                    ("DC", None),
+                    ("RANG", None)
+                ],
+                4
+            )
+        );
+    }
+
+    #[test]
+    fn relative_header() {
+        let header = HeaderParser::new(b"rang:auto").unwrap();
+        assert_eq!(header.kind(), HeaderKind::Relative);
+        assert!(!header.is_query());
+        assert_eq!(
+            segments(b"rang:auto"),
+            ([("rang", None), ("auto", None), ("", None), ("", None)], 2)
+        );
+    }
+
+    #[test]
+    fn common_header() {
+        let header = HeaderParser::new(b"*ESR?").unwrap();
+        assert_eq!(header.kind(), HeaderKind::Common);
+        assert!(header.is_query());
+        assert!(header.eq([("ESR", None)]));
+        assert!(HeaderParser::new(b"*RST").unwrap().eq([("RST", None)]));
+    }
+
+    #[test]
+    fn multi_digit_suffixes() {
+        assert_eq!(
+            segments(b"CHAN12:M4294967295"),
+            (
+                [
+                    ("CHAN", Some(12)),
+                    ("M", Some(4294967295)),
+                    ("", None),
+                    ("", None)
+                ],
+                2
+            )
+        );
+        assert_eq!(
+            HeaderParser::new(b"M4294967296").unwrap_err(),
+            HeaderError::SuffixOverflow { position: 1 }
+        );
+    }
+
+    #[test]
+    fn malformed_headers() {
+        let error = |header: &[u8]| HeaderParser::new(header).unwrap_err();
+        assert_eq!(error(b""), HeaderError::Empty);
+        assert_eq!(error(b"?"), HeaderError::Empty);
+        assert_eq!(error(b":"), HeaderError::EmptyMnemonic { position: 1 });
+        assert_eq!(error(b"*"), HeaderError::EmptyMnemonic { position: 1 });
+        assert_eq!(error(b"VOLT:"), HeaderError::EmptyMnemonic { position: 5 });
+        assert_eq!(error(b"A::B"), HeaderError::EmptyMnemonic { position: 2 });
+        assert_eq!(
+            error(b":VOLT:2DC"),
+            HeaderError::InvalidCharacter {
+                position: 6,
+                byte: b'2'
+            }
+        );
+        assert_eq!(
+            error(b"VOLT-DC"),
+            HeaderError::InvalidCharacter {
+                position: 4,
+                byte: b'-'
+            }
+        );
+        assert_eq!(
+            error(b"VOLT??"),
+            HeaderError::InvalidCharacter {
+                position: 4,
+                byte: b'?'
+            }
+        );
+        assert_eq!(
+            error("V\u{f6}LT".as_bytes()),
+            HeaderError::InvalidCharacter {
+                position: 1,
+                byte: 0xc3
+            }
+        );
+        assert_eq!(
+            error(b"*ESE:X"),
+            HeaderError::InvalidCharacter {
+                position: 4,
+                byte: b':'
+            }
+        );
+        assert_eq!(
+            error(b"SENS:ABCDEFGHIJKLM"),
+            HeaderError::MnemonicTooLong { position: 5 }
+        );
+        assert!(HeaderParser::new(b"SENS:ABCDEFGHIJKL").is_ok());
+    }
+}
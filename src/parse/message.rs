@@ -0,0 +1,312 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::{HeaderError, HeaderKind, HeaderParser, ProgramDataParser, Separator};
+
+/// A header node, a mnemonic and its optional numeric suffix
+pub type HeaderNode<'a> = (&'a str, Option<u32>);
+
+/// A program message unit with its header resolved from the root node
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MessageUnit<'a> {
+    pub kind: HeaderKind,
+    pub is_query: bool,
+    /// The header nodes, starting from the root. Common command headers have a single node without
+    /// the `*`.
+    pub path: Vec<HeaderNode<'a>>,
+    /// The program data without surrounding whitespace and the separator that followed it
+    pub data: &'a [u8],
+}
+
+/// Error returned for an invalid program message
+///
+/// Positions are byte offsets into the message.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SplitError {
+    /// A program message unit has no header, for example because of a leading `;`
+    EmptyUnit { position: usize },
+    /// The header starting at the position is invalid
+    Header { position: usize, error: HeaderError },
+    /// The program data starting at the position is invalid, or the message is not terminated
+    InvalidProgramData { position: usize },
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SplitError::EmptyUnit { position } => {
+                write!(f, "empty program message unit at position {}", position)
+            }
+            SplitError::Header { position, error } => {
+                write!(f, "invalid header at position {}: {}", position, error)
+            }
+            SplitError::InvalidProgramData { position } => {
+                write!(f, "invalid program data at position {}", position)
+            }
+        }
+    }
+}
+
+impl core::error::Error for SplitError {}
+
+/// Splits a program message into program message units, and resolves their headers
+///
+/// Headers are resolved like in an instrument: the message starts at the root node, and after each
+/// compound command or query, the current path is the path of its last node's parent. A header with
+/// a leading `:` starts from the root node, and other compound headers start from the current path.
+/// Common commands and queries don't change the current path.
+///
+/// The message must end with a terminator, and iteration stops after it, or after the first error.
+///
+/// ```
+/// use red_sculpin::parse::MessageUnitSplitter;
+///
+/// let mut units = MessageUnitSplitter::new(b":SENS:VOLT:RANG 10;RANG:AUTO OFF\n");
+/// let unit = units.next().unwrap().unwrap();
+/// assert_eq!(unit.path, [("SENS", None), ("VOLT", None), ("RANG", None)]);
+/// assert_eq!(unit.data, b"10");
+/// let unit = units.next().unwrap().unwrap();
+/// assert_eq!(
+///     unit.path,
+///     [("SENS", None), ("VOLT", None), ("RANG", None), ("AUTO", None)]
+/// );
+/// assert_eq!(unit.data, b"OFF");
+/// assert!(units.next().is_none());
+/// ```
+///
+/// References:
+///
+/// - IEEE 488.2: A.1.1 - Header Tree Traversal
+/// - SCPI 1999.0: 6.2.4 - Traversal of the Header Tree
+#[derive(Clone, Debug)]
+pub struct MessageUnitSplitter<'a> {
+    input: &'a [u8],
+    pos: usize,
+    current_path: Vec<HeaderNode<'a>>,
+    done: bool,
+}
+
+impl<'a> MessageUnitSplitter<'a> {
+    pub fn new(message: &'a [u8]) -> MessageUnitSplitter<'a> {
+        MessageUnitSplitter {
+            input: message,
+            pos: 0,
+            current_path: Vec::new(),
+            done: false,
+        }
+    }
+    fn next_unit(&mut self) -> Result<Option<MessageUnit<'a>>, SplitError> {
+        let start = self.pos + leading_whitespace(&self.input[self.pos..]);
+        let header_len = self.input[start..]
+            .iter()
+            .position(|&byte| matches!(byte, b';' | b'\n') || is_whitespace(byte))
+            .unwrap_or(self.input.len() - start);
+        if header_len == 0 {
+            return match self.input.get(start) {
+                Some(_) => Err(SplitError::EmptyUnit { position: start }),
+                None => Ok(None),
+            };
+        }
+        let header_end = start + header_len;
+        let header = HeaderParser::new(&self.input[start..header_end]).map_err(|error| {
+            SplitError::Header {
+                position: start,
+                error,
+            }
+        })?;
+
+        let invalid = SplitError::InvalidProgramData {
+            position: header_end,
+        };
+        let mut parser = ProgramDataParser::new(&self.input[header_end..]);
+        while parser.next_element().map_err(|_| invalid)?.is_some() {}
+        let separator = parser.end().ok_or(invalid)?;
+        // The separator is always a single byte
+        let data_end = self.input.len() - parser.into_inner().len() - 1;
+        self.pos = data_end + 1;
+        self.done = separator == Separator::Terminator;
+
+        let data = &self.input[header_end..data_end];
+        let trailing = data.iter().rev().take_while(|&&b| is_whitespace(b)).count();
+        let data = &data[..data.len() - trailing];
+        Ok(Some(MessageUnit {
+            kind: header.kind(),
+            is_query: header.is_query(),
+            path: resolve_path(&mut self.current_path, header),
+            data: &data[leading_whitespace(data)..],
+        }))
+    }
+}
+
+impl<'a> Iterator for MessageUnitSplitter<'a> {
+    type Item = Result<MessageUnit<'a>, SplitError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_unit();
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result.transpose()
+    }
+}
+
+/// Resolves the path of a header, and updates the current path.
+pub(crate) fn resolve_path<'a>(
+    current_path: &mut Vec<HeaderNode<'a>>,
+    header: HeaderParser<'a>,
+) -> Vec<HeaderNode<'a>> {
+    match header.kind() {
+        HeaderKind::Common => header.collect(),
+        kind => {
+            if kind == HeaderKind::Absolute {
+                current_path.clear();
+            }
+            let mut path = current_path.clone();
+            path.extend(header);
+            current_path.clone_from(&path);
+            current_path.pop();
+            path
+        }
+    }
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    // Reference: IEEE 488.2: 7.4.1.2 - Encoding syntax
+    matches!(byte, 0x00..=0x09 | 0x0b..=0x20)
+}
+
+fn leading_whitespace(input: &[u8]) -> usize {
+    input.iter().take_while(|&&b| is_whitespace(b)).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{HeaderNode, MessageUnitSplitter, SplitError};
+    use crate::parse::{HeaderError, HeaderKind};
+
+    fn split(message: &[u8]) -> Vec<(HeaderKind, bool, Vec<HeaderNode<'_>>, &[u8])> {
+        MessageUnitSplitter::new(message)
+            .map(|unit| {
+                let unit = unit.unwrap();
+                (unit.kind, unit.is_query, unit.path, unit.data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn relative_headers() {
+        let units = split(b":SENS:VOLT:RANG 10;RANG:AUTO OFF;*OPC\n");
+        assert_eq!(units.len(), 3);
+        assert_eq!(units[0].2, [("SENS", None), ("VOLT", None), ("RANG", None)]);
+        assert_eq!(units[0].3, b"10");
+        assert_eq!(
+            units[1].2,
+            [
+                ("SENS", None),
+                ("VOLT", None),
+                ("RANG", None),
+                ("AUTO", None)
+            ]
+        );
+        assert_eq!(units[1].3, b"OFF");
+        assert_eq!(
+            units[2],
+            (HeaderKind::Common, false, [("OPC", None)].into(), &b""[..])
+        );
+    }
+
+    #[test]
+    fn absolute_headers_reset_the_path() {
+        let units = split(b":SENS:VOLT:RANG 10;:RANG:AUTO OFF\n");
+        assert_eq!(units[1].0, HeaderKind::Absolute);
+        assert_eq!(units[1].2, [("RANG", None), ("AUTO", None)]);
+        let units = split(b"SOUR:VOLT 1\n");
+        assert_eq!(units[0].0, HeaderKind::Relative);
+        assert_eq!(units[0].2, [("SOUR", None), ("VOLT", None)]);
+    }
+
+    #[test]
+    fn common_commands_keep_the_path() {
+        let units = split(b":SOUR:VOLT 1;*CLS;CURR 2;*OPC?;CURR?;:OUTP2 ON\n");
+        let paths: Vec<_> = units
+            .iter()
+            .map(|(_, is_query, path, _)| (*is_query, path.as_slice()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (false, &[("SOUR", None), ("VOLT", None)][..]),
+                (false, &[("CLS", None)][..]),
+                (false, &[("SOUR", None), ("CURR", None)][..]),
+                (true, &[("OPC", None)][..]),
+                (true, &[("SOUR", None), ("CURR", None)][..]),
+                (false, &[("OUTP", Some(2))][..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn data_with_separators() {
+        let units = split(b":DISP:TEXT \"a;b\" , #13;\n; ;*RST \n");
+        assert_eq!(units.len(), 2);
+        assert_eq!(units[0].3, b"\"a;b\" , #13;\n;");
+        assert_eq!(units[1].2, [("RST", None)]);
+    }
+
+    #[test]
+    fn iteration_stops_at_terminator() {
+        let units = split(b"*RST\n*CLS\n");
+        assert_eq!(units.len(), 1);
+        assert_eq!(units[0].2, [("RST", None)]);
+    }
+
+    #[test]
+    fn leading_separator() {
+        let mut units = MessageUnitSplitter::new(b";*RST\n");
+        assert_eq!(
+            units.next(),
+            Some(Err(SplitError::EmptyUnit { position: 0 }))
+        );
+        assert_eq!(units.next(), None);
+
+        let mut units = MessageUnitSplitter::new(b"*RST; ;*CLS\n");
+        assert!(units.next().unwrap().is_ok());
+        assert_eq!(
+            units.next(),
+            Some(Err(SplitError::EmptyUnit { position: 6 }))
+        );
+        assert_eq!(units.next(), None);
+    }
+
+    #[test]
+    fn invalid_messages() {
+        assert_eq!(
+            MessageUnitSplitter::new(b"*RST;:VOLT:2DC 1\n").nth(1),
+            Some(Err(SplitError::Header {
+                position: 5,
+                error: HeaderError::InvalidCharacter {
+                    position: 6,
+                    byte: b'2'
+                }
+            }))
+        );
+        assert_eq!(
+            MessageUnitSplitter::new(b"VOLT @\n").next(),
+            Some(Err(SplitError::InvalidProgramData { position: 4 }))
+        );
+        assert_eq!(
+            MessageUnitSplitter::new(b"*RST").next(),
+            Some(Err(SplitError::InvalidProgramData { position: 4 }))
+        );
+        assert_eq!(MessageUnitSplitter::new(b"").next(), None);
+    }
+}
@@ -0,0 +1,435 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::fmt::{self, Write};
+
+use crate::buffer::ArrayBuffer;
+
+/// Significant digits used for computing the floating point value
+const MAX_SIGNIFICANT_DIGITS: usize = 100;
+
+/// Error returned for invalid decimal numeric program data
+///
+/// Positions are byte offsets into the input.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NumericError {
+    InvalidCharacter {
+        position: usize,
+        byte: u8,
+    },
+    UnexpectedEnd,
+    ExponentTooLarge,
+    /// The value doesn't fit in a `f64`
+    Overflow,
+}
+
+impl fmt::Display for NumericError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NumericError::InvalidCharacter { position, byte } => {
+                write!(
+                    f,
+                    "invalid character (0x{:02x}) at position {}",
+                    byte, position
+                )
+            }
+            NumericError::UnexpectedEnd => write!(f, "unexpected end of numeric data"),
+            NumericError::ExponentTooLarge => write!(f, "exponent is too large"),
+            NumericError::Overflow => write!(f, "numeric value is out of range"),
+        }
+    }
+}
+
+impl core::error::Error for NumericError {}
+
+/// Parsed decimal numeric program data
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParsedNumeric {
+    pub value: f64,
+    /// The exact value, if it is an integer that fits in an `i64`
+    pub integer: Option<i64>,
+    /// The value rounded to an integer, with halfway cases rounded away from zero
+    pub rounded: Option<i64>,
+    /// Number of input bytes that make up the number
+    pub len: usize,
+}
+
+/// Parses decimal numeric program data (NRf) from the start of the input.
+///
+/// The mantissa has an optional sign, and an optional fractional part, so `+1`, `1.`, and `-.5`
+/// are all accepted. It may be followed by whitespace and an exponent with an optional sign, which
+/// may also have whitespace after the `E`. An `E` that isn't followed by exponent digits is left
+/// unconsumed, because it can be the start of a suffix like `EXV`. Whitespace after the number is
+/// not consumed, so `len` points to the suffix or separator that follows.
+///
+/// Integer values are computed from the digits, so they are exact even if the `f64` value isn't.
+///
+/// ```
+/// use red_sculpin::parse::parse_decimal_numeric;
+///
+/// let numeric = parse_decimal_numeric(b"+2.5 e1 KHZ").unwrap();
+/// assert_eq!(numeric.value, 25.0);
+/// assert_eq!(numeric.integer, Some(25));
+/// assert_eq!(numeric.len, 7);
+/// assert_eq!(parse_decimal_numeric(b"-2.5").unwrap().rounded, Some(-3));
+/// ```
+///
+/// Reference: IEEE 488.2: 7.7.2 - \<DECIMAL NUMERIC PROGRAM DATA\>
+pub fn parse_decimal_numeric(input: &[u8]) -> Result<ParsedNumeric, NumericError> {
+    let mut pos = 0;
+    let negative = match input.first() {
+        Some(b'-') => {
+            pos += 1;
+            true
+        }
+        Some(b'+') => {
+            pos += 1;
+            false
+        }
+        _ => false,
+    };
+    let int_digits = digits(input, &mut pos);
+    let frac_digits = match input.get(pos) {
+        Some(b'.') => {
+            pos += 1;
+            digits(input, &mut pos)
+        }
+        _ => &[][..],
+    };
+    if int_digits.is_empty() && frac_digits.is_empty() {
+        return Err(match input.get(pos) {
+            Some(&byte) => NumericError::InvalidCharacter {
+                position: pos,
+                byte,
+            },
+            None => NumericError::UnexpectedEnd,
+        });
+    }
+    let mut len = pos;
+    let mut exponent = 0;
+    pos = skip_whitespace(input, pos);
+    if let Some(b'E' | b'e') = input.get(pos) {
+        pos = skip_whitespace(input, pos + 1);
+        let exp_negative = match input.get(pos) {
+            Some(b'-') => {
+                pos += 1;
+                true
+            }
+            Some(b'+') => {
+                pos += 1;
+                false
+            }
+            Some(b'0'..=b'9') => false,
+            // Not an exponent, but a suffix starting with E
+            _ => return finish(negative, int_digits, frac_digits, 0, len),
+        };
+        let exp_digits = digits(input, &mut pos);
+        if exp_digits.is_empty() {
+            return Err(match input.get(pos) {
+                Some(&byte) => NumericError::InvalidCharacter {
+                    position: pos,
+                    byte,
+                },
+                None => NumericError::UnexpectedEnd,
+            });
+        }
+        exponent = exp_digits
+            .iter()
+            .try_fold(0i32, |value, &digit| {
+                value.checked_mul(10)?.checked_add(i32::from(digit - b'0'))
+            })
+            .ok_or(NumericError::ExponentTooLarge)?;
+        if exp_negative {
+            exponent = -exponent;
+        }
+        len = pos;
+    }
+    finish(negative, int_digits, frac_digits, exponent, len)
+}
+
+fn finish(
+    negative: bool,
+    int_digits: &[u8],
+    frac_digits: &[u8],
+    exponent: i32,
+    len: usize,
+) -> Result<ParsedNumeric, NumericError> {
+    // The value is `significand * 10^scale`, where `significand` has no leading or trailing zeros
+    let mut digits = Digits(int_digits, frac_digits);
+    let leading = digits.iter().take_while(|&d| d == b'0').count();
+    let trailing = digits.iter().rev().take_while(|&d| d == b'0').count();
+    let total = int_digits.len() + frac_digits.len();
+    if leading == total {
+        let zero = ParsedNumeric {
+            value: if negative { -0.0 } else { 0.0 },
+            integer: Some(0),
+            rounded: Some(0),
+            len,
+        };
+        return Ok(zero);
+    }
+    digits.skip(leading, trailing);
+    let significand_len = total - leading - trailing;
+    let scale = i64::from(exponent) - frac_digits.len() as i64 + trailing as i64;
+
+    let integer = if scale >= 0 {
+        magnitude(digits.iter())
+            .and_then(|magnitude| (0..scale).try_fold(magnitude, |value, _| value.checked_mul(10)))
+            .and_then(|magnitude| to_i64(negative, magnitude))
+    } else {
+        None
+    };
+    let rounded = if scale >= 0 {
+        integer
+    } else {
+        // Significant digits before the decimal point, which may be none
+        let int_len = significand_len as i64 + scale;
+        let kept = int_len.max(0) as usize;
+        let round_up = int_len >= 0 && digits.iter().nth(kept).is_some_and(|digit| digit >= b'5');
+        magnitude(digits.iter().take(kept))
+            .and_then(|magnitude| magnitude.checked_add(u64::from(round_up)))
+            .and_then(|magnitude| to_i64(negative, magnitude))
+    };
+
+    let mut text: ArrayBuffer<{ MAX_SIGNIFICANT_DIGITS + 32 }> = ArrayBuffer::new();
+    let mut scale = scale;
+    if negative {
+        let _ = text.push(b'-');
+    }
+    for digit in digits.iter().take(MAX_SIGNIFICANT_DIGITS) {
+        let _ = text.push(digit);
+    }
+    if significand_len > MAX_SIGNIFICANT_DIGITS {
+        // A nonzero digit in place of the dropped ones keeps the rounding direction
+        let _ = text.push(b'1');
+        scale += (significand_len - MAX_SIGNIFICANT_DIGITS - 1) as i64;
+    }
+    let res = write!(&mut text, "E{}", scale);
+    debug_assert_eq!(res, Ok(()));
+    let value: f64 = text
+        .as_str()
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or(NumericError::Overflow)?;
+    if value.is_infinite() {
+        return Err(NumericError::Overflow);
+    }
+    Ok(ParsedNumeric {
+        value,
+        integer,
+        rounded,
+        len,
+    })
+}
+
+/// The mantissa digits, without the decimal point
+#[derive(Copy, Clone)]
+struct Digits<'a>(&'a [u8], &'a [u8]);
+
+impl<'a> Digits<'a> {
+    fn iter(&self) -> impl DoubleEndedIterator<Item = u8> + 'a {
+        self.0.iter().chain(self.1.iter()).copied()
+    }
+    fn skip(&mut self, leading: usize, trailing: usize) {
+        let (int, frac) = (self.0, self.1);
+        let int_leading = leading.min(int.len());
+        let frac_trailing = trailing.min(frac.len());
+        let int = &int[int_leading..];
+        let frac = &frac[leading - int_leading..];
+        let frac = &frac[..frac.len() - frac_trailing];
+        let int = &int[..int.len() - (trailing - frac_trailing)];
+        *self = Digits(int, frac);
+    }
+}
+
+fn magnitude(mut digits: impl Iterator<Item = u8>) -> Option<u64> {
+    digits.try_fold(0u64, |value, digit| {
+        value.checked_mul(10)?.checked_add(u64::from(digit - b'0'))
+    })
+}
+
+fn to_i64(negative: bool, magnitude: u64) -> Option<i64> {
+    if negative {
+        0i64.checked_sub_unsigned(magnitude)
+    } else {
+        i64::try_from(magnitude).ok()
+    }
+}
+
+fn digits<'a>(input: &'a [u8], pos: &mut usize) -> &'a [u8] {
+    let start = *pos;
+    while input.get(*pos).is_some_and(u8::is_ascii_digit) {
+        *pos += 1;
+    }
+    &input[start..*pos]
+}
+
+fn skip_whitespace(input: &[u8], mut pos: usize) -> usize {
+    // Reference: IEEE 488.2: 7.4.1.2 - Encoding syntax
+    while let Some(0x00..=0x09 | 0x0b..=0x20) = input.get(pos) {
+        pos += 1;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_decimal_numeric, NumericError, ParsedNumeric};
+
+    fn parse(input: &str) -> ParsedNumeric {
+        parse_decimal_numeric(input.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn mantissa_forms() {
+        for (input, value) in [
+            ("1", 1.0),
+            ("+1", 1.0),
+            ("-1", -1.0),
+            ("1.", 1.0),
+            ("1.5", 1.5),
+            (".5", 0.5),
+            ("-.5", -0.5),
+            ("000123.4500", 123.45),
+            ("0", 0.0),
+            ("-0.0", 0.0),
+        ] {
+            let numeric = parse(input);
+            assert_eq!(numeric.value, value, "{}", input);
+            assert_eq!(numeric.len, input.len(), "{}", input);
+        }
+        assert_eq!(parse("12").integer, Some(12));
+        assert_eq!(parse("12.000").integer, Some(12));
+        assert_eq!(parse("-0.0").integer, Some(0));
+        assert_eq!(parse("12.5").integer, None);
+    }
+
+    #[test]
+    fn exponents() {
+        for (input, value) in [
+            ("1E3", 1e3),
+            ("1e3", 1e3),
+            ("1E+3", 1e3),
+            ("1E-3", 1e-3),
+            ("1.5E003", 1.5e3),
+            ("1 E 3", 1e3),
+            ("1\tE -3", 1e-3),
+            ("0.000012E6", 12.0),
+        ] {
+            let numeric = parse(input);
+            assert_eq!(numeric.value, value, "{}", input);
+            assert_eq!(numeric.len, input.len(), "{}", input);
+        }
+        assert_eq!(parse("1.5E3").integer, Some(1500));
+        assert_eq!(parse("0.000012E6").integer, Some(12));
+        assert_eq!(parse("15E-1").integer, None);
+    }
+
+    #[test]
+    fn trailing_data_is_not_consumed() {
+        assert_eq!(parse("10 KHZ").len, 2);
+        assert_eq!(parse("10KHZ").len, 2);
+        assert_eq!(parse("1,2").len, 1);
+        assert_eq!(parse("1.5 ;").len, 3);
+        let numeric = parse("1 EXV");
+        assert_eq!((numeric.value, numeric.len), (1.0, 1));
+        let numeric = parse("2E3V");
+        assert_eq!((numeric.value, numeric.len), (2e3, 3));
+    }
+
+    #[test]
+    fn rounding() {
+        for (input, rounded) in [
+            ("0.4", 0),
+            ("0.5", 1),
+            ("-0.5", -1),
+            ("1.5", 2),
+            ("2.5", 3),
+            ("-2.5", -3),
+            ("2.49999", 2),
+            ("0.05", 0),
+            ("4.5E-1", 0),
+            ("5E-1", 1),
+            ("1E-20", 0),
+            ("123.5E1", 1235),
+            ("12345E-2", 123),
+            ("-9.5", -10),
+        ] {
+            assert_eq!(parse(input).rounded, Some(rounded), "{}", input);
+        }
+    }
+
+    #[test]
+    fn integer_overflow() {
+        assert_eq!(parse("9223372036854775807").integer, Some(i64::MAX));
+        assert_eq!(parse("-9223372036854775808").integer, Some(i64::MIN));
+        let numeric = parse("9223372036854775808");
+        assert_eq!(numeric.integer, None);
+        assert_eq!(numeric.rounded, None);
+        assert_eq!(numeric.value, 9223372036854775808.0);
+        assert_eq!(parse("1E19").integer, None);
+        assert_eq!(parse("9223372036854775807.5").rounded, None);
+        assert_eq!(parse("1E300").value, 1e300);
+    }
+
+    #[test]
+    fn float_overflow() {
+        assert_eq!(parse_decimal_numeric(b"1E400"), Err(NumericError::Overflow));
+        assert_eq!(parse("1E-400").value, 0.0);
+        assert_eq!(parse("0E99999").value, 0.0);
+        assert_eq!(
+            parse_decimal_numeric(b"1E99999999999"),
+            Err(NumericError::ExponentTooLarge)
+        );
+    }
+
+    #[test]
+    fn long_mantissa() {
+        let mut input = [b'0'; 300];
+        input[0] = b'1';
+        input[299] = b'1';
+        let numeric = parse_decimal_numeric(&input).unwrap();
+        assert_eq!(numeric.value, 1e299);
+        assert_eq!(numeric.len, 300);
+        assert_eq!(numeric.integer, None);
+    }
+
+    #[test]
+    fn malformed() {
+        let error = |input: &[u8]| parse_decimal_numeric(input).unwrap_err();
+        assert_eq!(error(b""), NumericError::UnexpectedEnd);
+        assert_eq!(error(b"+"), NumericError::UnexpectedEnd);
+        assert_eq!(error(b"."), NumericError::UnexpectedEnd);
+        assert_eq!(
+            error(b"-.E3"),
+            NumericError::InvalidCharacter {
+                position: 2,
+                byte: b'E'
+            }
+        );
+        assert_eq!(
+            error(b"MAX"),
+            NumericError::InvalidCharacter {
+                position: 0,
+                byte: b'M'
+            }
+        );
+        assert_eq!(error(b"1E+"), NumericError::UnexpectedEnd);
+        assert_eq!(error(b"1E -"), NumericError::UnexpectedEnd);
+        assert_eq!(
+            error(b"1E+V"),
+            NumericError::InvalidCharacter {
+                position: 3,
+                byte: b'V'
+            }
+        );
+        assert_eq!(
+            error(b"1E--3"),
+            NumericError::InvalidCharacter {
+                position: 3,
+                byte: b'-'
+            }
+        );
+    }
+}
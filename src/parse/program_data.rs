@@ -0,0 +1,870 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::{string::String, vec::Vec};
+
+use super::{parse_character_program_data, CharacterProgramData};
+use crate::{decode::DecodeError, ByteSink, ByteSource};
+
+const CHUNK_SIZE: usize = 512;
+/// Maximum number of bytes reserved up front for a definite length block, since the declared
+/// length comes from the controller
+const MAX_RESERVE: usize = 128 * CHUNK_SIZE;
+
+/// The separator or terminator that followed a program data element
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Separator {
+    /// Reference: IEEE 488.2: 7.4.2 - \<PROGRAM DATA SEPARATOR\>
+    Data,
+    /// Reference: IEEE 488.2: 7.4.1 - \<PROGRAM MESSAGE UNIT SEPARATOR\>
+    MessageUnit,
+    /// Reference: IEEE 488.2: 7.5 - \<PROGRAM MESSAGE TERMINATOR\>
+    Terminator,
+}
+
+/// Decimal numeric program data, with an optional suffix
+///
+/// References:
+///
+/// - IEEE 488.2: 7.7.2 - \<DECIMAL NUMERIC PROGRAM DATA\>
+/// - IEEE 488.2: 7.7.3 - \<SUFFIX PROGRAM DATA\>
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DecimalNumeric {
+    /// The number without whitespace, e.g. `-1.5E3`
+    pub text: String,
+    /// The suffix as written, e.g. `MHZ`
+    pub suffix: Option<String>,
+}
+
+impl DecimalNumeric {
+    /// Parses the number, ignoring the suffix.
+    pub fn value(&self) -> Option<f64> {
+        self.text.parse().ok()
+    }
+}
+
+/// A single program data element, classified but not interpreted
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ProgramDataElement {
+    /// Reference: IEEE 488.2: 7.7.1 - \<CHARACTER PROGRAM DATA\>
+    Characters(String),
+    /// Reference: IEEE 488.2: 7.7.2 - \<DECIMAL NUMERIC PROGRAM DATA\>
+    DecimalNumeric(DecimalNumeric),
+    /// Non-decimal numeric digits, and their radix (16, 8, or 2)
+    ///
+    /// Reference: IEEE 488.2: 7.7.4 - \<NONDECIMAL NUMERIC PROGRAM DATA\>
+    NonDecimalNumeric { radix: u32, digits: String },
+    /// Unescaped string contents, with either quote style
+    ///
+    /// Reference: IEEE 488.2: 7.7.5 - \<STRING PROGRAM DATA\>
+    String(String),
+    /// Reference: IEEE 488.2: 7.7.6 - \<ARBITRARY BLOCK PROGRAM DATA\>
+    Block(Vec<u8>),
+    /// The text between the outermost parentheses
+    ///
+    /// Reference: IEEE 488.2: 7.7.7 - \<EXPRESSION PROGRAM DATA\>
+    Expression(String),
+}
+
+/// The form that boolean program data was written in
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BooleanForm {
+    /// `ON` or `OFF`
+    Characters,
+    /// A decimal numeric value
+    Numeric,
+}
+
+/// Boolean program data received by an instrument
+///
+/// Reference: SCPI 1999.0: 7.3 - Boolean Program Data
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BooleanProgramData {
+    pub value: bool,
+    pub form: BooleanForm,
+}
+
+impl ProgramDataElement {
+    /// Returns the element as character program data, if it is one.
+    pub fn as_characters(&self) -> Option<CharacterProgramData<'_>> {
+        match self {
+            ProgramDataElement::Characters(text) => parse_character_program_data(text.as_bytes()),
+            _ => None,
+        }
+    }
+    /// Interprets the element as boolean program data.
+    ///
+    /// `ON` and `OFF` are accepted in any case. Decimal numeric values without a suffix are rounded
+    /// to an integer, and any nonzero integer is true. Rounding happens before the comparison, so
+    /// `0.1` is false and `0.5` is true.
+    ///
+    /// Reference: SCPI 1999.0: 7.3 - Boolean Program Data
+    pub fn to_boolean(&self) -> Option<BooleanProgramData> {
+        let (value, form) = match self {
+            ProgramDataElement::Characters(text) if text.eq_ignore_ascii_case("ON") => {
+                (true, BooleanForm::Characters)
+            }
+            ProgramDataElement::Characters(text) if text.eq_ignore_ascii_case("OFF") => {
+                (false, BooleanForm::Characters)
+            }
+            ProgramDataElement::DecimalNumeric(numeric) if numeric.suffix.is_none() => {
+                let value = numeric.value()?;
+                (!(-0.5 < value && value < 0.5), BooleanForm::Numeric)
+            }
+            _ => return None,
+        };
+        Some(BooleanProgramData { value, form })
+    }
+}
+
+/// Tokenizer for the program data of a single program message unit
+///
+/// The parser is positioned after a program header, and yields program data elements together with
+/// the separator that followed each of them. Whitespace allowed around separators is skipped.
+/// Elements are only classified, so interpreting them is up to the caller.
+///
+/// ```
+/// use red_sculpin::parse::{ProgramDataElement, ProgramDataParser, Separator};
+///
+/// let mut parser = ProgramDataParser::new(&b" MAX, \"text\"\n"[..]);
+/// assert_eq!(
+///     parser.next_element(),
+///     Ok(Some((ProgramDataElement::Characters("MAX".into()), Separator::Data)))
+/// );
+/// assert_eq!(
+///     parser.next_element(),
+///     Ok(Some((ProgramDataElement::String("text".into()), Separator::Terminator)))
+/// );
+/// assert_eq!(parser.next_element(), Ok(None));
+/// ```
+pub struct ProgramDataParser<S: ByteSource> {
+    source: S,
+    peeked: Option<u8>,
+    end: Option<Separator>,
+}
+
+impl<S: ByteSource> ProgramDataParser<S> {
+    pub fn new(source: S) -> ProgramDataParser<S> {
+        ProgramDataParser {
+            source,
+            peeked: None,
+            end: None,
+        }
+    }
+    /// Returns the separator that ended the program message unit, once it has been reached.
+    pub fn end(&self) -> Option<Separator> {
+        self.end
+    }
+    /// Returns the byte source, positioned after the program message unit if it has ended.
+    ///
+    /// A peeked byte is lost if the unit has not ended.
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+    /// Parses the next program data element as boolean program data.
+    ///
+    /// Returns an error if the element is not boolean program data, see
+    /// [`ProgramDataElement::to_boolean`].
+    pub fn parse_boolean_program_data(
+        &mut self,
+    ) -> Result<Option<(BooleanProgramData, Separator)>, S::Error> {
+        match self.next_element()? {
+            Some((element, separator)) => match element.to_boolean() {
+                Some(boolean) => Ok(Some((boolean, separator))),
+                None => Err(DecodeError::Parse.into()),
+            },
+            None => Ok(None),
+        }
+    }
+    /// Parses the next program data element as arbitrary block program data, and writes its
+    /// payload to the target.
+    ///
+    /// Returns the separator that followed the block, or `None` if the program message unit has no
+    /// more program data. An indefinite length block always ends with the terminator. A truncated
+    /// payload is reported as [`DecodeError::UnexpectedEnd`], and a target that can't hold the
+    /// payload as [`DecodeError::BufferOverflow`]. Other elements, and headers without a length
+    /// digit, are rejected with [`DecodeError::Parse`].
+    ///
+    /// ```
+    /// use red_sculpin::parse::{ProgramDataParser, Separator};
+    ///
+    /// let mut parser = ProgramDataParser::new(&b"#15hello,#0a;b\n"[..]);
+    /// let mut payload = Vec::new();
+    /// assert_eq!(parser.parse_arbitrary_block(&mut payload), Ok(Some(Separator::Data)));
+    /// assert_eq!(payload, b"hello");
+    /// payload.clear();
+    /// assert_eq!(parser.parse_arbitrary_block(&mut payload), Ok(Some(Separator::Terminator)));
+    /// assert_eq!(payload, b"a;b");
+    /// ```
+    ///
+    /// Reference: IEEE 488.2: 7.7.6 - \<ARBITRARY BLOCK PROGRAM DATA\>
+    pub fn parse_arbitrary_block<T: ByteSink>(
+        &mut self,
+        target: &mut T,
+    ) -> Result<Option<Separator>, S::Error> {
+        match self.block_start()? {
+            Some(length) => {
+                self.block_payload(length, target)?;
+                self.block_end().map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+    /// Skips to the next element, and parses its arbitrary block header.
+    ///
+    /// Returns `None` if the program message unit has no more program data.
+    fn block_start(&mut self) -> Result<Option<Option<usize>>, S::Error> {
+        if self.end.is_some() {
+            return Ok(None);
+        }
+        self.skip_whitespace()?;
+        match self.peek_byte()? {
+            b';' | b'\n' => {
+                self.separator()?;
+                return Ok(None);
+            }
+            b'#' => self.read_byte()?,
+            _ => return Err(DecodeError::Parse.into()),
+        };
+        let byte = self.read_byte()?;
+        self.block_length(byte).map(Some)
+    }
+    fn block_end(&mut self) -> Result<Separator, S::Error> {
+        match self.end {
+            // Indefinite length blocks consume the terminator
+            Some(separator) => Ok(separator),
+            None => {
+                self.skip_whitespace()?;
+                self.separator()
+            }
+        }
+    }
+    /// Returns the next program data element, and the separator that followed it.
+    ///
+    /// Returns `None` once the program message unit has ended, or if it has no program data.
+    pub fn next_element(&mut self) -> Result<Option<(ProgramDataElement, Separator)>, S::Error> {
+        if self.end.is_some() {
+            return Ok(None);
+        }
+        self.skip_whitespace()?;
+        let element = match self.peek_byte()? {
+            b';' | b'\n' => {
+                self.separator()?;
+                return Ok(None);
+            }
+            b'0'..=b'9' | b'+' | b'-' | b'.' => self.decimal_numeric()?,
+            byte if byte.is_ascii_alphabetic() => {
+                ProgramDataElement::Characters(self.take_while(is_mnemonic_byte)?)
+            }
+            b'"' | b'\'' => self.string()?,
+            b'#' => self.hash()?,
+            b'(' => self.expression()?,
+            _ => return Err(DecodeError::Parse.into()),
+        };
+        if let ProgramDataElement::Block(_) = element {
+            // Indefinite length blocks consume the terminator
+            if self.end == Some(Separator::Terminator) {
+                return Ok(Some((element, Separator::Terminator)));
+            }
+        }
+        self.skip_whitespace()?;
+        let separator = self.separator()?;
+        Ok(Some((element, separator)))
+    }
+
+    fn read_byte(&mut self) -> Result<u8, S::Error> {
+        match self.peeked.take() {
+            Some(byte) => Ok(byte),
+            None => self.source.read_byte(),
+        }
+    }
+    fn peek_byte(&mut self) -> Result<u8, S::Error> {
+        match self.peeked {
+            Some(byte) => Ok(byte),
+            None => {
+                let byte = self.source.read_byte()?;
+                self.peeked = Some(byte);
+                Ok(byte)
+            }
+        }
+    }
+    fn skip_whitespace(&mut self) -> Result<(), S::Error> {
+        // Reference: IEEE 488.2: 7.4.1.2 - Encoding syntax
+        while matches!(self.peek_byte()?, 0x00..=0x09 | 0x0b..=0x20) {
+            self.read_byte()?;
+        }
+        Ok(())
+    }
+    fn separator(&mut self) -> Result<Separator, S::Error> {
+        let separator = match self.read_byte()? {
+            b',' => return Ok(Separator::Data),
+            b';' => Separator::MessageUnit,
+            b'\n' => Separator::Terminator,
+            _ => return Err(DecodeError::Parse.into()),
+        };
+        self.end = Some(separator);
+        Ok(separator)
+    }
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> Result<String, S::Error> {
+        let mut text = String::new();
+        while f(self.peek_byte()?) {
+            text.push(self.read_byte()? as char);
+        }
+        Ok(text)
+    }
+    fn digits(&mut self, text: &mut String) -> Result<usize, S::Error> {
+        let digits = self.take_while(|byte| byte.is_ascii_digit())?;
+        text.push_str(&digits);
+        Ok(digits.len())
+    }
+    fn decimal_numeric(&mut self) -> Result<ProgramDataElement, S::Error> {
+        let mut text = String::new();
+        if let b'+' | b'-' = self.peek_byte()? {
+            text.push(self.read_byte()? as char);
+        }
+        let mut digits = self.digits(&mut text)?;
+        if self.peek_byte()? == b'.' {
+            text.push(self.read_byte()? as char);
+            digits += self.digits(&mut text)?;
+        }
+        if digits == 0 {
+            return Err(DecodeError::Parse.into());
+        }
+        self.skip_whitespace()?;
+        let mut suffix = String::new();
+        if let b'E' | b'e' = self.peek_byte()? {
+            self.read_byte()?;
+            self.skip_whitespace()?;
+            match self.peek_byte()? {
+                b'0'..=b'9' | b'+' | b'-' => {
+                    text.push('E');
+                    if let b'+' | b'-' = self.peek_byte()? {
+                        text.push(self.read_byte()? as char);
+                    }
+                    if self.digits(&mut text)? == 0 {
+                        return Err(DecodeError::Parse.into());
+                    }
+                    self.skip_whitespace()?;
+                }
+                // Not an exponent, but a suffix starting with E
+                _ => suffix.push('E'),
+            }
+        }
+        suffix.push_str(&self.take_while(is_suffix_byte)?);
+        Ok(ProgramDataElement::DecimalNumeric(DecimalNumeric {
+            text,
+            suffix: if suffix.is_empty() {
+                None
+            } else {
+                Some(suffix)
+            },
+        }))
+    }
+    fn string(&mut self) -> Result<ProgramDataElement, S::Error> {
+        let quote = self.read_byte()?;
+        let mut text = String::new();
+        loop {
+            match self.read_byte()? {
+                byte if byte == quote => {
+                    if self.peek_byte()? == quote {
+                        text.push(self.read_byte()? as char);
+                    } else {
+                        break Ok(ProgramDataElement::String(text));
+                    }
+                }
+                byte if byte.is_ascii() => text.push(byte as char),
+                _ => break Err(DecodeError::Parse.into()),
+            }
+        }
+    }
+    fn hash(&mut self) -> Result<ProgramDataElement, S::Error> {
+        self.read_byte()?;
+        let radix = match self.read_byte()? {
+            b'H' | b'h' => 16,
+            b'Q' | b'q' => 8,
+            b'B' | b'b' => 2,
+            byte @ b'0'..=b'9' => {
+                let length = self.block_length(byte)?;
+                let mut data = Vec::new();
+                self.block_payload(length, &mut data)?;
+                return Ok(ProgramDataElement::Block(data));
+            }
+            _ => return Err(DecodeError::Parse.into()),
+        };
+        let digits = self.take_while(|byte| byte.is_ascii_alphanumeric())?;
+        if digits.is_empty() || !digits.chars().all(|ch| ch.is_digit(radix)) {
+            return Err(DecodeError::Parse.into());
+        }
+        Ok(ProgramDataElement::NonDecimalNumeric {
+            radix,
+            digits: digits.to_ascii_uppercase(),
+        })
+    }
+    /// Parses the rest of an arbitrary block header after the `#`, starting from the given digit.
+    ///
+    /// Returns the block size for the definite length format, or `None` for the indefinite length
+    /// format.
+    fn block_length(&mut self, byte: u8) -> Result<Option<usize>, S::Error> {
+        match byte {
+            b'0' => Ok(None),
+            b'1'..=b'9' => {
+                let mut len = 0usize;
+                for _ in 0..(byte - b'0') {
+                    match self.read_byte()? {
+                        digit @ b'0'..=b'9' => len = len * 10 + usize::from(digit - b'0'),
+                        _ => return Err(DecodeError::Parse.into()),
+                    }
+                }
+                Ok(Some(len))
+            }
+            _ => Err(DecodeError::Parse.into()),
+        }
+    }
+    fn block_payload<T: ByteSink>(
+        &mut self,
+        length: Option<usize>,
+        target: &mut T,
+    ) -> Result<(), S::Error> {
+        let overflow = |_| S::Error::from(DecodeError::BufferOverflow);
+        match length {
+            Some(len) => {
+                target.reserve(len.min(MAX_RESERVE));
+                let mut buf = [0; CHUNK_SIZE];
+                let mut remaining = len;
+                while remaining > 0 {
+                    let chunk = &mut buf[..remaining.min(CHUNK_SIZE)];
+                    for byte in chunk.iter_mut() {
+                        *byte = self.read_byte()?;
+                    }
+                    target.write_bytes(chunk).map_err(overflow)?;
+                    remaining -= chunk.len();
+                }
+            }
+            // The indefinite length format ends with the program message terminator
+            None => {
+                loop {
+                    match self.read_byte()? {
+                        b'\n' => break,
+                        byte => target.write_byte(byte).map_err(overflow)?,
+                    }
+                }
+                self.end = Some(Separator::Terminator);
+            }
+        }
+        Ok(())
+    }
+    fn expression(&mut self) -> Result<ProgramDataElement, S::Error> {
+        self.read_byte()?;
+        let mut text = String::new();
+        let mut depth = 0;
+        loop {
+            match self.read_byte()? {
+                b')' if depth == 0 => break Ok(ProgramDataElement::Expression(text)),
+                byte @ b')' => {
+                    depth -= 1;
+                    text.push(byte as char);
+                }
+                byte @ b'(' => {
+                    depth += 1;
+                    text.push(byte as char);
+                }
+                b'"' | b'\'' | b';' | b'\n' => break Err(DecodeError::Parse.into()),
+                byte if byte.is_ascii() => text.push(byte as char),
+                _ => break Err(DecodeError::Parse.into()),
+            }
+        }
+    }
+}
+
+/// Zero-copy parsing when the whole program message is already in memory.
+impl<'a> ProgramDataParser<&'a [u8]> {
+    /// Parses the next program data element as arbitrary block program data, borrowing the payload
+    /// from the input.
+    ///
+    /// Works like [`parse_arbitrary_block`](ProgramDataParser::parse_arbitrary_block), but returns
+    /// the payload together with the separator.
+    ///
+    /// ```
+    /// use red_sculpin::parse::{ProgramDataParser, Separator};
+    ///
+    /// let mut parser = ProgramDataParser::new(&b"#0\x00\x01\n"[..]);
+    /// assert_eq!(
+    ///     parser.parse_block_borrowed(),
+    ///     Ok(Some((&[0, 1][..], Separator::Terminator)))
+    /// );
+    /// ```
+    ///
+    /// Reference: IEEE 488.2: 7.7.6 - \<ARBITRARY BLOCK PROGRAM DATA\>
+    pub fn parse_block_borrowed(&mut self) -> Result<Option<(&'a [u8], Separator)>, DecodeError> {
+        let length = match self.block_start()? {
+            Some(length) => length,
+            None => return Ok(None),
+        };
+        // The header is read with `read_byte`, so there is no peeked byte
+        debug_assert!(self.peeked.is_none());
+        let data = match length {
+            Some(len) if len <= self.source.len() => {
+                let (data, rest) = self.source.split_at(len);
+                self.source = rest;
+                data
+            }
+            Some(_) => return Err(DecodeError::UnexpectedEnd),
+            None => {
+                let len = self
+                    .source
+                    .iter()
+                    .position(|&byte| byte == b'\n')
+                    .ok_or(DecodeError::UnexpectedEnd)?;
+                let (data, rest) = self.source.split_at(len);
+                self.source = &rest[1..];
+                self.end = Some(Separator::Terminator);
+                data
+            }
+        };
+        Ok(Some((data, self.block_end()?)))
+    }
+}
+
+fn is_mnemonic_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+fn is_suffix_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'/' | b'.' | b'-')
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, vec::Vec};
+    use matches::assert_matches;
+
+    use super::{
+        BooleanForm, BooleanProgramData, DecimalNumeric, ProgramDataElement, ProgramDataParser,
+        Separator,
+    };
+    use crate::{
+        adapters::FnSink,
+        decode::DecodeError,
+        encode::{EncodeError, Encoder},
+        scpi::types::{Direction, Limit},
+    };
+
+    fn numeric(text: &str, suffix: Option<&str>) -> ProgramDataElement {
+        ProgramDataElement::DecimalNumeric(DecimalNumeric {
+            text: text.into(),
+            suffix: suffix.map(String::from),
+        })
+    }
+
+    fn parse_all(data: &[u8]) -> Result<Vec<(ProgramDataElement, Separator)>, DecodeError> {
+        let mut parser = ProgramDataParser::new(data);
+        let mut result = Vec::new();
+        while let Some(element) = parser.next_element()? {
+            result.push(element);
+        }
+        Ok(result)
+    }
+
+    #[test]
+    fn all_element_kinds() {
+        let data = b" 1.5E+3 MHZ , -.5 e -2,+7,4 E,VOLT_2 ,\"a\"\"b\",'c''d\"', #15a\nb,c,\
+                     #HfF,#q17,#B01,(@1,(2:3)) ;";
+        let mut parser = ProgramDataParser::new(&data[..]);
+        let mut next = || parser.next_element().unwrap().unwrap();
+        assert_eq!(next(), (numeric("1.5E+3", Some("MHZ")), Separator::Data));
+        assert_eq!(next(), (numeric("-.5E-2", None), Separator::Data));
+        assert_eq!(next(), (numeric("+7", None), Separator::Data));
+        assert_eq!(next(), (numeric("4", Some("E")), Separator::Data));
+        assert_eq!(
+            next(),
+            (
+                ProgramDataElement::Characters("VOLT_2".into()),
+                Separator::Data
+            )
+        );
+        assert_eq!(
+            next(),
+            (ProgramDataElement::String("a\"b".into()), Separator::Data)
+        );
+        assert_eq!(
+            next(),
+            (ProgramDataElement::String("c'd\"".into()), Separator::Data)
+        );
+        assert_eq!(
+            next(),
+            (
+                ProgramDataElement::Block(b"a\nb,c".to_vec()),
+                Separator::Data
+            )
+        );
+        assert_eq!(
+            next(),
+            (
+                ProgramDataElement::NonDecimalNumeric {
+                    radix: 16,
+                    digits: "FF".into()
+                },
+                Separator::Data
+            )
+        );
+        assert_eq!(
+            next(),
+            (
+                ProgramDataElement::NonDecimalNumeric {
+                    radix: 8,
+                    digits: "17".into()
+                },
+                Separator::Data
+            )
+        );
+        assert_eq!(
+            next(),
+            (
+                ProgramDataElement::NonDecimalNumeric {
+                    radix: 2,
+                    digits: "01".into()
+                },
+                Separator::Data
+            )
+        );
+        assert_eq!(
+            next(),
+            (
+                ProgramDataElement::Expression("@1,(2:3)".into()),
+                Separator::MessageUnit
+            )
+        );
+        assert_eq!(parser.next_element(), Ok(None));
+        assert_eq!(parser.end(), Some(Separator::MessageUnit));
+        assert!(parser.into_inner().is_empty());
+    }
+
+    #[test]
+    fn numeric_values() {
+        let values = parse_all(b"1.5E+3,-.5e-2,42.\n")
+            .unwrap()
+            .into_iter()
+            .map(|(element, _)| match element {
+                ProgramDataElement::DecimalNumeric(numeric) => numeric.value(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values, [Some(1500.0), Some(-0.005), Some(42.0)]);
+    }
+
+    #[test]
+    fn booleans() {
+        let boolean = |data: &[u8]| {
+            let mut parser = ProgramDataParser::new(data);
+            parser
+                .parse_boolean_program_data()
+                .map(|boolean| boolean.map(|(boolean, _)| (boolean.value, boolean.form)))
+        };
+        assert_eq!(boolean(b"ON\n"), Ok(Some((true, BooleanForm::Characters))));
+        assert_eq!(
+            boolean(b"off\n"),
+            Ok(Some((false, BooleanForm::Characters)))
+        );
+        assert_eq!(boolean(b"1\n"), Ok(Some((true, BooleanForm::Numeric))));
+        assert_eq!(boolean(b"0\n"), Ok(Some((false, BooleanForm::Numeric))));
+        assert_eq!(boolean(b"+0.0\n"), Ok(Some((false, BooleanForm::Numeric))));
+        assert_eq!(boolean(b"2\n"), Ok(Some((true, BooleanForm::Numeric))));
+        assert_eq!(boolean(b"-1\n"), Ok(Some((true, BooleanForm::Numeric))));
+        assert_eq!(boolean(b"1E0\n"), Ok(Some((true, BooleanForm::Numeric))));
+        // 0.1 rounds to 0, which is false
+        assert_eq!(boolean(b"1E-1\n"), Ok(Some((false, BooleanForm::Numeric))));
+        assert_eq!(boolean(b"0.5\n"), Ok(Some((true, BooleanForm::Numeric))));
+        assert_eq!(boolean(b"\n"), Ok(None));
+        assert_matches!(boolean(b"TRUE\n"), Err(DecodeError::Parse));
+        assert_matches!(boolean(b"ONE\n"), Err(DecodeError::Parse));
+        assert_matches!(boolean(b"1 V\n"), Err(DecodeError::Parse));
+        assert_matches!(boolean(b"\"ON\"\n"), Err(DecodeError::Parse));
+    }
+
+    #[test]
+    fn boolean_consumes_one_element() {
+        let mut parser = ProgramDataParser::new(&b"ON,OFF\n"[..]);
+        assert_eq!(
+            parser.parse_boolean_program_data(),
+            Ok(Some((
+                BooleanProgramData {
+                    value: true,
+                    form: BooleanForm::Characters
+                },
+                Separator::Data
+            )))
+        );
+        assert_eq!(
+            parser.next_element(),
+            Ok(Some((
+                ProgramDataElement::Characters("OFF".into()),
+                Separator::Terminator
+            )))
+        );
+    }
+
+    #[test]
+    fn special_character_data() {
+        let elements = parse_all(b"MAXIMUM,def,UP,\"MAX\"\n").unwrap();
+        let characters = |idx: usize| elements[idx].0.as_characters();
+        assert_eq!(characters(0).unwrap().to_limit(), Some(Limit::Max));
+        assert!(characters(1).unwrap().to_default());
+        assert_eq!(characters(2).unwrap().to_direction(), Some(Direction::Up));
+        assert_eq!(characters(3), None);
+    }
+
+    #[test]
+    fn indefinite_block_ends_the_message() {
+        let mut parser = ProgramDataParser::new(&b"#0ab;c\n*RST\n"[..]);
+        assert_eq!(
+            parser.next_element(),
+            Ok(Some((
+                ProgramDataElement::Block(b"ab;c".to_vec()),
+                Separator::Terminator
+            )))
+        );
+        assert_eq!(parser.next_element(), Ok(None));
+        assert_eq!(parser.into_inner(), b"*RST\n");
+    }
+
+    #[test]
+    fn no_program_data() {
+        let mut parser = ProgramDataParser::new(&b"  ;*RST\n"[..]);
+        assert_eq!(parser.next_element(), Ok(None));
+        assert_eq!(parser.end(), Some(Separator::MessageUnit));
+        assert_eq!(parser.into_inner(), b"*RST\n");
+
+        assert_matches!(parse_all(b"\n").as_deref(), Ok([]));
+    }
+
+    #[test]
+    fn malformed_input_mid_list() {
+        let mut parser = ProgramDataParser::new(&b"1,MAX,@,2\n"[..]);
+        assert_matches!(parser.next_element(), Ok(Some(_)));
+        assert_matches!(parser.next_element(), Ok(Some(_)));
+        assert_matches!(parser.next_element(), Err(DecodeError::Parse));
+
+        assert_matches!(parse_all(b"1,+\n"), Err(DecodeError::Parse));
+        assert_matches!(parse_all(b"1,2E+\n"), Err(DecodeError::Parse));
+        assert_matches!(parse_all(b"1,#HG\n"), Err(DecodeError::Parse));
+        assert_matches!(parse_all(b"1,#2a\n"), Err(DecodeError::Parse));
+        assert_matches!(parse_all(b"1,#15abc"), Err(DecodeError::UnexpectedEnd));
+        assert_matches!(parse_all(b"1,\"open\n"), Err(DecodeError::UnexpectedEnd));
+        assert_matches!(parse_all(b"1,(a;b)\n"), Err(DecodeError::Parse));
+        assert_matches!(parse_all(b"1,MAX MIN\n"), Err(DecodeError::Parse));
+        assert_matches!(parse_all(b"1,,2\n"), Err(DecodeError::Parse));
+    }
+
+    #[test]
+    fn definite_blocks_round_trip() {
+        for payload in [&b""[..], b"a", b"#15;\n,", &[0xff; 1000]] {
+            let mut encoder = Encoder::new(Vec::new());
+            encoder.begin_message_unit().unwrap();
+            encoder.write_bytes(b"MMEM:DATA").unwrap();
+            encoder.begin_program_data().unwrap();
+            encoder.encode_definite_block(payload).unwrap();
+            encoder.begin_program_data().unwrap();
+            encoder.write_bytes(b"1").unwrap();
+            let message = encoder.finish().unwrap();
+            let data = message.strip_prefix(b"MMEM:DATA").unwrap();
+
+            let mut parser = ProgramDataParser::new(data);
+            let mut target = Vec::new();
+            assert_eq!(
+                parser.parse_arbitrary_block(&mut target),
+                Ok(Some(Separator::Data))
+            );
+            assert_eq!(target, payload);
+            assert_eq!(
+                parser.next_element(),
+                Ok(Some((numeric("1", None), Separator::Terminator)))
+            );
+            assert!(parser.into_inner().is_empty());
+
+            let mut parser = ProgramDataParser::new(data);
+            assert_eq!(
+                parser.parse_block_borrowed(),
+                Ok(Some((payload, Separator::Data)))
+            );
+            assert_eq!(
+                parser.next_element(),
+                Ok(Some((numeric("1", None), Separator::Terminator)))
+            );
+            assert!(parser.into_inner().is_empty());
+        }
+    }
+
+    #[test]
+    fn indefinite_blocks() {
+        let mut parser = ProgramDataParser::new(&b" #0#15;,\r\n*RST\n"[..]);
+        let mut target = Vec::new();
+        assert_eq!(
+            parser.parse_arbitrary_block(&mut target),
+            Ok(Some(Separator::Terminator))
+        );
+        assert_eq!(target, b"#15;,\r");
+        assert_eq!(parser.parse_arbitrary_block(&mut target), Ok(None));
+        assert_eq!(parser.into_inner(), b"*RST\n");
+
+        let mut parser = ProgramDataParser::new(&b"#0\n*RST\n"[..]);
+        assert_eq!(
+            parser.parse_block_borrowed(),
+            Ok(Some((&b""[..], Separator::Terminator)))
+        );
+        assert_eq!(parser.parse_block_borrowed(), Ok(None));
+        assert_eq!(parser.into_inner(), b"*RST\n");
+    }
+
+    #[test]
+    fn no_block() {
+        let mut parser = ProgramDataParser::new(&b" ;*RST\n"[..]);
+        assert_eq!(parser.parse_arbitrary_block(&mut Vec::new()), Ok(None));
+        assert_eq!(parser.end(), Some(Separator::MessageUnit));
+        assert_eq!(parser.into_inner(), b"*RST\n");
+
+        let mut parser = ProgramDataParser::new(&b"#13abc\n"[..]);
+        assert_eq!(
+            parser.parse_block_borrowed(),
+            Ok(Some((&b"abc"[..], Separator::Terminator)))
+        );
+        assert_eq!(parser.parse_block_borrowed(), Ok(None));
+    }
+
+    #[test]
+    fn malformed_blocks() {
+        let block =
+            |data: &[u8]| ProgramDataParser::new(data).parse_arbitrary_block(&mut Vec::new());
+        let borrowed = |data: &'static [u8]| {
+            ProgramDataParser::new(data)
+                .parse_block_borrowed()
+                .map(|block| block.map(|(payload, _)| payload.len()))
+        };
+        for data in [
+            &b"#A\n"[..],
+            b"#\n",
+            b"#H1\n",
+            b"#2a1\n",
+            b"#15abcde6\n",
+            b"\"abc\"\n",
+            b"1\n",
+        ] {
+            assert_eq!(block(data), Err(DecodeError::Parse));
+            assert_eq!(borrowed(data), Err(DecodeError::Parse));
+        }
+        for data in [&b"#15abc"[..], b"#0abc", b"#2", b"#"] {
+            assert_eq!(block(data), Err(DecodeError::UnexpectedEnd));
+            assert_eq!(borrowed(data), Err(DecodeError::UnexpectedEnd));
+        }
+    }
+
+    #[test]
+    fn block_target_overflow() {
+        let mut target = FnSink::new(|_: &[u8]| Err(EncodeError::BufferOverflow));
+        let mut parser = ProgramDataParser::new(&b"#13abc\n"[..]);
+        assert_eq!(
+            parser.parse_arbitrary_block(&mut target),
+            Err(DecodeError::BufferOverflow)
+        );
+    }
+}
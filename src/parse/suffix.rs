@@ -0,0 +1,335 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::fmt;
+
+use crate::scpi::types::{Multiplier, Suffix, Unit};
+
+/// Maximum length of suffix program data
+///
+/// Reference: IEEE 488.2: 7.7.3.4 - Rules
+const MAX_SUFFIX_LEN: usize = 12;
+
+/// Multipliers, with two-letter mnemonics first so `MA` is tried before `M`
+const MULTIPLIERS: [Multiplier; 12] = [
+    Multiplier::Exa,
+    Multiplier::Peta,
+    Multiplier::Mega,
+    Multiplier::Tera,
+    Multiplier::Giga,
+    Multiplier::Kilo,
+    Multiplier::Milli,
+    Multiplier::Micro,
+    Multiplier::Nano,
+    Multiplier::Pico,
+    Multiplier::Femto,
+    Multiplier::Atto,
+];
+
+const UNITS: [Unit; 19] = [
+    Unit::Ampere,
+    Unit::Celsius,
+    Unit::Coulomb,
+    Unit::Decibel,
+    Unit::DecibelMilliwatt,
+    Unit::Degree,
+    Unit::Farad,
+    Unit::Henry,
+    Unit::Hertz,
+    Unit::Joule,
+    Unit::Kelvin,
+    Unit::Meter,
+    Unit::Ohm,
+    Unit::Percent,
+    Unit::Radian,
+    Unit::Second,
+    Unit::Siemens,
+    Unit::Volt,
+    Unit::Watt,
+];
+
+/// Error returned for invalid suffix program data
+///
+/// Positions are byte offsets into the suffix.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SuffixError {
+    Empty,
+    InvalidCharacter {
+        position: usize,
+        byte: u8,
+    },
+    TooLong,
+    /// The suffix has a `/`, which is not supported
+    CompoundUnit {
+        position: usize,
+    },
+    UnknownUnit,
+}
+
+impl fmt::Display for SuffixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SuffixError::Empty => write!(f, "empty suffix"),
+            SuffixError::InvalidCharacter { position, byte } => {
+                write!(
+                    f,
+                    "invalid character (0x{:02x}) at position {}",
+                    byte, position
+                )
+            }
+            SuffixError::TooLong => {
+                write!(f, "suffix is longer than {} characters", MAX_SUFFIX_LEN)
+            }
+            SuffixError::CompoundUnit { position } => {
+                write!(f, "unsupported compound unit at position {}", position)
+            }
+            SuffixError::UnknownUnit => write!(f, "unknown unit"),
+        }
+    }
+}
+
+impl core::error::Error for SuffixError {}
+
+/// Parses suffix program data, like `KHZ` in `FREQ 10 KHZ`.
+///
+/// A suffix is an optional multiplier, a unit, and an optional unit exponent from `-9` to `9`,
+/// e.g. `MM2`. Parsing ignores case. Compound units with `/`, like `V/S`, are not supported.
+///
+/// `M` is ambiguous: alone it is the meter, and in front of a unit it is milli, except for `MHZ`
+/// and `MOHM`, which are megahertz and megaohm. Mega is otherwise written `MA`, but a bare `MA` is
+/// milliampere, because a multiplier is always followed by a unit.
+///
+/// ```
+/// use red_sculpin::{
+///     parse::parse_suffix,
+///     scpi::types::{Multiplier, Unit},
+/// };
+///
+/// let suffix = parse_suffix(b"khz").unwrap();
+/// assert_eq!(suffix.multiplier, Some(Multiplier::Kilo));
+/// assert_eq!(suffix.unit, Unit::Hertz);
+/// assert_eq!(suffix.scale_to_base(10.0), 10000.0);
+/// ```
+///
+/// Reference: IEEE 488.2: 7.7.3 - \<SUFFIX PROGRAM DATA\>
+pub fn parse_suffix(input: &[u8]) -> Result<Suffix, SuffixError> {
+    if input.is_empty() {
+        return Err(SuffixError::Empty);
+    }
+    if input.len() > MAX_SUFFIX_LEN {
+        return Err(SuffixError::TooLong);
+    }
+    let letters = input.iter().take_while(|b| b.is_ascii_alphabetic()).count();
+    if letters == 0 {
+        return Err(SuffixError::InvalidCharacter {
+            position: 0,
+            byte: input[0],
+        });
+    }
+    let exponent = parse_exponent(&input[letters..]).map_err(|position| {
+        let position = letters + position;
+        match input[position] {
+            b'/' => SuffixError::CompoundUnit { position },
+            byte => SuffixError::InvalidCharacter { position, byte },
+        }
+    })?;
+    let mut buffer = [0; MAX_SUFFIX_LEN];
+    let name = &mut buffer[..letters];
+    name.copy_from_slice(&input[..letters]);
+    name.make_ascii_uppercase();
+
+    let (multiplier, unit) = match &name[..] {
+        b"MHZ" => (Some(Multiplier::Mega), Unit::Hertz),
+        b"MOHM" => (Some(Multiplier::Mega), Unit::Ohm),
+        name => match unit(name) {
+            Some(unit) => (None, unit),
+            None => MULTIPLIERS
+                .iter()
+                .find_map(|&multiplier| {
+                    let rest = name.strip_prefix(multiplier.mnemonic().as_bytes())?;
+                    Some((Some(multiplier), unit(rest)?))
+                })
+                .ok_or(SuffixError::UnknownUnit)?,
+        },
+    };
+    Ok(Suffix {
+        multiplier,
+        unit,
+        exponent,
+    })
+}
+
+fn unit(name: &[u8]) -> Option<Unit> {
+    UNITS
+        .iter()
+        .copied()
+        .find(|unit| unit.mnemonic().as_bytes() == name)
+}
+
+/// Parses an optional unit exponent, returning the position of the first invalid byte on error.
+fn parse_exponent(input: &[u8]) -> Result<i8, usize> {
+    match input {
+        [] => Ok(1),
+        [digit @ b'1'..=b'9'] => Ok((digit - b'0') as i8),
+        [b'-', digit @ b'1'..=b'9'] => Ok(-((digit - b'0') as i8)),
+        [b'-', b'1'..=b'9', _, ..] => Err(2),
+        [b'-' | b'1'..=b'9', _, ..] => Err(1),
+        _ => Err(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_suffix, SuffixError};
+    use crate::scpi::types::{Multiplier, Suffix, Unit};
+
+    fn suffix(multiplier: Option<Multiplier>, unit: Unit) -> Suffix {
+        Suffix {
+            multiplier,
+            unit,
+            exponent: 1,
+        }
+    }
+
+    #[test]
+    fn plain_units() {
+        assert_eq!(parse_suffix(b"V"), Ok(suffix(None, Unit::Volt)));
+        assert_eq!(parse_suffix(b"ohm"), Ok(suffix(None, Unit::Ohm)));
+        assert_eq!(parse_suffix(b"Hz"), Ok(suffix(None, Unit::Hertz)));
+        assert_eq!(parse_suffix(b"A"), Ok(suffix(None, Unit::Ampere)));
+        assert_eq!(
+            parse_suffix(b"DBM"),
+            Ok(suffix(None, Unit::DecibelMilliwatt))
+        );
+        assert_eq!(parse_suffix(b"PCT"), Ok(suffix(None, Unit::Percent)));
+        assert_eq!(parse_suffix(b"s").unwrap().scale_to_base(1.5), 1.5);
+    }
+
+    #[test]
+    fn multiplied_units() {
+        assert_eq!(
+            parse_suffix(b"KHZ"),
+            Ok(suffix(Some(Multiplier::Kilo), Unit::Hertz))
+        );
+        assert_eq!(
+            parse_suffix(b"us"),
+            Ok(suffix(Some(Multiplier::Micro), Unit::Second))
+        );
+        assert_eq!(
+            parse_suffix(b"GHZ"),
+            Ok(suffix(Some(Multiplier::Giga), Unit::Hertz))
+        );
+        assert_eq!(
+            parse_suffix(b"KOHM"),
+            Ok(suffix(Some(Multiplier::Kilo), Unit::Ohm))
+        );
+        assert_eq!(
+            parse_suffix(b"PF"),
+            Ok(suffix(Some(Multiplier::Pico), Unit::Farad))
+        );
+        assert_eq!(
+            parse_suffix(b"EXW"),
+            Ok(suffix(Some(Multiplier::Exa), Unit::Watt))
+        );
+        assert_eq!(parse_suffix(b"KHZ").unwrap().scale_to_base(10.0), 10000.0);
+        assert_eq!(parse_suffix(b"NS").unwrap().scale_to_base(5.0), 5e-9);
+    }
+
+    #[test]
+    fn ambiguous_m() {
+        assert_eq!(parse_suffix(b"M"), Ok(suffix(None, Unit::Meter)));
+        assert_eq!(
+            parse_suffix(b"MS"),
+            Ok(suffix(Some(Multiplier::Milli), Unit::Second))
+        );
+        assert_eq!(
+            parse_suffix(b"MV"),
+            Ok(suffix(Some(Multiplier::Milli), Unit::Volt))
+        );
+        assert_eq!(
+            parse_suffix(b"MA"),
+            Ok(suffix(Some(Multiplier::Milli), Unit::Ampere))
+        );
+        assert_eq!(
+            parse_suffix(b"MAV"),
+            Ok(suffix(Some(Multiplier::Mega), Unit::Volt))
+        );
+        assert_eq!(
+            parse_suffix(b"mhz"),
+            Ok(suffix(Some(Multiplier::Mega), Unit::Hertz))
+        );
+        assert_eq!(
+            parse_suffix(b"MOHM"),
+            Ok(suffix(Some(Multiplier::Mega), Unit::Ohm))
+        );
+        assert_eq!(
+            parse_suffix(b"MM"),
+            Ok(suffix(Some(Multiplier::Milli), Unit::Meter))
+        );
+        assert_eq!(parse_suffix(b"MHZ").unwrap().scale_to_base(2.5), 2.5e6);
+    }
+
+    #[test]
+    fn exponents() {
+        let square_millimeter = parse_suffix(b"MM2").unwrap();
+        assert_eq!(
+            square_millimeter,
+            Suffix {
+                multiplier: Some(Multiplier::Milli),
+                unit: Unit::Meter,
+                exponent: 2,
+            }
+        );
+        assert_eq!(square_millimeter.scale_to_base(3.0), 3e-6);
+        assert_eq!(parse_suffix(b"S-1").map(|suffix| suffix.exponent), Ok(-1));
+    }
+
+    #[test]
+    fn garbage() {
+        assert_eq!(parse_suffix(b""), Err(SuffixError::Empty));
+        assert_eq!(parse_suffix(b"XYZ"), Err(SuffixError::UnknownUnit));
+        assert_eq!(parse_suffix(b"KX"), Err(SuffixError::UnknownUnit));
+        assert_eq!(parse_suffix(b"VOLT"), Err(SuffixError::UnknownUnit));
+        assert_eq!(parse_suffix(b"ABCDEFGHIJKLM"), Err(SuffixError::TooLong));
+        assert_eq!(
+            parse_suffix(b"V/S"),
+            Err(SuffixError::CompoundUnit { position: 1 })
+        );
+        assert_eq!(
+            parse_suffix(b"2V"),
+            Err(SuffixError::InvalidCharacter {
+                position: 0,
+                byte: b'2'
+            })
+        );
+        assert_eq!(
+            parse_suffix(b"M0"),
+            Err(SuffixError::InvalidCharacter {
+                position: 1,
+                byte: b'0'
+            })
+        );
+        assert_eq!(
+            parse_suffix(b"M22"),
+            Err(SuffixError::InvalidCharacter {
+                position: 2,
+                byte: b'2'
+            })
+        );
+        assert_eq!(
+            parse_suffix(b"V-"),
+            Err(SuffixError::InvalidCharacter {
+                position: 1,
+                byte: b'-'
+            })
+        );
+        assert_eq!(
+            parse_suffix(b"V "),
+            Err(SuffixError::InvalidCharacter {
+                position: 1,
+                byte: b' '
+            })
+        );
+    }
+}
@@ -2,7 +2,16 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use crate::encode::{EncodeSink, Encoder};
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec::Vec};
+
+use core::convert::TryFrom;
+
+use crate::{
+    encode::{EncodeError, EncodeSink, Encoder},
+    internal::{impl_tuples, Float, Unsigned},
+    utils::{is_program_mnemonic, is_program_mnemonic_bytes},
+};
 
 /// Trait for types that can be used as IEEE/SCPI message program data
 pub trait ProgramData {
@@ -25,6 +34,64 @@ where
     }
 }
 
+/// A homogeneous list of program data values produced by an iterator
+///
+/// Encoded like [`ProgramList`], but the values are encoded as the iterator produces them, so they
+/// don't need to be collected first. The iterator is cloned on every encode, because encoding only
+/// borrows the program data, e.g. `ProgramIter((0..5).map(|i| i as f64))`.
+#[derive(Copy, Clone, Debug)]
+pub struct ProgramIter<I>(pub I);
+
+impl<I> ProgramData for ProgramIter<I>
+where
+    I: Clone + IntoIterator,
+    I::Item: ProgramData,
+{
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        for data in self.0.clone() {
+            data.encode(encoder)?;
+        }
+        Ok(())
+    }
+}
+
+/// Encoded as an arbitrary block, like `[u8]`.
+impl<const N: usize> ProgramData for [u8; N] {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        self[..].encode(encoder)
+    }
+}
+
+/// Implements `ProgramData` for arrays of the given element types, which are encoded as separate
+/// program data values, like [`ProgramList`].
+///
+/// A blanket impl for `[T; N]` would overlap with `[u8; N]`, which is encoded as an arbitrary
+/// block instead. Arrays of other integer types are left out, so an array of unsuffixed integer
+/// literals like `[0x11, 0x22]` is still inferred to be `[u8; N]`. Lists of other integers are
+/// encoded with [`ProgramList`].
+macro_rules! impl_program_data_array {
+    ($($ty:ty),*) => {
+        $(
+            impl<const N: usize> ProgramData for [$ty; N] {
+                fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+                    ProgramList(self).encode(encoder)
+                }
+            }
+        )*
+    };
+}
+
+impl_program_data_array!(f32, f64, bool, char, &str);
+
+impl<T> ProgramData for &T
+where
+    T: ProgramData + ?Sized,
+{
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        (**self).encode(encoder)
+    }
+}
+
 impl ProgramData for () {
     fn encode<S: EncodeSink>(&self, _: &mut Encoder<S>) -> Result<(), S::Error> {
         Ok(())
@@ -132,24 +199,23 @@ impl ProgramData for isize {
     }
 }
 
-impl<'a> ProgramData for &'a str {
+impl ProgramData for str {
     fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
         encoder.begin_program_data()?;
         encoder.encode_string(self)
     }
 }
 
-impl ProgramData for str {
+impl ProgramData for char {
     fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
-        encoder.begin_program_data()?;
-        encoder.encode_string(self)
+        self.encode_utf8(&mut [0; 4]).encode(encoder)
     }
 }
 
-impl<'a> ProgramData for &'a [u8] {
+#[cfg(feature = "alloc")]
+impl ProgramData for String {
     fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
-        encoder.begin_program_data()?;
-        encoder.encode_definite_block(self)
+        self.as_str().encode(encoder)
     }
 }
 
@@ -160,6 +226,13 @@ impl ProgramData for [u8] {
     }
 }
 
+#[cfg(feature = "alloc")]
+impl ProgramData for Vec<u8> {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        self.as_slice().encode(encoder)
+    }
+}
+
 impl ProgramData for bool {
     fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
         encoder.begin_program_data()?;
@@ -167,54 +240,139 @@ impl ProgramData for bool {
     }
 }
 
-pub trait Encodable<T> {
-    type Error;
-    fn encode(&mut self, value: &T) -> Result<(), Self::Error>;
+macro_rules! impl_program_data_tuple {
+    ($($idx:tt $name:ident),*) => {
+        impl<$($name),*> ProgramData for ($($name,)*)
+        where
+            $($name: ProgramData,)*
+        {
+            fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+                $(self.$idx.encode(encoder)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_tuples!(impl_program_data_tuple);
+
+/// An unsigned integer encoded as hexadecimal numeric program data, e.g. `#H2A`
+///
+/// The non-decimal formats have no sign, so signed integers are rejected at compile time:
+///
+/// ```compile_fail
+/// use red_sculpin::{buffer::ArrayBuffer, encode::Encoder, HexValue, ProgramData};
+///
+/// let mut encoder = Encoder::new(ArrayBuffer::<16>::new());
+/// let _ = HexValue(-1i8).encode(&mut encoder);
+/// ```
+///
+/// Reference: IEEE 488.2: 7.7.4 - \<NON-DECIMAL NUMERIC PROGRAM DATA\>
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HexValue<T>(pub T);
+
+impl<T: Unsigned> ProgramData for HexValue<T> {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        encoder.begin_program_data()?;
+        encoder.encode_hexadecimal_integer(self.0)
+    }
 }
 
-impl<A, B> ProgramData for (A, B)
-where
-    A: ProgramData,
-    B: ProgramData,
-{
+/// An unsigned integer encoded as octal numeric program data, e.g. `#Q52`
+///
+/// Reference: IEEE 488.2: 7.7.4 - \<NON-DECIMAL NUMERIC PROGRAM DATA\>
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OctalValue<T>(pub T);
+
+impl<T: Unsigned> ProgramData for OctalValue<T> {
     fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
-        self.0.encode(encoder)?;
-        self.1.encode(encoder)
+        encoder.begin_program_data()?;
+        encoder.encode_octal_integer(self.0)
     }
 }
 
-impl<A, B, C> ProgramData for (A, B, C)
-where
-    A: ProgramData,
-    B: ProgramData,
-    C: ProgramData,
-{
+/// An unsigned integer encoded as binary numeric program data, e.g. `#B101010`
+///
+/// Reference: IEEE 488.2: 7.7.4 - \<NON-DECIMAL NUMERIC PROGRAM DATA\>
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BinaryValue<T>(pub T);
+
+impl<T: Unsigned> ProgramData for BinaryValue<T> {
     fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
-        self.0.encode(encoder)?;
-        self.1.encode(encoder)?;
-        self.2.encode(encoder)
+        encoder.begin_program_data()?;
+        encoder.encode_binary_integer(self.0)
     }
 }
 
-impl<A, B, C, D> ProgramData for (A, B, C, D)
-where
-    A: ProgramData,
-    B: ProgramData,
-    C: ProgramData,
-    D: ProgramData,
-{
+/// A floating point value encoded as decimal numeric program data without an exponent, e.g.
+/// `0.0012`
+///
+/// For instruments that don't accept the exponential format. See
+/// [`Encoder::encode_numeric_float_plain`] for details.
+///
+/// Reference: IEEE 488.2: 7.7.2 - \<DECIMAL NUMERIC PROGRAM DATA\>
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PlainFloat<T>(pub T);
+
+impl<T: Float> ProgramData for PlainFloat<T> {
     fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
-        self.0.encode(encoder)?;
-        self.1.encode(encoder)?;
-        self.2.encode(encoder)?;
-        self.3.encode(encoder)
+        encoder.begin_program_data()?;
+        encoder.encode_numeric_float_plain(self.0)
     }
 }
 
+/// Character program data, e.g. `VOLT` or `IMM`
+///
+/// The contents must be a program mnemonic: an ASCII letter followed by ASCII letters, digits or
+/// underscores. The wrapped string is public and isn't checked until it's encoded, so prefer
+/// [`new`](Self::new), [`from_static`](Self::from_static) or `TryFrom<&str>`, which reject invalid
+/// contents when the value is built instead of in the middle of a message.
+///
+/// Reference: IEEE 488.2: 7.7.1 - \<CHARACTER PROGRAM DATA\>
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct CharacterProgramData<'a>(pub &'a str);
 
+impl<'a> CharacterProgramData<'a> {
+    /// Creates character program data from a program mnemonic.
+    pub fn new(value: &'a str) -> Result<Self, EncodeError> {
+        if is_program_mnemonic(value) {
+            Ok(CharacterProgramData(value))
+        } else {
+            Err(EncodeError::InvalidCharacterData)
+        }
+    }
+}
+
+impl CharacterProgramData<'static> {
+    /// Creates character program data from a string literal, which is validated at compile time
+    /// when used in a constant.
+    ///
+    /// ```
+    /// use red_sculpin::CharacterProgramData;
+    ///
+    /// const IMMEDIATE: CharacterProgramData = CharacterProgramData::from_static("IMM");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the string is not a program mnemonic.
+    pub const fn from_static(value: &'static str) -> Self {
+        assert!(
+            is_program_mnemonic_bytes(value.as_bytes()),
+            "invalid character data"
+        );
+        CharacterProgramData(value)
+    }
+}
+
+impl<'a> TryFrom<&'a str> for CharacterProgramData<'a> {
+    type Error = EncodeError;
+    fn try_from(value: &'a str) -> Result<Self, EncodeError> {
+        CharacterProgramData::new(value)
+    }
+}
+
 impl<'a> ProgramData for CharacterProgramData<'a> {
     fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
         encoder.begin_program_data()?;
@@ -222,12 +380,41 @@ impl<'a> ProgramData for CharacterProgramData<'a> {
     }
 }
 
-#[cfg(test)]
-use crate::encode::EncodeError;
-#[cfg(test)]
-use alloc::vec::Vec;
+/// Expression program data, encoded in parentheses, e.g. `(TRA-TRB)` or `(@1:3)`
+///
+/// The wrapped text doesn't include the outer parentheses.
+///
+/// Reference: IEEE 488.2: 7.7.7 - \<EXPRESSION PROGRAM DATA\>
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Expression<'a>(pub &'a str);
+
+impl<'a> ProgramData for Expression<'a> {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        encoder.begin_program_data()?;
+        encoder.encode_expression(self.0)
+    }
+}
+
+/// Program data bytes written as-is, for vendor-specific syntax that isn't covered by the other
+/// program data types, e.g. `(@1!2)`
+///
+/// The bytes are not validated at all, so the caller is responsible for keeping the message valid:
+/// they must not contain a program message terminator, a `;` or a `,` outside of quotes or
+/// parentheses, or anything else that would end the program data early. A separator is still
+/// written before them like before any other program data.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct RawProgramData<'a>(pub &'a [u8]);
+
+impl<'a> ProgramData for RawProgramData<'a> {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        encoder.begin_program_data()?;
+        encoder.write_bytes(self.0)
+    }
+}
 
-#[cfg(test)]
+#[cfg(all(test, feature = "alloc"))]
 fn encode_test<F: FnOnce(&mut Encoder<Vec<u8>>) -> Result<(), EncodeError>>(
     f: F,
 ) -> Result<Vec<u8>, EncodeError> {
@@ -240,12 +427,14 @@ fn encode_test<F: FnOnce(&mut Encoder<Vec<u8>>) -> Result<(), EncodeError>>(
 }
 
 #[test]
+#[cfg(feature = "alloc")]
 fn test_str() {
     let result = encode_test(|encoder| "foo".encode(encoder)).unwrap();
     assert_eq!(result, b"TEST \"foo\"\n");
 }
 
 #[test]
+#[cfg(feature = "alloc")]
 fn test_str_escape() {
     let result =
         encode_test(|encoder| r#"what if "quotes" break 'stuff'?"#.encode(encoder)).unwrap();
@@ -253,48 +442,233 @@ fn test_str_escape() {
 }
 
 #[test]
+#[cfg(feature = "alloc")]
+fn test_owned() {
+    let text = String::from(r#"say "hi""#);
+    assert_eq!(
+        encode_test(|encoder| text.encode(encoder)).unwrap(),
+        encode_test(|encoder| text.as_str().encode(encoder)).unwrap()
+    );
+    let bytes = (0..=255).collect::<Vec<u8>>();
+    assert_eq!(
+        encode_test(|encoder| bytes.encode(encoder)).unwrap(),
+        encode_test(|encoder| bytes.as_slice().encode(encoder)).unwrap()
+    );
+    let result = encode_test(|encoder| (text.clone(), b"ab".to_vec()).encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST \"say \"\"hi\"\"\",#12ab\n");
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn test_char() {
+    let result = encode_test(|encoder| ','.encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST \",\"\n");
+    let result = encode_test(|encoder| '"'.encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST \"\"\"\"\n");
+    let result = encode_test(|encoder| (Some('a'), None::<char>, 'b').encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST \"a\",\"b\"\n");
+    assert!(matches!(
+        encode_test(|encoder| 'µ'.encode(encoder)),
+        Err(EncodeError::NonAsciiString)
+    ));
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn test_nondecimal() {
+    let result = encode_test(|encoder| HexValue(0x2Au8).encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST #H2A\n");
+    let result = encode_test(|encoder| {
+        (
+            OctalValue(0o52u16),
+            BinaryValue(0b101010u32),
+            HexValue(0usize),
+        )
+            .encode(encoder)
+    })
+    .unwrap();
+    assert_eq!(result, b"TEST #Q52,#B101010,#H0\n");
+    let result = encode_test(|encoder| BinaryValue(u128::MAX).encode(encoder)).unwrap();
+    assert_eq!(result.len(), "TEST #B\n".len() + 128);
+    let result = encode_test(|encoder| HexValue(u64::MAX).encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST #HFFFFFFFFFFFFFFFF\n");
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn test_expression() {
+    let result = encode_test(|encoder| Expression("TRA-TRB").encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST (TRA-TRB)\n");
+    let result =
+        encode_test(|encoder| (Expression("(A+B)*(C-(D/2))"), Expression("")).encode(encoder))
+            .unwrap();
+    assert_eq!(result, b"TEST ((A+B)*(C-(D/2))),()\n");
+    for text in ["A\nB", "\"A\"", "'A'", "A;B", "#H1", "(A", "A)", ")A(", "µ"] {
+        assert!(matches!(
+            encode_test(|encoder| Expression(text).encode(encoder)),
+            Err(EncodeError::InvalidExpression)
+        ));
+    }
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn test_plain_float() {
+    let result = encode_test(|encoder| (PlainFloat(1.2e-3f64), PlainFloat(2f32)).encode(encoder));
+    assert_eq!(result.unwrap(), b"TEST 0.0012,2.0\n");
+    let result = encode_test(|encoder| PlainFloat(f32::NAN).encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST NAN\n");
+}
+
+#[test]
+#[cfg(feature = "alloc")]
 fn test_definite_block() {
     let result = encode_test(|encoder| [0x11, 0x22, 0x33].encode(encoder)).unwrap();
     assert_eq!(result, b"TEST #13\x11\x22\x33\n");
 }
 
 #[test]
+#[cfg(feature = "alloc")]
+fn test_array() {
+    let result = encode_test(|encoder| [0.5f64, -1.0, 2e3].encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST 5E-1,-1E0,2E3\n");
+    let result = encode_test(|encoder| ("ON", [true, false]).encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST \"ON\",1,0\n");
+    let result = encode_test(|encoder| ([1.0f32; 0], 2u8).encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST 2\n");
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn test_byte_array() {
+    let result = encode_test(|encoder| [0x11u8, 0x22].encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST #12\x11\x22\n");
+    let result = encode_test(|encoder| [0u8; 0].encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST #10\n");
+}
+
+#[test]
+#[cfg(feature = "alloc")]
 fn test_f32_positive() {
     let result = encode_test(|encoder| 1.2345678E11f32.encode(encoder)).unwrap();
     assert_eq!(result, b"TEST 1.2345678E11\n");
 }
 
 #[test]
+#[cfg(feature = "alloc")]
 fn test_f32_negative() {
     let result = encode_test(|encoder| (-1.2345678E-11f32).encode(encoder)).unwrap();
     assert_eq!(result, b"TEST -1.2345678E-11\n");
 }
 
 #[test]
+#[cfg(feature = "alloc")]
 fn test_f64_positive() {
     let result = encode_test(|encoder| 1.234567891234567E11f64.encode(encoder)).unwrap();
     assert_eq!(result, b"TEST 1.234567891234567E11\n");
 }
 
 #[test]
+#[cfg(feature = "alloc")]
 fn test_f64_negative() {
     let result = encode_test(|encoder| (-1.234567891234567E-11f64).encode(encoder)).unwrap();
     assert_eq!(result, b"TEST -1.234567891234567E-11\n");
 }
 
 #[test]
+#[cfg(feature = "alloc")]
 fn test_tuple2() {
     let result = encode_test(|encoder| ("mixed", -42i32).encode(encoder)).unwrap();
     assert_eq!(result, b"TEST \"mixed\",-42\n");
 }
 
 #[test]
+#[cfg(feature = "alloc")]
 fn test_tuple3() {
     let result = encode_test(|encoder| (1u8, -1i8, -420000f32).encode(encoder)).unwrap();
     assert_eq!(result, b"TEST 1,-1,-4.2E5\n");
 }
 
 #[test]
+fn test_character_program_data_validation() {
+    for valid in ["VOLT", "volt", "IEEE_488", "a", "Ch1_rng"] {
+        assert!(matches!(CharacterProgramData::new(valid), Ok(data) if data.0 == valid));
+        assert!(matches!(CharacterProgramData::try_from(valid), Ok(data) if data.0 == valid));
+        assert_eq!(
+            CharacterProgramData::from_static(valid),
+            CharacterProgramData(valid)
+        );
+    }
+    for invalid in ["", "VOLT AC", " VOLT", "1VOLT", "_VOLT", "VOLT?", "VÖLT"] {
+        assert!(matches!(
+            CharacterProgramData::new(invalid),
+            Err(EncodeError::InvalidCharacterData)
+        ));
+        assert!(CharacterProgramData::try_from(invalid).is_err());
+    }
+}
+
+#[test]
+#[should_panic(expected = "invalid character data")]
+fn test_character_program_data_from_static_panics() {
+    CharacterProgramData::from_static("2ND");
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn test_raw_program_data() {
+    let result = encode_test(|encoder| RawProgramData(b"(@1!2)").encode(encoder)).unwrap();
+    assert_eq!(result, b"TEST (@1!2)\n");
+    let result = encode_test(|encoder| {
+        (
+            1u8,
+            RawProgramData(b"SENS:VOLT"),
+            CharacterProgramData("IMM"),
+            RawProgramData(b"(@1!2)"),
+        )
+            .encode(encoder)
+    })
+    .unwrap();
+    assert_eq!(result, b"TEST 1,SENS:VOLT,IMM,(@1!2)\n");
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn test_program_iter() {
+    let points = ProgramIter((0..5).map(|i| i as f64));
+    let result = encode_test(|encoder| points.encode(encoder)).unwrap();
+    let list = encode_test(|encoder| ProgramList(&[0.0, 1.0, 2.0, 3.0, 4.0]).encode(encoder));
+    assert_eq!(result, list.unwrap());
+    assert_eq!(result, b"TEST 0E0,1E0,2E0,3E0,4E0\n");
+    // Encoding again starts from a fresh clone of the iterator
+    assert_eq!(
+        encode_test(|encoder| points.encode(encoder)).unwrap(),
+        result
+    );
+    let result = encode_test(|encoder| ProgramIter(core::iter::empty::<u8>()).encode(encoder));
+    assert_eq!(result.unwrap(), b"TEST\n");
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn test_tuple6() {
+    let result = encode_test(|encoder| {
+        (
+            CharacterProgramData("VOLT"),
+            10u8,
+            1e-3f64,
+            "(@101)",
+            true,
+            CharacterProgramData("IMM"),
+        )
+            .encode(encoder)
+    })
+    .unwrap();
+    assert_eq!(result, b"TEST VOLT,10,1E-3,\"(@101)\",1,IMM\n");
+}
+
+#[test]
+#[cfg(feature = "alloc")]
 fn test_tuple4() {
     let result = encode_test(|encoder| {
         (&[1, 2, 3][..], f64::NAN, f32::INFINITY, f32::NEG_INFINITY).encode(encoder)
@@ -0,0 +1,288 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::fmt;
+use std::io;
+
+use crate::{
+    encode::{EncodeSink, PROGRAM_MESSAGE_TERMINATOR},
+    ByteSink, ByteSource, Error, Io,
+};
+
+const ESC: u8 = 0x1b;
+
+/// How the controller reads a response from the instrument
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ReadMode {
+    /// Read until EOI is asserted (`++read eoi`)
+    #[default]
+    Eoi,
+    /// Read until the read timeout expires (`++read`)
+    Timeout,
+    /// Read until the given byte is received (`++read <char>`)
+    Until(u8),
+}
+
+/// Configuration of a Prologix controller
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PrologixConfig {
+    /// Primary GPIB address of the instrument, 0-30
+    pub address: u8,
+    pub read_mode: ReadMode,
+    /// Inter-character read timeout in milliseconds (`++read_tmo_ms`), or `None` to keep the
+    /// controller setting
+    pub read_timeout_ms: Option<u16>,
+}
+
+impl PrologixConfig {
+    pub fn new(address: u8) -> PrologixConfig {
+        PrologixConfig {
+            address,
+            read_mode: ReadMode::Eoi,
+            read_timeout_ms: None,
+        }
+    }
+}
+
+/// A Prologix GPIB-USB or GPIB-Ethernet controller in controller mode
+///
+/// The controller treats every line starting with `++` as a controller command, and every other
+/// line as data for the instrument. Carriage returns, newlines, escape characters and `+`
+/// characters in the data must be escaped with an escape character, so arbitrary block program
+/// data can be sent as-is. Program messages are sent with their terminator, and EOI asserted with
+/// the last byte.
+///
+/// Read-after-write is disabled, so responses must be requested explicitly. A read command is
+/// sent when the first byte of a response is read after a program message, and response bytes
+/// are passed through unchanged. The controller doesn't report EOI to the host, so responses must
+/// end with a newline.
+///
+/// ```
+/// # #[cfg(feature = "test-util")]
+/// # {
+/// use red_sculpin::{
+///     decode::Decoder,
+///     encode::Encoder,
+///     ieee::message::OperationCompleteQuery,
+///     mock::{Expect, MockTransport},
+///     prologix::{PrologixAdapter, PrologixConfig},
+///     Query,
+/// };
+///
+/// let mut transport = MockTransport::new([
+///     Expect::write("++mode 1\n++auto 0\n++eos 3\n++eoi 1\n++addr 5\n"),
+///     Expect::write("*OPC?\x1b\n\n++read eoi\n").then_respond("1\n"),
+/// ]);
+/// let mut adapter = PrologixAdapter::new(&mut transport, PrologixConfig::new(5)).unwrap();
+///
+/// let mut encoder = Encoder::new(&mut adapter);
+/// OperationCompleteQuery.encode(&mut encoder).unwrap();
+/// encoder.finish().unwrap();
+/// let mut decoder = Decoder::new(&mut adapter);
+/// assert!(OperationCompleteQuery.decode(&mut decoder).unwrap());
+/// decoder.finish().unwrap();
+/// # }
+/// ```
+///
+/// Reference: Prologix GPIB-USB Controller User Manual
+pub struct PrologixAdapter<T> {
+    transport: T,
+    config: PrologixConfig,
+    read_requested: bool,
+}
+
+impl<T> PrologixAdapter<T>
+where
+    T: io::Read + io::Write,
+{
+    /// Configures the controller, and returns the adapter.
+    pub fn new(transport: T, config: PrologixConfig) -> Result<PrologixAdapter<T>, Error> {
+        let mut adapter = PrologixAdapter {
+            transport,
+            config,
+            read_requested: false,
+        };
+        // Controller mode, no read-after-write, no appended terminator, EOI with the last byte
+        adapter.write_command(format_args!("mode 1"))?;
+        adapter.write_command(format_args!("auto 0"))?;
+        adapter.write_command(format_args!("eos 3"))?;
+        adapter.write_command(format_args!("eoi 1"))?;
+        adapter.set_address(config.address)?;
+        if let Some(timeout) = config.read_timeout_ms {
+            adapter.write_command(format_args!("read_tmo_ms {}", timeout))?;
+        }
+        Ok(adapter)
+    }
+    pub fn config(&self) -> &PrologixConfig {
+        &self.config
+    }
+    /// Changes the GPIB address of the instrument.
+    pub fn set_address(&mut self, address: u8) -> Result<(), Error> {
+        if address > 30 {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidInput, "GPIB address out of range").into(),
+            );
+        }
+        self.write_command(format_args!("addr {}", address))?;
+        self.config.address = address;
+        Ok(())
+    }
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+    /// Sends a controller command without the `++` prefix, e.g. `clr` for a selected device clear.
+    pub fn command(&mut self, command: &str) -> Result<(), Error> {
+        self.write_command(format_args!("{}", command))
+    }
+    fn write_command(&mut self, command: fmt::Arguments) -> Result<(), Error> {
+        writeln!(self.transport, "++{}", command)?;
+        self.read_requested = false;
+        Ok(())
+    }
+    fn request_read(&mut self) -> Result<(), Error> {
+        match self.config.read_mode {
+            ReadMode::Eoi => self.write_command(format_args!("read eoi"))?,
+            ReadMode::Timeout => self.write_command(format_args!("read"))?,
+            ReadMode::Until(byte) => self.write_command(format_args!("read {}", byte))?,
+        }
+        self.read_requested = true;
+        Ok(())
+    }
+}
+
+impl<T> ByteSource for PrologixAdapter<T>
+where
+    T: io::Read + io::Write,
+{
+    type Error = Error;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        if !self.read_requested {
+            self.request_read()?;
+        }
+        Io(&mut self.transport).read_byte()
+    }
+}
+
+impl<T> ByteSink for PrologixAdapter<T>
+where
+    T: io::Read + io::Write,
+{
+    type Error = Error;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.read_requested = false;
+        let mut sink = Io(&mut self.transport);
+        for part in bytes.split_inclusive(|&byte| needs_escape(byte)) {
+            match part.split_last() {
+                Some((&last, rest)) if needs_escape(last) => {
+                    sink.write_bytes_vectored(&[rest, &[ESC, last]])?
+                }
+                _ => sink.write_bytes(part)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> EncodeSink for PrologixAdapter<T>
+where
+    T: io::Read + io::Write,
+{
+    /// Sends the terminator to the instrument escaped, and ends the line with an unescaped newline.
+    ///
+    /// The controller asserts EOI with the terminator, because it is the last data byte of the line.
+    fn terminate_message(&mut self) -> Result<(), Self::Error> {
+        self.write_byte(PROGRAM_MESSAGE_TERMINATOR)?;
+        Io(&mut self.transport).write_byte(b'\n')
+    }
+}
+
+fn needs_escape(byte: u8) -> bool {
+    matches!(byte, b'\r' | b'\n' | ESC | b'+')
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use matches::assert_matches;
+
+    use super::{PrologixAdapter, PrologixConfig, ReadMode};
+    use crate::{
+        decode::Decoder,
+        encode::Encoder,
+        ieee::message::IdentificationQuery,
+        mock::{Expect, MockTransport},
+        Error, Query, ResponseData,
+    };
+
+    const SETUP: &[u8] = b"++mode 1\n++auto 0\n++eos 3\n++eoi 1\n++addr 5\n";
+
+    #[test]
+    fn query_round_trip() {
+        let mut transport = MockTransport::new([
+            Expect::write(SETUP),
+            Expect::write("*IDN?\x1b\n\n++read eoi\n").then_respond("ACME,PSU1,0,1.0\n"),
+        ]);
+        let mut adapter = PrologixAdapter::new(&mut transport, PrologixConfig::new(5)).unwrap();
+        let mut encoder = Encoder::new(&mut adapter);
+        IdentificationQuery.encode(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+        let mut decoder = Decoder::new(&mut adapter);
+        let identification = IdentificationQuery.decode(&mut decoder).unwrap();
+        decoder.finish().unwrap();
+        assert_eq!(identification.model, "PSU1");
+    }
+
+    #[test]
+    fn binary_blocks_are_escaped() {
+        let payload = b"\r\n\x1b++x";
+        let mut transport = MockTransport::new([
+            Expect::write(SETUP),
+            Expect::write(b"MMEM:DATA #16\x1b\r\x1b\n\x1b\x1b\x1b+\x1b+x\x1b\n\n"),
+            Expect::write("MMEM:DATA?\x1b\n\n++read eoi\n").then_respond(b"#16\r\n\x1b++x\n"),
+        ]);
+        let mut adapter = PrologixAdapter::new(&mut transport, PrologixConfig::new(5)).unwrap();
+
+        let mut encoder = Encoder::new(&mut adapter);
+        encoder.begin_message_unit().unwrap();
+        encoder.write_bytes(b"MMEM:DATA").unwrap();
+        encoder.begin_program_data().unwrap();
+        encoder.encode_definite_block(payload).unwrap();
+        encoder.end_message().unwrap();
+        encoder.begin_message_unit().unwrap();
+        encoder.write_bytes(b"MMEM:DATA?").unwrap();
+        encoder.finish().unwrap();
+
+        // A newline in the payload doesn't end the read
+        let mut decoder = Decoder::new(&mut adapter);
+        assert_eq!(Vec::<u8>::decode(&mut decoder).unwrap(), payload);
+        decoder.finish().unwrap();
+    }
+
+    #[test]
+    fn configuration() {
+        let mut transport = MockTransport::new([
+            Expect::write(SETUP),
+            Expect::write("++read_tmo_ms 100\n"),
+            Expect::write("++addr 7\n"),
+            Expect::write("*OPC?\x1b\n\n++read 10\n").then_respond("1\n"),
+        ]);
+        let config = PrologixConfig {
+            read_mode: ReadMode::Until(b'\n'),
+            read_timeout_ms: Some(100),
+            ..PrologixConfig::new(5)
+        };
+        let mut adapter = PrologixAdapter::new(&mut transport, config).unwrap();
+        assert_matches!(adapter.set_address(31), Err(Error::Io(_)));
+        adapter.set_address(7).unwrap();
+        assert_eq!(adapter.config().address, 7);
+
+        let query = crate::ieee::message::OperationCompleteQuery;
+        let mut encoder = Encoder::new(&mut adapter);
+        query.encode(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+        assert!(query.decode(&mut Decoder::new(&mut adapter)).unwrap());
+    }
+}
@@ -0,0 +1,575 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+    buffer::ArrayBuffer,
+    encode::{
+        definite_block_header, format_float, format_integer, EncodeError, EncodeSink, EncodeState,
+    },
+    internal::{impl_tuples, Float, Integer},
+    ErrorCode,
+};
+#[cfg(feature = "alloc")]
+use crate::{DeviceIdentification, SystemErrorResponse};
+
+/// Reference: IEEE 488.2: 8.4.1 - \<RESPONSE MESSAGE UNIT SEPARATOR\>
+pub const RESPONSE_MESSAGE_UNIT_SEPARATOR: u8 = b';';
+
+/// Reference: IEEE 488.2: 8.4.2 - \<RESPONSE DATA SEPARATOR\>
+pub const RESPONSE_DATA_SEPARATOR: u8 = b',';
+
+/// Encoder for response messages, for implementing instrument emulators
+///
+/// This is the write side of [`Decoder`](crate::decode::Decoder): everything encoded by it can be
+/// decoded back by the decoder. Like program messages with [`Encoder`](crate::encode::Encoder),
+/// each response message unit is started with [`begin_message_unit`], and the message is ended
+/// with [`finish`] or [`end_message`], which write the terminator exactly once. The encode state
+/// is reported as an [`EncodeState`], where `Header` means that a message unit has been started,
+/// but it doesn't contain response data yet.
+///
+/// [`begin_message_unit`]: ResponseEncoder::begin_message_unit
+/// [`finish`]: ResponseEncoder::finish
+/// [`end_message`]: ResponseEncoder::end_message
+///
+/// ```
+/// use red_sculpin::{buffer::ArrayBuffer, respond::ResponseEncoder};
+///
+/// let mut encoder = ResponseEncoder::new(ArrayBuffer::<32>::new());
+/// encoder.begin_message_unit().unwrap();
+/// encoder.begin_response_data().unwrap();
+/// encoder.encode_nr1(42).unwrap();
+/// encoder.begin_response_data().unwrap();
+/// encoder.encode_string("forty-two").unwrap();
+/// assert_eq!(encoder.finish().unwrap().as_slice(), b"42,\"forty-two\"\n");
+/// ```
+#[must_use]
+#[derive(Copy, Clone, Debug)]
+pub struct ResponseEncoder<S: EncodeSink> {
+    sink: S,
+    state: EncodeState,
+    /// Arbitrary ASCII response data has been written, so only the terminator can follow
+    terminal: bool,
+}
+
+impl<S: EncodeSink> ResponseEncoder<S> {
+    pub fn new(sink: S) -> ResponseEncoder<S> {
+        ResponseEncoder {
+            sink,
+            state: EncodeState::default(),
+            terminal: false,
+        }
+    }
+    pub fn state(&self) -> EncodeState {
+        self.state
+    }
+    pub fn write_byte(&mut self, byte: u8) -> Result<(), S::Error> {
+        debug_assert!(self.state == EncodeState::Data);
+        self.sink.write_byte(byte)
+    }
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), S::Error> {
+        debug_assert!(self.state == EncodeState::Data);
+        self.sink.write_bytes(bytes)
+    }
+    /// Starts a new response message unit, writing a separator if needed.
+    pub fn begin_message_unit(&mut self) -> Result<(), S::Error> {
+        self.state = match self.state {
+            EncodeState::Initial => EncodeState::Header,
+            EncodeState::Header | EncodeState::Data if !self.terminal => {
+                self.sink.write_byte(RESPONSE_MESSAGE_UNIT_SEPARATOR)?;
+                EncodeState::Header
+            }
+            _ => return Err(EncodeError::InvalidEncodeState(self.state).into()),
+        };
+        Ok(())
+    }
+    /// Starts new response data in the current message unit, writing a separator if needed.
+    pub fn begin_response_data(&mut self) -> Result<(), S::Error> {
+        self.state = match self.state {
+            EncodeState::Header => EncodeState::Data,
+            EncodeState::Data if !self.terminal => {
+                self.sink.write_byte(RESPONSE_DATA_SEPARATOR)?;
+                EncodeState::Data
+            }
+            _ => return Err(EncodeError::InvalidEncodeState(self.state).into()),
+        };
+        Ok(())
+    }
+    pub fn end_message(&mut self) -> Result<(), S::Error> {
+        self.state = match self.state {
+            EncodeState::Header | EncodeState::Data => {
+                self.sink.terminate_message()?;
+                EncodeState::End
+            }
+            EncodeState::End => EncodeState::End,
+            _ => return Err(EncodeError::InvalidEncodeState(self.state).into()),
+        };
+        self.terminal = false;
+        Ok(())
+    }
+    pub fn finish(mut self) -> Result<S, S::Error> {
+        self.end_message()?;
+        Ok(self.sink)
+    }
+    /// Encodes a boolean as NR1 numeric response data.
+    ///
+    /// Reference: SCPI 1999.0: 7.3 - Boolean Program Data
+    pub fn encode_boolean(&mut self, value: bool) -> Result<(), S::Error> {
+        self.write_byte(match value {
+            true => b'1',
+            false => b'0',
+        })
+    }
+    /// Encodes an integer value into NR1 numeric response data bytes.
+    ///
+    /// Reference: IEEE 488.2: 8.7.2 - \<NR1 NUMERIC RESPONSE DATA\>
+    pub fn encode_nr1<T: Integer>(&mut self, value: T) -> Result<(), S::Error> {
+        self.write_bytes(format_integer(&value).as_slice())
+    }
+    /// Encodes a floating point value into NR3 numeric response data bytes.
+    ///
+    /// Non-finite values are encoded with the values recommended by SCPI: 9.9E+37 for infinity,
+    /// -9.9E+37 for negative infinity, and 9.91E+37 for NaN.
+    ///
+    /// References:
+    ///   - IEEE 488.2: 8.7.4 - \<NR3 NUMERIC RESPONSE DATA\>
+    ///   - SCPI 1999.0: 7.2.1 - Special Numeric Values
+    pub fn encode_nr3<T: Float>(&mut self, value: T) -> Result<(), S::Error> {
+        if value.is_finite() {
+            self.write_bytes(nr3(format_float(&value).as_slice()).as_slice())
+        } else if value.is_nan() {
+            self.write_bytes(b"9.91E+37")
+        } else if value.is_sign_positive() {
+            self.write_bytes(b"9.9E+37")
+        } else {
+            self.write_bytes(b"-9.9E+37")
+        }
+    }
+    /// Encodes a string into character response data bytes.
+    ///
+    /// Reference: IEEE 488.2: 8.7.1 - \<CHARACTER RESPONSE DATA\>
+    pub fn encode_characters(&mut self, value: &str) -> Result<(), S::Error> {
+        match value.as_bytes() {
+            [b'A'..=b'Z', tail @ ..]
+                if tail
+                    .iter()
+                    .all(|&b| matches!(b, b'A'..=b'Z' | b'0'..=b'9' | b'_')) =>
+            {
+                self.write_bytes(value.as_bytes())
+            }
+            _ => Err(EncodeError::InvalidCharacterData.into()),
+        }
+    }
+    /// Encodes an ASCII string into string response data bytes.
+    ///
+    /// Reference: IEEE 488.2: 8.7.8 - \<STRING RESPONSE DATA\>
+    pub fn encode_string(&mut self, data: &str) -> Result<(), S::Error> {
+        if !data.is_ascii() {
+            return Err(EncodeError::NonAsciiString.into());
+        }
+        self.write_byte(b'"')?;
+        let mut chunk_iter = data.as_bytes().split(|&ch| ch == b'"').peekable();
+        while let Some(chunk) = chunk_iter.next() {
+            self.write_bytes(chunk)?;
+            if chunk_iter.peek().is_some() {
+                self.write_bytes(b"\"\"")?;
+            }
+        }
+        self.write_byte(b'"')
+    }
+    /// Encodes a slice of bytes into definite length arbitrary block response data bytes.
+    ///
+    /// Reference: IEEE 488.2: 8.7.9 - \<DEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA\>
+    pub fn encode_definite_block(&mut self, data: &[u8]) -> Result<(), S::Error> {
+        debug_assert!(self.state == EncodeState::Data);
+        let mut header = definite_block_header(data.len())?;
+        self.sink.write_bytes_vectored(&[header.finish(), data])
+    }
+    /// Encodes an ASCII string into arbitrary ASCII response data bytes.
+    ///
+    /// Arbitrary ASCII response data is terminated by the response message terminator, so it must
+    /// be the last response data in the message, and the string can't contain newlines.
+    ///
+    /// Reference: IEEE 488.2: 8.7.11 - \<ARBITRARY ASCII RESPONSE DATA\>
+    pub fn encode_arbitrary_ascii(&mut self, data: &str) -> Result<(), S::Error> {
+        if !data.is_ascii() {
+            return Err(EncodeError::NonAsciiString.into());
+        }
+        if data.contains('\n') {
+            return Err(EncodeError::InvalidArbitraryAscii.into());
+        }
+        self.write_bytes(data.as_bytes())?;
+        self.terminal = true;
+        Ok(())
+    }
+}
+
+/// Converts Rust `{:E}` formatting into NR3 syntax, which requires a fractional part and an
+/// explicit exponent sign
+fn nr3(formatted: &[u8]) -> ArrayBuffer<72> {
+    let mut result = ArrayBuffer::new();
+    let (mantissa, exponent) = match formatted.iter().position(|&b| b == b'E') {
+        Some(pos) => (&formatted[..pos], &formatted[pos + 1..]),
+        None => (formatted, &b"0"[..]),
+    };
+    // The buffer is larger than any formatted float, so pushing can't fail
+    let _ = result.push_all(mantissa);
+    if !mantissa.contains(&b'.') {
+        let _ = result.push_all(b".0");
+    }
+    let _ = result.push(b'E');
+    if !exponent.starts_with(b"-") {
+        let _ = result.push(b'+');
+    }
+    let _ = result.push_all(exponent);
+    result
+}
+
+/// Trait for types that can be encoded as IEEE/SCPI response data
+///
+/// This is the dual of [`ResponseData`](crate::ResponseData).
+pub trait ResponseDataEncode {
+    /// Encodes this value as response data bytes into the given encoder.
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error>;
+}
+
+impl<T> ResponseDataEncode for &T
+where
+    T: ResponseDataEncode + ?Sized,
+{
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error> {
+        (**self).encode(encoder)
+    }
+}
+
+impl ResponseDataEncode for bool {
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error> {
+        encoder.begin_response_data()?;
+        encoder.encode_boolean(*self)
+    }
+}
+
+macro_rules! impl_response_data_encode_integer {
+    ($($ty:ty),*) => {
+        $(
+            impl ResponseDataEncode for $ty {
+                fn encode<S: EncodeSink>(
+                    &self,
+                    encoder: &mut ResponseEncoder<S>,
+                ) -> Result<(), S::Error> {
+                    encoder.begin_response_data()?;
+                    encoder.encode_nr1(*self)
+                }
+            }
+        )*
+    };
+}
+
+impl_response_data_encode_integer!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+impl ResponseDataEncode for f32 {
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error> {
+        encoder.begin_response_data()?;
+        encoder.encode_nr3(*self)
+    }
+}
+impl ResponseDataEncode for f64 {
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error> {
+        encoder.begin_response_data()?;
+        encoder.encode_nr3(*self)
+    }
+}
+
+impl ResponseDataEncode for str {
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error> {
+        encoder.begin_response_data()?;
+        encoder.encode_string(self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ResponseDataEncode for String {
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error> {
+        self.as_str().encode(encoder)
+    }
+}
+
+impl ResponseDataEncode for [u8] {
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error> {
+        encoder.begin_response_data()?;
+        encoder.encode_definite_block(self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ResponseDataEncode for Vec<u8> {
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error> {
+        self.as_slice().encode(encoder)
+    }
+}
+
+impl ResponseDataEncode for ErrorCode {
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error> {
+        i16::from(*self).encode(encoder)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ResponseDataEncode for SystemErrorResponse {
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error> {
+        (self.code, self.message.as_str()).encode(encoder)
+    }
+}
+
+/// Encodes the identification as arbitrary ASCII response data, with empty fields written as `0`.
+///
+/// The fields can't contain commas or newlines.
+#[cfg(feature = "alloc")]
+impl ResponseDataEncode for DeviceIdentification {
+    fn encode<S: EncodeSink>(&self, encoder: &mut ResponseEncoder<S>) -> Result<(), S::Error> {
+        let fields = [
+            &self.manufacturer,
+            &self.model,
+            &self.serial_number,
+            &self.firmware_level,
+        ];
+        if fields.iter().any(|field| field.contains(',')) {
+            return Err(EncodeError::InvalidArbitraryAscii.into());
+        }
+        let text = fields
+            .map(|field| {
+                if field.is_empty() {
+                    "0"
+                } else {
+                    field.as_str()
+                }
+            })
+            .join(",");
+        encoder.begin_response_data()?;
+        encoder.encode_arbitrary_ascii(&text)
+    }
+}
+
+macro_rules! impl_response_data_encode_tuple {
+    ($($idx:tt $name:ident),*) => {
+        impl<$($name),*> ResponseDataEncode for ($($name,)*)
+        where
+            $($name: ResponseDataEncode,)*
+        {
+            fn encode<S: EncodeSink>(
+                &self,
+                encoder: &mut ResponseEncoder<S>,
+            ) -> Result<(), S::Error> {
+                $(self.$idx.encode(encoder)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_tuples!(impl_response_data_encode_tuple);
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use alloc::{string::String, vec::Vec};
+    use core::fmt::Debug;
+    use matches::assert_matches;
+
+    use super::{ResponseDataEncode, ResponseEncoder};
+    use crate::{
+        decode::{DecodeState, Decoder},
+        encode::{EncodeError, EncodeState},
+        ArbitraryAscii, ResponseData,
+    };
+
+    fn round_trip<T>(value: T)
+    where
+        T: ResponseDataEncode + ResponseData + Debug + PartialEq,
+    {
+        let mut encoder = ResponseEncoder::new(Vec::new());
+        encoder.begin_message_unit().unwrap();
+        value.encode(&mut encoder).unwrap();
+        let bytes = encoder.finish().unwrap();
+        let mut decoder = Decoder::new(&bytes[..]);
+        assert_eq!(T::decode(&mut decoder).unwrap(), value, "{:?}", bytes);
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn integers_round_trip() {
+        round_trip(true);
+        round_trip(false);
+        round_trip(u8::MAX);
+        round_trip(u64::MAX);
+        round_trip(i8::MIN);
+        round_trip(i64::MIN);
+        round_trip(0isize);
+        round_trip((
+            1u8, 2u16, 3u32, 4u64, 5usize, -6i8, -7i16, -8i32, -9i64, -10isize, 11u8, true,
+        ));
+    }
+
+    #[test]
+    fn floats_round_trip() {
+        for value in [0.0, -0.0, 1.0, -1.5, 1e-300, 1.7976931348623157e308, 42.69] {
+            round_trip(value);
+        }
+        for value in [0.0f32, 3.0, -5.123456, f32::MIN_POSITIVE, f32::MAX] {
+            round_trip(value);
+        }
+    }
+
+    #[test]
+    fn float_format_is_nr3() {
+        let mut encoder = ResponseEncoder::new(Vec::new());
+        encoder.begin_message_unit().unwrap();
+        (1000.0, -0.25, f64::INFINITY, f64::NAN)
+            .encode(&mut encoder)
+            .unwrap();
+        assert_eq!(
+            encoder.finish().unwrap(),
+            b"1.0E+3,-2.5E-1,9.9E+37,9.91E+37\n"
+        );
+    }
+
+    #[test]
+    fn strings_and_blocks_round_trip() {
+        round_trip(String::from("quote:\"\"\" and more"));
+        round_trip(String::new());
+        round_trip(Vec::from(&b"\x00\n\"#;,"[..]));
+        round_trip(Vec::new());
+        round_trip((String::from("a,b;c"), Vec::from(&b"\n"[..]), 7u8, -2.5f64));
+    }
+
+    #[test]
+    fn characters_round_trip() {
+        let mut encoder = ResponseEncoder::new(Vec::new());
+        encoder.begin_message_unit().unwrap();
+        encoder.begin_response_data().unwrap();
+        encoder.encode_characters("VOLT_2").unwrap();
+        encoder.begin_response_data().unwrap();
+        assert_matches!(
+            encoder.encode_characters("volt"),
+            Err(EncodeError::InvalidCharacterData)
+        );
+        assert_matches!(
+            encoder.encode_characters("2VOLT"),
+            Err(EncodeError::InvalidCharacterData)
+        );
+        encoder.encode_characters("CURR").unwrap();
+        let bytes = encoder.finish().unwrap();
+
+        let mut decoder = Decoder::new(&bytes[..]);
+        let mut text = String::new();
+        decoder.begin_response_data().unwrap();
+        decoder.decode_characters(&mut text).unwrap();
+        assert_eq!(text, "VOLT_2");
+        text.clear();
+        decoder.begin_response_data().unwrap();
+        decoder.decode_characters(&mut text).unwrap();
+        assert_eq!(text, "CURR");
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn message_units_round_trip() {
+        let mut encoder = ResponseEncoder::new(Vec::new());
+        encoder.begin_message_unit().unwrap();
+        (1u8, 2u8).encode(&mut encoder).unwrap();
+        encoder.begin_message_unit().unwrap();
+        "three".encode(&mut encoder).unwrap();
+        encoder.begin_message_unit().unwrap();
+        encoder.begin_response_data().unwrap();
+        encoder.encode_arbitrary_ascii("ACME, Model 1").unwrap();
+        assert_matches!(
+            encoder.begin_response_data(),
+            Err(EncodeError::InvalidEncodeState(EncodeState::Data))
+        );
+        assert_matches!(
+            encoder.begin_message_unit(),
+            Err(EncodeError::InvalidEncodeState(EncodeState::Data))
+        );
+        let bytes = encoder.finish().unwrap();
+        assert_eq!(bytes, b"1,2;\"three\";ACME, Model 1\n");
+
+        let mut decoder = Decoder::new(&bytes[..]);
+        assert_eq!(<(u8, u8)>::decode(&mut decoder), Ok((1, 2)));
+        assert_eq!(String::decode(&mut decoder).as_deref(), Ok("three"));
+        let ascii: String = ArbitraryAscii::decode(&mut decoder).unwrap().into();
+        assert_eq!(ascii, "ACME, Model 1");
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_arbitrary_ascii() {
+        let mut encoder = ResponseEncoder::new(Vec::new());
+        encoder.begin_message_unit().unwrap();
+        encoder.begin_response_data().unwrap();
+        assert_matches!(
+            encoder.encode_arbitrary_ascii("two\nlines"),
+            Err(EncodeError::InvalidArbitraryAscii)
+        );
+        assert_matches!(
+            encoder.encode_arbitrary_ascii("\u{e4}"),
+            Err(EncodeError::NonAsciiString)
+        );
+    }
+
+    #[test]
+    fn two_units_round_trip() {
+        let mut encoder = ResponseEncoder::new(Vec::new());
+        encoder.begin_message_unit().unwrap();
+        32u8.encode(&mut encoder).unwrap();
+        encoder.begin_message_unit().unwrap();
+        (-100i16, "Command error").encode(&mut encoder).unwrap();
+        let bytes = encoder.finish().unwrap();
+        assert_eq!(bytes, b"32;-100,\"Command error\"\n");
+
+        let mut decoder = Decoder::new(&bytes[..]);
+        assert_eq!(u8::decode(&mut decoder), Ok(32));
+        assert_eq!(decoder.state(), DecodeState::MessageUnitExpected);
+        let (code, message) = <(i16, String)>::decode(&mut decoder).unwrap();
+        assert_eq!((code, message.as_str()), (-100, "Command error"));
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn data_before_message_unit_is_invalid() {
+        let mut encoder = ResponseEncoder::new(Vec::new());
+        assert_matches!(
+            encoder.begin_response_data(),
+            Err(EncodeError::InvalidEncodeState(EncodeState::Initial))
+        );
+        assert_matches!(
+            1u8.encode(&mut encoder),
+            Err(EncodeError::InvalidEncodeState(EncodeState::Initial))
+        );
+        assert_eq!(encoder.state(), EncodeState::Initial);
+    }
+
+    #[test]
+    fn terminator_is_written_once() {
+        let mut encoder = ResponseEncoder::new(Vec::new());
+        encoder.begin_message_unit().unwrap();
+        true.encode(&mut encoder).unwrap();
+        encoder.end_message().unwrap();
+        assert_eq!(encoder.state(), EncodeState::End);
+        encoder.end_message().unwrap();
+        assert_matches!(
+            encoder.begin_message_unit(),
+            Err(EncodeError::InvalidEncodeState(EncodeState::End))
+        );
+        assert_eq!(encoder.finish().unwrap(), b"1\n");
+    }
+
+    #[test]
+    fn empty_message_is_invalid() {
+        assert_matches!(
+            ResponseEncoder::new(Vec::new()).finish(),
+            Err(EncodeError::InvalidEncodeState(EncodeState::Initial))
+        );
+    }
+}
@@ -2,214 +2,1003 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+#[cfg(feature = "alloc")]
 use alloc::{string::String, vec::Vec};
 
+#[cfg(not(feature = "alloc"))]
+use crate::buffer::ScpiString;
 use crate::{
-    decode::{DecodeError, Decoder},
-    ByteSource,
+    decode::{CharacterParseError, DecodeError, DecodeState, Decoder},
+    internal::impl_tuples,
+    ByteSource, ProtocolObserver,
 };
 
 /// Trait for types that can be parsed from IEEE/SCPI response bytes
 pub trait ResponseData: Sized {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error>;
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error>;
+    /// Decodes a complete response message from bytes.
+    ///
+    /// The response must end with a terminator, and nothing may follow it.
+    ///
+    /// ```
+    /// use red_sculpin::ResponseData;
+    ///
+    /// assert_eq!(f32::from_response_bytes(b"1999.0\n"), Ok(1999.0));
+    /// assert!(f32::from_response_bytes(b"1999.0").is_err());
+    /// ```
+    fn from_response_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        decode_complete(bytes, Self::decode)
+    }
+}
+
+/// Decodes a complete response message, and rejects bytes after its terminator.
+pub(crate) fn decode_complete<'a, T>(
+    bytes: &'a [u8],
+    decode: impl FnOnce(&mut Decoder<&'a [u8]>) -> Result<T, DecodeError>,
+) -> Result<T, DecodeError> {
+    let mut decoder = Decoder::new(bytes);
+    let value = decode(&mut decoder)?;
+    match decoder.finish()? {
+        [] => Ok(value),
+        _ => Err(DecodeError::Parse),
+    }
+}
+
+/// Trait for response data types that can be decoded into an existing value, reusing its
+/// allocations
+pub trait ResponseDataInto: ResponseData {
+    /// Decodes response data into the target, replacing its previous contents.
+    ///
+    /// If decoding fails, the target may contain partially decoded data.
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        target: &mut Self,
+    ) -> Result<(), S::Error>;
 }
 
 impl ResponseData for bool {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_boolean()
     }
 }
 
 impl ResponseData for u8 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_integer()
     }
 }
 impl ResponseData for u16 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_integer()
     }
 }
 impl ResponseData for u32 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_integer()
     }
 }
 impl ResponseData for u64 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_integer()
     }
 }
 impl ResponseData for usize {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_integer()
     }
 }
 
 impl ResponseData for i8 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_integer()
     }
 }
 impl ResponseData for i16 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_integer()
     }
 }
 impl ResponseData for i32 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_integer()
     }
 }
 impl ResponseData for i64 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_integer()
     }
 }
 impl ResponseData for isize {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_integer()
     }
 }
 
 impl ResponseData for f32 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_float()
     }
 }
 
 impl ResponseData for f64 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
         decoder.decode_numeric_float()
     }
 }
 
+#[cfg(feature = "alloc")]
 impl ResponseData for String {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
-        decoder.begin_response_data()?;
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         let mut text = String::new();
-        decoder.decode_string(&mut text)?;
+        String::decode_into(decoder, &mut text)?;
         Ok(text)
     }
 }
 
-impl ResponseData for Vec<u8> {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+#[cfg(feature = "alloc")]
+impl ResponseDataInto for String {
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        target: &mut Self,
+    ) -> Result<(), S::Error> {
+        target.clear();
         decoder.begin_response_data()?;
+        decoder.decode_string(target)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ResponseData for Vec<u8> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         let mut result = Vec::new();
-        decoder.decode_arbitrary_block(&mut result)?;
+        Vec::decode_into(decoder, &mut result)?;
         Ok(result)
     }
 }
 
-impl<A, B> ResponseData for (A, B)
-where
-    A: ResponseData,
-    B: ResponseData,
-{
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
-        let a = A::decode(decoder)?;
-        let b = B::decode(decoder)?;
-        Ok((a, b))
+#[cfg(feature = "alloc")]
+impl ResponseDataInto for Vec<u8> {
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        target: &mut Self,
+    ) -> Result<(), S::Error> {
+        target.clear();
+        decoder.begin_response_data()?;
+        decoder.decode_arbitrary_block(target)
     }
 }
 
-impl<A, B, C> ResponseData for (A, B, C)
-where
-    A: ResponseData,
-    B: ResponseData,
-    C: ResponseData,
-{
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
-        let a = A::decode(decoder)?;
-        let b = B::decode(decoder)?;
-        let c = C::decode(decoder)?;
-        Ok((a, b, c))
+macro_rules! impl_response_data_tuple {
+    ($($idx:tt $name:ident),*) => {
+        impl<$($name),*> ResponseData for ($($name,)*)
+        where
+            $($name: ResponseData,)*
+        {
+            fn decode<S: ByteSource, O: ProtocolObserver>(
+                decoder: &mut Decoder<S, O>,
+            ) -> Result<Self, S::Error> {
+                Ok(($($name::decode(decoder)?,)*))
+            }
+        }
+    };
+}
+
+impl_tuples!(impl_response_data_tuple);
+
+#[cfg(feature = "heapless")]
+impl<const N: usize> ResponseData for heapless::String<N> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        let mut text = heapless::String::new();
+        heapless::String::decode_into(decoder, &mut text)?;
+        Ok(text)
     }
 }
 
-impl<A, B, C, D> ResponseData for (A, B, C, D)
-where
-    A: ResponseData,
-    B: ResponseData,
-    C: ResponseData,
-    D: ResponseData,
-{
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
-        let a = A::decode(decoder)?;
-        let b = B::decode(decoder)?;
-        let c = C::decode(decoder)?;
-        let d = D::decode(decoder)?;
-        Ok((a, b, c, d))
+#[cfg(feature = "heapless")]
+impl<const N: usize> ResponseDataInto for heapless::String<N> {
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        target: &mut Self,
+    ) -> Result<(), S::Error> {
+        target.clear();
+        decoder.begin_response_data()?;
+        decoder.decode_string(target)
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl<const N: usize> ResponseData for heapless::Vec<u8, N> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        let mut result = heapless::Vec::new();
+        heapless::Vec::decode_into(decoder, &mut result)?;
+        Ok(result)
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl<const N: usize> ResponseDataInto for heapless::Vec<u8, N> {
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        target: &mut Self,
+    ) -> Result<(), S::Error> {
+        target.clear();
+        decoder.begin_response_data()?;
+        decoder.decode_arbitrary_block(target)
     }
 }
 
 /// IEEE 488.2 Arbitrary Ascii Response Data
 ///
 /// Reference: IEEE 488.2: 8.7.11 - \<ARBITRARY ASCII RESPONSE DATA\>
+#[cfg(feature = "alloc")]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ArbitraryAscii(String);
 
+#[cfg(feature = "alloc")]
 impl From<ArbitraryAscii> for String {
     fn from(ascii: ArbitraryAscii) -> String {
         ascii.0
     }
 }
 
+#[cfg(feature = "alloc")]
 impl ResponseData for ArbitraryAscii {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        let mut ascii = ArbitraryAscii(String::new());
+        ArbitraryAscii::decode_into(decoder, &mut ascii)?;
+        Ok(ascii)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ResponseDataInto for ArbitraryAscii {
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        target: &mut Self,
+    ) -> Result<(), S::Error> {
+        target.0.clear();
         decoder.begin_response_data()?;
-        let mut text = String::new();
-        decoder.decode_arbitrary_ascii(&mut text)?;
-        Ok(ArbitraryAscii(text))
+        decoder.decode_arbitrary_ascii(&mut target.0)
     }
 }
 
 /// A homogeneous list of response data values
+#[cfg(feature = "alloc")]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ResponseList<T>(pub Vec<T>);
 
+#[cfg(feature = "alloc")]
 impl<T> ResponseData for ResponseList<T>
 where
     T: ResponseData,
 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
-        let mut result = Vec::new();
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        ResponseList::decode_with_capacity(decoder, 0)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> ResponseDataInto for ResponseList<T>
+where
+    T: ResponseData,
+{
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        target: &mut Self,
+    ) -> Result<(), S::Error> {
+        ResponseList::decode_into_vec(decoder, &mut target.0)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> ResponseList<T>
+where
+    T: ResponseData,
+{
+    /// Decodes a list with room for at least `capacity` elements reserved up front.
+    pub fn decode_with_capacity<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        capacity: usize,
+    ) -> Result<Self, S::Error> {
+        let mut result = Vec::with_capacity(capacity);
+        ResponseList::decode_into_vec(decoder, &mut result)?;
+        Ok(ResponseList(result))
+    }
+    /// Decodes list elements into the given vector, replacing its previous contents.
+    ///
+    /// The vector is cleared first, so its allocation can be reused between queries.
+    pub fn decode_into_vec<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        target: &mut Vec<T>,
+    ) -> Result<(), S::Error> {
+        target.clear();
         loop {
-            result.push(T::decode(decoder)?);
+            target.push(T::decode(decoder)?);
+            if decoder.is_at_end() {
+                break Ok(());
+            }
+        }
+    }
+}
+
+/// A list of response data values preceded by their count
+///
+/// Unlike [`ResponseList`], exactly `count` values are decoded, so fields following the list are
+/// left for the caller. A response message that ends before `count` values have been decoded is
+/// rejected with [`DecodeError::ListLengthMismatch`].
+///
+/// ```
+/// use red_sculpin::{decode::Decoder, CountedList, ResponseData};
+///
+/// let mut decoder = Decoder::new(&b"3,1.5,2.5,3.5,\"V\"\n"[..]);
+/// let CountedList(readings) = CountedList::<f64>::decode(&mut decoder).unwrap();
+/// assert_eq!(readings, [1.5, 2.5, 3.5]);
+/// assert_eq!(String::decode(&mut decoder).unwrap(), "V");
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CountedList<T>(pub Vec<T>);
+
+#[cfg(feature = "alloc")]
+impl<T> ResponseData for CountedList<T>
+where
+    T: ResponseData,
+{
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        let mut list = CountedList(Vec::new());
+        CountedList::decode_into(decoder, &mut list)?;
+        Ok(list)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> ResponseDataInto for CountedList<T>
+where
+    T: ResponseData,
+{
+    fn decode_into<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        target: &mut Self,
+    ) -> Result<(), S::Error> {
+        let count = u32::decode(decoder)?;
+        CountedList::decode_n_into(decoder, count as usize, &mut target.0)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> CountedList<T>
+where
+    T: ResponseData,
+{
+    /// Decodes exactly `n` values, with the count supplied by the caller instead of the response.
+    pub fn decode_n<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        n: usize,
+    ) -> Result<Self, S::Error> {
+        // The count comes from the instrument, so don't trust it for the initial allocation
+        let mut result = Vec::with_capacity(n.min(64));
+        CountedList::decode_n_into(decoder, n, &mut result)?;
+        Ok(CountedList(result))
+    }
+    /// Decodes exactly `n` values into the given vector, replacing its previous contents.
+    pub fn decode_n_into<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+        n: usize,
+        target: &mut Vec<T>,
+    ) -> Result<(), S::Error> {
+        target.clear();
+        for actual in 0..n {
             if decoder.is_at_end() {
-                break Ok(ResponseList(result));
+                let err = DecodeError::ListLengthMismatch {
+                    expected: n,
+                    actual,
+                };
+                return Err(decoder.error(err));
             }
+            target.push(T::decode(decoder)?);
         }
+        Ok(())
+    }
+}
+
+/// Response data decoded as `T`, together with the raw bytes it was decoded from
+///
+/// The raw bytes include any whitespace before the response data, and the separator or terminator
+/// following it.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Captured<T>(pub T, pub Vec<u8>);
+
+#[cfg(feature = "alloc")]
+impl<T> ResponseData for Captured<T>
+where
+    T: ResponseData,
+{
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        let (value, raw) = decoder.capture(T::decode)?;
+        Ok(Captured(value, raw))
     }
 }
 
+/// Response data decoded as `T`, ignoring any extra fields that follow it in the same response
+/// message unit
+///
+/// Some instruments append vendor-specific fields to standard responses, e.g. a fifth `*IDN?`
+/// field, which makes strict decoding fail. The extra fields are skipped with
+/// [`Decoder::skip_response_data`].
+///
+/// ```
+/// use red_sculpin::{decode::Decoder, IgnoreTrailing, ResponseData};
+///
+/// let mut decoder = Decoder::new(&b"1,2,\"extra\"\n"[..]);
+/// let IgnoreTrailing(value) = IgnoreTrailing::<(u8, u8)>::decode(&mut decoder).unwrap();
+/// assert_eq!(value, (1, 2));
+/// assert!(decoder.is_at_end());
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct IgnoreTrailing<T>(pub T);
+
+impl<T> ResponseData for IgnoreTrailing<T>
+where
+    T: ResponseData,
+{
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        let value = T::decode(decoder)?;
+        while decoder.state() == DecodeState::DataExpected {
+            decoder.skip_response_data()?;
+        }
+        Ok(IgnoreTrailing(value))
+    }
+}
+
+/// Response data decoded as `T`, or `None` if the response data element is empty
+///
+/// Some instruments respond with an empty string or nothing at all between separators when a value
+/// isn't available. See [`Decoder::skip_empty_response_data`] for what counts as empty.
+///
+/// ```
+/// use red_sculpin::{EmptyAsNone, ResponseData};
+///
+/// assert_eq!(
+///     <(u8, EmptyAsNone<u8>, u8)>::from_response_bytes(b"1,,3\n"),
+///     Ok((1, EmptyAsNone(None), 3))
+/// );
+/// ```
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct EmptyAsNone<T>(pub Option<T>);
+
+impl<T> ResponseData for EmptyAsNone<T>
+where
+    T: ResponseData,
+{
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        if decoder.skip_empty_response_data()? {
+            Ok(EmptyAsNone(None))
+        } else {
+            T::decode(decoder).map(|value| EmptyAsNone(Some(value)))
+        }
+    }
+}
+
+/// Maximum length of the text decoded for [`CharacterResponseData`] without the `alloc` feature
+#[cfg(not(feature = "alloc"))]
+const CHARACTER_RESPONSE_DATA_LEN: usize = 64;
+
 /// Trait for types that can be decoded from character response data.
+///
+/// Text that isn't recognized is rejected with [`DecodeError::UnknownCharacters`], which contains
+/// the text.
+///
+/// The text is collected into a `String`. Without the `alloc` feature, it's collected into a
+/// [`ScpiString`](crate::buffer::ScpiString) instead, and text longer than 64 bytes is rejected
+/// with [`DecodeError::BufferOverflow`].
 pub trait CharacterResponseData: Sized {
     fn parse(text: &str) -> Option<Self>;
+    /// Parses the text like [`parse`](CharacterResponseData::parse), but returns an error that
+    /// contains the text if it isn't recognized.
+    ///
+    /// Override this to report something other than the whole text.
+    fn try_parse(text: &str) -> Result<Self, CharacterParseError> {
+        Self::parse(text).ok_or_else(|| CharacterParseError::new(text))
+    }
 }
 
 impl<T> ResponseData for T
 where
     T: CharacterResponseData,
 {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         decoder.begin_response_data()?;
+        #[cfg(feature = "alloc")]
         let mut text = String::new();
+        #[cfg(not(feature = "alloc"))]
+        let mut text = ScpiString::<CHARACTER_RESPONSE_DATA_LEN>::new();
         decoder.decode_arbitrary_ascii(&mut text)?;
-        T::parse(&text).ok_or_else(|| DecodeError::Parse.into())
+        T::try_parse(&text).map_err(|err| decoder.error(DecodeError::UnknownCharacters(err)))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use alloc::{string::String, vec::Vec};
+    use matches::assert_matches;
+
+    use super::{
+        Captured, CharacterResponseData, CountedList, EmptyAsNone, IgnoreTrailing, ResponseData,
+        ResponseDataInto, ResponseList,
+    };
+    use crate::decode::{CharacterParseError, DecodeError, DecodeState, Decoder};
+
+    #[test]
+    fn from_response_bytes() {
+        assert_eq!(<(u8, f32)>::from_response_bytes(b"1,2.5\n"), Ok((1, 2.5)));
+        assert_eq!(
+            String::from_response_bytes(b"\"abc\"\n"),
+            Ok(String::from("abc"))
+        );
+        // Trailing garbage before and after the terminator
+        assert_matches!(
+            u8::from_response_bytes(b"1,2\n"),
+            Err(DecodeError::InvalidDecodeState(DecodeState::DataExpected))
+        );
+        assert_matches!(u8::from_response_bytes(b"1\n2\n"), Err(DecodeError::Parse));
+        // Missing terminator
+        assert_matches!(
+            u8::from_response_bytes(b"1"),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn tuple6() {
+        assert_eq!(
+            <(String, u8, f64, bool, i32, Vec<u8>)>::from_response_bytes(
+                b"\"VOLT\",10,+1.0E-03,1,-5,#12ab\n"
+            ),
+            Ok((String::from("VOLT"), 10, 1e-3, true, -5, b"ab".to_vec()))
+        );
+    }
+
+    #[test]
+    fn query_decode_from_slice() {
+        use crate::{scpi::message::SystemVersionQuery, Query};
+
+        assert_eq!(
+            SystemVersionQuery.decode_from_slice(b"1999.0\n"),
+            Ok(1999.0)
+        );
+        assert_matches!(
+            SystemVersionQuery.decode_from_slice(b"1999.0\n\n"),
+            Err(DecodeError::Parse)
+        );
+        assert_matches!(
+            SystemVersionQuery.decode_from_slice(b"1999.0"),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn captured_includes_separators() {
+        let mut decoder = Decoder::new(&b"42, \"forty\"\"two\";7\n"[..]);
+        let Captured((number, text), raw) =
+            Captured::<(u16, String)>::decode(&mut decoder).unwrap();
+        assert_eq!(number, 42);
+        assert_eq!(text, "forty\"two");
+        assert_eq!(raw, b"42, \"forty\"\"two\";");
+        assert_matches!(u8::decode(&mut decoder), Ok(7));
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn captured_excludes_next_field() {
+        let mut decoder = Decoder::new(&b"1,  #13abc\n"[..]);
+        let Captured(first, first_raw) = Captured::<u8>::decode(&mut decoder).unwrap();
+        let Captured(block, block_raw) = Captured::<Vec<u8>>::decode(&mut decoder).unwrap();
+        assert_eq!(first, 1);
+        assert_eq!(first_raw, b"1,");
+        assert_eq!(block, b"abc");
+        assert_eq!(block_raw, b"  #13abc\n");
+    }
+
+    #[test]
+    fn nested_captures_are_included_in_outer_capture() {
+        let mut decoder = Decoder::new(&b"1,2\n"[..]);
+        let Captured((Captured(_, inner), _), outer) =
+            Captured::<(Captured<u8>, u8)>::decode(&mut decoder).unwrap();
+        assert_eq!(inner, b"1,");
+        assert_eq!(outer, b"1,2\n");
+    }
+
+    #[test]
+    fn response_list_decode_into_vec_reuses_vec() {
+        let mut values = Vec::with_capacity(16);
+        let ptr = values.as_ptr();
+        let mut decoder = Decoder::new(&b"1.5,2.5,3.5\n4.5\n"[..]);
+        ResponseList::<f64>::decode_into_vec(&mut decoder, &mut values).unwrap();
+        assert_eq!(values, [1.5, 2.5, 3.5]);
+        let mut decoder = Decoder::new(decoder.finish().unwrap());
+        ResponseList::<f64>::decode_into_vec(&mut decoder, &mut values).unwrap();
+        assert_eq!(values, [4.5]);
+        assert_eq!(values.capacity(), 16);
+        assert_eq!(values.as_ptr(), ptr);
+
+        let mut list = ResponseList(values);
+        let mut decoder = Decoder::new(&b"5.5,6.5\n"[..]);
+        ResponseList::decode_into(&mut decoder, &mut list).unwrap();
+        assert_eq!(list.0, [5.5, 6.5]);
+        assert_eq!(list.0.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn response_list_with_capacity() {
+        let mut decoder = Decoder::new(&b"1,2,3\n"[..]);
+        let ResponseList(values) =
+            ResponseList::<u8>::decode_with_capacity(&mut decoder, 100).unwrap();
+        assert_eq!(values, [1, 2, 3]);
+        assert!(values.capacity() >= 100);
+    }
+
+    #[test]
+    fn counted_list() {
+        let mut decoder = Decoder::new(&b"0;3,1,2,3,4\n"[..]);
+        assert_eq!(CountedList::<u8>::decode(&mut decoder).unwrap().0, []);
+        let CountedList(values) = CountedList::<u8>::decode(&mut decoder).unwrap();
+        assert_eq!(values, [1, 2, 3]);
+        assert_eq!(u8::decode(&mut decoder).unwrap(), 4);
+        assert!(decoder.finish().unwrap().is_empty());
+
+        let mut decoder = Decoder::new(&b"1,\"A\",2,\"B\"\n"[..]);
+        let CountedList(entries) = CountedList::<(u8, String)>::decode_n(&mut decoder, 2).unwrap();
+        assert_eq!(entries[1], (2, String::from("B")));
+        assert!(decoder.is_at_end());
+    }
+
+    #[test]
+    fn counted_list_ending_early() {
+        let mut decoder = Decoder::new(&b"3,1,2\n"[..]);
+        assert_matches!(
+            CountedList::<u8>::decode(&mut decoder),
+            Err(DecodeError::ListLengthMismatch {
+                expected: 3,
+                actual: 2
+            })
+        );
+        let mut decoder = Decoder::new(&b"2\n"[..]);
+        assert_matches!(
+            CountedList::<u8>::decode(&mut decoder),
+            Err(DecodeError::ListLengthMismatch {
+                expected: 2,
+                actual: 0
+            })
+        );
+    }
+
+    #[test]
+    fn query_decode_into_reuses_buffers() {
+        use crate::{ieee::message::DefineDeviceTriggerQuery, Query};
+
+        let mut block = Vec::new();
+        let mut decoder = Decoder::new(&b"#15*TRG;\n#13*WA\n"[..]);
+        DefineDeviceTriggerQuery
+            .decode_into(&mut decoder, &mut block)
+            .unwrap();
+        assert_eq!(block, b"*TRG;");
+        let (ptr, capacity) = (block.as_ptr(), block.capacity());
+        let mut decoder = Decoder::new(decoder.finish().unwrap());
+        DefineDeviceTriggerQuery
+            .decode_into(&mut decoder, &mut block)
+            .unwrap();
+        assert_eq!(block, b"*WA");
+        assert_eq!((block.as_ptr(), block.capacity()), (ptr, capacity));
+    }
+
+    #[test]
+    fn counted_list_decode_into_reuses_vec() {
+        let mut list = CountedList(Vec::with_capacity(4));
+        let ptr = list.0.as_ptr();
+        let mut decoder = Decoder::new(&b"2,1,2;3,3,4,5\n"[..]);
+        CountedList::<u8>::decode_into(&mut decoder, &mut list).unwrap();
+        assert_eq!(list.0, [1, 2]);
+        CountedList::<u8>::decode_into(&mut decoder, &mut list).unwrap();
+        assert_eq!(list.0, [3, 4, 5]);
+        assert_eq!((list.0.as_ptr(), list.0.capacity()), (ptr, 4));
+    }
+
+    #[test]
+    fn block_and_string_decode_into_reuse_buffers() {
+        let mut block = Vec::with_capacity(8);
+        let mut text = String::with_capacity(8);
+        let mut decoder = Decoder::new(&b"#14abcd,\"abcd\";#12ef,\"ef\"\n"[..]);
+        Vec::decode_into(&mut decoder, &mut block).unwrap();
+        String::decode_into(&mut decoder, &mut text).unwrap();
+        assert_eq!((block.as_slice(), text.as_str()), (&b"abcd"[..], "abcd"));
+        Vec::decode_into(&mut decoder, &mut block).unwrap();
+        String::decode_into(&mut decoder, &mut text).unwrap();
+        assert_eq!((block.as_slice(), text.as_str()), (&b"ef"[..], "ef"));
+        assert_eq!((block.capacity(), text.capacity()), (8, 8));
+    }
+
+    #[test]
+    fn ignore_trailing_fields() {
+        let error = |response: &[u8]| {
+            let mut decoder = Decoder::new(response);
+            let IgnoreTrailing(value) =
+                IgnoreTrailing::<(i16, String)>::decode(&mut decoder).unwrap();
+            assert!(decoder.finish().unwrap().is_empty());
+            value
+        };
+        let expected = (-100, String::from("Command error"));
+        assert_eq!(error(b"-100,\"Command error\"\n"), expected);
+        assert_eq!(error(b"-100,\"Command error\",42\n"), expected);
+        assert_eq!(
+            error(b"-100,\"Command error\",\"a,b;c\"\"\",#13,;\n,MAX\n"),
+            expected
+        );
+    }
+
+    #[test]
+    fn ignore_trailing_stops_at_message_unit() {
+        let mut decoder = Decoder::new(&b"1,2,3;4\n"[..]);
+        assert_matches!(
+            IgnoreTrailing::<u8>::decode(&mut decoder),
+            Ok(IgnoreTrailing(1))
+        );
+        assert_eq!(decoder.state(), DecodeState::MessageUnitExpected);
+        assert_matches!(u8::decode(&mut decoder), Ok(4));
+        assert!(decoder.is_at_end());
+
+        let mut decoder = Decoder::new(&b"1,2\n"[..]);
+        assert_matches!(
+            <(u8, u8, u8)>::decode(&mut decoder),
+            Err(DecodeError::InvalidDecodeState(DecodeState::End))
+        );
+    }
+
+    #[test]
+    fn empty_as_none() {
+        type Fields = (EmptyAsNone<String>, EmptyAsNone<u8>, EmptyAsNone<String>);
+        let some = |text: &str| EmptyAsNone(Some(String::from(text)));
+        assert_eq!(
+            Fields::from_response_bytes(b"\"\",,\"\"\n"),
+            Ok((EmptyAsNone(None), EmptyAsNone(None), EmptyAsNone(None)))
+        );
+        assert_eq!(
+            Fields::from_response_bytes(b"\"a\",42,\"\"\"\"\n"),
+            Ok((some("a"), EmptyAsNone(Some(42)), some("\"")))
+        );
+        assert_eq!(
+            Fields::from_response_bytes(b" \t, \"\",  \"b\"\n"),
+            Ok((EmptyAsNone(None), EmptyAsNone(None), some("b")))
+        );
+        assert_eq!(
+            <(EmptyAsNone<u8>, u8)>::from_response_bytes(b";2\n"),
+            Ok((EmptyAsNone(None), 2))
+        );
+        assert_eq!(
+            EmptyAsNone::<u8>::from_response_bytes(b"\n"),
+            Ok(EmptyAsNone(None))
+        );
+        assert_matches!(
+            EmptyAsNone::<u8>::from_response_bytes(b"x\n"),
+            Err(DecodeError::Parse)
+        );
+        assert_matches!(
+            EmptyAsNone::<u8>::from_response_bytes(b"\"\"x\n"),
+            Err(DecodeError::InvalidDataTerminator { byte: b'x' })
+        );
+
+        let mut decoder = Decoder::new(&b":SYST:LOCK:OWN \"\"\n"[..]);
+        decoder.set_echoed_headers(true);
+        assert_matches!(
+            EmptyAsNone::<String>::decode(&mut decoder),
+            Ok(EmptyAsNone(None))
+        );
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    enum Range {
+        Auto,
+        Manual,
+    }
+
+    impl CharacterResponseData for Range {
+        fn parse(text: &str) -> Option<Self> {
+            match text {
+                "AUTO" => Some(Range::Auto),
+                "MAN" => Some(Range::Manual),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Debug, Eq, PartialEq)]
+    struct Prefixed;
+
+    impl CharacterResponseData for Prefixed {
+        fn parse(text: &str) -> Option<Self> {
+            Self::try_parse(text).ok()
+        }
+        fn try_parse(text: &str) -> Result<Self, CharacterParseError> {
+            match text.strip_prefix("X_") {
+                Some("OK") => Ok(Prefixed),
+                Some(rest) => Err(CharacterParseError::new(rest)),
+                None => Err(CharacterParseError::new(text)),
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_characters() {
+        use alloc::string::ToString;
+
+        assert_eq!(Range::from_response_bytes(b"MAN\n"), Ok(Range::Manual));
+        let err = Range::from_response_bytes(b"HOLD\n").unwrap_err();
+        assert_eq!(
+            err,
+            DecodeError::UnknownCharacters(CharacterParseError::new("HOLD"))
+        );
+        assert_eq!(err.to_string(), "unknown character data \"HOLD\"");
+        let long = [&[b'A'; 100][..], b"\n"].concat();
+        assert_matches!(
+            Range::from_response_bytes(&long),
+            Err(DecodeError::UnknownCharacters(err)) if err.is_truncated()
+        );
+
+        assert_eq!(Prefixed::from_response_bytes(b"X_OK\n"), Ok(Prefixed));
+        let err = Prefixed::from_response_bytes(b"X_BAD\n").unwrap_err();
+        assert_eq!(err.to_string(), "unknown character data \"BAD\"");
+
+        let long = "A".repeat(40);
+        let err = CharacterParseError::new(&long);
+        assert!(err.is_truncated());
+        assert_eq!(err.text(), &long[..32]);
+        assert_eq!(
+            DecodeError::UnknownCharacters(err).to_string(),
+            alloc::format!("unknown character data \"{}...\"", &long[..32])
+        );
+        // Truncated at a character boundary
+        let euros = "\u{20ac}".repeat(11);
+        let err = CharacterParseError::new(&euros);
+        assert_eq!(err.text(), &euros[..30]);
+        assert!(err.is_truncated());
+    }
+}
+
+#[cfg(all(test, feature = "heapless"))]
+mod heapless_tests {
+    use matches::assert_matches;
+
+    use crate::{
+        decode::{DecodeError, Decoder},
+        encode::{EncodeError, Encoder},
+        scpi::message::StatusOperationEnable,
+        Command, ResponseData,
+    };
+
+    #[test]
+    fn decode_into_replaces_contents() {
+        use crate::ResponseDataInto;
+
+        let mut text = heapless::String::<4>::new();
+        let mut block = heapless::Vec::<u8, 4>::new();
+        let mut decoder = Decoder::new(&b"\"abcd\",#13xyz;\"e\",#0z\n"[..]);
+        for expected in [("abcd", &b"xyz"[..]), ("e", b"z")] {
+            heapless::String::decode_into(&mut decoder, &mut text).unwrap();
+            heapless::Vec::decode_into(&mut decoder, &mut block).unwrap();
+            assert_eq!((text.as_str(), block.as_slice()), expected);
+        }
+    }
+
+    #[test]
+    fn string_at_exact_capacity() {
+        let mut decoder = Decoder::new(&b"\"ab\"\"c\"\n"[..]);
+        let text = heapless::String::<4>::decode(&mut decoder).unwrap();
+        assert_eq!(text, "ab\"c");
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn string_overflow() {
+        let mut decoder = Decoder::new(&b"\"abcde\"\n"[..]);
+        assert_matches!(
+            heapless::String::<4>::decode(&mut decoder),
+            Err(DecodeError::BufferOverflow)
+        );
+    }
+
+    #[test]
+    fn block_at_exact_capacity() {
+        let mut decoder = Decoder::new(&b"#14abcd,#0efgh\n"[..]);
+        let (definite, indefinite) =
+            <(heapless::Vec<u8, 4>, heapless::Vec<u8, 4>)>::decode(&mut decoder).unwrap();
+        assert_eq!(definite, b"abcd");
+        assert_eq!(indefinite, b"efgh");
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn block_overflow() {
+        let mut decoder = Decoder::new(&b"#15abcde\n"[..]);
+        assert_matches!(
+            heapless::Vec::<u8, 4>::decode(&mut decoder),
+            Err(DecodeError::BufferOverflow)
+        );
+    }
+
+    #[test]
+    fn vec_as_encode_target() {
+        let mut encoder = Encoder::new(heapless::Vec::<u8, 21>::new());
+        StatusOperationEnable(42).encode(&mut encoder).unwrap();
+        assert_eq!(encoder.finish().unwrap(), b":STAT:OPER:ENAB 42\n");
+
+        let mut encoder = Encoder::new(heapless::Vec::<u8, 8>::new());
+        assert_matches!(
+            StatusOperationEnable(42).encode(&mut encoder),
+            Err(EncodeError::BufferOverflow)
+        );
     }
 }
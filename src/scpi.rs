@@ -2,6 +2,8 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+/// Channel numbers as numeric suffixes of commands and queries
+pub mod channel;
 /// SCPI 1999.0 standard commands and queries
 pub mod message;
 /// Program / response data types defined by SCPI 1999.0
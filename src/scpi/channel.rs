@@ -0,0 +1,244 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::{
+    buffer::ArrayBuffer,
+    decode::{DecodeError, Decoder},
+    encode::{format_integer, EncodeError, EncodeSink, Encoder},
+    ByteSource, Command, ProgramData, ProtocolObserver, Query,
+};
+
+/// A command or query with a channel number applied as a numeric suffix to its header
+///
+/// The channel is inserted after the header segment with the index `SEGMENT`, which is the first
+/// one by default. Segments are separated by `:`, and a leading `:` and a trailing `?` are not part
+/// of them. For example, channel 2 turns `:OUTP:STAT` into `:OUTP2:STAT`, and with `SEGMENT` 1,
+/// `:MEAS:VOLT?` into `:MEAS:VOLT2?`.
+///
+/// Trailing digits of the segment are treated as an existing numeric suffix, and replaced with the
+/// channel, so `:SOUR1:VOLT` becomes `:SOUR2:VOLT`. Encoding fails with
+/// [`EncodeError::InvalidChannel`] if the channel is 0, because numeric suffixes start from 1, and
+/// with [`EncodeError::MissingHeaderSegment`] if the header has no segment with the index
+/// `SEGMENT`. Common command headers like `*RST` have no segments.
+///
+/// [`mnemonic`](Command::mnemonic) returns the header of the inner command or query without the
+/// channel.
+///
+/// ```
+/// use red_sculpin::{scpi::{channel::Channeled, message::StatusOperationEnable}, Command};
+///
+/// let mut buf = [0; 32];
+/// let len = Channeled::<_>(2, StatusOperationEnable(1)).encode_to_slice(&mut buf).unwrap();
+/// assert_eq!(&buf[..len], b":STAT2:OPER:ENAB 1\n");
+/// let len = Channeled::<_, 1>(2, StatusOperationEnable(1)).encode_to_slice(&mut buf).unwrap();
+/// assert_eq!(&buf[..len], b":STAT:OPER2:ENAB 1\n");
+/// ```
+///
+/// Reference: SCPI 1999.0: 6.2.5 - Numeric Suffixes
+#[derive(Copy, Clone, Debug)]
+pub struct Channeled<C, const SEGMENT: usize = 0>(pub u8, pub C);
+
+impl<C, const SEGMENT: usize> Channeled<C, SEGMENT> {
+    fn encode_header<S: EncodeSink>(
+        &self,
+        encoder: &mut Encoder<S>,
+        mnemonic: &str,
+    ) -> Result<(), S::Error> {
+        let (start, end) = suffix_range(mnemonic, SEGMENT, self.0)?;
+        let header = mnemonic.as_bytes();
+        let channel = format_integer(&self.0);
+        encoder.begin_message_unit()?;
+        encoder.write_bytes_vectored(&[&header[..start], channel.as_slice(), &header[end..]])
+    }
+    fn skip_echoed_header<S: ByteSource, O: ProtocolObserver>(
+        &self,
+        decoder: &mut Decoder<S, O>,
+        mnemonic: &str,
+    ) -> Result<(), S::Error> {
+        if !decoder.echoed_headers() {
+            return Ok(());
+        }
+        let bytes = mnemonic.as_bytes();
+        let mut header = ArrayBuffer::<64>::new();
+        match suffix_range(mnemonic, SEGMENT, self.0) {
+            Ok((start, end))
+                if header
+                    .push_all(&bytes[..start])
+                    .and_then(|_| header.push_all(format_integer(&self.0).as_slice()))
+                    .and_then(|_| header.push_all(&bytes[end..]))
+                    .is_ok() =>
+            {
+                decoder.skip_echoed_header(header.as_str().unwrap_or_default())
+            }
+            _ => Err(decoder.error(DecodeError::BufferOverflow)),
+        }
+    }
+}
+
+/// Returns the byte range of the numeric suffix of the given header segment, which is empty if the
+/// segment has no suffix yet.
+fn suffix_range(
+    mnemonic: &str,
+    segment: usize,
+    channel: u8,
+) -> Result<(usize, usize), EncodeError> {
+    if channel == 0 {
+        return Err(EncodeError::InvalidChannel(channel));
+    }
+    if mnemonic.starts_with('*') {
+        return Err(EncodeError::MissingHeaderSegment(segment));
+    }
+    let bytes = mnemonic.as_bytes();
+    let mut start = usize::from(bytes.first() == Some(&b':'));
+    for _ in 0..segment {
+        match bytes[start..].iter().position(|&byte| byte == b':') {
+            Some(pos) => start += pos + 1,
+            None => return Err(EncodeError::MissingHeaderSegment(segment)),
+        }
+    }
+    let end = bytes[start..]
+        .iter()
+        .position(|&byte| matches!(byte, b':' | b'?'))
+        .map_or(bytes.len(), |pos| start + pos);
+    let digits = bytes[start..end]
+        .iter()
+        .rev()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    if end - digits == start {
+        return Err(EncodeError::MissingHeaderSegment(segment));
+    }
+    Ok((end - digits, end))
+}
+
+impl<C: Command, const SEGMENT: usize> Command for Channeled<C, SEGMENT> {
+    type ProgramData = C::ProgramData;
+    fn mnemonic(&self) -> &str {
+        self.1.mnemonic()
+    }
+    fn program_data(&self) -> Self::ProgramData {
+        self.1.program_data()
+    }
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        self.encode_header(encoder, self.1.mnemonic())?;
+        self.1.program_data().encode(encoder)
+    }
+}
+
+impl<Q: Query, const SEGMENT: usize> Query for Channeled<Q, SEGMENT> {
+    type ProgramData = Q::ProgramData;
+    type ResponseData = Q::ResponseData;
+    fn mnemonic(&self) -> &str {
+        self.1.mnemonic()
+    }
+    fn program_data(&self) -> Self::ProgramData {
+        self.1.program_data()
+    }
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        self.encode_header(encoder, self.1.mnemonic())?;
+        self.1.program_data().encode(encoder)
+    }
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        &self,
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self::ResponseData, S::Error> {
+        self.skip_echoed_header(decoder, self.1.mnemonic())?;
+        self.1.decode(decoder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::Channeled;
+    use crate::{
+        decode::Decoder,
+        encode::EncodeError,
+        ieee::message::{ClearStatus, OperationCompleteQuery},
+        internal::{declare_tuple_command, declare_tuple_query},
+        scpi::message::{StatusOperationConditionQuery, StatusOperationEnable},
+        Command, Query,
+    };
+
+    declare_tuple_command! {
+        #[derive(Copy, Clone, Debug)]
+        pub struct OutputState<":OUTP:STAT">(pub bool);
+    }
+
+    declare_tuple_command! {
+        #[derive(Copy, Clone, Debug)]
+        pub struct SourceVoltage<":SOUR1:VOLT">(pub f32);
+    }
+
+    declare_tuple_query! {
+        #[derive(Copy, Clone, Debug)]
+        pub struct OutputStateQuery<"OUTP?", bool>;
+    }
+
+    fn encode_command<C: Command>(command: C) -> Result<([u8; 64], usize), EncodeError> {
+        let mut buf = [0; 64];
+        let len = command.encode_to_slice(&mut buf)?;
+        Ok((buf, len))
+    }
+
+    fn encode_query<Q: Query>(query: Q) -> Result<([u8; 64], usize), EncodeError> {
+        let mut buf = [0; 64];
+        let len = query.encode_to_slice(&mut buf)?;
+        Ok((buf, len))
+    }
+
+    #[test]
+    fn suffix_is_inserted_after_the_segment() {
+        let (buf, len) = encode_command(Channeled::<_>(2, OutputState(true))).unwrap();
+        assert_eq!(&buf[..len], b":OUTP2:STAT 1\n");
+        let (buf, len) = encode_command(Channeled::<_, 1>(12, OutputState(true))).unwrap();
+        assert_eq!(&buf[..len], b":OUTP:STAT12 1\n");
+        let (buf, len) = encode_query(Channeled::<_, 2>(3, StatusOperationConditionQuery)).unwrap();
+        assert_eq!(&buf[..len], b":STAT:OPER:COND3?\n");
+        let (buf, len) = encode_query(Channeled::<_>(4, OutputStateQuery)).unwrap();
+        assert_eq!(&buf[..len], b"OUTP4?\n");
+    }
+
+    #[test]
+    fn existing_suffix_is_replaced() {
+        let (buf, len) = encode_command(Channeled::<_>(2, SourceVoltage(1.5))).unwrap();
+        assert_eq!(&buf[..len], b":SOUR2:VOLT 1.5E0\n");
+        let (buf, len) = encode_command(Channeled::<_>(1, SourceVoltage(1.5))).unwrap();
+        assert_eq!(&buf[..len], b":SOUR1:VOLT 1.5E0\n");
+    }
+
+    #[test]
+    fn invalid_channels_and_segments() {
+        assert_matches!(
+            encode_command(Channeled::<_>(0, OutputState(true))),
+            Err(EncodeError::InvalidChannel(0))
+        );
+        assert_matches!(
+            encode_command(Channeled::<_, 2>(1, StatusOperationEnable(0))),
+            Ok(_)
+        );
+        assert_matches!(
+            encode_command(Channeled::<_, 3>(1, StatusOperationEnable(0))),
+            Err(EncodeError::MissingHeaderSegment(3))
+        );
+        assert_matches!(
+            encode_command(Channeled::<_>(1, ClearStatus)),
+            Err(EncodeError::MissingHeaderSegment(0))
+        );
+        assert_matches!(
+            encode_query(Channeled::<_>(1, OperationCompleteQuery)),
+            Err(EncodeError::MissingHeaderSegment(0))
+        );
+    }
+
+    #[test]
+    fn echoed_header_includes_the_suffix() {
+        let query = Channeled::<_>(2, StatusOperationConditionQuery);
+        let mut decoder = Decoder::new(&b":STATUS2:OPERATION:CONDITION 16\n"[..]);
+        decoder.set_echoed_headers(true);
+        assert_eq!(query.decode(&mut decoder), Ok(16));
+        assert_eq!(query.mnemonic(), ":STAT:OPER:COND?");
+    }
+}
@@ -2,19 +2,34 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+#[cfg(feature = "alloc")]
+use crate::scpi::types::SystemErrorResponse;
 use crate::{
+    buffer::ArrayBuffer,
+    decode::{DecodeError, Decoder},
+    encode::{EncodeSink, Encoder},
     internal::{declare_tuple_command, declare_tuple_query},
-    scpi::types::SystemErrorResponse,
+    scpi::types::{
+        ErrorCode, FunctionShape, StatusRegister, StatusRegisterSnapshot, WaveformPreamble,
+    },
+    ByteSource, ProtocolObserver, Query, ResponseData,
 };
 
 // Mandatory SCPI 1999.0 commands
 
+#[cfg(feature = "alloc")]
 declare_tuple_query! {
     /// SCPI 1999.0 System -\> Error [-\> Next]?
     #[derive(Copy, Clone, Debug)]
     pub struct SystemErrorQuery<":SYST:ERR?", SystemErrorResponse>;
 }
 
+declare_tuple_query! {
+    /// SCPI 1999.0 System -\> Error -\> Code [-\> Next]?
+    #[derive(Copy, Clone, Debug)]
+    pub struct SystemErrorCodeQuery<":SYST:ERR:CODE?", ErrorCode>;
+}
+
 declare_tuple_query! {
     /// SCPI 1999.0 System -\> Version?
     #[derive(Copy, Clone, Debug)]
@@ -74,3 +89,78 @@ declare_tuple_command! {
     #[derive(Copy, Clone, Debug)]
     pub struct StatusPreset<":STAT:PRES">;
 }
+
+/// Condition, event and enable queries of a status register, sent in one program message
+///
+/// For example, `StatusRegisterSnapshotQuery(StatusRegister::Operation)` is encoded as
+/// `:STAT:OPER:COND?;:STAT:OPER?;:STAT:OPER:ENAB?`. Reading the event register also clears it.
+#[derive(Copy, Clone, Debug)]
+pub struct StatusRegisterSnapshotQuery<'a>(pub StatusRegister<'a>);
+
+/// Header suffixes of the condition, event and enable queries, in response order
+const SNAPSHOT_QUERIES: [&str; 3] = [":COND?", "?", ":ENAB?"];
+
+impl<'a> Query for StatusRegisterSnapshotQuery<'a> {
+    type ProgramData = ();
+    type ResponseData = StatusRegisterSnapshot;
+    fn mnemonic(&self) -> &str {
+        self.0.path()
+    }
+    fn program_data(&self) -> Self::ProgramData {}
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        for suffix in SNAPSHOT_QUERIES {
+            encoder.begin_message_unit()?;
+            encoder.write_bytes_vectored(&[self.0.path().as_bytes(), suffix.as_bytes()])?;
+        }
+        Ok(())
+    }
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        &self,
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self::ResponseData, S::Error> {
+        let mut registers = [0; 3];
+        for (register, suffix) in registers.iter_mut().zip(SNAPSHOT_QUERIES) {
+            if decoder.echoed_headers() {
+                let mut header = ArrayBuffer::<64>::new();
+                if header
+                    .push_all(self.0.path().as_bytes())
+                    .and_then(|_| header.push_all(suffix.as_bytes()))
+                    .is_err()
+                {
+                    return Err(decoder.error(DecodeError::BufferOverflow));
+                }
+                decoder.skip_echoed_header(header.as_str().unwrap_or_default())?;
+            }
+            *register = u16::decode(decoder)?;
+        }
+        let [condition, event, enable] = registers;
+        Ok(StatusRegisterSnapshot {
+            condition,
+            event,
+            enable,
+        })
+    }
+}
+
+// Source subsystem
+
+declare_tuple_command! {
+    /// SCPI 1999.0 [Source -\>] Function [-\> Shape]
+    #[derive(Copy, Clone, Debug)]
+    pub struct SourceFunctionShape<'a, ":SOUR:FUNC:SHAP">(pub &'a FunctionShape);
+}
+
+#[cfg(feature = "alloc")]
+declare_tuple_query! {
+    /// SCPI 1999.0 [Source -\>] Function [-\> Shape]?
+    #[derive(Copy, Clone, Debug)]
+    pub struct SourceFunctionShapeQuery<":SOUR:FUNC:SHAP?", FunctionShape>;
+}
+
+// Waveform subsystem, not defined by SCPI 1999.0 but shared by most oscilloscopes
+
+declare_tuple_query! {
+    /// Waveform -\> Preamble?
+    #[derive(Copy, Clone, Debug)]
+    pub struct WaveformPreambleQuery<":WAV:PRE?", WaveformPreamble>;
+}
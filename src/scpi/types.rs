@@ -2,15 +2,23 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+#[cfg(feature = "alloc")]
 use alloc::string::String;
-use core::convert::TryFrom;
+use core::{
+    convert::TryFrom,
+    fmt::{self, Write},
+    ops,
+};
 
 use crate::{
-    decode::Decoder,
-    encode::{EncodeSink, Encoder},
+    buffer::ArrayBuffer,
+    decode::{DecodeError, DecodeState, Decoder},
+    encode::{format_integer, EncodeError, EncodeSink, Encoder},
+    internal::Float,
     program_data::ProgramData,
-    response_data::ResponseData,
-    ByteSource,
+    response_data::{CharacterResponseData, ResponseData},
+    utils::mnemonic_matches,
+    ByteSource, ProtocolObserver,
 };
 
 /// Special program data that allows the instrument to select a numeric value.
@@ -67,6 +75,374 @@ impl ProgramData for Direction {
     }
 }
 
+/// Boolean program data encoded as `ON` or `OFF` instead of `1` or `0`
+///
+/// Boolean response data is always `1` or `0`, so this is only used for encoding.
+///
+/// Reference: SCPI 1999.0: 7.3 - Boolean Program Data
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct OnOff(pub bool);
+
+impl From<bool> for OnOff {
+    fn from(value: bool) -> Self {
+        OnOff(value)
+    }
+}
+
+impl From<OnOff> for bool {
+    fn from(value: OnOff) -> Self {
+        value.0
+    }
+}
+
+impl ProgramData for OnOff {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        encoder.begin_program_data()?;
+        encoder.encode_characters(if self.0 { "ON" } else { "OFF" })
+    }
+}
+
+/// Unit of suffix program data
+///
+/// References:
+///
+/// - IEEE 488.2: 7.7.3 - \<SUFFIX PROGRAM DATA\>
+/// - SCPI 1999.0: 7.2.3 - Suffixes
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Unit {
+    Ampere,
+    Celsius,
+    Coulomb,
+    Decibel,
+    DecibelMilliwatt,
+    Degree,
+    Farad,
+    Henry,
+    Hertz,
+    Joule,
+    Kelvin,
+    Meter,
+    Ohm,
+    Percent,
+    Radian,
+    Second,
+    Siemens,
+    Volt,
+    Watt,
+}
+
+impl Unit {
+    /// Returns the suffix mnemonic of the unit, e.g. `HZ`.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Unit::Ampere => "A",
+            Unit::Celsius => "CEL",
+            Unit::Coulomb => "C",
+            Unit::Decibel => "DB",
+            Unit::DecibelMilliwatt => "DBM",
+            Unit::Degree => "DEG",
+            Unit::Farad => "F",
+            Unit::Henry => "H",
+            Unit::Hertz => "HZ",
+            Unit::Joule => "J",
+            Unit::Kelvin => "K",
+            Unit::Meter => "M",
+            Unit::Ohm => "OHM",
+            Unit::Percent => "PCT",
+            Unit::Radian => "RAD",
+            Unit::Second => "S",
+            Unit::Siemens => "SIE",
+            Unit::Volt => "V",
+            Unit::Watt => "W",
+        }
+    }
+}
+
+/// Decimal multiplier of suffix program data
+///
+/// Reference: IEEE 488.2: 7.7.3.2 - Encoding syntax
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Multiplier {
+    Exa,
+    Peta,
+    Tera,
+    Giga,
+    Mega,
+    Kilo,
+    Milli,
+    Micro,
+    Nano,
+    Pico,
+    Femto,
+    Atto,
+}
+
+impl Multiplier {
+    /// Returns the suffix mnemonic of the multiplier, e.g. `MA` for mega.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Multiplier::Exa => "EX",
+            Multiplier::Peta => "PE",
+            Multiplier::Tera => "T",
+            Multiplier::Giga => "G",
+            Multiplier::Mega => "MA",
+            Multiplier::Kilo => "K",
+            Multiplier::Milli => "M",
+            Multiplier::Micro => "U",
+            Multiplier::Nano => "N",
+            Multiplier::Pico => "P",
+            Multiplier::Femto => "F",
+            Multiplier::Atto => "A",
+        }
+    }
+    /// Returns the power of ten of the multiplier, e.g. 3 for kilo.
+    pub fn exponent(&self) -> i32 {
+        match self {
+            Multiplier::Exa => 18,
+            Multiplier::Peta => 15,
+            Multiplier::Tera => 12,
+            Multiplier::Giga => 9,
+            Multiplier::Mega => 6,
+            Multiplier::Kilo => 3,
+            Multiplier::Milli => -3,
+            Multiplier::Micro => -6,
+            Multiplier::Nano => -9,
+            Multiplier::Pico => -12,
+            Multiplier::Femto => -15,
+            Multiplier::Atto => -18,
+        }
+    }
+}
+
+/// Suffix of decimal numeric program data, e.g. `KHZ` or `MM2`
+///
+/// Reference: IEEE 488.2: 7.7.3 - \<SUFFIX PROGRAM DATA\>
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Suffix {
+    pub multiplier: Option<Multiplier>,
+    pub unit: Unit,
+    /// Power of the unit, 1 unless the suffix has an exponent like `M2`
+    pub exponent: i8,
+}
+
+impl Suffix {
+    /// Converts a value with this suffix to the unscaled unit, e.g. 10 KHZ to 10000 HZ.
+    ///
+    /// The unit exponent applies to the multiplier too, so 1 MM2 is 1e-6 M2.
+    pub fn scale_to_base(&self, value: f64) -> f64 {
+        let exponent = match self.multiplier {
+            Some(multiplier) => multiplier.exponent() * i32::from(self.exponent),
+            None => return value,
+        };
+        let factor = (0..exponent.unsigned_abs()).fold(1.0, |factor, _| factor * 10.0);
+        if exponent < 0 {
+            value / factor
+        } else {
+            value * factor
+        }
+    }
+}
+
+/// Decimal numeric program data with a suffix, e.g. `1.5 V` or `10 MHZ`
+///
+/// INF, NINF and NAN are encoded without the suffix, because they are character program data
+/// which can't have one.
+///
+/// ```
+/// use red_sculpin::{
+///     buffer::ArrayBuffer,
+///     encode::Encoder,
+///     scpi::types::{Multiplier, Quantity, Unit},
+///     ProgramData,
+/// };
+///
+/// let mut encoder = Encoder::new(ArrayBuffer::<32>::new());
+/// encoder.begin_message_unit().unwrap();
+/// encoder.write_bytes(b"FREQ").unwrap();
+/// Quantity::with_multiplier(10.0, Multiplier::Mega, Unit::Hertz)
+///     .encode(&mut encoder)
+///     .unwrap();
+/// encoder.end_message().unwrap();
+/// assert_eq!(encoder.finish().unwrap().as_slice(), b"FREQ 1E1 MHZ\n");
+/// ```
+///
+/// References:
+///
+/// - IEEE 488.2: 7.7.3 - \<SUFFIX PROGRAM DATA\>
+/// - SCPI 1999.0: 7.2.3 - Suffixes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quantity<T> {
+    pub value: T,
+    pub suffix: Suffix,
+}
+
+impl<T> Quantity<T> {
+    pub fn new(value: T, unit: Unit) -> Quantity<T> {
+        Quantity {
+            value,
+            suffix: Suffix {
+                multiplier: None,
+                unit,
+                exponent: 1,
+            },
+        }
+    }
+    pub fn with_multiplier(value: T, multiplier: Multiplier, unit: Unit) -> Quantity<T> {
+        Quantity {
+            value,
+            suffix: Suffix {
+                multiplier: Some(multiplier),
+                unit,
+                exponent: 1,
+            },
+        }
+    }
+}
+
+impl<T: Float> ProgramData for Quantity<T> {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        encoder.begin_program_data()?;
+        encoder.encode_numeric_float(self.value)?;
+        if self.value.is_finite() {
+            encoder.encode_suffix(&self.suffix)?;
+        }
+        Ok(())
+    }
+}
+
+/// Channel list program data, e.g. `(@101,103:110,3!2)`
+///
+/// Entries are single channels, ranges of channels, and channels with several dimensions
+/// separated by `!`, like slot and channel. They are formatted into a buffer of `N` bytes as they
+/// are added, and encoding fails with [`EncodeError::BufferOverflow`] if they don't fit. An empty
+/// list, or a channel without dimensions, fails with [`EncodeError::InvalidChannelList`]. The
+/// ends of a range must have the same number of dimensions. A range can be descending, like
+/// `5:1`.
+///
+/// ```
+/// use red_sculpin::{buffer::ArrayBuffer, encode::Encoder, scpi::types::ChannelList, ProgramData};
+///
+/// let channels = ChannelList::new().channel(101).range(103, 110);
+/// let mut encoder = Encoder::new(ArrayBuffer::<32>::new());
+/// encoder.begin_message_unit().unwrap();
+/// encoder.write_bytes(b"ROUT:CLOS").unwrap();
+/// channels.encode(&mut encoder).unwrap();
+/// encoder.end_message().unwrap();
+/// assert_eq!(encoder.finish().unwrap().as_slice(), b"ROUT:CLOS (@101,103:110)\n");
+/// ```
+///
+/// Reference: SCPI 1999.0: 8.3.2 - Channel Lists
+#[derive(Clone, Debug, Default)]
+pub struct ChannelList<const N: usize = 64> {
+    text: ArrayBuffer<N>,
+    invalid: bool,
+    overflow: bool,
+}
+
+impl ChannelList {
+    /// Creates an empty channel list with room for 64 bytes.
+    ///
+    /// Lists of other sizes are created with [`ChannelList::default`].
+    pub fn new() -> ChannelList {
+        ChannelList::default()
+    }
+}
+
+impl<const N: usize> ChannelList<N> {
+    /// Adds a single channel, e.g. `101`.
+    pub fn channel(self, channel: u32) -> Self {
+        self.multi_channel(&[channel])
+    }
+    /// Adds a range of channels, e.g. `103:110`.
+    pub fn range(self, first: u32, last: u32) -> Self {
+        self.multi_range(&[first], &[last])
+    }
+    /// Adds a channel with several dimensions, e.g. `3!2`.
+    pub fn multi_channel(mut self, channel: &[u32]) -> Self {
+        self.invalid |= channel.is_empty();
+        self.separate();
+        self.push_channel(channel);
+        self
+    }
+    /// Adds a range of channels with several dimensions, e.g. `1!1:1!8`.
+    pub fn multi_range(mut self, first: &[u32], last: &[u32]) -> Self {
+        self.invalid |= first.is_empty() || first.len() != last.len();
+        self.separate();
+        self.push_channel(first);
+        self.push(format_args!(":"));
+        self.push_channel(last);
+        self
+    }
+    fn separate(&mut self) {
+        let separator = if self.text.is_empty() { "@" } else { "," };
+        self.push(format_args!("{}", separator));
+    }
+    fn push_channel(&mut self, channel: &[u32]) {
+        for (idx, dimension) in channel.iter().enumerate() {
+            let separator = if idx == 0 { "" } else { "!" };
+            self.push(format_args!("{}{}", separator, dimension));
+        }
+    }
+    fn push(&mut self, args: fmt::Arguments) {
+        self.overflow |= self.text.write_fmt(args).is_err();
+    }
+}
+
+impl<const N: usize> ProgramData for ChannelList<N> {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        if self.invalid || self.text.is_empty() {
+            return Err(EncodeError::InvalidChannelList.into());
+        }
+        if self.overflow {
+            return Err(EncodeError::BufferOverflow.into());
+        }
+        encoder.begin_program_data()?;
+        encoder.encode_expression(self.text.as_str().unwrap_or_default())
+    }
+}
+
+/// Item of a [`NumericList`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NumericListItem {
+    Single(i64),
+    /// Inclusive range, encoded as-is even if the start is greater than the end, like `5:1`
+    Range(i64, i64),
+}
+
+/// Numeric list program data, e.g. `(1:5,7,9:12)`
+///
+/// Encoding an empty list fails with [`EncodeError::EmptyNumericList`].
+///
+/// Reference: SCPI 1999.0: 8.8.3 - Numeric Lists
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct NumericList<'a>(pub &'a [NumericListItem]);
+
+impl<'a> ProgramData for NumericList<'a> {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        if self.0.is_empty() {
+            return Err(EncodeError::EmptyNumericList.into());
+        }
+        encoder.begin_program_data()?;
+        encoder.write_bytes(b"(")?;
+        for (idx, item) in self.0.iter().enumerate() {
+            if idx > 0 {
+                encoder.write_bytes(b",")?;
+            }
+            match *item {
+                NumericListItem::Single(value) => {
+                    encoder.write_bytes(format_integer(&value).as_slice())?
+                }
+                NumericListItem::Range(start, end) => encoder.write_bytes_vectored(&[
+                    format_integer(&start).as_slice(),
+                    b":",
+                    format_integer(&end).as_slice(),
+                ])?,
+            }
+        }
+        encoder.write_bytes(b")")
+    }
+}
+
 /// Standard error/event code defined by SCPI 1999.0
 ///
 /// Reference: SCPI 1999.0: 21.8 - :ERRor Subsystem
@@ -367,19 +743,40 @@ impl From<ErrorCode> for i16 {
     }
 }
 
+impl ResponseData for StandardErrorCode {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        let code = i16::decode(decoder)?;
+        StandardErrorCode::try_from(code).map_err(|_| decoder.error(DecodeError::Parse))
+    }
+}
+
+impl ResponseData for ErrorCode {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        i16::decode(decoder).map(ErrorCode::from)
+    }
+}
+
 /// SCPI 1999.0 error/event queue item
 ///
 /// Returned by error/event queue query (:SYSTem:ERRor:NEXT?).
 ///
 /// Reference: SCPI 1999.0: 21.8 - :ERRor Subsystem
+#[cfg(feature = "alloc")]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct SystemErrorResponse {
     pub code: ErrorCode,
     pub message: String,
 }
 
+#[cfg(feature = "alloc")]
 impl ResponseData for SystemErrorResponse {
-    fn decode<S: ByteSource>(decoder: &mut Decoder<S>) -> Result<Self, S::Error> {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
         let (code, message): (i16, String) = ResponseData::decode(decoder)?;
         Ok(SystemErrorResponse {
             code: ErrorCode::from(code),
@@ -516,3 +913,1395 @@ impl ProgramData for DefaultOrLimit {
         }
     }
 }
+
+/// Decimal numeric program data, or any of the character data that SCPI allows in its place
+///
+/// Covers the other `ValueOr*` types, which can be converted into it.
+///
+/// Reference: SCPI 1999.0: 7.2.1 - Syntax
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NumericValue<T> {
+    Value(T),
+    /// `DEF`
+    Default,
+    /// `MIN`
+    Min,
+    /// `MAX`
+    Max,
+    /// `UP`
+    Up,
+    /// `DOWN`
+    Down,
+    /// `INF`
+    Infinity,
+    /// `NINF`
+    NegInfinity,
+    /// `NAN`
+    NotANumber,
+}
+
+impl<T> NumericValue<T> {
+    pub fn map<O, F: FnOnce(T) -> O>(self, f: F) -> NumericValue<O> {
+        use NumericValue::*;
+        match self {
+            Value(value) => Value(f(value)),
+            Default => Default,
+            Min => Min,
+            Max => Max,
+            Up => Up,
+            Down => Down,
+            Infinity => Infinity,
+            NegInfinity => NegInfinity,
+            NotANumber => NotANumber,
+        }
+    }
+}
+
+impl<T> From<DefaultValue> for NumericValue<T> {
+    fn from(_: DefaultValue) -> Self {
+        NumericValue::Default
+    }
+}
+
+impl<T> From<Limit> for NumericValue<T> {
+    fn from(limit: Limit) -> Self {
+        match limit {
+            Limit::Min => NumericValue::Min,
+            Limit::Max => NumericValue::Max,
+        }
+    }
+}
+
+impl<T> From<Direction> for NumericValue<T> {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Up => NumericValue::Up,
+            Direction::Down => NumericValue::Down,
+        }
+    }
+}
+
+impl<T> From<DefaultOrLimit> for NumericValue<T> {
+    fn from(value: DefaultOrLimit) -> Self {
+        match value {
+            DefaultOrLimit::Default => NumericValue::Default,
+            DefaultOrLimit::Limit(limit) => limit.into(),
+        }
+    }
+}
+
+impl<T> From<ValueOrLimit<T>> for NumericValue<T> {
+    fn from(value: ValueOrLimit<T>) -> Self {
+        match value {
+            ValueOrLimit::Value(value) => NumericValue::Value(value),
+            ValueOrLimit::Limit(limit) => limit.into(),
+        }
+    }
+}
+
+impl<T> From<ValueOrDefault<T>> for NumericValue<T> {
+    fn from(value: ValueOrDefault<T>) -> Self {
+        match value {
+            ValueOrDefault::Value(value) => NumericValue::Value(value),
+            ValueOrDefault::Default => NumericValue::Default,
+        }
+    }
+}
+
+impl<T> From<ValueOrDefaultOrLimit<T>> for NumericValue<T> {
+    fn from(value: ValueOrDefaultOrLimit<T>) -> Self {
+        match value {
+            ValueOrDefaultOrLimit::Value(value) => NumericValue::Value(value),
+            ValueOrDefaultOrLimit::Default => NumericValue::Default,
+            ValueOrDefaultOrLimit::Limit(limit) => limit.into(),
+        }
+    }
+}
+
+impl<T> ProgramData for NumericValue<T>
+where
+    T: ProgramData,
+{
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        let characters = match self {
+            NumericValue::Value(value) => return value.encode(encoder),
+            NumericValue::Default => "DEF",
+            NumericValue::Min => "MIN",
+            NumericValue::Max => "MAX",
+            NumericValue::Up => "UP",
+            NumericValue::Down => "DOWN",
+            NumericValue::Infinity => "INF",
+            NumericValue::NegInfinity => "NINF",
+            NumericValue::NotANumber => "NAN",
+        };
+        encoder.begin_program_data()?;
+        encoder.encode_characters(characters)
+    }
+}
+
+/// Source of a trigger event, shared by the trigger subsystems of most instrument classes
+///
+/// Encoded in the short form, and decoded from either form ignoring case. Sources not defined here
+/// are decoded as `TriggerSource::Other`, so vendor extensions don't make decoding fail. Without
+/// the `alloc` feature they are rejected with [`DecodeError::UnknownCharacters`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum TriggerSource {
+    Immediate,
+    External,
+    Bus,
+    Timer,
+    Internal,
+    Manual,
+    Line,
+    /// A vendor-specific source, as written by the instrument
+    #[cfg(feature = "alloc")]
+    Other(String),
+}
+
+impl TriggerSource {
+    const SPECS: [(&'static str, TriggerSource); 7] = [
+        ("IMMediate", TriggerSource::Immediate),
+        ("EXTernal", TriggerSource::External),
+        ("BUS", TriggerSource::Bus),
+        ("TIMer", TriggerSource::Timer),
+        ("INTernal", TriggerSource::Internal),
+        ("MANual", TriggerSource::Manual),
+        ("LINe", TriggerSource::Line),
+    ];
+
+    /// Returns the short form mnemonic of the source, e.g. `IMM`.
+    pub fn mnemonic(&self) -> &str {
+        match self {
+            TriggerSource::Immediate => "IMM",
+            TriggerSource::External => "EXT",
+            TriggerSource::Bus => "BUS",
+            TriggerSource::Timer => "TIM",
+            TriggerSource::Internal => "INT",
+            TriggerSource::Manual => "MAN",
+            TriggerSource::Line => "LIN",
+            #[cfg(feature = "alloc")]
+            TriggerSource::Other(text) => text,
+        }
+    }
+}
+
+impl ProgramData for TriggerSource {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        encoder.begin_program_data()?;
+        encoder.encode_characters(self.mnemonic())
+    }
+}
+
+impl CharacterResponseData for TriggerSource {
+    fn parse(text: &str) -> Option<Self> {
+        let source = TriggerSource::SPECS
+            .into_iter()
+            .find(|(spec, _)| mnemonic_matches(spec, text))
+            .map(|(_, source)| source);
+        #[cfg(feature = "alloc")]
+        let source = source.or_else(|| Some(TriggerSource::Other(String::from(text))));
+        source
+    }
+}
+
+/// Input coupling, shared by the coupling parameters of scopes, multimeters and analyzers
+///
+/// Encoded in the short form, and decoded from either form ignoring case. Ground is encoded as
+/// `GRO`, but the `GND` spelling used by many instruments is also accepted.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Coupling {
+    Ac,
+    Dc,
+    Ground,
+    /// DC coupling with a limit on the DC input level, used by analyzers
+    DcLimit,
+    /// AC coupling with a limit on the input level, used by analyzers
+    AcLimit,
+}
+
+impl Coupling {
+    const SPECS: [(&'static str, Coupling); 6] = [
+        ("AC", Coupling::Ac),
+        ("DC", Coupling::Dc),
+        ("GROund", Coupling::Ground),
+        ("GND", Coupling::Ground),
+        ("DCLimit", Coupling::DcLimit),
+        ("ACLimit", Coupling::AcLimit),
+    ];
+
+    /// Returns the short form mnemonic of the coupling, e.g. `AC`.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Coupling::Ac => "AC",
+            Coupling::Dc => "DC",
+            Coupling::Ground => "GRO",
+            Coupling::DcLimit => "DCL",
+            Coupling::AcLimit => "ACL",
+        }
+    }
+}
+
+impl ProgramData for Coupling {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        encoder.begin_program_data()?;
+        encoder.encode_characters(self.mnemonic())
+    }
+}
+
+impl CharacterResponseData for Coupling {
+    fn parse(text: &str) -> Option<Self> {
+        Coupling::SPECS
+            .into_iter()
+            .find(|(spec, _)| mnemonic_matches(spec, text))
+            .map(|(_, coupling)| coupling)
+    }
+}
+
+/// Edge slope or polarity, shared by the trigger, counter and timing subsystems
+///
+/// Encoded in the short form, and decoded from either form ignoring case.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Slope {
+    Positive,
+    Negative,
+    Either,
+}
+
+impl Slope {
+    const SPECS: [(&'static str, Slope); 3] = [
+        ("POSitive", Slope::Positive),
+        ("NEGative", Slope::Negative),
+        ("EITHer", Slope::Either),
+    ];
+
+    /// Returns the short form mnemonic of the slope, e.g. `POS`.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Slope::Positive => "POS",
+            Slope::Negative => "NEG",
+            Slope::Either => "EITH",
+        }
+    }
+}
+
+/// Flips a positive slope to negative and vice versa. `Either` stays the same.
+impl ops::Not for Slope {
+    type Output = Slope;
+
+    fn not(self) -> Self::Output {
+        match self {
+            Slope::Positive => Slope::Negative,
+            Slope::Negative => Slope::Positive,
+            Slope::Either => Slope::Either,
+        }
+    }
+}
+
+impl ProgramData for Slope {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        encoder.begin_program_data()?;
+        encoder.encode_characters(self.mnemonic())
+    }
+}
+
+impl CharacterResponseData for Slope {
+    fn parse(text: &str) -> Option<Self> {
+        Slope::SPECS
+            .into_iter()
+            .find(|(spec, _)| mnemonic_matches(spec, text))
+            .map(|(_, slope)| slope)
+    }
+}
+
+/// Waveform shape of a function generator
+///
+/// Encoded in the short form, and decoded from either form ignoring case. Arbitrary waveforms are
+/// encoded as `USER`, and both `USER` and the `ARBitrary` spelling used by many instruments are
+/// decoded as [`FunctionShape::Arbitrary`]. Unknown shapes are decoded as `FunctionShape::Other`, or
+/// rejected with [`DecodeError::UnknownCharacters`] without the `alloc` feature.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum FunctionShape {
+    Sinusoid,
+    Square,
+    Ramp,
+    Pulse,
+    Noise,
+    Dc,
+    Triangle,
+    Prbs,
+    Arbitrary,
+    /// A vendor-specific shape, as written by the instrument
+    #[cfg(feature = "alloc")]
+    Other(String),
+}
+
+impl FunctionShape {
+    const SPECS: [(&'static str, FunctionShape); 10] = [
+        ("SINusoid", FunctionShape::Sinusoid),
+        ("SQUare", FunctionShape::Square),
+        ("RAMP", FunctionShape::Ramp),
+        ("PULSe", FunctionShape::Pulse),
+        ("NOISe", FunctionShape::Noise),
+        ("DC", FunctionShape::Dc),
+        ("TRIangle", FunctionShape::Triangle),
+        ("PRBS", FunctionShape::Prbs),
+        ("USER", FunctionShape::Arbitrary),
+        ("ARBitrary", FunctionShape::Arbitrary),
+    ];
+
+    /// Returns the short form mnemonic of the shape, e.g. `SIN`.
+    pub fn mnemonic(&self) -> &str {
+        match self {
+            FunctionShape::Sinusoid => "SIN",
+            FunctionShape::Square => "SQU",
+            FunctionShape::Ramp => "RAMP",
+            FunctionShape::Pulse => "PULS",
+            FunctionShape::Noise => "NOIS",
+            FunctionShape::Dc => "DC",
+            FunctionShape::Triangle => "TRI",
+            FunctionShape::Prbs => "PRBS",
+            FunctionShape::Arbitrary => "USER",
+            #[cfg(feature = "alloc")]
+            FunctionShape::Other(text) => text,
+        }
+    }
+}
+
+impl ProgramData for FunctionShape {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        encoder.begin_program_data()?;
+        encoder.encode_characters(self.mnemonic())
+    }
+}
+
+impl CharacterResponseData for FunctionShape {
+    fn parse(text: &str) -> Option<Self> {
+        let shape = FunctionShape::SPECS
+            .into_iter()
+            .find(|(spec, _)| mnemonic_matches(spec, text))
+            .map(|(_, shape)| shape);
+        #[cfg(feature = "alloc")]
+        let shape = shape.or_else(|| Some(FunctionShape::Other(String::from(text))));
+        shape
+    }
+}
+
+/// Measurement function of a multimeter, e.g. `"VOLT:DC"` in `SENS:FUNC "VOLT:DC"`
+///
+/// Unlike the other enumerated types, a measurement function is string data. It is encoded as the
+/// quoted short form, and decoded from either form ignoring case and a leading `:`. The optional
+/// `DC` node is accepted both with and without it, so `"CURR"` is [`MeasurementFunction::CurrentDc`].
+/// Other functions are decoded as [`MeasurementFunction::Custom`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum MeasurementFunction {
+    VoltageDc,
+    VoltageAc,
+    /// Ratio of the DC voltage to a reference voltage
+    VoltageDcRatio,
+    CurrentDc,
+    CurrentAc,
+    Resistance,
+    /// Four-wire resistance
+    FourWireResistance,
+    Frequency,
+    Period,
+    Continuity,
+    Diode,
+    Temperature,
+    Capacitance,
+    /// A function not listed here, as written
+    #[cfg(feature = "alloc")]
+    Custom(String),
+}
+
+impl MeasurementFunction {
+    /// Returns the function in the short form, e.g. `VOLT:DC`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            MeasurementFunction::VoltageDc => "VOLT:DC",
+            MeasurementFunction::VoltageAc => "VOLT:AC",
+            MeasurementFunction::VoltageDcRatio => "VOLT:DC:RAT",
+            MeasurementFunction::CurrentDc => "CURR:DC",
+            MeasurementFunction::CurrentAc => "CURR:AC",
+            MeasurementFunction::Resistance => "RES",
+            MeasurementFunction::FourWireResistance => "FRES",
+            MeasurementFunction::Frequency => "FREQ",
+            MeasurementFunction::Period => "PER",
+            MeasurementFunction::Continuity => "CONT",
+            MeasurementFunction::Diode => "DIOD",
+            MeasurementFunction::Temperature => "TEMP",
+            MeasurementFunction::Capacitance => "CAP",
+            #[cfg(feature = "alloc")]
+            MeasurementFunction::Custom(text) => text,
+        }
+    }
+    /// Parses a function in the short or long form, or returns `None` if it's not listed here.
+    pub fn parse_known(text: &str) -> Option<MeasurementFunction> {
+        use MeasurementFunction::*;
+        let mut nodes = text.strip_prefix(':').unwrap_or(text).split(':');
+        let root = nodes.next()?;
+        let mut rest = [nodes.next(), nodes.next()];
+        if nodes.next().is_some() {
+            return None;
+        }
+        let ac = rest[0].is_some_and(|node| node.eq_ignore_ascii_case("AC"));
+        let dc = rest[0].is_some_and(|node| node.eq_ignore_ascii_case("DC"));
+        if ac || dc {
+            rest = [rest[1], None];
+        }
+        let function = if mnemonic_matches("VOLTage", root) {
+            match rest {
+                [None, _] if ac => VoltageAc,
+                [None, _] => VoltageDc,
+                [Some(node), None] if !ac && mnemonic_matches("RATio", node) => VoltageDcRatio,
+                _ => return None,
+            }
+        } else if mnemonic_matches("CURRent", root) {
+            match rest {
+                [None, _] if ac => CurrentAc,
+                [None, _] => CurrentDc,
+                _ => return None,
+            }
+        } else if ac || dc || rest[0].is_some() {
+            return None;
+        } else {
+            [
+                ("RESistance", Resistance),
+                ("FRESistance", FourWireResistance),
+                ("FREQuency", Frequency),
+                ("PERiod", Period),
+                ("CONTinuity", Continuity),
+                ("DIODe", Diode),
+                ("TEMPerature", Temperature),
+                ("CAPacitance", Capacitance),
+            ]
+            .into_iter()
+            .find(|(spec, _)| mnemonic_matches(spec, root))?
+            .1
+        };
+        Some(function)
+    }
+}
+
+impl ProgramData for MeasurementFunction {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        self.as_str().encode(encoder)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ResponseData for MeasurementFunction {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        let text = String::decode(decoder)?;
+        Ok(MeasurementFunction::parse_known(&text).unwrap_or(MeasurementFunction::Custom(text)))
+    }
+}
+
+/// Input impedance, either a value in ohms or infinite
+///
+/// Infinite impedance is encoded as `INF` character data. Both `INFinity` and the SCPI
+/// representation of infinity, 9.9E+37, are decoded as [`Impedance::Infinite`].
+///
+/// Reference: SCPI 1999.0: 7.2.1.4 - INFinity and Negative INFinity (NINF)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Impedance {
+    Ohms(f64),
+    Infinite,
+}
+
+impl Impedance {
+    /// 50 Ω, the usual low impedance setting of scope inputs
+    pub fn fifty() -> Impedance {
+        Impedance::Ohms(50.0)
+    }
+    /// 1 MΩ, the usual high impedance setting of scope inputs
+    pub fn one_meg() -> Impedance {
+        Impedance::Ohms(1e6)
+    }
+}
+
+impl ProgramData for Impedance {
+    fn encode<S: EncodeSink>(&self, encoder: &mut Encoder<S>) -> Result<(), S::Error> {
+        encoder.begin_program_data()?;
+        match self {
+            Impedance::Ohms(ohms) => encoder.encode_numeric_float(*ohms),
+            Impedance::Infinite => encoder.encode_characters("INF"),
+        }
+    }
+}
+
+impl ResponseData for Impedance {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        decoder.begin_response_data()?;
+        if decoder.peek_byte()?.is_ascii_alphabetic() {
+            let mut text = ArrayBuffer::<12>::new();
+            decoder.decode_characters(&mut text)?;
+            return match text.as_str() {
+                Ok(text) if mnemonic_matches("INFinity", text) => Ok(Impedance::Infinite),
+                _ => Err(decoder.error(DecodeError::Parse)),
+            };
+        }
+        // Reference: SCPI 1999.0: 7.2.1.4 - INFinity and Negative INFinity (NINF)
+        match decoder.decode_numeric_float()? {
+            ohms if ohms >= 9.9e37 => Ok(Impedance::Infinite),
+            ohms => Ok(Impedance::Ohms(ohms)),
+        }
+    }
+}
+
+/// Scaling and format of waveform data, returned by [`WaveformPreambleQuery`]
+///
+/// The preamble is a list of ten fields shared by most oscilloscopes. Numeric fields are accepted
+/// in any decimal format, and fields that some instruments append after the ten standard ones
+/// are ignored.
+///
+/// [`WaveformPreambleQuery`]: crate::scpi::message::WaveformPreambleQuery
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WaveformPreamble {
+    /// Data format, e.g. 0 for bytes and 1 for words. The codes depend on the instrument
+    pub format: i32,
+    /// Acquisition type, e.g. 0 for normal acquisition. The codes depend on the instrument
+    pub acquisition_type: i32,
+    pub points: u32,
+    /// Number of averages or acquisitions combined into the waveform
+    pub count: u32,
+    /// Time between samples in seconds
+    pub x_increment: f64,
+    /// Time of the reference sample in seconds
+    pub x_origin: f64,
+    /// Index of the reference sample
+    pub x_reference: f64,
+    /// Voltage per raw data unit
+    pub y_increment: f64,
+    /// Voltage at the reference raw value
+    pub y_origin: f64,
+    /// Raw value that corresponds to `y_origin`
+    pub y_reference: f64,
+}
+
+impl WaveformPreamble {
+    /// Returns the time of the sample at the given index in seconds.
+    pub fn time_of_sample(&self, index: usize) -> f64 {
+        (index as f64 - self.x_reference) * self.x_increment + self.x_origin
+    }
+    /// Converts a raw data value to volts.
+    ///
+    /// Some instruments define `y_origin` as a raw value instead of a voltage, so their values
+    /// need to be converted by hand.
+    pub fn value_of_raw(&self, raw: impl Into<f64>) -> f64 {
+        (raw.into() - self.y_reference) * self.y_increment + self.y_origin
+    }
+}
+
+impl ResponseData for WaveformPreamble {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        fn decimal<S: ByteSource, O: ProtocolObserver>(
+            decoder: &mut Decoder<S, O>,
+        ) -> Result<f64, S::Error> {
+            decoder.begin_response_data()?;
+            decoder.decode_numeric_decimal()
+        }
+        let preamble = WaveformPreamble {
+            format: i32::decode(decoder)?,
+            acquisition_type: i32::decode(decoder)?,
+            points: u32::decode(decoder)?,
+            count: u32::decode(decoder)?,
+            x_increment: decimal(decoder)?,
+            x_origin: decimal(decoder)?,
+            x_reference: decimal(decoder)?,
+            y_increment: decimal(decoder)?,
+            y_origin: decimal(decoder)?,
+            y_reference: decimal(decoder)?,
+        };
+        while decoder.state() == DecodeState::DataExpected {
+            decoder.skip_response_data()?;
+        }
+        Ok(preamble)
+    }
+}
+
+/// A register of the SCPI status reporting structure
+///
+/// Used with [`StatusRegisterSnapshotQuery`].
+///
+/// [`StatusRegisterSnapshotQuery`]: crate::scpi::message::StatusRegisterSnapshotQuery
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StatusRegister<'a> {
+    /// :STATus:OPERation
+    Operation,
+    /// :STATus:QUEStionable
+    Questionable,
+    /// Any other register, given as a header path like `:STAT:OPER:INST:ISUM1`
+    Path(&'a str),
+}
+
+impl<'a> StatusRegister<'a> {
+    /// Returns the header path of the register.
+    pub fn path(&self) -> &'a str {
+        match self {
+            StatusRegister::Operation => ":STAT:OPER",
+            StatusRegister::Questionable => ":STAT:QUES",
+            StatusRegister::Path(path) => path,
+        }
+    }
+}
+
+/// The condition, event and enable registers of a status register, read together
+///
+/// Returned by [`StatusRegisterSnapshotQuery`].
+///
+/// [`StatusRegisterSnapshotQuery`]: crate::scpi::message::StatusRegisterSnapshotQuery
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct StatusRegisterSnapshot {
+    pub condition: u16,
+    pub event: u16,
+    pub enable: u16,
+}
+
+impl StatusRegisterSnapshot {
+    /// Compares the event register with the enable register.
+    pub fn summarize(&self) -> StatusSummary {
+        StatusSummary {
+            unreported: self.event & !self.enable,
+            enabled_not_set: self.enable & !self.event,
+        }
+    }
+}
+
+impl ResponseData for StatusRegisterSnapshot {
+    fn decode<S: ByteSource, O: ProtocolObserver>(
+        decoder: &mut Decoder<S, O>,
+    ) -> Result<Self, S::Error> {
+        Ok(StatusRegisterSnapshot {
+            condition: u16::decode(decoder)?,
+            event: u16::decode(decoder)?,
+            enable: u16::decode(decoder)?,
+        })
+    }
+}
+
+/// Differences between the event and enable registers of a [`StatusRegisterSnapshot`]
+///
+/// Displays the bit numbers of both masks, e.g.
+/// `unreported events: 3, 9; enabled but not set: none`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct StatusSummary {
+    /// Bits set in the event register but not enabled, so they don't reach the summary bit
+    pub unreported: u16,
+    /// Bits enabled but not set in the event register
+    pub enabled_not_set: u16,
+}
+
+impl fmt::Display for StatusSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn bits(f: &mut fmt::Formatter, mask: u16) -> fmt::Result {
+            if mask == 0 {
+                return f.write_str("none");
+            }
+            let mut set = (0..16).filter(|bit| mask & (1 << bit) != 0);
+            if let Some(bit) = set.next() {
+                write!(f, "{}", bit)?;
+            }
+            for bit in set {
+                write!(f, ", {}", bit)?;
+            }
+            Ok(())
+        }
+        f.write_str("unreported events: ")?;
+        bits(f, self.unreported)?;
+        f.write_str("; enabled but not set: ")?;
+        bits(f, self.enabled_not_set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::{
+        ChannelList, DefaultOrLimit, DefaultValue, Direction, ErrorCode, Limit, Multiplier,
+        NumericList, NumericListItem, NumericValue, OnOff, Quantity, StandardErrorCode, Suffix,
+        Unit, ValueOrDefault, ValueOrDefaultOrLimit, ValueOrLimit,
+    };
+    use crate::{
+        buffer::ArrayBuffer,
+        decode::{DecodeError, Decoder},
+        encode::{EncodeError, Encoder},
+        parse::parse_suffix,
+        scpi::message::SystemErrorCodeQuery,
+        ProgramData, Query, ResponseData,
+    };
+
+    fn encode_quantity(quantity: Quantity<f64>) -> Result<ArrayBuffer<64>, EncodeError> {
+        let mut encoder = Encoder::new(ArrayBuffer::new());
+        encoder.begin_message_unit()?;
+        encoder.write_bytes(b"SOUR")?;
+        quantity.encode(&mut encoder)?;
+        encoder.end_message()?;
+        encoder.finish()
+    }
+
+    fn suffix(multiplier: Option<Multiplier>, unit: Unit, exponent: i8) -> Suffix {
+        Suffix {
+            multiplier,
+            unit,
+            exponent,
+        }
+    }
+
+    fn encode_channels<const N: usize>(
+        channels: ChannelList<N>,
+    ) -> Result<ArrayBuffer<64>, EncodeError> {
+        let mut encoder = Encoder::new(ArrayBuffer::new());
+        encoder.begin_message_unit()?;
+        encoder.write_bytes(b"ROUT:CLOS")?;
+        channels.encode(&mut encoder)?;
+        encoder.end_message()?;
+        encoder.finish()
+    }
+
+    #[test]
+    fn channel_list() {
+        let encoded = encode_channels(ChannelList::new().channel(101)).unwrap();
+        assert_eq!(encoded.as_slice(), b"ROUT:CLOS (@101)\n");
+        let channels = ChannelList::new()
+            .channel(1)
+            .range(2, 5)
+            .multi_channel(&[3, 2])
+            .multi_range(&[1, 1], &[1, 8])
+            .range(9, 7);
+        let encoded = encode_channels(channels).unwrap();
+        assert_eq!(encoded.as_slice(), b"ROUT:CLOS (@1,2:5,3!2,1!1:1!8,9:7)\n");
+    }
+
+    #[test]
+    fn invalid_channel_list() {
+        assert_matches!(
+            encode_channels(ChannelList::new()),
+            Err(EncodeError::InvalidChannelList)
+        );
+        assert_matches!(
+            encode_channels(ChannelList::new().channel(1).multi_channel(&[])),
+            Err(EncodeError::InvalidChannelList)
+        );
+        assert_matches!(
+            encode_channels(ChannelList::new().multi_range(&[1, 1], &[2])),
+            Err(EncodeError::InvalidChannelList)
+        );
+        assert_matches!(
+            encode_channels(ChannelList::new().multi_range(&[], &[])),
+            Err(EncodeError::InvalidChannelList)
+        );
+        assert_matches!(
+            encode_channels(ChannelList::<8>::default().channel(1001).range(1002, 1003)),
+            Err(EncodeError::BufferOverflow)
+        );
+        let encoded = encode_channels(ChannelList::<8>::default().channel(1001).channel(2));
+        assert_eq!(encoded.unwrap().as_slice(), b"ROUT:CLOS (@1001,2)\n");
+    }
+
+    #[test]
+    fn numeric_value() {
+        fn encode(value: NumericValue<f32>) -> ArrayBuffer<16> {
+            let mut encoder = Encoder::new(ArrayBuffer::new());
+            encoder.begin_message_unit().unwrap();
+            encoder.write_bytes(b"VOLT").unwrap();
+            value.encode(&mut encoder).unwrap();
+            encoder.finish().unwrap()
+        }
+
+        for (value, expected) in [
+            (NumericValue::Value(1.5), &b"VOLT 1.5E0\n"[..]),
+            (NumericValue::Default, b"VOLT DEF\n"),
+            (NumericValue::Min, b"VOLT MIN\n"),
+            (NumericValue::Max, b"VOLT MAX\n"),
+            (NumericValue::Up, b"VOLT UP\n"),
+            (NumericValue::Down, b"VOLT DOWN\n"),
+            (NumericValue::Infinity, b"VOLT INF\n"),
+            (NumericValue::NegInfinity, b"VOLT NINF\n"),
+            (NumericValue::NotANumber, b"VOLT NAN\n"),
+        ] {
+            assert_eq!(encode(value).as_slice(), expected);
+        }
+        assert_eq!(
+            encode(NumericValue::Value(1.5).map(|value| value * 2.0)).as_slice(),
+            b"VOLT 3E0\n"
+        );
+    }
+
+    #[test]
+    fn numeric_value_conversions() {
+        type Value = NumericValue<u8>;
+
+        assert_eq!(Value::from(DefaultValue), NumericValue::Default);
+        assert_eq!(Value::from(Limit::Min), NumericValue::Min);
+        assert_eq!(Value::from(Direction::Down), NumericValue::Down);
+        assert_eq!(
+            Value::from(DefaultOrLimit::Limit(Limit::Max)),
+            NumericValue::Max
+        );
+        assert_eq!(Value::from(DefaultOrLimit::Default), NumericValue::Default);
+        assert_eq!(Value::from(ValueOrLimit::Value(1)), NumericValue::Value(1));
+        assert_eq!(
+            Value::from(ValueOrLimit::Limit(Limit::Min)),
+            NumericValue::Min
+        );
+        assert_eq!(
+            Value::from(ValueOrDefault::Value(2)),
+            NumericValue::Value(2)
+        );
+        assert_eq!(Value::from(ValueOrDefault::Default), NumericValue::Default);
+        assert_eq!(
+            Value::from(ValueOrDefaultOrLimit::Value(3)),
+            NumericValue::Value(3)
+        );
+        assert_eq!(
+            Value::from(ValueOrDefaultOrLimit::Default),
+            NumericValue::Default
+        );
+        assert_eq!(
+            Value::from(ValueOrDefaultOrLimit::Limit(Limit::Max)),
+            NumericValue::Max
+        );
+    }
+
+    #[test]
+    fn on_off() {
+        for (value, expected) in [(true, &b"TEST ON\n"[..]), (false, b"TEST OFF\n")] {
+            let mut encoder = Encoder::new(ArrayBuffer::<16>::new());
+            encoder.begin_message_unit().unwrap();
+            encoder.write_bytes(b"TEST").unwrap();
+            OnOff::from(value).encode(&mut encoder).unwrap();
+            assert_eq!(encoder.finish().unwrap().as_slice(), expected);
+            assert_eq!(bool::from(OnOff(value)), value);
+        }
+    }
+
+    #[test]
+    fn numeric_list() {
+        use NumericListItem::*;
+
+        let mut encoder = Encoder::new(ArrayBuffer::<64>::new());
+        encoder.begin_message_unit().unwrap();
+        encoder.write_bytes(b"CALC:DATA").unwrap();
+        NumericList(&[Range(1, 5), Single(7), Range(9, 12)])
+            .encode(&mut encoder)
+            .unwrap();
+        NumericList(&[Range(5, -1), Single(-3)])
+            .encode(&mut encoder)
+            .unwrap();
+        encoder.end_message().unwrap();
+        assert_eq!(
+            encoder.finish().unwrap().as_slice(),
+            b"CALC:DATA (1:5,7,9:12),(5:-1,-3)\n"
+        );
+
+        let mut encoder = Encoder::new(ArrayBuffer::<64>::new());
+        encoder.begin_message_unit().unwrap();
+        encoder.write_bytes(b"CALC:DATA").unwrap();
+        assert_matches!(
+            NumericList(&[]).encode(&mut encoder),
+            Err(EncodeError::EmptyNumericList)
+        );
+    }
+
+    #[test]
+    fn quantity() {
+        let encoded = encode_quantity(Quantity::new(1.5, Unit::Volt)).unwrap();
+        assert_eq!(encoded.as_slice(), b"SOUR 1.5E0 V\n");
+        let encoded = encode_quantity(Quantity::with_multiplier(
+            200.0,
+            Multiplier::Milli,
+            Unit::Second,
+        ));
+        assert_eq!(encoded.unwrap().as_slice(), b"SOUR 2E2 MS\n");
+        let encoded = encode_quantity(Quantity::new(-3.0, Unit::DecibelMilliwatt)).unwrap();
+        assert_eq!(encoded.as_slice(), b"SOUR -3E0 DBM\n");
+        let encoded = encode_quantity(Quantity {
+            value: 2.0,
+            suffix: suffix(Some(Multiplier::Milli), Unit::Meter, 2),
+        });
+        assert_eq!(encoded.unwrap().as_slice(), b"SOUR 2E0 MM2\n");
+        let encoded = encode_quantity(Quantity {
+            value: 5.0,
+            suffix: suffix(None, Unit::Second, -1),
+        });
+        assert_eq!(encoded.unwrap().as_slice(), b"SOUR 5E0 S-1\n");
+    }
+
+    #[test]
+    fn quantity_special_values_have_no_suffix() {
+        let encoded = encode_quantity(Quantity::new(f64::INFINITY, Unit::Volt)).unwrap();
+        assert_eq!(encoded.as_slice(), b"SOUR INF\n");
+        let encoded = encode_quantity(Quantity::new(f64::NEG_INFINITY, Unit::Volt)).unwrap();
+        assert_eq!(encoded.as_slice(), b"SOUR NINF\n");
+        let encoded = encode_quantity(Quantity::new(f64::NAN, Unit::Volt)).unwrap();
+        assert_eq!(encoded.as_slice(), b"SOUR NAN\n");
+    }
+
+    #[test]
+    fn quantity_invalid_suffix() {
+        for unit in [Unit::Hertz, Unit::Ohm] {
+            assert_matches!(
+                encode_quantity(Quantity::with_multiplier(1.0, Multiplier::Milli, unit)),
+                Err(EncodeError::InvalidSuffix)
+            );
+        }
+        for exponent in [0, 10, -10] {
+            assert_matches!(
+                encode_quantity(Quantity {
+                    value: 1.0,
+                    suffix: suffix(None, Unit::Meter, exponent),
+                }),
+                Err(EncodeError::InvalidSuffix)
+            );
+        }
+    }
+
+    #[test]
+    fn quantity_suffix_can_be_parsed() {
+        use Multiplier::*;
+        use Unit::*;
+
+        let multipliers = [
+            None,
+            Some(Exa),
+            Some(Peta),
+            Some(Tera),
+            Some(Giga),
+            Some(Mega),
+            Some(Kilo),
+            Some(Milli),
+            Some(Micro),
+            Some(Nano),
+            Some(Pico),
+            Some(Femto),
+            Some(Atto),
+        ];
+        let units = [
+            Ampere,
+            Celsius,
+            Coulomb,
+            Decibel,
+            DecibelMilliwatt,
+            Degree,
+            Farad,
+            Henry,
+            Hertz,
+            Joule,
+            Kelvin,
+            Meter,
+            Ohm,
+            Percent,
+            Radian,
+            Second,
+            Siemens,
+            Volt,
+            Watt,
+        ];
+        for multiplier in multipliers {
+            for unit in units {
+                for exponent in [-9, -1, 1, 2, 9] {
+                    let suffix = suffix(multiplier, unit, exponent);
+                    let Ok(encoded) = encode_quantity(Quantity { value: 1.0, suffix }) else {
+                        assert!(multiplier == Some(Milli) && matches!(unit, Hertz | Ohm));
+                        continue;
+                    };
+                    let encoded = encoded.as_slice();
+                    let text = &encoded[b"SOUR 1E0 ".len()..encoded.len() - 1];
+                    assert_eq!(parse_suffix(text), Ok(suffix));
+                }
+            }
+        }
+    }
+
+    fn decode<T: ResponseData>(response: &[u8]) -> Result<T, DecodeError> {
+        let mut decoder = Decoder::new(response);
+        let value = T::decode(&mut decoder)?;
+        decoder.finish()?;
+        Ok(value)
+    }
+
+    #[test]
+    fn standard_error_code() {
+        assert_eq!(
+            decode::<ErrorCode>(b"-113\n"),
+            Ok(ErrorCode::Standard(StandardErrorCode::UndefinedHeader))
+        );
+        assert_eq!(
+            decode::<StandardErrorCode>(b"-113\n"),
+            Ok(StandardErrorCode::UndefinedHeader)
+        );
+    }
+
+    #[test]
+    fn no_error() {
+        assert_eq!(decode::<ErrorCode>(b"+0\n"), Ok(ErrorCode::NoError));
+        assert_matches!(decode::<StandardErrorCode>(b"0\n"), Err(DecodeError::Parse));
+    }
+
+    #[test]
+    fn vendor_specific_error_code() {
+        assert_eq!(decode::<ErrorCode>(b"201\n"), Ok(ErrorCode::Other(201)));
+        assert_matches!(
+            decode::<StandardErrorCode>(b"201\n"),
+            Err(DecodeError::Parse)
+        );
+    }
+
+    #[test]
+    fn system_error_code_query() {
+        let mut decoder = Decoder::new(&b"-222\n"[..]);
+        assert_eq!(
+            SystemErrorCodeQuery.decode(&mut decoder),
+            Ok(ErrorCode::Standard(StandardErrorCode::DataOutOfRange))
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn trigger_source() {
+        use alloc::{string::String, vec::Vec};
+
+        use super::TriggerSource;
+        use crate::{encode::Encoder, ProgramData};
+
+        let sources = [
+            (TriggerSource::Immediate, "IMMEDIATE"),
+            (TriggerSource::External, "external"),
+            (TriggerSource::Bus, "Bus"),
+            (TriggerSource::Timer, "TIMER"),
+            (TriggerSource::Internal, "INTERNAL"),
+            (TriggerSource::Manual, "manual"),
+            (TriggerSource::Line, "LINE"),
+            (TriggerSource::Other(String::from("EXT2")), "EXT2"),
+        ];
+        for (source, long) in sources {
+            let mut encoder = Encoder::new(Vec::new());
+            encoder.begin_message_unit().unwrap();
+            encoder.write_bytes(b"TRIG:SOUR").unwrap();
+            source.encode(&mut encoder).unwrap();
+            let data = encoder.finish().unwrap();
+            let short = source.mnemonic();
+            assert_eq!(data, [b"TRIG:SOUR ", short.as_bytes(), b"\n"].concat());
+
+            let response = [short.as_bytes(), b"\n"].concat();
+            assert_eq!(decode::<TriggerSource>(&response), Ok(source.clone()));
+            let response = [long.as_bytes(), b"\n"].concat();
+            assert_eq!(decode::<TriggerSource>(&response), Ok(source));
+        }
+        assert_eq!(
+            decode::<TriggerSource>(b"IMMED\n"),
+            Ok(TriggerSource::Other(String::from("IMMED")))
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn coupling() {
+        use alloc::vec::Vec;
+
+        use super::Coupling;
+        use crate::{encode::Encoder, ProgramData};
+
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.begin_message_unit().unwrap();
+        encoder.write_bytes(b"INP:COUP").unwrap();
+        Coupling::Ground.encode(&mut encoder).unwrap();
+        assert_eq!(encoder.finish().unwrap(), b"INP:COUP GRO\n");
+
+        assert_eq!(decode::<Coupling>(b"AC\n"), Ok(Coupling::Ac));
+        assert_eq!(decode::<Coupling>(b"dc\n"), Ok(Coupling::Dc));
+        assert_eq!(decode::<Coupling>(b"GRO\n"), Ok(Coupling::Ground));
+        assert_eq!(decode::<Coupling>(b"GND\n"), Ok(Coupling::Ground));
+        assert_eq!(decode::<Coupling>(b"ground\n"), Ok(Coupling::Ground));
+        assert_eq!(decode::<Coupling>(b"DCLIMIT\n"), Ok(Coupling::DcLimit));
+        assert_eq!(decode::<Coupling>(b"ACL\n"), Ok(Coupling::AcLimit));
+        for junk in [&b"ACDC\n"[..], b"GROU\n", b"1\n", b"\"AC\"\n"] {
+            assert_matches!(
+                decode::<Coupling>(junk),
+                Err(DecodeError::UnknownCharacters(_))
+            );
+        }
+    }
+
+    #[test]
+    fn slope_not() {
+        use super::Slope;
+
+        assert_eq!(!Slope::Positive, Slope::Negative);
+        assert_eq!(!Slope::Negative, Slope::Positive);
+        assert_eq!(!Slope::Either, Slope::Either);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn slope() {
+        use alloc::vec::Vec;
+
+        use super::Slope;
+        use crate::{encode::Encoder, ProgramData};
+
+        for (slope, long) in [
+            (Slope::Positive, "positive"),
+            (Slope::Negative, "NEGATIVE"),
+            (Slope::Either, "Either"),
+        ] {
+            let mut encoder = Encoder::new(Vec::new());
+            encoder.begin_message_unit().unwrap();
+            encoder.write_bytes(b"TRIG:SLOP").unwrap();
+            slope.encode(&mut encoder).unwrap();
+            let data = encoder.finish().unwrap();
+            let response = &data[b"TRIG:SLOP ".len()..];
+            assert_eq!(response, [slope.mnemonic().as_bytes(), b"\n"].concat());
+            assert_eq!(decode::<Slope>(response), Ok(slope));
+            let response = [long.as_bytes(), b"\n"].concat();
+            assert_eq!(decode::<Slope>(&response), Ok(slope));
+        }
+        match decode::<Slope>(b"EITH2\n") {
+            Err(DecodeError::UnknownCharacters(err)) => assert_eq!(err.text(), "EITH2"),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn function_shape() {
+        use alloc::{string::String, vec::Vec};
+
+        use super::FunctionShape;
+        use crate::{
+            encode::Encoder,
+            scpi::message::{SourceFunctionShape, SourceFunctionShapeQuery},
+            Command,
+        };
+
+        let shapes = [
+            (FunctionShape::Sinusoid, "SINUSOID"),
+            (FunctionShape::Square, "square"),
+            (FunctionShape::Ramp, "RAMP"),
+            (FunctionShape::Pulse, "PULSE"),
+            (FunctionShape::Noise, "noise"),
+            (FunctionShape::Dc, "DC"),
+            (FunctionShape::Triangle, "TRIANGLE"),
+            (FunctionShape::Prbs, "PRBS"),
+            (FunctionShape::Arbitrary, "ARBITRARY"),
+            (FunctionShape::Other(String::from("HAV")), "HAV"),
+        ];
+        for (shape, long) in shapes {
+            let mut encoder = Encoder::new(Vec::new());
+            SourceFunctionShape(&shape).encode(&mut encoder).unwrap();
+            let data = encoder.finish().unwrap();
+            let response = &data[b":SOUR:FUNC:SHAP ".len()..];
+            assert_eq!(response, [shape.mnemonic().as_bytes(), b"\n"].concat());
+            let mut decoder = Decoder::new(response);
+            assert_eq!(
+                SourceFunctionShapeQuery.decode(&mut decoder),
+                Ok(shape.clone())
+            );
+            let response = [long.as_bytes(), b"\n"].concat();
+            assert_eq!(decode::<FunctionShape>(&response), Ok(shape));
+        }
+        for arbitrary in [&b"USER\n"[..], b"ARB\n", b"arb\n"] {
+            assert_eq!(
+                decode::<FunctionShape>(arbitrary),
+                Ok(FunctionShape::Arbitrary)
+            );
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn measurement_function() {
+        use alloc::{string::String, vec::Vec};
+
+        use super::MeasurementFunction;
+        use crate::{encode::Encoder, ProgramData};
+
+        assert_eq!(
+            decode::<MeasurementFunction>(b"\"VOLT:DC\"\n"),
+            Ok(MeasurementFunction::VoltageDc)
+        );
+        assert_eq!(
+            decode::<MeasurementFunction>(b"\"curr\"\n"),
+            Ok(MeasurementFunction::CurrentDc)
+        );
+        assert_eq!(
+            decode::<MeasurementFunction>(b"\":FRES\"\n"),
+            Ok(MeasurementFunction::FourWireResistance)
+        );
+        assert_eq!(
+            decode::<MeasurementFunction>(b"\"voltage:ratio\"\n"),
+            Ok(MeasurementFunction::VoltageDcRatio)
+        );
+        assert_eq!(
+            decode::<MeasurementFunction>(b"\"Current:AC\"\n"),
+            Ok(MeasurementFunction::CurrentAc)
+        );
+        for custom in ["VOLT:AC:RAT", "RES:DC", "CURR:DC:AC", "TEMP:FRTD", ""] {
+            let response = [b"\"", custom.as_bytes(), b"\"\n"].concat();
+            assert_eq!(
+                decode::<MeasurementFunction>(&response),
+                Ok(MeasurementFunction::Custom(String::from(custom)))
+            );
+        }
+
+        let functions = [
+            MeasurementFunction::VoltageDc,
+            MeasurementFunction::VoltageAc,
+            MeasurementFunction::VoltageDcRatio,
+            MeasurementFunction::CurrentDc,
+            MeasurementFunction::CurrentAc,
+            MeasurementFunction::Resistance,
+            MeasurementFunction::FourWireResistance,
+            MeasurementFunction::Frequency,
+            MeasurementFunction::Period,
+            MeasurementFunction::Continuity,
+            MeasurementFunction::Diode,
+            MeasurementFunction::Temperature,
+            MeasurementFunction::Capacitance,
+            MeasurementFunction::Custom(String::from("TEMP:FRTD")),
+        ];
+        for function in functions {
+            let mut encoder = Encoder::new(Vec::new());
+            encoder.begin_message_unit().unwrap();
+            encoder.write_bytes(b"FUNC").unwrap();
+            function.encode(&mut encoder).unwrap();
+            let data = encoder.finish().unwrap();
+            assert_eq!(
+                data,
+                [b"FUNC \"", function.as_str().as_bytes(), b"\"\n"].concat()
+            );
+            assert_eq!(decode::<MeasurementFunction>(&data[5..]), Ok(function));
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn impedance() {
+        use alloc::vec::Vec;
+
+        use super::Impedance;
+        use crate::{encode::Encoder, ProgramData};
+
+        let encode = |impedance: Impedance| {
+            let mut encoder = Encoder::new(Vec::new());
+            encoder.begin_message_unit().unwrap();
+            encoder.write_bytes(b"INP:IMP").unwrap();
+            impedance.encode(&mut encoder).unwrap();
+            encoder.finish().unwrap()
+        };
+        for (impedance, data, response) in [
+            (
+                Impedance::fifty(),
+                &b"INP:IMP 5E1\n"[..],
+                &b"+5.000000E+01\n"[..],
+            ),
+            (Impedance::one_meg(), b"INP:IMP 1E6\n", b"1000000.0\n"),
+            (Impedance::Infinite, b"INP:IMP INF\n", b"INF\n"),
+        ] {
+            assert_eq!(encode(impedance), data);
+            assert_eq!(decode::<Impedance>(response), Ok(impedance));
+        }
+        assert_eq!(decode::<Impedance>(b"+5.0E+01\n"), Ok(Impedance::fifty()));
+        assert_eq!(decode::<Impedance>(b"9.9E+37\n"), Ok(Impedance::Infinite));
+        assert_eq!(
+            decode::<Impedance>(b"+9.90000000E+37\n"),
+            Ok(Impedance::Infinite)
+        );
+        assert_eq!(decode::<Impedance>(b"INFINITY\n"), Ok(Impedance::Infinite));
+        assert_matches!(decode::<Impedance>(b"MAX\n"), Err(DecodeError::Parse));
+    }
+
+    #[test]
+    fn waveform_preamble() {
+        use super::WaveformPreamble;
+        use crate::{scpi::message::WaveformPreambleQuery, Query};
+
+        // Keysight InfiniiVision, with a vendor-specific field appended
+        let mut decoder = Decoder::new(
+            &b"+0,+0,+62500,+1,+1.60000000E-08,-5.00000000E-04,+0,+7.93650794E-04,\
++0.00000000E+00,+128,+1\n"[..],
+        );
+        let preamble = WaveformPreambleQuery.decode(&mut decoder).unwrap();
+        assert!(decoder.finish().unwrap().is_empty());
+        assert_eq!(preamble.points, 62500);
+        assert_eq!(preamble.y_reference, 128.0);
+        assert_eq!(preamble.time_of_sample(0), -5e-4);
+        assert_eq!(preamble.time_of_sample(31250), 0.0);
+        assert_eq!(preamble.value_of_raw(128u8), 0.0);
+        assert!((preamble.value_of_raw(254u8) - 0.1).abs() < 1e-9);
+
+        // Rigol DS1000Z
+        assert_eq!(
+            decode::<WaveformPreamble>(
+                b"0,0,1200,1,1.000000e-09,-6.000000e-07,0,4.132813e-02,0,122\n"
+            ),
+            Ok(WaveformPreamble {
+                format: 0,
+                acquisition_type: 0,
+                points: 1200,
+                count: 1,
+                x_increment: 1e-9,
+                x_origin: -6e-7,
+                x_reference: 0.0,
+                y_increment: 4.132813e-2,
+                y_origin: 0.0,
+                y_reference: 122.0,
+            })
+        );
+        assert_matches!(
+            decode::<WaveformPreamble>(b"0,0,1200,1,1.0E-09\n"),
+            Err(DecodeError::InvalidDecodeState(_))
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn status_summary() {
+        use alloc::string::ToString;
+
+        use super::{StatusRegisterSnapshot, StatusSummary};
+
+        let snapshot = decode::<StatusRegisterSnapshot>(b"16;520;513\n").unwrap();
+        assert_eq!(
+            snapshot,
+            StatusRegisterSnapshot {
+                condition: 16,
+                event: 520,
+                enable: 513,
+            }
+        );
+        let summary = snapshot.summarize();
+        assert_eq!(
+            summary,
+            StatusSummary {
+                unreported: 8,
+                enabled_not_set: 1,
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            "unreported events: 3; enabled but not set: 0"
+        );
+        let summary = StatusRegisterSnapshot {
+            condition: 0,
+            event: 0x8101,
+            enable: 0,
+        }
+        .summarize();
+        assert_eq!(
+            summary.to_string(),
+            "unreported events: 0, 8, 15; enabled but not set: none"
+        );
+    }
+}
@@ -0,0 +1,391 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::boxed::Box;
+use core::time::Duration;
+use std::io;
+
+use serialport::{DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+use crate::{
+    encode::{EncodeSink, PROGRAM_MESSAGE_TERMINATOR},
+    ByteSink, ByteSource, Error,
+};
+
+/// Read timeout set by [`SerialIo::open`] and [`SerialIo::configure`]
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Configuration of the modem control lines of a [`SerialIo`]
+///
+/// Some instruments only accept data while DTR or RTS is asserted, or use the lines to separate
+/// program messages.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SerialConfig {
+    /// Asserts DTR when a program message is started, and deasserts it once the message has been
+    /// sent
+    pub dtr_during_message: bool,
+    /// Asserts RTS when a program message is started, and deasserts it once the message has been
+    /// sent
+    pub rts_during_message: bool,
+}
+
+/// A serial port transport
+///
+/// A read timeout of the port is reported as an `io::ErrorKind::TimedOut` error, which is
+/// classified as a timeout by [`Timeout`](crate::adapters::Timeout), so reads can be retried with
+/// [`DeadlineSource`](crate::adapters::DeadlineSource). Other serial port errors are converted
+/// into I/O errors.
+///
+/// ```no_run
+/// use red_sculpin::{helpers, ieee::message::IdentificationQuery, serial::SerialIo};
+///
+/// let mut port = SerialIo::open("/dev/ttyUSB0", 9600)?;
+/// let identification = helpers::query(&mut port, &IdentificationQuery)?;
+/// # Ok::<(), red_sculpin::Error>(())
+/// ```
+pub struct SerialIo {
+    port: Box<dyn SerialPort>,
+    config: SerialConfig,
+    in_message: bool,
+}
+
+impl SerialIo {
+    pub fn new(port: Box<dyn SerialPort>) -> SerialIo {
+        SerialIo::with_config(port, SerialConfig::default())
+    }
+    pub fn with_config(port: Box<dyn SerialPort>, config: SerialConfig) -> SerialIo {
+        SerialIo {
+            port,
+            config,
+            in_message: false,
+        }
+    }
+    /// Opens a serial port with typical settings for SCPI instruments, see
+    /// [`configure`](SerialIo::configure).
+    pub fn open(path: &str, baud_rate: u32) -> Result<SerialIo, Error> {
+        let port = serialport::new(path, baud_rate)
+            .timeout(DEFAULT_TIMEOUT)
+            .open()
+            .map_err(io::Error::from)?;
+        SerialIo::configure(port, baud_rate)
+    }
+    /// Applies typical settings for SCPI instruments to a serial port: the given baud rate, 8 data
+    /// bits, no parity, 1 stop bit, no flow control, and a read timeout of 1 second.
+    pub fn configure(mut port: Box<dyn SerialPort>, baud_rate: u32) -> Result<SerialIo, Error> {
+        let mut apply = || -> serialport::Result<()> {
+            port.set_baud_rate(baud_rate)?;
+            port.set_data_bits(DataBits::Eight)?;
+            port.set_parity(Parity::None)?;
+            port.set_stop_bits(StopBits::One)?;
+            port.set_flow_control(FlowControl::None)?;
+            port.set_timeout(DEFAULT_TIMEOUT)
+        };
+        apply().map_err(io::Error::from)?;
+        Ok(SerialIo::new(port))
+    }
+    pub fn config(&self) -> &SerialConfig {
+        &self.config
+    }
+    pub fn port(&mut self) -> &mut dyn SerialPort {
+        &mut *self.port
+    }
+    pub fn into_inner(self) -> Box<dyn SerialPort> {
+        self.port
+    }
+    fn set_control_lines(&mut self, level: bool) -> Result<(), Error> {
+        if self.config.dtr_during_message {
+            self.port
+                .write_data_terminal_ready(level)
+                .map_err(io::Error::from)?;
+        }
+        if self.config.rts_during_message {
+            self.port
+                .write_request_to_send(level)
+                .map_err(io::Error::from)?;
+        }
+        Ok(())
+    }
+}
+
+impl io::Read for SerialIo {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.port.read(buf) {
+            // Some platforms report a read timeout as an empty read
+            Ok(0) if !buf.is_empty() => Err(io::ErrorKind::TimedOut.into()),
+            result => result,
+        }
+    }
+}
+
+impl io::Write for SerialIo {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.port.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+impl ByteSource for SerialIo {
+    type Error = Error;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        let mut buf = [0];
+        loop {
+            match io::Read::read(self, &mut buf) {
+                Ok(_) => break Ok(buf[0]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => break Err(err.into()),
+            }
+        }
+    }
+}
+
+impl ByteSink for SerialIo {
+    type Error = Error;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        if !self.in_message {
+            self.set_control_lines(true)?;
+            self.in_message = true;
+        }
+        io::Write::write_all(&mut self.port, bytes)?;
+        Ok(())
+    }
+}
+
+impl EncodeSink for SerialIo {
+    /// Writes the terminator and waits until the message has been sent, then deasserts the
+    /// configured control lines.
+    fn terminate_message(&mut self) -> Result<(), Self::Error> {
+        self.write_byte(PROGRAM_MESSAGE_TERMINATOR)?;
+        io::Write::flush(&mut self.port)?;
+        self.in_message = false;
+        self.set_control_lines(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, collections::VecDeque, string::String, sync::Arc, vec::Vec};
+    use core::time::Duration;
+    use std::{io, sync::Mutex};
+
+    use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+    use super::{SerialConfig, SerialIo};
+    use crate::{
+        adapters::Timeout, decode::Decoder, encode::Encoder, ieee::message::OperationCompleteQuery,
+        scpi::message::SystemVersionQuery, Query,
+    };
+
+    #[derive(Debug, Eq, PartialEq)]
+    enum Event {
+        Write(Vec<u8>),
+        Flush,
+        Dtr(bool),
+        Rts(bool),
+        Settings(u32, DataBits, Parity, StopBits, FlowControl),
+    }
+
+    /// A port that echoes written bytes back, and logs everything else
+    #[derive(Clone, Default)]
+    struct LoopbackPort {
+        readable: Arc<Mutex<VecDeque<u8>>>,
+        events: Arc<Mutex<Vec<Event>>>,
+        baud_rate: u32,
+        data_bits: Option<DataBits>,
+        parity: Option<Parity>,
+        stop_bits: Option<StopBits>,
+        flow_control: Option<FlowControl>,
+    }
+
+    impl LoopbackPort {
+        fn log(&self, event: Event) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    impl io::Read for LoopbackPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut readable = self.readable.lock().unwrap();
+            if readable.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let len = buf.len().min(readable.len());
+            for (target, byte) in buf.iter_mut().zip(readable.drain(..len)) {
+                *target = byte;
+            }
+            Ok(len)
+        }
+    }
+
+    impl io::Write for LoopbackPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.readable.lock().unwrap().extend(buf);
+            self.log(Event::Write(buf.to_vec()));
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.log(Event::Flush);
+            Ok(())
+        }
+    }
+
+    impl SerialPort for LoopbackPort {
+        fn name(&self) -> Option<String> {
+            None
+        }
+        fn baud_rate(&self) -> serialport::Result<u32> {
+            Ok(self.baud_rate)
+        }
+        fn data_bits(&self) -> serialport::Result<DataBits> {
+            Ok(self.data_bits.unwrap_or(DataBits::Seven))
+        }
+        fn flow_control(&self) -> serialport::Result<FlowControl> {
+            Ok(self.flow_control.unwrap_or(FlowControl::Hardware))
+        }
+        fn parity(&self) -> serialport::Result<Parity> {
+            Ok(self.parity.unwrap_or(Parity::Even))
+        }
+        fn stop_bits(&self) -> serialport::Result<StopBits> {
+            Ok(self.stop_bits.unwrap_or(StopBits::Two))
+        }
+        fn timeout(&self) -> Duration {
+            Duration::ZERO
+        }
+        fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+            self.baud_rate = baud_rate;
+            Ok(())
+        }
+        fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+            self.data_bits = Some(data_bits);
+            Ok(())
+        }
+        fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+            self.flow_control = Some(flow_control);
+            Ok(())
+        }
+        fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+            self.parity = Some(parity);
+            Ok(())
+        }
+        fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+            self.stop_bits = Some(stop_bits);
+            Ok(())
+        }
+        fn set_timeout(&mut self, _: Duration) -> serialport::Result<()> {
+            let settings = Event::Settings(
+                self.baud_rate,
+                self.data_bits()?,
+                self.parity()?,
+                self.stop_bits()?,
+                self.flow_control()?,
+            );
+            self.log(settings);
+            Ok(())
+        }
+        fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+            self.log(Event::Rts(level));
+            Ok(())
+        }
+        fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+            self.log(Event::Dtr(level));
+            Ok(())
+        }
+        fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+            Ok(false)
+        }
+        fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn bytes_to_read(&self) -> serialport::Result<u32> {
+            Ok(self.readable.lock().unwrap().len() as u32)
+        }
+        fn bytes_to_write(&self) -> serialport::Result<u32> {
+            Ok(0)
+        }
+        fn clear(&self, _: ClearBuffer) -> serialport::Result<()> {
+            self.readable.lock().unwrap().clear();
+            Ok(())
+        }
+        fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+            Ok(Box::new(self.clone()))
+        }
+        fn set_break(&self) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn clear_break(&self) -> serialport::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn loopback_round_trip() {
+        let port = LoopbackPort::default();
+        let events = port.events.clone();
+        let mut serial = SerialIo::configure(Box::new(port), 9600).unwrap();
+        assert_eq!(
+            events.lock().unwrap()[..],
+            [Event::Settings(
+                9600,
+                DataBits::Eight,
+                Parity::None,
+                StopBits::One,
+                FlowControl::None
+            )]
+        );
+
+        // The encoded query comes back as a response message
+        let mut encoder = Encoder::new(&mut serial);
+        encoder.begin_message_unit().unwrap();
+        encoder.write_bytes(b"1999.0").unwrap();
+        encoder.finish().unwrap();
+        let mut decoder = Decoder::new(&mut serial);
+        assert_eq!(SystemVersionQuery.decode(&mut decoder).unwrap(), 1999.0);
+        decoder.finish().unwrap();
+    }
+
+    #[test]
+    fn read_timeout() {
+        let mut serial = SerialIo::new(Box::new(LoopbackPort::default()));
+        let err = OperationCompleteQuery
+            .decode(&mut Decoder::new(&mut serial))
+            .unwrap_err();
+        assert!(err.is_timeout());
+    }
+
+    #[test]
+    fn control_lines_around_messages() {
+        let port = LoopbackPort::default();
+        let events = port.events.clone();
+        let config = SerialConfig {
+            dtr_during_message: true,
+            rts_during_message: true,
+        };
+        let mut serial = SerialIo::with_config(Box::new(port), config);
+        let mut encoder = Encoder::new(&mut serial);
+        OperationCompleteQuery.encode(&mut encoder).unwrap();
+        encoder.end_message().unwrap();
+        OperationCompleteQuery.encode(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+        let message = [
+            Event::Dtr(true),
+            Event::Rts(true),
+            Event::Write(b"*OPC?".to_vec()),
+            Event::Write(b"\n".to_vec()),
+            Event::Flush,
+            Event::Dtr(false),
+            Event::Rts(false),
+        ];
+        assert_eq!(events.lock().unwrap()[..7], message);
+        assert_eq!(events.lock().unwrap()[7..], message);
+    }
+}
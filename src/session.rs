@@ -0,0 +1,577 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+use std::io;
+
+use crate::{
+    adapters::Counters,
+    decode::{DecodeError, Decoder},
+    encode::Encoder,
+    ieee::message::StandardEventStatusRegisterQuery,
+    scpi::{
+        message::{StatusRegisterSnapshotQuery, SystemErrorQuery},
+        types::{StatusRegister, StatusRegisterSnapshot},
+    },
+    ByteSource, Command, Error, ErrorCode, Io, Phase, ProtocolObserver, Query, StandardEventStatus,
+    Transport,
+};
+
+/// Maximum number of block bytes transferred between progress callbacks
+const BLOCK_CHUNK_SIZE: usize = 4096;
+
+/// Strategy for checking device errors after commands
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ErrorCheck {
+    /// Errors are not checked automatically
+    #[default]
+    None,
+    /// The next item of the error/event queue is read with `:SYST:ERR?`, and any error is
+    /// returned as [`Error::Device`]
+    ///
+    /// Reference: SCPI 1999.0: 21.8 - :ERRor Subsystem
+    SystemError,
+    /// The standard event status register is read with `*ESR?`, and any set error bit is returned
+    /// as [`Error::DeviceStatus`]
+    ///
+    /// Reference: IEEE 488.2: 11.5.1 - Standard Event Status Register Model
+    EventStatus,
+}
+
+/// Progress of an arbitrary block transfer
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BlockProgress {
+    /// Number of payload bytes received so far
+    pub received: u64,
+    /// Total number of payload bytes for definite length blocks, or `None` for indefinite length
+    /// blocks
+    pub total: Option<u64>,
+}
+
+/// Unread response bytes that must be discarded before the next exchange
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Desync {
+    /// Block payload bytes followed by the rest of the response message
+    Block { remaining: u64 },
+    /// The rest of the response message
+    Message,
+}
+
+/// A session with a device over a std I/O transport
+///
+/// The session manages the encoder/decoder lifecycles of every message exchange. If decoding a
+/// response fails, the rest of the response message is discarded so that the transport remains
+/// usable for subsequent exchanges.
+#[derive(Debug)]
+pub struct Session<T> {
+    io: T,
+    error_check: ErrorCheck,
+    desync: Option<Desync>,
+    counters: Counters,
+}
+
+impl<T> Session<T>
+where
+    T: Transport,
+{
+    pub fn new(io: T) -> Session<T> {
+        Session {
+            io,
+            error_check: ErrorCheck::default(),
+            desync: None,
+            counters: Counters::default(),
+        }
+    }
+    pub fn with_error_check(mut self, error_check: ErrorCheck) -> Session<T> {
+        self.error_check = error_check;
+        self
+    }
+    pub fn error_check(&self) -> ErrorCheck {
+        self.error_check
+    }
+    pub fn set_error_check(&mut self, error_check: ErrorCheck) {
+        self.error_check = error_check;
+    }
+    /// Returns the protocol event counters of all exchanges done with this session.
+    pub fn counters(&self) -> &Counters {
+        &self.counters
+    }
+    pub fn reset_counters(&mut self) {
+        self.counters = Counters::default();
+    }
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+    /// Sends the given command, and checks device errors according to the configured
+    /// [`ErrorCheck`] strategy.
+    pub fn send<C: Command>(&mut self, command: &C) -> Result<(), Error> {
+        self.resynchronize()?;
+        let mut encoder = Encoder::with_observer(Io(&mut self.io), &mut self.counters);
+        command
+            .encode(&mut encoder)
+            .and_then(|_| encoder.finish())
+            .map_err(|err| err.with_context(command.mnemonic(), Phase::Encode, None))?;
+        self.check_errors()
+    }
+    /// Sends the given query, and decodes the response.
+    ///
+    /// The response message must be decoded completely, so trailing response data is an error.
+    pub fn query<Q: Query>(&mut self, query: &Q) -> Result<Q::ResponseData, Error> {
+        self.resynchronize()?;
+        let mut encoder = Encoder::with_observer(Io(&mut self.io), &mut self.counters);
+        query
+            .encode(&mut encoder)
+            .and_then(|_| encoder.finish())
+            .map_err(|err| err.with_context(query.mnemonic(), Phase::Encode, None))?;
+
+        let mut decoder = Decoder::with_observer(Io(&mut self.io), &mut self.counters);
+        let result = query
+            .decode(&mut decoder)
+            .map_err(|err| {
+                err.with_context(query.mnemonic(), Phase::Decode, Some(decoder.offset()))
+            })
+            .and_then(|response| {
+                if decoder.is_at_end() {
+                    Ok(response)
+                } else {
+                    let err = Error::from(DecodeError::InvalidDecodeState(decoder.state()));
+                    Err(err.with_context(query.mnemonic(), Phase::Finish, Some(decoder.offset())))
+                }
+            });
+        if result.is_err() {
+            let _ = decoder.resynchronize();
+        }
+        result
+    }
+    /// Sends the given query, and streams the arbitrary block response payload into the given
+    /// sink.
+    ///
+    /// The progress callback is called after every transferred chunk of the payload, and the
+    /// transfer can be cancelled by returning `ControlFlow::Break`, which leads to
+    /// [`Error::TransferCancelled`]. If the transfer is cancelled or fails, the rest of the
+    /// response is left unread and the session is flagged as needing resynchronization (see
+    /// [`resynchronize`](Session::resynchronize)).
+    ///
+    /// References:
+    ///
+    /// - IEEE 488.2: 8.7.9 - \<DEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA\>
+    /// - IEEE 488.2: 8.7.10 - \<INDEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA\>
+    pub fn query_block_with_progress<Q, W, F>(
+        &mut self,
+        query: &Q,
+        mut sink: W,
+        mut progress: F,
+    ) -> Result<(), Error>
+    where
+        Q: Query<ResponseData = Vec<u8>>,
+        W: io::Write,
+        F: FnMut(BlockProgress) -> ControlFlow<()>,
+    {
+        self.resynchronize()?;
+        let mut encoder = Encoder::with_observer(Io(&mut self.io), &mut self.counters);
+        query
+            .encode(&mut encoder)
+            .and_then(|_| encoder.finish())
+            .map_err(|err| err.with_context(query.mnemonic(), Phase::Encode, None))?;
+
+        let mut decoder = Decoder::with_observer(Io(&mut self.io), &mut self.counters);
+        let header = decoder
+            .begin_response_data()
+            .and_then(|_| decoder.decode_arbitrary_block_header());
+        let total = match header {
+            Ok(total) => total,
+            Err(err) => {
+                let _ = decoder.resynchronize();
+                return Err(err.with_context(
+                    query.mnemonic(),
+                    Phase::Decode,
+                    Some(decoder.offset()),
+                ));
+            }
+        };
+        let mut consumed = 0;
+        let result = (|| {
+            let mut chunk = [0; BLOCK_CHUNK_SIZE];
+            let mut received = 0;
+            loop {
+                let mut len = 0;
+                while len < chunk.len() && total != Some(consumed) && !decoder.is_at_end() {
+                    match decoder.read_byte()? {
+                        b'\n' if total.is_none() => decoder.end_with(b'\n')?,
+                        byte => {
+                            chunk[len] = byte;
+                            len += 1;
+                            consumed += 1;
+                        }
+                    }
+                }
+                sink.write_all(&chunk[..len])?;
+                received += len as u64;
+                if let ControlFlow::Break(()) = progress(BlockProgress { received, total }) {
+                    break Err(Error::TransferCancelled);
+                }
+                if decoder.is_at_end() {
+                    break Ok(());
+                }
+                if total == Some(consumed) {
+                    let byte = decoder.read_byte()?;
+                    break decoder.end_with(byte);
+                }
+            }
+        })()
+        .map_err(|err| err.with_context(query.mnemonic(), Phase::Decode, Some(decoder.offset())));
+        if decoder.is_at_end() {
+            result
+        } else {
+            self.desync = Some(match total {
+                Some(total) => Desync::Block {
+                    remaining: total - consumed,
+                },
+                None => Desync::Message,
+            });
+            let err = Error::from(DecodeError::InvalidDecodeState(decoder.state()));
+            result.and(Err(err.with_context(
+                query.mnemonic(),
+                Phase::Finish,
+                Some(decoder.offset()),
+            )))
+        }
+    }
+    /// Reads the condition, event and enable registers of the given status register with one
+    /// compound query.
+    ///
+    /// Reading the event register also clears it. See [`StatusRegisterSnapshotQuery`].
+    pub fn status_snapshot(
+        &mut self,
+        register: StatusRegister,
+    ) -> Result<StatusRegisterSnapshot, Error> {
+        self.query(&StatusRegisterSnapshotQuery(register))
+    }
+    /// Returns true if a failed or cancelled exchange has left unread response bytes in the
+    /// transport.
+    pub fn needs_resynchronization(&self) -> bool {
+        self.desync.is_some()
+    }
+    /// Discards any response bytes left unread by a failed or cancelled exchange.
+    ///
+    /// This is done automatically before every exchange.
+    pub fn resynchronize(&mut self) -> Result<(), Error> {
+        if let Some(Desync::Block { remaining }) = self.desync {
+            for remaining in (0..remaining).rev() {
+                Io(&mut self.io).read_byte()?;
+                self.counters.on_bytes_read(1);
+                self.desync = Some(Desync::Block { remaining });
+            }
+            self.desync = Some(Desync::Message);
+        }
+        if let Some(Desync::Message) = self.desync {
+            let mut decoder = Decoder::with_observer(Io(&mut self.io), &mut self.counters);
+            decoder.resynchronize()?;
+            self.desync = None;
+        }
+        Ok(())
+    }
+    fn check_errors(&mut self) -> Result<(), Error> {
+        match self.error_check {
+            ErrorCheck::None => Ok(()),
+            ErrorCheck::SystemError => {
+                let response = self.query(&SystemErrorQuery)?;
+                if response.code == ErrorCode::NoError {
+                    Ok(())
+                } else {
+                    Err(Error::Device(response))
+                }
+            }
+            ErrorCheck::EventStatus => {
+                let status = self.query(&StandardEventStatusRegisterQuery)?;
+                let errors = StandardEventStatus::CME
+                    | StandardEventStatus::E
+                    | StandardEventStatus::DDE
+                    | StandardEventStatus::QYE;
+                if status.intersects(errors) {
+                    Err(Error::DeviceStatus(status))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use alloc::vec::Vec;
+    use core::ops::ControlFlow;
+
+    use super::{BlockProgress, ErrorCheck, Session};
+    use crate::{
+        adapters::Counters,
+        decode::{DecodeError, DecodeState},
+        ieee::message::{OperationCompleteQuery, ProtectedUserDataQuery, Reset, StatusByteQuery},
+        mock::{Expect, MockTransport},
+        scpi::types::StatusRegister,
+        Error, ErrorCode, OwnedIoPair, Phase, StandardErrorCode, StandardEventStatus,
+    };
+
+    #[test]
+    fn errors_are_not_checked_by_default() {
+        let mut session = Session::new(MockTransport::new([Expect::write(b"*RST\n")]));
+        assert_matches!(session.send(&Reset), Ok(()));
+    }
+
+    #[test]
+    fn status_snapshot_is_one_exchange() {
+        let mut session = Session::new(MockTransport::new([Expect::write(
+            b":STAT:OPER:COND?;:STAT:OPER?;:STAT:OPER:ENAB?\n",
+        )
+        .then_respond(b"0;8;0\n")]));
+        let snapshot = session.status_snapshot(StatusRegister::Operation).unwrap();
+        assert_eq!(snapshot.summarize().unreported, 8);
+        assert_eq!(session.counters().messages_sent, 1);
+        assert_eq!(session.counters().responses_received, 1);
+    }
+
+    #[test]
+    fn system_error_check_passes_with_no_error() {
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*RST\n"),
+            Expect::write(b":SYST:ERR?\n").then_respond(b"0,\"No error\"\n"),
+        ]))
+        .with_error_check(ErrorCheck::SystemError);
+        assert_matches!(session.send(&Reset), Ok(()));
+    }
+
+    #[test]
+    fn system_error_check_returns_device_error() {
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*RST\n"),
+            Expect::write(b":SYST:ERR?\n").then_respond(b"-113,\"Undefined header\"\n"),
+        ]))
+        .with_error_check(ErrorCheck::SystemError);
+        match session.send(&Reset) {
+            Err(Error::Device(response)) => {
+                assert_eq!(
+                    response.code,
+                    ErrorCode::Standard(StandardErrorCode::UndefinedHeader)
+                );
+                assert_eq!(response.message, "Undefined header");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn event_status_check_returns_error_bits() {
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*RST\n"),
+            Expect::write(b"*ESR?\n").then_respond(b"1\n"),
+            Expect::write(b"*RST\n"),
+            Expect::write(b"*ESR?\n").then_respond(b"33\n"),
+        ]))
+        .with_error_check(ErrorCheck::EventStatus);
+        assert_matches!(session.send(&Reset), Ok(()));
+        assert_matches!(
+            session.send(&Reset),
+            Err(Error::DeviceStatus(status))
+                if status == StandardEventStatus::CME | StandardEventStatus::OPC
+        );
+    }
+
+    #[test]
+    fn queries_are_not_error_checked() {
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*STB?\n").then_respond(b"0\n")
+        ]))
+        .with_error_check(ErrorCheck::SystemError);
+        assert_matches!(session.query(&StatusByteQuery), Ok(0));
+    }
+
+    #[test]
+    fn trailing_response_data_is_an_error() {
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*STB?\n").then_respond(b"1,2\n"),
+            Expect::write(b"*OPC?\n").then_respond(b"1\n"),
+        ]));
+        assert_matches!(
+            session
+                .query(&StatusByteQuery)
+                .map_err(|err| err.context().map(|c| c.phase)),
+            Err(Some(Phase::Finish))
+        );
+        assert_matches!(session.query(&OperationCompleteQuery), Ok(true));
+    }
+
+    #[test]
+    fn encode_and_io_errors_have_context() {
+        let mut buf = [0; 2];
+        let mut session = Session::new(OwnedIoPair(&b""[..], &mut buf[..]));
+        assert_matches!(
+            session
+                .send(&Reset)
+                .map_err(|err| err.context().map(|c| c.phase)),
+            Err(Some(Phase::Encode))
+        );
+
+        let mut session = Session::new(MockTransport::new([Expect::write(b"*STB?\n")]));
+        let err = session.query(&StatusByteQuery).unwrap_err();
+        let context = err.context().expect("no context");
+        assert_eq!(context.phase, Phase::Decode);
+        assert_matches!(context.source, Error::Io(_));
+    }
+
+    #[test]
+    fn trailing_response_data_error_has_context() {
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*STB?\n").then_respond(b"1,2\n")
+        ]));
+        assert_matches!(
+            session
+                .query(&StatusByteQuery)
+                .as_ref()
+                .map_err(Error::without_context),
+            Err(Error::Decode(DecodeError::InvalidDecodeState(
+                DecodeState::DataExpected
+            )))
+        );
+    }
+
+    #[test]
+    fn counters_track_exchanges() {
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*RST\n"),
+            Expect::write(b"*STB?\n").then_respond(b"64\n"),
+            Expect::write(b"*STB?\n").then_respond(b"x\n"),
+        ]));
+        session.send(&Reset).unwrap();
+        assert_matches!(session.query(&StatusByteQuery), Ok(64));
+        assert!(session.query(&StatusByteQuery).is_err());
+        assert_eq!(
+            *session.counters(),
+            Counters {
+                bytes_written: 17,
+                bytes_read: 5,
+                messages_sent: 3,
+                response_data: 2,
+                responses_received: 1,
+                decode_errors: 1,
+            }
+        );
+        session.reset_counters();
+        assert_eq!(*session.counters(), Counters::default());
+    }
+
+    #[test]
+    fn session_is_usable_after_decode_error() {
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*STB?\n").then_respond(b"garbage\n"),
+            Expect::write(b"*STB?\n").then_respond(b"64\n"),
+        ]));
+        assert_matches!(
+            session
+                .query(&StatusByteQuery)
+                .as_ref()
+                .map_err(Error::without_context),
+            Err(Error::Decode(DecodeError::Parse))
+        );
+        assert_matches!(session.query(&StatusByteQuery), Ok(64));
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|idx| idx as u8).collect()
+    }
+
+    #[test]
+    fn definite_block_progress() {
+        let payload = payload(10000);
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*PUD?\n").then_respond(b"#510000"),
+            Expect::respond(&payload[..3000]),
+            Expect::respond(&payload[3000..]),
+            Expect::respond(b"\n"),
+        ]));
+        let mut data = Vec::new();
+        let mut reports = Vec::new();
+        let result = session.query_block_with_progress(&ProtectedUserDataQuery, &mut data, |p| {
+            reports.push(p);
+            ControlFlow::Continue(())
+        });
+        assert_matches!(result, Ok(()));
+        assert_eq!(data, payload);
+        let received: Vec<_> = reports.iter().map(|p| p.received).collect();
+        assert_eq!(received, [4096, 8192, 10000]);
+        assert!(reports.iter().all(|p| p.total == Some(10000)));
+        assert!(!session.needs_resynchronization());
+    }
+
+    #[test]
+    fn indefinite_block_progress() {
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*PUD?\n").then_respond(b"#0abc"),
+            Expect::respond(b"def\n"),
+        ]));
+        let mut data = Vec::new();
+        let mut reports = Vec::new();
+        let result = session.query_block_with_progress(&ProtectedUserDataQuery, &mut data, |p| {
+            reports.push(p);
+            ControlFlow::Continue(())
+        });
+        assert_matches!(result, Ok(()));
+        assert_eq!(data, b"abcdef");
+        assert_eq!(
+            reports,
+            [BlockProgress {
+                received: 6,
+                total: None
+            }]
+        );
+    }
+
+    #[test]
+    fn cancelled_block_transfer_is_resynchronized() {
+        let payload = payload(10000);
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*PUD?\n").then_respond(b"#510000"),
+            Expect::respond(&payload),
+            Expect::respond(b"\n"),
+            Expect::write(b"*STB?\n").then_respond(b"0\n"),
+        ]));
+        let mut data = Vec::new();
+        let result = session.query_block_with_progress(&ProtectedUserDataQuery, &mut data, |_| {
+            ControlFlow::Break(())
+        });
+        assert_matches!(result, Err(Error::TransferCancelled));
+        assert_eq!(data, &payload[..4096]);
+        assert!(session.needs_resynchronization());
+        assert_matches!(session.query(&StatusByteQuery), Ok(0));
+        assert!(!session.needs_resynchronization());
+    }
+
+    #[test]
+    fn trailing_data_after_block_is_an_error() {
+        let mut session = Session::new(MockTransport::new([
+            Expect::write(b"*PUD?\n").then_respond(b"#13abc,1\n"),
+            Expect::write(b"*STB?\n").then_respond(b"0\n"),
+        ]));
+        let mut data = Vec::new();
+        let result = session.query_block_with_progress(&ProtectedUserDataQuery, &mut data, |_| {
+            ControlFlow::Continue(())
+        });
+        assert_matches!(
+            result.as_ref().map_err(Error::without_context),
+            Err(Error::Decode(DecodeError::InvalidDecodeState(
+                DecodeState::DataExpected
+            )))
+        );
+        assert_matches!(session.query(&StatusByteQuery), Ok(0));
+    }
+}
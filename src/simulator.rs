@@ -0,0 +1,851 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    decode::DecodeError,
+    encode::{EncodeError, EncodeSink, EncodeState},
+    ieee::types::{DeviceIdentification, StandardEventStatus, StatusByte},
+    mnemonic_matches,
+    parse::{
+        parse_decimal_numeric, resolve_path, HeaderError, HeaderKind, HeaderNode, HeaderParser,
+        ProgramDataElement, ProgramDataParser, Separator,
+    },
+    respond::{ResponseDataEncode, ResponseEncoder},
+    scpi::types::{ErrorCode, StandardErrorCode, SystemErrorResponse},
+    ByteSink, ByteSource,
+};
+
+/// Maximum number of entries in the error/event queue
+///
+/// Reference: SCPI 1999.0: 21.8.2 - Error/Event Queue
+const ERROR_QUEUE_LEN: usize = 16;
+
+type Handler =
+    Box<dyn FnMut(&[ProgramDataElement], &mut ResponseEncoder<Vec<u8>>) -> HandlerResult>;
+
+type HandlerResult = Result<(), SystemErrorResponse>;
+
+/// A stateful instrument emulator for testing code built on top of this crate.
+///
+/// Bytes written to the instrument are parsed as program messages, and each complete message is
+/// executed as soon as its terminator has been written. Query responses become readable once the
+/// whole message has been executed. Like with [`MockTransport`](crate::mock::MockTransport),
+/// reading with no response available returns [`DecodeError::UnexpectedEnd`] from the
+/// [`ByteSource`] implementation, and an `io::ErrorKind::TimedOut` error from the `io::Read`
+/// implementation.
+///
+/// The mandatory IEEE 488.2 common commands are implemented over an internal status model:
+/// `*IDN?`, `*CLS`, `*ESE`, `*ESE?`, `*ESR?`, `*OPC`, `*OPC?`, `*RST`, `*SRE`, `*SRE?`, `*STB?`,
+/// `*WAI`, as well as `:SYSTem:ERRor[:NEXT]?`. Other headers are handled by the handlers
+/// registered with [`add_command`](SimulatedInstrument::add_command) and
+/// [`add_query`](SimulatedInstrument::add_query), which take precedence over the built-in ones.
+/// Unknown headers push a `-113, "Undefined header"` error onto the error/event queue.
+///
+/// Compound headers are resolved with the usual header tree traversal rules, like in
+/// [`MessageUnitSplitter`](crate::parse::MessageUnitSplitter). Handler paths must include all
+/// nodes, because optional nodes aren't supported.
+///
+/// ```
+/// # #[cfg(feature = "test-util")]
+/// # {
+/// use red_sculpin::{
+///     decode::Decoder, simulator::SimulatedInstrument, DeviceIdentification, ResponseData,
+/// };
+///
+/// let mut instrument = SimulatedInstrument::new(DeviceIdentification {
+///     manufacturer: "ACME".into(),
+///     model: "PSU1".into(),
+///     serial_number: "".into(),
+///     firmware_level: "1.0".into(),
+/// });
+/// instrument.add_query(":SOURce:VOLTage", |_| Ok(5.0));
+/// instrument.receive(b":SOUR:VOLT?;*IDN?\n");
+///
+/// let mut decoder = Decoder::new(&mut instrument);
+/// assert_eq!(f64::decode(&mut decoder), Ok(5.0));
+/// let mut idn = String::new();
+/// decoder.begin_response_data().unwrap();
+/// decoder.decode_arbitrary_ascii(&mut idn).unwrap();
+/// assert_eq!(idn, "ACME,PSU1,0,1.0");
+/// # }
+/// ```
+///
+/// References:
+///
+/// - IEEE 488.2: 10 - Common Commands
+/// - IEEE 488.2: 11 - Device Status Reporting
+pub struct SimulatedInstrument {
+    identification: DeviceIdentification,
+    handlers: Vec<(Vec<String>, bool, Handler)>,
+    reset: Option<Box<dyn FnMut()>>,
+    event_status: StandardEventStatus,
+    event_status_enable: StandardEventStatus,
+    service_request_enable: StatusByte,
+    errors: VecDeque<SystemErrorResponse>,
+    input: Vec<u8>,
+    output: VecDeque<u8>,
+}
+
+impl SimulatedInstrument {
+    pub fn new(identification: DeviceIdentification) -> SimulatedInstrument {
+        SimulatedInstrument {
+            identification,
+            handlers: Vec::new(),
+            reset: None,
+            event_status: StandardEventStatus::empty(),
+            event_status_enable: StandardEventStatus::empty(),
+            service_request_enable: 0,
+            errors: VecDeque::new(),
+            input: Vec::new(),
+            output: VecDeque::new(),
+        }
+    }
+    /// Sets the function that is called when `*RST` is executed.
+    pub fn on_reset(&mut self, reset: impl FnMut() + 'static) {
+        self.reset = Some(Box::new(reset));
+    }
+    /// Adds a handler for a command header, like `:SOURce:VOLTage` or `*TRG`.
+    ///
+    /// The header is written in mixed case, and both its short and long forms are accepted. The
+    /// handler receives the program data of the command, and an error it returns is pushed onto
+    /// the error/event queue.
+    pub fn add_command(
+        &mut self,
+        header: &str,
+        mut handler: impl FnMut(&[ProgramDataElement]) -> HandlerResult + 'static,
+    ) {
+        let handler =
+            move |data: &[ProgramDataElement], _: &mut ResponseEncoder<Vec<u8>>| handler(data);
+        self.handlers
+            .push((header_nodes(header), false, Box::new(handler)));
+    }
+    /// Adds a handler for a query header, like `:SOURce:VOLTage`, written without the `?`.
+    ///
+    /// The handler receives the program data of the query, and the value it returns is encoded as
+    /// the response message unit.
+    pub fn add_query<T: ResponseDataEncode>(
+        &mut self,
+        header: &str,
+        mut handler: impl FnMut(&[ProgramDataElement]) -> Result<T, SystemErrorResponse> + 'static,
+    ) {
+        let handler = move |data: &[ProgramDataElement], encoder: &mut ResponseEncoder<Vec<u8>>| {
+            let value = handler(data)?;
+            respond(encoder, &value)
+        };
+        self.handlers
+            .push((header_nodes(header), true, Box::new(handler)));
+    }
+    /// Pushes an error onto the error/event queue, and sets the matching standard event status
+    /// bit.
+    pub fn push_error(&mut self, err: SystemErrorResponse) {
+        self.event_status |= match i16::from(err.code) {
+            -199..=-100 => StandardEventStatus::CME,
+            -299..=-200 => StandardEventStatus::E,
+            -399..=-300 => StandardEventStatus::DDE,
+            -499..=-400 => StandardEventStatus::QYE,
+            _ => StandardEventStatus::empty(),
+        };
+        if self.errors.len() >= ERROR_QUEUE_LEN {
+            // Reference: SCPI 1999.0: 21.8.2 - Error/Event Queue
+            self.errors.pop_back();
+            self.errors
+                .push_back(error(StandardErrorCode::QueueOverflow, "Queue overflow"));
+        } else {
+            self.errors.push_back(err);
+        }
+    }
+    /// Returns the errors in the error/event queue, oldest first.
+    pub fn errors(&self) -> impl Iterator<Item = &SystemErrorResponse> {
+        self.errors.iter()
+    }
+    /// Returns the standard event status register, without clearing it.
+    pub fn event_status(&self) -> StandardEventStatus {
+        self.event_status
+    }
+    /// Returns the status byte register.
+    ///
+    /// Reference: IEEE 488.2: 11.2 - Status Byte Register
+    pub fn status_byte(&self) -> StatusByte {
+        let mut status = 0;
+        // Reference: SCPI 1999.0: 9.1 - Status Byte
+        if !self.errors.is_empty() {
+            status |= 0b0000_0100;
+        }
+        if !self.output.is_empty() {
+            status |= 0b0001_0000;
+        }
+        if self.event_status.intersects(self.event_status_enable) {
+            status |= 0b0010_0000;
+        }
+        if status & self.service_request_enable != 0 {
+            status |= 0b0100_0000;
+        }
+        status
+    }
+    /// Returns true if there are no complete responses waiting to be read.
+    pub fn is_idle(&self) -> bool {
+        self.output.is_empty()
+    }
+    /// Receives program message bytes, executing every message that is completed by them.
+    pub fn receive(&mut self, bytes: &[u8]) {
+        self.input.extend_from_slice(bytes);
+        if bytes.contains(&b'\n') {
+            self.process();
+        }
+    }
+    fn process(&mut self) {
+        loop {
+            let start = skip_whitespace(&self.input);
+            match self.input.get(start) {
+                None => return,
+                Some(b'\n') => {
+                    self.input.drain(..=start);
+                    continue;
+                }
+                Some(_) => (),
+            }
+            let mut units = Vec::new();
+            let mut current_path = Vec::new();
+            let mut rest = &self.input[..];
+            let result = loop {
+                match parse_unit(rest, &mut current_path) {
+                    Ok((unit, separator, tail)) => {
+                        units.push(unit);
+                        rest = tail;
+                        if separator == Separator::Terminator {
+                            break Ok(());
+                        }
+                    }
+                    Err(err) => break Err(err),
+                }
+            };
+            let consumed = self.input.len() - rest.len();
+            let consumed = match result {
+                Ok(()) => consumed,
+                // Wait for the rest of the message
+                Err(DecodeError::UnexpectedEnd) => return,
+                // Skip the rest of the message after a syntax error
+                Err(_) => match rest.iter().position(|&byte| byte == b'\n') {
+                    Some(pos) => consumed + pos + 1,
+                    None => return,
+                },
+            };
+            self.input.drain(..consumed);
+            self.execute(units);
+            if result.is_err() {
+                self.push_error(error(StandardErrorCode::SyntaxError, "Syntax error"));
+            }
+        }
+    }
+    fn execute(&mut self, units: Vec<Unit>) {
+        let mut encoder = ResponseEncoder::new(Vec::new());
+        for unit in units {
+            let result = match unit.header {
+                Ok(header) => self.execute_unit(&header, &unit.data, &mut encoder),
+                Err(HeaderError::MnemonicTooLong { .. }) => Err(error(
+                    StandardErrorCode::ProgramMnemonicTooLong,
+                    "Program mnemonic too long",
+                )),
+                Err(HeaderError::SuffixOverflow { .. }) => Err(error(
+                    StandardErrorCode::HeaderSuffixOutOfRange,
+                    "Header suffix out of range",
+                )),
+                Err(_) => Err(error(
+                    StandardErrorCode::CommandHeaderError,
+                    "Command header error",
+                )),
+            };
+            if let Err(err) = result {
+                self.push_error(err);
+            }
+        }
+        if encoder.state() != EncodeState::Initial {
+            if let Ok(response) = encoder.finish() {
+                self.output.extend(response);
+            }
+        }
+    }
+    fn execute_unit(
+        &mut self,
+        header: &Header,
+        data: &[ProgramDataElement],
+        encoder: &mut ResponseEncoder<Vec<u8>>,
+    ) -> HandlerResult {
+        let handler = self.handlers.iter_mut().find(|(nodes, is_query, _)| {
+            *is_query == header.is_query && header.matches(nodes.iter().map(String::as_str))
+        });
+        if let Some((_, _, handler)) = handler {
+            return handler(data, encoder);
+        }
+        if header.kind == HeaderKind::Common {
+            return self.execute_common(header, data, encoder);
+        }
+        if header.is_query
+            && (header.matches(["SYSTem", "ERRor"]) || header.matches(["SYSTem", "ERRor", "NEXT"]))
+        {
+            no_parameters(data)?;
+            let error = self.errors.pop_front().unwrap_or(SystemErrorResponse {
+                code: ErrorCode::NoError,
+                message: String::from("No error"),
+            });
+            return respond(encoder, &(i16::from(error.code), error.message.as_str()));
+        }
+        Err(error(
+            StandardErrorCode::UndefinedHeader,
+            "Undefined header",
+        ))
+    }
+    /// Reference: IEEE 488.2: 10 - Common Commands
+    fn execute_common(
+        &mut self,
+        header: &Header,
+        data: &[ProgramDataElement],
+        encoder: &mut ResponseEncoder<Vec<u8>>,
+    ) -> HandlerResult {
+        let name = header.nodes[0].to_ascii_uppercase();
+        match (name.as_str(), header.is_query) {
+            ("ESE", false) => {
+                let value = u8_parameter(data)?;
+                self.event_status_enable = StandardEventStatus::from_bits_truncate(value.into());
+                Ok(())
+            }
+            ("SRE", false) => {
+                self.service_request_enable = u8_parameter(data)?;
+                Ok(())
+            }
+            (_, _) => {
+                no_parameters(data)?;
+                match (name.as_str(), header.is_query) {
+                    ("CLS", false) => {
+                        self.event_status = StandardEventStatus::empty();
+                        self.errors.clear();
+                        Ok(())
+                    }
+                    ("ESE", true) => respond(encoder, &self.event_status_enable.bits()),
+                    ("ESR", true) => {
+                        let event_status = self.event_status;
+                        self.event_status = StandardEventStatus::empty();
+                        respond(encoder, &event_status.bits())
+                    }
+                    ("IDN", true) => {
+                        let idn = &self.identification;
+                        let fields = [
+                            &idn.manufacturer,
+                            &idn.model,
+                            &idn.serial_number,
+                            &idn.firmware_level,
+                        ]
+                        .map(|field| match field.as_str() {
+                            "" => "0",
+                            field => field,
+                        });
+                        encoder
+                            .begin_message_unit()
+                            .and_then(|_| encoder.begin_response_data())
+                            .and_then(|_| encoder.encode_arbitrary_ascii(&fields.join(",")))
+                            .map_err(encode_error)
+                    }
+                    ("OPC", false) => {
+                        self.event_status |= StandardEventStatus::OPC;
+                        Ok(())
+                    }
+                    ("OPC", true) => respond(encoder, &true),
+                    ("RST", false) => {
+                        if let Some(reset) = &mut self.reset {
+                            reset();
+                        }
+                        Ok(())
+                    }
+                    ("SRE", true) => respond(encoder, &self.service_request_enable),
+                    ("STB", true) => respond(encoder, &self.status_byte()),
+                    ("WAI", false) => Ok(()),
+                    _ => Err(error(
+                        StandardErrorCode::UndefinedHeader,
+                        "Undefined header",
+                    )),
+                }
+            }
+        }
+    }
+}
+
+impl ByteSource for SimulatedInstrument {
+    type Error = DecodeError;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        self.output.pop_front().ok_or(DecodeError::UnexpectedEnd)
+    }
+}
+
+impl ByteSink for SimulatedInstrument {
+    type Error = EncodeError;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.receive(bytes);
+        Ok(())
+    }
+}
+
+impl EncodeSink for SimulatedInstrument {}
+
+#[cfg(feature = "std")]
+impl std::io::Read for SimulatedInstrument {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.output.is_empty() && !buf.is_empty() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        let len = buf.len().min(self.output.len());
+        for (target, byte) in buf.iter_mut().zip(self.output.drain(..len)) {
+            *target = byte;
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for SimulatedInstrument {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.receive(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl core::fmt::Debug for SimulatedInstrument {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("SimulatedInstrument")
+            .field("identification", &self.identification)
+            .field("handlers", &self.handlers.len())
+            .field("event_status", &self.event_status)
+            .field("event_status_enable", &self.event_status_enable)
+            .field("service_request_enable", &self.service_request_enable)
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+/// A parsed program message unit
+struct Unit {
+    header: Result<Header, HeaderError>,
+    data: Vec<ProgramDataElement>,
+}
+
+struct Header {
+    kind: HeaderKind,
+    is_query: bool,
+    /// Mnemonics including their numeric suffixes
+    nodes: Vec<String>,
+}
+
+impl Header {
+    fn parse<'a>(
+        bytes: &'a [u8],
+        current_path: &mut Vec<HeaderNode<'a>>,
+    ) -> Result<Header, HeaderError> {
+        let parser = HeaderParser::new(bytes)?;
+        let (kind, is_query) = (parser.kind(), parser.is_query());
+        let nodes = resolve_path(current_path, parser)
+            .into_iter()
+            .map(|(mnemonic, suffix)| match suffix {
+                Some(suffix) => mnemonic.to_string() + &suffix.to_string(),
+                None => mnemonic.to_string(),
+            })
+            .collect();
+        Ok(Header {
+            kind,
+            is_query,
+            nodes,
+        })
+    }
+    fn matches<'a>(&self, spec: impl IntoIterator<Item = &'a str>) -> bool {
+        let mut spec = spec.into_iter();
+        let mut nodes = self.nodes.iter();
+        if self.kind == HeaderKind::Common {
+            return match (spec.next(), nodes.next()) {
+                (Some(spec), Some(node)) => spec
+                    .strip_prefix('*')
+                    .is_some_and(|spec| spec.eq_ignore_ascii_case(node)),
+                _ => false,
+            };
+        }
+        loop {
+            match (spec.next(), nodes.next()) {
+                (Some(spec), Some(node)) if mnemonic_matches(spec, node) => (),
+                (None, None) => break true,
+                _ => break false,
+            }
+        }
+    }
+}
+
+/// Splits a handler header like `:SOURce:VOLTage` into nodes.
+fn header_nodes(header: &str) -> Vec<String> {
+    match header.strip_prefix(':') {
+        Some(header) => header.split(':').map(String::from).collect(),
+        None if header.starts_with('*') => Vec::from([String::from(header)]),
+        None => header.split(':').map(String::from).collect(),
+    }
+}
+
+/// Parses a program message unit, returning the rest of the input after its separator.
+fn parse_unit<'a>(
+    input: &'a [u8],
+    current_path: &mut Vec<HeaderNode<'a>>,
+) -> Result<(Unit, Separator, &'a [u8]), DecodeError> {
+    let start = skip_whitespace(input);
+    let len = input[start..]
+        .iter()
+        .position(|&byte| matches!(byte, b';' | b'\n') || is_whitespace(byte))
+        .ok_or(DecodeError::UnexpectedEnd)?;
+    let header = Header::parse(&input[start..start + len], current_path);
+    let mut parser = ProgramDataParser::new(&input[start + len..]);
+    let mut data = Vec::new();
+    while let Some((element, _)) = parser.next_element()? {
+        data.push(element);
+    }
+    let separator = parser.end().ok_or(DecodeError::Parse)?;
+    Ok((Unit { header, data }, separator, parser.into_inner()))
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    // Reference: IEEE 488.2: 7.4.1.2 - Encoding syntax
+    matches!(byte, 0x00..=0x09 | 0x0b..=0x20)
+}
+
+fn skip_whitespace(input: &[u8]) -> usize {
+    input
+        .iter()
+        .position(|&byte| !is_whitespace(byte))
+        .unwrap_or(input.len())
+}
+
+fn respond(
+    encoder: &mut ResponseEncoder<Vec<u8>>,
+    value: &impl ResponseDataEncode,
+) -> HandlerResult {
+    encoder
+        .begin_message_unit()
+        .and_then(|_| value.encode(encoder))
+        .map_err(encode_error)
+}
+
+fn no_parameters(data: &[ProgramDataElement]) -> HandlerResult {
+    match data {
+        [] => Ok(()),
+        _ => Err(error(
+            StandardErrorCode::ParameterNotAllowed,
+            "Parameter not allowed",
+        )),
+    }
+}
+
+fn u8_parameter(data: &[ProgramDataElement]) -> Result<u8, SystemErrorResponse> {
+    match data {
+        [ProgramDataElement::DecimalNumeric(numeric)] if numeric.suffix.is_none() => {
+            parse_decimal_numeric(numeric.text.as_bytes())
+                .ok()
+                .and_then(|numeric| numeric.rounded)
+                .and_then(|value| u8::try_from(value).ok())
+                .ok_or_else(|| error(StandardErrorCode::DataOutOfRange, "Data out of range"))
+        }
+        [] => Err(error(
+            StandardErrorCode::MissingParameter,
+            "Missing parameter",
+        )),
+        [_] => Err(error(StandardErrorCode::DataTypeError, "Data type error")),
+        _ => Err(error(
+            StandardErrorCode::ParameterNotAllowed,
+            "Parameter not allowed",
+        )),
+    }
+}
+
+fn error(code: StandardErrorCode, message: &str) -> SystemErrorResponse {
+    SystemErrorResponse {
+        code: ErrorCode::Standard(code),
+        message: String::from(message),
+    }
+}
+
+fn encode_error(_: EncodeError) -> SystemErrorResponse {
+    error(
+        StandardErrorCode::DeviceSpecificError,
+        "Response encoding failed",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{rc::Rc, string::String, vec::Vec};
+    use core::cell::Cell;
+    use matches::assert_matches;
+
+    use super::SimulatedInstrument;
+    use crate::{
+        decode::{DecodeError, Decoder},
+        encode::Encoder,
+        ieee::{
+            message::{
+                ClearStatus, IdentificationQuery, OperationComplete, OperationCompleteQuery, Reset,
+                ServiceRequestEnable, StandardEventStatusEnable, StandardEventStatusRegisterQuery,
+                StatusByteQuery,
+            },
+            types::{DeviceIdentification, StandardEventStatus},
+        },
+        parse::ProgramDataElement,
+        scpi::{
+            message::SystemErrorQuery,
+            types::{ErrorCode, StandardErrorCode, SystemErrorResponse},
+        },
+        Command, Query, ResponseData,
+    };
+
+    fn instrument() -> SimulatedInstrument {
+        SimulatedInstrument::new(DeviceIdentification {
+            manufacturer: String::from("ACME"),
+            model: String::from("PSU1"),
+            serial_number: String::new(),
+            firmware_level: String::from("1.0"),
+        })
+    }
+
+    fn send(instrument: &mut SimulatedInstrument, command: &impl Command) {
+        let mut encoder = Encoder::new(instrument);
+        command.encode(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    fn ask<Q: Query>(instrument: &mut SimulatedInstrument, query: &Q) -> Q::ResponseData {
+        let mut encoder = Encoder::new(&mut *instrument);
+        query.encode(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+        let mut decoder = Decoder::new(&mut *instrument);
+        let response = query.decode(&mut decoder).unwrap();
+        decoder.finish().unwrap();
+        response
+    }
+
+    fn next_error(instrument: &mut SimulatedInstrument) -> (i16, String) {
+        let response = ask(instrument, &SystemErrorQuery);
+        (response.code.into(), response.message)
+    }
+
+    struct VoltageQuery;
+
+    impl Query for VoltageQuery {
+        type ProgramData = ();
+        type ResponseData = f64;
+        fn mnemonic(&self) -> &str {
+            ":SOUR:VOLT?"
+        }
+        fn program_data(&self) -> Self::ProgramData {}
+    }
+
+    struct Voltage(f64);
+
+    impl Command for Voltage {
+        type ProgramData = f64;
+        fn mnemonic(&self) -> &str {
+            ":SOURCE:VOLTAGE"
+        }
+        fn program_data(&self) -> Self::ProgramData {
+            self.0
+        }
+    }
+
+    #[test]
+    fn identification() {
+        let idn = ask(&mut instrument(), &IdentificationQuery);
+        assert_eq!(idn.manufacturer, "ACME");
+        assert_eq!(idn.model, "PSU1");
+        assert_eq!(idn.serial_number, "");
+        assert_eq!(idn.firmware_level, "1.0");
+    }
+
+    #[test]
+    fn registered_handlers() {
+        let mut instrument = instrument();
+        let voltage = Rc::new(Cell::new(0.0));
+        let setter = Rc::clone(&voltage);
+        instrument.add_command(":SOURce:VOLTage", move |data| match data {
+            [ProgramDataElement::DecimalNumeric(numeric)] => {
+                setter.set(numeric.value().unwrap_or_default());
+                Ok(())
+            }
+            _ => Err(SystemErrorResponse {
+                code: ErrorCode::Standard(StandardErrorCode::IllegalParameterValue),
+                message: String::from("Illegal parameter value"),
+            }),
+        });
+        let getter = Rc::clone(&voltage);
+        instrument.add_query(":SOURce:VOLTage", move |_| Ok(getter.get()));
+
+        send(&mut instrument, &Voltage(12.5));
+        assert_eq!(voltage.get(), 12.5);
+        assert_eq!(ask(&mut instrument, &VoltageQuery), 12.5);
+
+        instrument.receive(b"SOUR:VOLT MAX\n");
+        assert_eq!(
+            next_error(&mut instrument),
+            (-224, "Illegal parameter value".into())
+        );
+        assert_eq!(voltage.get(), 12.5);
+    }
+
+    #[test]
+    fn relative_headers() {
+        let mut instrument = instrument();
+        let voltage = Rc::new(Cell::new(0.0));
+        let current = Rc::new(Cell::new(0.0));
+        let setter = Rc::clone(&voltage);
+        instrument.add_command(":SOURce:VOLTage", move |data| {
+            if let [ProgramDataElement::DecimalNumeric(numeric)] = data {
+                setter.set(numeric.value().unwrap_or_default());
+            }
+            Ok(())
+        });
+        let setter = Rc::clone(&current);
+        instrument.add_command(":SOURce:CURRent", move |data| {
+            if let [ProgramDataElement::DecimalNumeric(numeric)] = data {
+                setter.set(numeric.value().unwrap_or_default());
+            }
+            Ok(())
+        });
+
+        instrument.receive(b":SOUR:VOLT 5;*CLS;CURR 0.5\n");
+        assert_eq!(voltage.get(), 5.0);
+        assert_eq!(current.get(), 0.5);
+        assert_eq!(next_error(&mut instrument), (0, "No error".into()));
+
+        // Each message starts from the root node
+        instrument.receive(b"VOLT 1\n");
+        assert_eq!(voltage.get(), 5.0);
+        assert_eq!(
+            next_error(&mut instrument),
+            (-113, "Undefined header".into())
+        );
+    }
+
+    #[test]
+    fn unknown_header_is_queued() {
+        let mut instrument = instrument();
+        instrument.receive(b":SOUR:CURR 1\n");
+        assert_eq!(
+            ask(&mut instrument, &StatusByteQuery) & 0b0000_0100,
+            0b0000_0100
+        );
+        assert_eq!(
+            next_error(&mut instrument),
+            (-113, "Undefined header".into())
+        );
+        assert_eq!(next_error(&mut instrument), (0, "No error".into()));
+        assert_eq!(
+            ask(&mut instrument, &StandardEventStatusRegisterQuery),
+            StandardEventStatus::CME
+        );
+        assert_eq!(
+            ask(&mut instrument, &StandardEventStatusRegisterQuery),
+            StandardEventStatus::empty()
+        );
+    }
+
+    #[test]
+    fn status_model() {
+        let mut instrument = instrument();
+        send(
+            &mut instrument,
+            &StandardEventStatusEnable(StandardEventStatus::OPC),
+        );
+        send(&mut instrument, &ServiceRequestEnable(0b0010_0000));
+        assert_eq!(ask(&mut instrument, &StatusByteQuery), 0);
+        send(&mut instrument, &OperationComplete);
+        assert_eq!(ask(&mut instrument, &StatusByteQuery), 0b0110_0000);
+        send(&mut instrument, &ClearStatus);
+        assert_eq!(ask(&mut instrument, &StatusByteQuery), 0);
+        assert!(ask(&mut instrument, &OperationCompleteQuery));
+
+        instrument.receive(b"*ESE 256\n*SRE\n*CLS 1\n");
+        let errors: Vec<_> = instrument
+            .errors()
+            .map(|error| i16::from(error.code))
+            .collect();
+        assert_eq!(errors, [-222, -109, -108]);
+    }
+
+    #[test]
+    fn compound_message() {
+        let mut instrument = instrument();
+        let resets = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&resets);
+        instrument.on_reset(move || counter.set(counter.get() + 1));
+        instrument.add_query("MEASure:VOLTage", |_| Ok((1.5, -2i8)));
+
+        let mut encoder = Encoder::new(&mut instrument);
+        Reset.encode(&mut encoder).unwrap();
+        OperationCompleteQuery.encode(&mut encoder).unwrap();
+        VoltageQuery.encode(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(resets.get(), 1);
+
+        // Only *OPC? has a response, because :SOUR:VOLT? is not registered
+        let mut decoder = Decoder::new(&mut instrument);
+        assert!(OperationCompleteQuery.decode(&mut decoder).unwrap());
+        decoder.finish().unwrap();
+        assert_eq!(
+            next_error(&mut instrument),
+            (-113, "Undefined header".into())
+        );
+
+        instrument.receive(b"meas:volt?;*OPC?\n");
+        let mut decoder = Decoder::new(&mut instrument);
+        assert_eq!(<(f64, i8)>::decode(&mut decoder), Ok((1.5, -2)));
+        assert_eq!(bool::decode(&mut decoder), Ok(true));
+        decoder.finish().unwrap();
+        assert!(instrument.is_idle());
+        assert_matches!(
+            bool::decode(&mut Decoder::new(&mut instrument)),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn messages_wait_for_terminator() {
+        let mut instrument = instrument();
+        instrument.add_query("DATA", |data: &[ProgramDataElement]| match data {
+            [ProgramDataElement::Block(block)] => Ok(block.clone()),
+            _ => Ok(Vec::new()),
+        });
+        instrument.receive(b"DATA? #15ab\n");
+        assert!(instrument.is_idle());
+        instrument.receive(b"cd\n");
+        let mut decoder = Decoder::new(&mut instrument);
+        assert_eq!(
+            ResponseData::decode(&mut decoder),
+            Ok(Vec::from(&b"ab\ncd"[..]))
+        );
+        decoder.finish().unwrap();
+    }
+
+    #[test]
+    fn syntax_error_skips_message() {
+        let mut instrument = instrument();
+        instrument.receive(b"*OPC;*ESE @;*CLS\n*OPC?\n");
+        assert_eq!(
+            instrument.event_status(),
+            StandardEventStatus::OPC | StandardEventStatus::CME
+        );
+        let mut decoder = Decoder::new(&mut instrument);
+        assert_eq!(ResponseData::decode(&mut decoder), Ok(true));
+        assert_eq!(next_error(&mut instrument), (-102, "Syntax error".into()));
+    }
+}
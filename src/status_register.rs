@@ -0,0 +1,165 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[doc(hidden)]
+pub use bitflags::bitflags;
+
+/// Declares a device-specific status register type.
+///
+/// The register is declared like a `bitflags` struct, but each flag is given as a bit number
+/// instead of a mask. Like [`StandardEventStatus`](crate::StandardEventStatus), the register is
+/// encoded as NR1 numeric program data and decoded from integer numeric response data.
+///
+/// By default decoding is strict, and a value with any undeclared bit set is rejected with
+/// [`DecodeError::Parse`](crate::decode::DecodeError::Parse). With `lenient` after the integer
+/// type, undeclared bits are dropped instead.
+///
+/// ```
+/// use red_sculpin::{declare_status_register, ResponseData};
+///
+/// declare_status_register! {
+///     /// Laser status register
+///     pub struct LaserStatus: u16 {
+///         /// Laser emission is on
+///         EMISSION = 0,
+///         /// Safety interlock is open
+///         INTERLOCK = 1,
+///     }
+/// }
+///
+/// declare_status_register! {
+///     pub struct VendorStatus: u16, lenient {
+///         READY = 15,
+///     }
+/// }
+///
+/// assert_eq!(LaserStatus::from_response_bytes(b"3\n"), Ok(LaserStatus::all()));
+/// assert!(LaserStatus::from_response_bytes(b"4\n").is_err());
+/// assert_eq!(VendorStatus::from_response_bytes(b"32772\n"), Ok(VendorStatus::READY));
+/// ```
+#[macro_export]
+macro_rules! declare_status_register {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: $bits:ty $(, $mode:ident)? {
+            $(
+                $(#[$flag_attr:ident $($flag_args:tt)*])*
+                $flag:ident = $bit:literal
+            ),* $(,)?
+        }
+    ) => {
+        $crate::status_register::bitflags! {
+            $(#[$attr])*
+            $vis struct $name: $bits {
+                $(
+                    $(#[$flag_attr $($flag_args)*])*
+                    const $flag = 1 << $bit;
+                )*
+            }
+        }
+
+        impl $crate::ProgramData for $name {
+            fn encode<S: $crate::encode::EncodeSink>(
+                &self,
+                encoder: &mut $crate::encode::Encoder<S>,
+            ) -> Result<(), S::Error> {
+                $crate::ProgramData::encode(&self.bits(), encoder)
+            }
+        }
+
+        impl $crate::ResponseData for $name {
+            fn decode<S: $crate::ByteSource, O: $crate::ProtocolObserver>(
+                decoder: &mut $crate::decode::Decoder<S, O>,
+            ) -> Result<Self, S::Error> {
+                let value = <$bits as $crate::ResponseData>::decode(decoder)?;
+                $crate::declare_status_register!(@from_bits $name, value $(, $mode)?)
+            }
+        }
+    };
+    (@from_bits $name:ident, $value:ident) => {
+        $crate::declare_status_register!(@from_bits $name, $value, strict)
+    };
+    (@from_bits $name:ident, $value:ident, strict) => {
+        $name::from_bits($value).ok_or_else(|| $crate::decode::DecodeError::Parse.into())
+    };
+    (@from_bits $name:ident, $value:ident, lenient) => {
+        Ok($name::from_bits_truncate($value))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use crate::{decode::DecodeError, internal::declare_tuple_command, Command, ResponseData};
+
+    declare_status_register! {
+        /// Laser status register
+        pub struct LaserStatus: u16 {
+            /// Laser emission is on
+            EMISSION = 0,
+            INTERLOCK = 1,
+            OVERTEMP = 9,
+        }
+    }
+
+    declare_status_register! {
+        pub(crate) struct TecStatus: u8, lenient {
+            STABLE = 0,
+            LIMIT = 7
+        }
+    }
+
+    declare_tuple_command! {
+        #[derive(Copy, Clone, Debug)]
+        pub struct LaserStatusEnable<":LAS:STAT:ENAB">(pub LaserStatus);
+    }
+
+    #[test]
+    fn encode() {
+        let mut buf = [0; 32];
+        let status = LaserStatus::EMISSION | LaserStatus::OVERTEMP;
+        let len = LaserStatusEnable(status).encode_to_slice(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b":LAS:STAT:ENAB 513\n");
+        let len = LaserStatusEnable(LaserStatus::empty())
+            .encode_to_slice(&mut buf)
+            .unwrap();
+        assert_eq!(&buf[..len], b":LAS:STAT:ENAB 0\n");
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        let mut buf = [0; 32];
+        for status in [
+            LaserStatus::empty(),
+            LaserStatus::INTERLOCK,
+            LaserStatus::EMISSION | LaserStatus::OVERTEMP,
+            LaserStatus::all(),
+        ] {
+            let len = LaserStatusEnable(status).encode_to_slice(&mut buf).unwrap();
+            let data = &buf[b":LAS:STAT:ENAB ".len()..len];
+            assert_eq!(LaserStatus::from_response_bytes(data), Ok(status));
+        }
+        assert_eq!(
+            LaserStatus::from_response_bytes(b"#H203\n"),
+            Ok(LaserStatus::EMISSION | LaserStatus::INTERLOCK | LaserStatus::OVERTEMP)
+        );
+    }
+
+    #[test]
+    fn reserved_bits() {
+        assert_matches!(
+            LaserStatus::from_response_bytes(b"4\n"),
+            Err(DecodeError::Parse)
+        );
+        assert_eq!(
+            TecStatus::from_response_bytes(b"255\n"),
+            Ok(TecStatus::STABLE | TecStatus::LIMIT)
+        );
+        assert_matches!(
+            TecStatus::from_response_bytes(b"256\n"),
+            Err(DecodeError::Parse)
+        );
+    }
+}
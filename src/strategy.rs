@@ -0,0 +1,446 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::{format, string::String, vec::Vec};
+use core::{fmt::Debug, iter};
+use proptest::{collection::vec, prelude::*, strategy::LazyJust};
+
+use crate::{
+    encode::Encoder,
+    format::Escaped,
+    parse::{ProgramDataElement, ProgramDataParser},
+    respond::{ResponseDataEncode, ResponseEncoder},
+    DeviceIdentification, ErrorCode, Limit, ProgramData, ResponseData, SystemErrorResponse,
+    ValueOrDefault, ValueOrDefaultOrLimit, ValueOrLimit,
+};
+
+/// Returns a strategy for integers together with their numeric response data message, in NR1,
+/// hexadecimal, octal, or binary format.
+///
+/// ```
+/// use proptest::prelude::*;
+/// use red_sculpin::{strategy::radix_integer, ResponseData};
+///
+/// proptest!(|((value, message) in radix_integer())| {
+///     prop_assert_eq!(u64::from_response_bytes(&message), Ok(value));
+/// });
+/// ```
+///
+/// References:
+///
+/// - IEEE 488.2: 8.7.2 - \<NR1 NUMERIC RESPONSE DATA\>
+/// - IEEE 488.2: 8.7.5 - \<HEXADECIMAL NUMERIC RESPONSE DATA\>
+/// - IEEE 488.2: 8.7.6 - \<OCTAL NUMERIC RESPONSE DATA\>
+/// - IEEE 488.2: 8.7.7 - \<BINARY NUMERIC RESPONSE DATA\>
+pub fn radix_integer() -> impl Strategy<Value = (u64, Vec<u8>)> {
+    (any::<u64>(), 0..4).prop_map(|(value, radix)| {
+        let text = match radix {
+            0 => format!("{}\n", value),
+            1 => format!("#H{:X}\n", value),
+            2 => format!("#Q{:o}\n", value),
+            _ => format!("#B{:b}\n", value),
+        };
+        (value, text.into_bytes())
+    })
+}
+
+/// Returns a strategy for floats, including infinity and negative infinity, which are encoded as
+/// `INF` and `NINF` in program data, and as 9.9E+37 and -9.9E+37 in response data.
+///
+/// NaN is left out because it isn't equal to itself, and so are the finite values that are
+/// decoded as infinity or NaN.
+///
+/// Reference: SCPI 1999.0: 7.2.1 - Special Numeric Values
+pub fn scpi_float() -> impl Strategy<Value = f64> {
+    use proptest::num::f64::{NEGATIVE, NORMAL, POSITIVE, SUBNORMAL, ZERO};
+    prop_oneof![
+        1 => prop_oneof![Just(f64::INFINITY), Just(f64::NEG_INFINITY)],
+        4 => (POSITIVE | NEGATIVE | NORMAL | SUBNORMAL | ZERO).prop_filter(
+            "special values are decoded as infinity or NaN",
+            |value| ![9.9E37, -9.9E37, 9.91E37].contains(value)
+        ),
+    ]
+}
+
+/// Returns a strategy for printable ASCII strings, with embedded quotes of both styles.
+pub fn ascii_string() -> impl Strategy<Value = String> {
+    let ch = prop_oneof![Just('"'), Just('\''), (b' '..=b'~').prop_map(char::from),];
+    vec(ch, 0..64).prop_map(|chars| chars.into_iter().collect())
+}
+
+/// Returns a strategy for arbitrary block payloads.
+pub fn block() -> impl Strategy<Value = Vec<u8>> {
+    vec(any::<u8>(), 0..256)
+}
+
+/// Returns a strategy for error codes, biased towards the standard codes.
+pub fn error_code() -> impl Strategy<Value = ErrorCode> {
+    prop_oneof![-800i16..=0, any::<i16>()].prop_map(ErrorCode::from)
+}
+
+/// Returns a strategy for error/event queue items.
+pub fn system_error_response() -> impl Strategy<Value = SystemErrorResponse> {
+    (error_code(), ascii_string()).prop_map(|(code, message)| SystemErrorResponse { code, message })
+}
+
+/// Returns a strategy for device identifications, with fields that survive the response
+/// encoding: no commas, no surrounding whitespace, and no field that is just `0`.
+pub fn device_identification() -> impl Strategy<Value = DeviceIdentification> {
+    let field = || {
+        let ch = prop_oneof![
+            b'A'..=b'Z',
+            b'a'..=b'z',
+            b'0'..=b'9',
+            Just(b'-'),
+            Just(b'.'),
+            Just(b'_'),
+        ];
+        vec(ch, 0..16)
+            .prop_map(|bytes| bytes.into_iter().map(char::from).collect::<String>())
+            .prop_filter("0 is decoded as an empty field", |field| field != "0")
+    };
+    (field(), field(), field(), field()).prop_map(
+        |(manufacturer, model, serial_number, firmware_level)| DeviceIdentification {
+            manufacturer,
+            model,
+            serial_number,
+            firmware_level,
+        },
+    )
+}
+
+/// Returns a strategy for MIN and MAX.
+pub fn limit() -> impl Strategy<Value = Limit> {
+    prop_oneof![Just(Limit::Min), Just(Limit::Max)]
+}
+
+/// Returns a strategy for either a value from the given strategy, or a limit.
+pub fn value_or_limit<T: Strategy>(value: T) -> impl Strategy<Value = ValueOrLimit<T::Value>> {
+    prop_oneof![
+        value.prop_map(ValueOrLimit::Value),
+        limit().prop_map(ValueOrLimit::Limit),
+    ]
+}
+
+/// Returns a strategy for either a value from the given strategy, or the default.
+pub fn value_or_default<T: Strategy>(value: T) -> impl Strategy<Value = ValueOrDefault<T::Value>> {
+    prop_oneof![
+        value.prop_map(ValueOrDefault::Value),
+        LazyJust::new(|| ValueOrDefault::Default),
+    ]
+}
+
+/// Returns a strategy for either a value from the given strategy, the default, or a limit.
+pub fn value_or_default_or_limit<T: Strategy>(
+    value: T,
+) -> impl Strategy<Value = ValueOrDefaultOrLimit<T::Value>> {
+    prop_oneof![
+        value.prop_map(ValueOrDefaultOrLimit::Value),
+        LazyJust::new(|| ValueOrDefaultOrLimit::Default),
+        limit().prop_map(ValueOrDefaultOrLimit::Limit),
+    ]
+}
+
+/// Trait for program data types that can be recovered from the elements tokenized by a
+/// [`ProgramDataParser`], for round-trip tests
+pub trait FromProgramData: Sized {
+    /// Converts the next elements into a value, or returns `None` if they don't match.
+    fn from_elements<I>(elements: &mut I) -> Option<Self>
+    where
+        I: Iterator<Item = ProgramDataElement>;
+}
+
+macro_rules! impl_from_program_data_numeric {
+    ($($ty:ty),*) => {
+        $(
+            impl FromProgramData for $ty {
+                fn from_elements<I>(elements: &mut I) -> Option<Self>
+                where
+                    I: Iterator<Item = ProgramDataElement>,
+                {
+                    match elements.next()? {
+                        ProgramDataElement::DecimalNumeric(numeric) if numeric.suffix.is_none() => {
+                            numeric.text.parse().ok()
+                        }
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_program_data_numeric!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl FromProgramData for f32 {
+    fn from_elements<I>(elements: &mut I) -> Option<Self>
+    where
+        I: Iterator<Item = ProgramDataElement>,
+    {
+        match elements.next()? {
+            ProgramDataElement::DecimalNumeric(numeric) if numeric.suffix.is_none() => {
+                numeric.text.parse().ok()
+            }
+            ProgramDataElement::Characters(text) => special_float(&text).map(|value| value as f32),
+            _ => None,
+        }
+    }
+}
+
+impl FromProgramData for f64 {
+    fn from_elements<I>(elements: &mut I) -> Option<Self>
+    where
+        I: Iterator<Item = ProgramDataElement>,
+    {
+        match elements.next()? {
+            ProgramDataElement::DecimalNumeric(numeric) if numeric.suffix.is_none() => {
+                numeric.text.parse().ok()
+            }
+            ProgramDataElement::Characters(text) => special_float(&text),
+            _ => None,
+        }
+    }
+}
+
+/// Reference: SCPI 1999.0: 7.2.1 - Special Numeric Values
+fn special_float(text: &str) -> Option<f64> {
+    if text.eq_ignore_ascii_case("INF") {
+        Some(f64::INFINITY)
+    } else if text.eq_ignore_ascii_case("NINF") {
+        Some(f64::NEG_INFINITY)
+    } else if text.eq_ignore_ascii_case("NAN") {
+        Some(f64::NAN)
+    } else {
+        None
+    }
+}
+
+impl FromProgramData for bool {
+    fn from_elements<I>(elements: &mut I) -> Option<Self>
+    where
+        I: Iterator<Item = ProgramDataElement>,
+    {
+        elements.next()?.to_boolean().map(|boolean| boolean.value)
+    }
+}
+
+impl FromProgramData for String {
+    fn from_elements<I>(elements: &mut I) -> Option<Self>
+    where
+        I: Iterator<Item = ProgramDataElement>,
+    {
+        match elements.next()? {
+            ProgramDataElement::String(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+impl FromProgramData for Vec<u8> {
+    fn from_elements<I>(elements: &mut I) -> Option<Self>
+    where
+        I: Iterator<Item = ProgramDataElement>,
+    {
+        match elements.next()? {
+            ProgramDataElement::Block(data) => Some(data),
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromProgramData> FromProgramData for ValueOrLimit<T> {
+    fn from_elements<I>(elements: &mut I) -> Option<Self>
+    where
+        I: Iterator<Item = ProgramDataElement>,
+    {
+        let element = elements.next()?;
+        match element.as_characters().and_then(|data| data.to_limit()) {
+            Some(limit) => Some(ValueOrLimit::Limit(limit)),
+            None => T::from_elements(&mut iter::once(element)).map(ValueOrLimit::Value),
+        }
+    }
+}
+
+impl<T: FromProgramData> FromProgramData for ValueOrDefault<T> {
+    fn from_elements<I>(elements: &mut I) -> Option<Self>
+    where
+        I: Iterator<Item = ProgramDataElement>,
+    {
+        let element = elements.next()?;
+        if element
+            .as_characters()
+            .is_some_and(|data| data.to_default())
+        {
+            Some(ValueOrDefault::Default)
+        } else {
+            T::from_elements(&mut iter::once(element)).map(ValueOrDefault::Value)
+        }
+    }
+}
+
+impl<T: FromProgramData> FromProgramData for ValueOrDefaultOrLimit<T> {
+    fn from_elements<I>(elements: &mut I) -> Option<Self>
+    where
+        I: Iterator<Item = ProgramDataElement>,
+    {
+        let element = elements.next()?;
+        match element.as_characters() {
+            Some(data) if data.to_default() => Some(ValueOrDefaultOrLimit::Default),
+            Some(data) => data.to_limit().map(ValueOrDefaultOrLimit::Limit),
+            None => T::from_elements(&mut iter::once(element)).map(ValueOrDefaultOrLimit::Value),
+        }
+    }
+}
+
+impl<A: FromProgramData, B: FromProgramData> FromProgramData for (A, B) {
+    fn from_elements<I>(elements: &mut I) -> Option<Self>
+    where
+        I: Iterator<Item = ProgramDataElement>,
+    {
+        Some((A::from_elements(elements)?, B::from_elements(elements)?))
+    }
+}
+
+impl<A: FromProgramData, B: FromProgramData, C: FromProgramData> FromProgramData for (A, B, C) {
+    fn from_elements<I>(elements: &mut I) -> Option<Self>
+    where
+        I: Iterator<Item = ProgramDataElement>,
+    {
+        Some((
+            A::from_elements(elements)?,
+            B::from_elements(elements)?,
+            C::from_elements(elements)?,
+        ))
+    }
+}
+
+/// Encodes the value as program data, tokenizes the encoded bytes with a [`ProgramDataParser`]
+/// like an instrument would, and asserts that the value recovered from the elements equals the
+/// value.
+///
+/// # Panics
+///
+/// Panics if encoding or parsing fails, or the recovered value is different.
+pub fn assert_roundtrip_program<T>(value: T)
+where
+    T: ProgramData + FromProgramData + Debug + PartialEq,
+{
+    let mut encoder = Encoder::new(Vec::new());
+    encoder.begin_message_unit().unwrap();
+    encoder.write_bytes(b"X").unwrap();
+    if let Err(err) = value.encode(&mut encoder) {
+        panic!("failed to encode {:?}: {:?}", value, err);
+    }
+    let bytes = encoder.finish().unwrap();
+    let mut parser = ProgramDataParser::new(&bytes[1..]);
+    let mut elements = Vec::new();
+    loop {
+        match parser.next_element() {
+            Ok(Some((element, _))) => elements.push(element),
+            Ok(None) => break,
+            Err(err) => panic!("failed to parse {}: {:?}", Escaped(&bytes), err),
+        }
+    }
+    let mut elements = elements.into_iter();
+    let decoded = T::from_elements(&mut elements);
+    assert_eq!(decoded, Some(value), "encoded as {}", Escaped(&bytes));
+    assert_eq!(elements.len(), 0, "encoded as {}", Escaped(&bytes));
+}
+
+/// Encodes the value as response data like an instrument emulator, decodes it back, and asserts
+/// that the result equals the value.
+///
+/// # Panics
+///
+/// Panics if encoding or decoding fails, or the decoded value is different.
+pub fn assert_roundtrip_response<T>(value: T)
+where
+    T: ResponseDataEncode + ResponseData + Debug + PartialEq,
+{
+    let mut encoder = ResponseEncoder::new(Vec::new());
+    encoder.begin_message_unit().unwrap();
+    if let Err(err) = value.encode(&mut encoder) {
+        panic!("failed to encode {:?}: {:?}", value, err);
+    }
+    let bytes = encoder.finish().unwrap();
+    let decoded = T::from_response_bytes(&bytes);
+    assert_eq!(decoded, Ok(value), "encoded as {}", Escaped(&bytes));
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, vec::Vec};
+    use proptest::prelude::*;
+
+    use super::{
+        ascii_string, assert_roundtrip_program, assert_roundtrip_response, block,
+        device_identification, radix_integer, scpi_float, system_error_response, value_or_default,
+        value_or_default_or_limit, value_or_limit,
+    };
+    use crate::ResponseData;
+
+    proptest! {
+        #[test]
+        fn integers(value in any::<i64>(), unsigned in any::<u32>(), small in any::<i8>()) {
+            assert_roundtrip_program(value);
+            assert_roundtrip_response(value);
+            assert_roundtrip_program(unsigned);
+            assert_roundtrip_response(unsigned);
+            assert_roundtrip_program(small);
+            assert_roundtrip_response(small);
+        }
+
+        #[test]
+        fn radix_integers((value, message) in radix_integer()) {
+            prop_assert_eq!(u64::from_response_bytes(&message), Ok(value));
+        }
+
+        #[test]
+        fn floats(value in scpi_float()) {
+            assert_roundtrip_program(value);
+            assert_roundtrip_response(value);
+        }
+
+        #[test]
+        fn strings(value in ascii_string()) {
+            assert_roundtrip_program(value.clone());
+            assert_roundtrip_response(value);
+        }
+
+        #[test]
+        fn blocks(value in block()) {
+            assert_roundtrip_program(value.clone());
+            assert_roundtrip_response(value);
+        }
+
+        #[test]
+        fn tuples(number in scpi_float(), text in ascii_string(), flag in any::<bool>()) {
+            assert_roundtrip_program((number, text.clone(), flag));
+            assert_roundtrip_response((number, text, flag));
+        }
+
+        #[test]
+        fn responses(error in system_error_response(), identification in device_identification()) {
+            assert_roundtrip_response(error);
+            assert_roundtrip_response(identification);
+        }
+
+        #[test]
+        fn special_values(
+            limited in value_or_limit(any::<u16>()),
+            defaulted in value_or_default(scpi_float()),
+            both in value_or_default_or_limit(any::<i32>()),
+        ) {
+            assert_roundtrip_program(limited);
+            assert_roundtrip_program(defaulted);
+            assert_roundtrip_program((both, limited));
+        }
+    }
+
+    #[test]
+    fn empty_values() {
+        assert_roundtrip_program(String::new());
+        assert_roundtrip_response(Vec::<u8>::new());
+    }
+}
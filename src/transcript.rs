@@ -0,0 +1,525 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::vec::Vec;
+use core::fmt;
+use std::io::{self, BufRead};
+
+use crate::{format::write_escaped, ByteSink, ByteSource, EncodeSink, Error, Io};
+
+/// Direction of a transcript record
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// Bytes written to the instrument
+    Tx,
+    /// Bytes read from the instrument
+    Rx,
+}
+
+impl Direction {
+    fn tag(self) -> &'static str {
+        match self {
+            Direction::Tx => "TX",
+            Direction::Rx => "RX",
+        }
+    }
+}
+
+/// A run of bytes transferred in one direction
+///
+/// In a transcript file, every record is a line with a `TX` or `RX` tag, a space, and the bytes.
+/// Printable ASCII bytes are written as-is, and other bytes are escaped as `\n`, `\r`, `\t`, `\\`
+/// or `\xNN`, e.g. `TX *IDN?\n`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    pub direction: Direction,
+    pub bytes: Vec<u8>,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.direction.tag())?;
+        f.write_str(" ")?;
+        for &byte in &self.bytes {
+            write_escaped(f, byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error returned for an invalid transcript, or for traffic that doesn't match a transcript
+///
+/// Records are numbered from 0, and lines from 1.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TranscriptError {
+    /// A line of a transcript file is not a valid record
+    InvalidRecord { line: usize },
+    /// A written byte doesn't match the transcript. `expected` is `None` if the record at the
+    /// position is not a `TX` record, or if the transcript has ended.
+    UnexpectedWrite {
+        record: usize,
+        offset: usize,
+        expected: Option<u8>,
+        byte: u8,
+    },
+    /// A read was attempted, but the record at the position is not an `RX` record, or the
+    /// transcript has ended
+    UnexpectedRead { record: usize },
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranscriptError::InvalidRecord { line } => {
+                write!(f, "invalid transcript record on line {}", line)
+            }
+            TranscriptError::UnexpectedWrite {
+                record,
+                offset,
+                expected: Some(expected),
+                byte,
+            } => write!(
+                f,
+                "unexpected write in record {} at offset {}: expected 0x{:02x}, got 0x{:02x}",
+                record, offset, expected, byte
+            ),
+            TranscriptError::UnexpectedWrite {
+                record,
+                expected: None,
+                byte,
+                ..
+            } => write!(
+                f,
+                "unexpected write at record {}: no write recorded, got 0x{:02x}",
+                record, byte
+            ),
+            TranscriptError::UnexpectedRead { record } => {
+                write!(
+                    f,
+                    "unexpected read at record {}: no response recorded",
+                    record
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for TranscriptError {}
+
+impl From<TranscriptError> for io::Error {
+    fn from(err: TranscriptError) -> Self {
+        let kind = match err {
+            // Like a transport with no data available
+            TranscriptError::UnexpectedRead { .. } => io::ErrorKind::TimedOut,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// Reads the records of a transcript file.
+///
+/// Empty lines are ignored. An invalid record is reported as an `io::ErrorKind::InvalidData`
+/// error wrapping a [`TranscriptError`].
+pub fn read_transcript(reader: impl BufRead) -> io::Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for (idx, line) in reader.split(b'\n').enumerate() {
+        let line = line?;
+        let line = line.strip_suffix(b"\r").unwrap_or(&line);
+        if line.is_empty() {
+            continue;
+        }
+        let record = parse_record(line).ok_or(TranscriptError::InvalidRecord { line: idx + 1 })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn parse_record(line: &[u8]) -> Option<Record> {
+    let (direction, mut escaped) = match line {
+        [b'T', b'X', b' ', rest @ ..] => (Direction::Tx, rest),
+        [b'R', b'X', b' ', rest @ ..] => (Direction::Rx, rest),
+        _ => return None,
+    };
+    let mut bytes = Vec::with_capacity(escaped.len());
+    while let Some((&byte, rest)) = escaped.split_first() {
+        escaped = rest;
+        let byte = match byte {
+            b'\\' => {
+                let (&kind, rest) = escaped.split_first()?;
+                escaped = rest;
+                match kind {
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'\\' => b'\\',
+                    b'x' => {
+                        let digits = escaped.get(..2)?;
+                        escaped = &escaped[2..];
+                        let digits = core::str::from_utf8(digits).ok()?;
+                        u8::from_str_radix(digits, 16).ok()?
+                    }
+                    _ => return None,
+                }
+            }
+            0x20..=0x7e => byte,
+            _ => return None,
+        };
+        bytes.push(byte);
+    }
+    Some(Record { direction, bytes })
+}
+
+/// A transport wrapper that records all traffic to a transcript
+///
+/// Consecutive writes are collected into one `TX` record, and consecutive reads into one `RX`
+/// record, so a record usually contains one program or response message. A record is written to
+/// the log once the direction changes, and the last one is written by
+/// [`finish`](TranscriptRecorder::finish). Transport errors are passed through, and not recorded.
+///
+/// ```
+/// # #[cfg(feature = "test-util")]
+/// # {
+/// use red_sculpin::{
+///     helpers,
+///     ieee::message::OperationCompleteQuery,
+///     mock::{Expect, MockTransport},
+///     transcript::TranscriptRecorder,
+/// };
+///
+/// let mut transport = MockTransport::new([Expect::write("*OPC?\n").then_respond("1\n")]);
+/// let mut recorder = TranscriptRecorder::new(&mut transport, Vec::new());
+/// assert_eq!(helpers::query(&mut recorder, &OperationCompleteQuery).unwrap(), true);
+/// let (_, log) = recorder.finish().unwrap();
+/// assert_eq!(log, b"TX *OPC?\\n\nRX 1\\n\n");
+/// # }
+/// ```
+pub struct TranscriptRecorder<T, W> {
+    transport: T,
+    log: W,
+    record: Option<Record>,
+}
+
+impl<T, W> TranscriptRecorder<T, W>
+where
+    W: io::Write,
+{
+    pub fn new(transport: T, log: W) -> TranscriptRecorder<T, W> {
+        TranscriptRecorder {
+            transport,
+            log,
+            record: None,
+        }
+    }
+    /// Writes the last record to the log, and returns the transport and the log.
+    pub fn finish(mut self) -> io::Result<(T, W)> {
+        self.write_record()?;
+        self.log.flush()?;
+        Ok((self.transport, self.log))
+    }
+    fn record(&mut self, direction: Direction, bytes: &[u8]) -> io::Result<()> {
+        if self
+            .record
+            .as_ref()
+            .is_some_and(|record| record.direction != direction)
+        {
+            self.write_record()?;
+        }
+        self.record
+            .get_or_insert_with(|| Record {
+                direction,
+                bytes: Vec::new(),
+            })
+            .bytes
+            .extend_from_slice(bytes);
+        Ok(())
+    }
+    fn write_record(&mut self) -> io::Result<()> {
+        if let Some(record) = self.record.take() {
+            writeln!(self.log, "{}", record)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, W> io::Read for TranscriptRecorder<T, W>
+where
+    T: io::Read,
+    W: io::Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.transport.read(buf)?;
+        self.record(Direction::Rx, &buf[..len])?;
+        Ok(len)
+    }
+}
+
+impl<T, W> io::Write for TranscriptRecorder<T, W>
+where
+    T: io::Write,
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.transport.write(buf)?;
+        self.record(Direction::Tx, &buf[..len])?;
+        Ok(len)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.transport.flush()
+    }
+}
+
+impl<T, W> ByteSource for TranscriptRecorder<T, W>
+where
+    T: io::Read,
+    W: io::Write,
+{
+    type Error = Error;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        Io(self).read_byte()
+    }
+}
+
+impl<T, W> ByteSink for TranscriptRecorder<T, W>
+where
+    T: io::Write,
+    W: io::Write,
+{
+    type Error = Error;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        Io(self).write_bytes(bytes)
+    }
+}
+
+impl<T, W> EncodeSink for TranscriptRecorder<T, W>
+where
+    T: io::Write,
+    W: io::Write,
+{
+}
+
+/// A transport that replays a transcript
+///
+/// Written bytes are compared against the `TX` records, and reads are served from the `RX`
+/// records, in order. Traffic that doesn't match the transcript fails with a [`TranscriptError`]
+/// wrapped in an `io::Error`. Reading when the next record is not an `RX` record behaves like a
+/// transport with no data available, and returns an `io::ErrorKind::TimedOut` error.
+///
+/// ```
+/// use red_sculpin::{
+///     helpers,
+///     ieee::message::OperationCompleteQuery,
+///     transcript::{read_transcript, TranscriptPlayer},
+/// };
+///
+/// let records = read_transcript(&b"TX *OPC?\\n\nRX 1\\n\n"[..]).unwrap();
+/// let mut player = TranscriptPlayer::new(records);
+/// assert_eq!(helpers::query(&mut player, &OperationCompleteQuery).unwrap(), true);
+/// assert!(player.is_done());
+/// ```
+#[derive(Clone, Debug)]
+pub struct TranscriptPlayer {
+    records: Vec<Record>,
+    index: usize,
+    offset: usize,
+}
+
+impl TranscriptPlayer {
+    pub fn new(records: impl IntoIterator<Item = Record>) -> TranscriptPlayer {
+        let mut player = TranscriptPlayer {
+            records: records.into_iter().collect(),
+            index: 0,
+            offset: 0,
+        };
+        player.advance();
+        player
+    }
+    /// Returns true if all records have been replayed.
+    pub fn is_done(&self) -> bool {
+        self.index == self.records.len()
+    }
+    /// Returns the index of the record that is being replayed.
+    pub fn position(&self) -> usize {
+        self.index
+    }
+    fn current(&self, direction: Direction) -> Option<&[u8]> {
+        self.records
+            .get(self.index)
+            .filter(|record| record.direction == direction)
+            .map(|record| &record.bytes[self.offset..])
+    }
+    fn advance(&mut self) {
+        while self
+            .records
+            .get(self.index)
+            .is_some_and(|record| self.offset == record.bytes.len())
+        {
+            self.index += 1;
+            self.offset = 0;
+        }
+    }
+}
+
+impl io::Read for TranscriptPlayer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let available = self
+            .current(Direction::Rx)
+            .ok_or(TranscriptError::UnexpectedRead { record: self.index })?;
+        let len = buf.len().min(available.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.offset += len;
+        self.advance();
+        Ok(len)
+    }
+}
+
+impl io::Write for TranscriptPlayer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            let expected = self.current(Direction::Tx).map(|expected| expected[0]);
+            if expected != Some(byte) {
+                return Err(TranscriptError::UnexpectedWrite {
+                    record: self.index,
+                    offset: self.offset,
+                    expected,
+                    byte,
+                }
+                .into());
+            }
+            self.offset += 1;
+            self.advance();
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ByteSource for TranscriptPlayer {
+    type Error = Error;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        Io(self).read_byte()
+    }
+}
+
+impl ByteSink for TranscriptPlayer {
+    type Error = Error;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        Io(self).write_bytes(bytes)
+    }
+}
+
+impl EncodeSink for TranscriptPlayer {}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec::Vec};
+
+    use super::{read_transcript, Direction, Record, TranscriptPlayer, TranscriptRecorder};
+    use crate::{
+        helpers,
+        ieee::message::{ClearStatus, IdentificationQuery},
+        simulator::SimulatedInstrument,
+        DeviceIdentification, Error, Query, Transport,
+    };
+
+    fn instrument() -> SimulatedInstrument {
+        let mut instrument = SimulatedInstrument::new(DeviceIdentification {
+            manufacturer: "ACME".into(),
+            model: "PSU1".into(),
+            serial_number: "".into(),
+            firmware_level: "1.0".into(),
+        });
+        instrument.add_query(":MMEMory:DATA", |_| Ok(Vec::from(&b"\x00\n\\\xff"[..])));
+        instrument
+    }
+
+    struct DataQuery;
+
+    impl Query for DataQuery {
+        type ProgramData = ();
+        type ResponseData = Vec<u8>;
+        fn mnemonic(&self) -> &str {
+            ":MMEM:DATA?"
+        }
+        fn program_data(&self) {}
+    }
+
+    fn driver<T: Transport>(io: &mut T) -> Result<(DeviceIdentification, Vec<u8>), Error> {
+        helpers::send(io, &ClearStatus)?;
+        let identification = helpers::query(io, &IdentificationQuery)?;
+        let data = helpers::query(io, &DataQuery)?;
+        Ok((identification, data))
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut instrument = instrument();
+        let mut recorder = TranscriptRecorder::new(&mut instrument, Vec::new());
+        let recorded = driver(&mut recorder).unwrap();
+        let (_, log) = recorder.finish().unwrap();
+
+        let records = read_transcript(&log[..]).unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| record.direction)
+                .collect::<Vec<_>>(),
+            [Direction::Tx, Direction::Rx, Direction::Tx, Direction::Rx]
+        );
+        assert_eq!(records[0].to_string(), "TX *CLS\\n*IDN?\\n");
+        assert_eq!(records[3].to_string(), "RX #14\\x00\\n\\\\\\xff\\n");
+
+        let mut player = TranscriptPlayer::new(records);
+        assert_eq!(driver(&mut player).unwrap(), recorded);
+        assert!(player.is_done());
+    }
+
+    #[test]
+    fn mismatched_write() {
+        let mut player = TranscriptPlayer::new([
+            Record {
+                direction: Direction::Tx,
+                bytes: b"*RST\n".to_vec(),
+            },
+            Record {
+                direction: Direction::Rx,
+                bytes: b"1\n".to_vec(),
+            },
+        ]);
+        let err = helpers::send(&mut player, &ClearStatus).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "while encoding \"*CLS\": unexpected write in record 0 at offset 1: expected 0x52, got 0x43"
+        );
+    }
+
+    #[test]
+    fn unexpected_read() {
+        let mut player = TranscriptPlayer::new([Record {
+            direction: Direction::Tx,
+            bytes: b"*IDN?\n".to_vec(),
+        }]);
+        let err = helpers::query(&mut player, &IdentificationQuery).unwrap_err();
+        assert!(err.to_string().contains("unexpected read at record 1"));
+        assert!(player.is_done());
+    }
+
+    #[test]
+    fn invalid_transcripts() {
+        let err = read_transcript(&b"TX *RST\\n\n\nRX 1\\q\n"[..]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid transcript record on line 3");
+        assert!(read_transcript(&b"XX *RST\n"[..]).is_err());
+        assert!(read_transcript(&b"TX \\x0\n"[..]).is_err());
+        assert!(read_transcript(&b"TX \xff\n"[..]).is_err());
+    }
+}
@@ -6,14 +6,84 @@
 ///
 /// Reference: IEEE 488.2: 7.6.1.2 - Encoding syntax
 pub fn is_program_mnemonic(bytes: impl AsRef<[u8]>) -> bool {
+    is_program_mnemonic_bytes(bytes.as_ref())
+}
+
+/// Returns true if the given bytes form a valid program mnemonic, in constant contexts.
+pub(crate) const fn is_program_mnemonic_bytes(bytes: &[u8]) -> bool {
+    !bytes.is_empty() && invalid_mnemonic_byte(bytes).is_none()
+}
+
+/// Returns the position of the first byte that is not valid in a program mnemonic.
+pub(crate) const fn invalid_mnemonic_byte(bytes: &[u8]) -> Option<usize> {
     // ASCII alphabetic + 0-N times ASCII alphanumeric or underscore
-    match bytes.as_ref() {
-        [head, tail @ ..] => {
-            head.is_ascii_alphabetic()
-                && tail.iter().all(|&b| b.is_ascii_alphanumeric() || b == b'_')
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let b = bytes[idx];
+        let valid = match idx {
+            0 => b.is_ascii_alphabetic(),
+            _ => b.is_ascii_alphanumeric() || b == b'_',
+        };
+        if !valid {
+            return Some(idx);
         }
-        [] => false,
+        idx += 1;
     }
+    None
+}
+
+/// Returns the short form of a mnemonic spec, i.e. its leading uppercase part.
+///
+/// Mnemonics are documented in mixed case, like `VOLTage`: the uppercase letters form the short
+/// form, and the whole mnemonic is the long form. Digits and underscores are part of the short form
+/// when they appear before the first lowercase letter (`AM1`, `IEEE_488`). Digits after the
+/// lowercase part are a numeric suffix (`CHANnel1`), which is part of both forms but is not
+/// included here, see [`numeric_suffix`].
+///
+/// Reference: SCPI 1999.0: 6.2.1 - Keywords
+pub fn short_form(spec: &str) -> &str {
+    let end = spec
+        .bytes()
+        .position(|b| b.is_ascii_lowercase())
+        .unwrap_or(spec.len());
+    &spec[..end]
+}
+
+/// Returns the numeric suffix that follows the lowercase part of a mnemonic spec.
+///
+/// `CHANnel1` has the numeric suffix `1`. Specs without a lowercase part have no numeric suffix,
+/// so `AM1` has none.
+///
+/// Reference: SCPI 1999.0: 6.2.5 - Numeric Suffixes
+pub fn numeric_suffix(spec: &str) -> &str {
+    let head = short_form(spec).len();
+    let start = spec.len()
+        - spec[head..]
+            .bytes()
+            .rev()
+            .take_while(u8::is_ascii_digit)
+            .count();
+    &spec[start.max(head)..]
+}
+
+/// Returns true if the input matches the short or long form of a mnemonic spec, ignoring case.
+///
+/// The spec is written in mixed case, like `VOLTage`. Only the two forms are accepted: `VOLT` and
+/// `voltage` match, but `VOLTA` doesn't, because SCPI doesn't allow abbreviations other than the
+/// short form. A numeric suffix after the lowercase part must be present in both forms, so
+/// `CHANnel1` matches `CHAN1` and `CHANNEL1`.
+///
+/// Reference: SCPI 1999.0: 6.2.1 - Keywords
+pub fn mnemonic_matches(spec: &str, input: &str) -> bool {
+    if input.eq_ignore_ascii_case(spec) {
+        return true;
+    }
+    let short = short_form(spec);
+    let suffix = numeric_suffix(spec);
+    input.len() == short.len() + suffix.len()
+        && input.is_char_boundary(short.len())
+        && input[..short.len()].eq_ignore_ascii_case(short)
+        && &input[short.len()..] == suffix
 }
 
 #[cfg(test)]
@@ -21,6 +91,7 @@ mod program_mnemonic {
     use crate::is_program_mnemonic;
 
     #[test]
+    #[allow(clippy::needless_borrows_for_generic_args)]
     fn is_not_empty() {
         assert!(!is_program_mnemonic(&[]));
     }
@@ -41,3 +112,57 @@ mod program_mnemonic {
         assert!(!is_program_mnemonic("NOPE\n"));
     }
 }
+
+#[cfg(test)]
+mod mnemonic_forms {
+    use super::{mnemonic_matches, numeric_suffix, short_form};
+
+    #[test]
+    fn forms_of_a_spec() {
+        assert_eq!(short_form("VOLTage"), "VOLT");
+        assert_eq!(short_form("AM1"), "AM1");
+        assert_eq!(short_form("IEEE_488"), "IEEE_488");
+        assert_eq!(short_form("CHANnel12"), "CHAN");
+        assert_eq!(short_form("NEG_LIMit"), "NEG_LIM");
+        assert_eq!(numeric_suffix("CHANnel12"), "12");
+        assert_eq!(numeric_suffix("VOLTage"), "");
+        assert_eq!(numeric_suffix("AM1"), "");
+        assert_eq!(numeric_suffix("NEG_LIMit"), "");
+    }
+
+    #[test]
+    fn short_and_long_forms_match() {
+        for input in ["VOLT", "volt", "Volt", "VOLTAGE", "voltage", "VoLtAgE"] {
+            assert!(mnemonic_matches("VOLTage", input), "{}", input);
+        }
+        for input in ["AM1", "am1"] {
+            assert!(mnemonic_matches("AM1", input), "{}", input);
+        }
+        for input in ["CHAN1", "chan1", "CHANNEL1", "channel1"] {
+            assert!(mnemonic_matches("CHANnel1", input), "{}", input);
+        }
+        for input in ["NEG_LIM", "neg_lim", "NEG_LIMIT", "neg_limit"] {
+            assert!(mnemonic_matches("NEG_LIMit", input), "{}", input);
+        }
+        assert!(mnemonic_matches("IEEE", "ieee"));
+    }
+
+    #[test]
+    fn other_inputs_dont_match() {
+        for input in [
+            "", "V", "VOL", "VOLTA", "VOLTAG", "VOLTAGES", "VOLT1", "CURR",
+        ] {
+            assert!(!mnemonic_matches("VOLTage", input), "{}", input);
+        }
+        for input in ["AM", "AM2", "AM11", "A1"] {
+            assert!(!mnemonic_matches("AM1", input), "{}", input);
+        }
+        for input in ["CHAN", "CHAN2", "CHANN1", "CHANNEL", "CHANNEL2"] {
+            assert!(!mnemonic_matches("CHANnel1", input), "{}", input);
+        }
+        for input in ["NEG", "NEG_", "NEGLIM", "NEG_LIMI"] {
+            assert!(!mnemonic_matches("NEG_LIMit", input), "{}", input);
+        }
+        assert!(!mnemonic_matches("VOLTage", "VÖLT"));
+    }
+}
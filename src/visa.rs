@@ -0,0 +1,341 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::fmt;
+use std::io;
+
+use crate::{
+    encode::{EncodeSink, PROGRAM_MESSAGE_TERMINATOR},
+    ByteSink, ByteSource, Error,
+};
+
+/// A VISA completion or error code
+pub type ViStatus = i32;
+
+/// The operation completed, and a read ended with the END indicator
+pub const VI_SUCCESS: ViStatus = 0;
+/// A read ended with the termination character
+pub const VI_SUCCESS_TERM_CHAR: ViStatus = 0x3fff_0005;
+/// A read filled the buffer, and more data may be available
+pub const VI_SUCCESS_MAX_CNT: ViStatus = 0x3fff_0006;
+/// The operation timed out
+pub const VI_ERROR_TMO: ViStatus = 0xbfff_0015_u32 as ViStatus;
+/// The connection to the device was lost
+pub const VI_ERROR_CONN_LOST: ViStatus = 0xbfff_00a6_u32 as ViStatus;
+
+/// Size of the buffer used for reads
+pub const DEFAULT_READ_BUFFER_LEN: usize = 4096;
+
+/// An open VISA session, implemented on top of a VISA binding
+///
+/// The methods correspond to `viRead` and `viWrite`, and return the VISA status code together
+/// with the number of bytes transferred. Other session functions, such as setting attributes, are
+/// outside the scope of this trait and can be used through [`VisaIo::session`].
+pub trait VisaSession {
+    fn read(&mut self, buf: &mut [u8]) -> (ViStatus, usize);
+    fn write(&mut self, buf: &[u8]) -> (ViStatus, usize);
+}
+
+impl<T> VisaSession for &mut T
+where
+    T: VisaSession + ?Sized,
+{
+    fn read(&mut self, buf: &mut [u8]) -> (ViStatus, usize) {
+        (**self).read(buf)
+    }
+    fn write(&mut self, buf: &[u8]) -> (ViStatus, usize) {
+        (**self).write(buf)
+    }
+}
+
+/// Error returned for a VISA error status code
+///
+/// Converted into an `io::Error` with kind `TimedOut` for [`VI_ERROR_TMO`], `ConnectionAborted`
+/// for [`VI_ERROR_CONN_LOST`], and `Other` for other codes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct VisaError {
+    pub status: ViStatus,
+}
+
+impl fmt::Display for VisaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VISA error 0x{:08x}", self.status)
+    }
+}
+
+impl core::error::Error for VisaError {}
+
+impl From<VisaError> for io::Error {
+    fn from(err: VisaError) -> Self {
+        let kind = match err.status {
+            VI_ERROR_TMO => io::ErrorKind::TimedOut,
+            VI_ERROR_CONN_LOST => io::ErrorKind::ConnectionAborted,
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+fn check(status: ViStatus) -> Result<(), Error> {
+    match status {
+        0.. => Ok(()),
+        _ => Err(io::Error::from(VisaError { status }).into()),
+    }
+}
+
+/// A transport over a VISA session
+///
+/// Reads are done in chunks. A response message ends when a read completes with the END
+/// indicator or the termination character, which is reported with the last byte through
+/// [`ByteSource::read_byte_with_end`], so the [`Decoder`](crate::decode::Decoder) sees the end of
+/// the response even if it doesn't end with a newline. The next read after that starts a new
+/// response message.
+///
+/// A program message is collected into a buffer and written once its terminator has been
+/// encoded, because VISA asserts END on the last byte of every `viWrite` by default.
+pub struct VisaIo<S> {
+    session: S,
+    read_buffer: Box<[u8]>,
+    read_pos: usize,
+    read_len: usize,
+    read_ended: bool,
+    write_buffer: Vec<u8>,
+}
+
+impl<S> VisaIo<S>
+where
+    S: VisaSession,
+{
+    pub fn new(session: S) -> VisaIo<S> {
+        VisaIo::with_read_buffer_len(session, DEFAULT_READ_BUFFER_LEN)
+    }
+    pub fn with_read_buffer_len(session: S, len: usize) -> VisaIo<S> {
+        VisaIo {
+            session,
+            read_buffer: vec![0; len.max(1)].into_boxed_slice(),
+            read_pos: 0,
+            read_len: 0,
+            read_ended: false,
+            write_buffer: Vec::new(),
+        }
+    }
+    /// Returns the session, for example for setting attributes.
+    pub fn session(&mut self) -> &mut S {
+        &mut self.session
+    }
+    /// Returns the session. Buffered bytes are discarded.
+    pub fn into_inner(self) -> S {
+        self.session
+    }
+    fn fill_read_buffer(&mut self) -> Result<(), Error> {
+        let (status, len) = self.session.read(&mut self.read_buffer);
+        check(status)?;
+        self.read_pos = 0;
+        self.read_len = len.min(self.read_buffer.len());
+        self.read_ended = matches!(status, VI_SUCCESS | VI_SUCCESS_TERM_CHAR);
+        if self.read_ended && self.read_len == 0 {
+            // END without data can't be reported with a byte, so report it with a terminator
+            self.read_buffer[0] = b'\n';
+            self.read_len = 1;
+        }
+        Ok(())
+    }
+}
+
+impl<S> ByteSource for VisaIo<S>
+where
+    S: VisaSession,
+{
+    type Error = Error;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        self.read_byte_with_end().map(|(byte, _)| byte)
+    }
+    fn read_byte_with_end(&mut self) -> Result<(u8, bool), Self::Error> {
+        while self.read_pos == self.read_len {
+            self.fill_read_buffer()?;
+        }
+        let byte = self.read_buffer[self.read_pos];
+        self.read_pos += 1;
+        Ok((byte, self.read_ended && self.read_pos == self.read_len))
+    }
+}
+
+impl<S> ByteSink for VisaIo<S>
+where
+    S: VisaSession,
+{
+    type Error = Error;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.write_buffer.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl<S> EncodeSink for VisaIo<S>
+where
+    S: VisaSession,
+{
+    /// Writes the buffered program message and its terminator.
+    fn terminate_message(&mut self) -> Result<(), Self::Error> {
+        self.write_buffer.push(PROGRAM_MESSAGE_TERMINATOR);
+        let mut pos = 0;
+        let result = loop {
+            if pos == self.write_buffer.len() {
+                break Ok(());
+            }
+            let (status, len) = self.session.write(&self.write_buffer[pos..]);
+            if let Err(err) = check(status) {
+                break Err(err);
+            }
+            if len == 0 {
+                break Err(io::Error::from(io::ErrorKind::WriteZero).into());
+            }
+            pos += len;
+        };
+        self.write_buffer.clear();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{collections::VecDeque, string::ToString, vec::Vec};
+    use matches::assert_matches;
+
+    use super::{
+        ViStatus, VisaIo, VisaSession, VI_ERROR_CONN_LOST, VI_ERROR_TMO, VI_SUCCESS,
+        VI_SUCCESS_MAX_CNT, VI_SUCCESS_TERM_CHAR,
+    };
+    use crate::{
+        adapters::Timeout,
+        decode::Decoder,
+        encode::Encoder,
+        ieee::message::IdentificationQuery,
+        scpi::message::{StatusOperationEnable, SystemVersionQuery},
+        ByteSource, Command, Error, Query, ResponseData,
+    };
+
+    /// A session that returns scripted reads, and accepts writes of at most `max_write` bytes
+    #[derive(Default)]
+    struct MockSession {
+        reads: VecDeque<(&'static [u8], ViStatus)>,
+        read_lens: Vec<usize>,
+        max_write: usize,
+        write_status: ViStatus,
+        writes: Vec<Vec<u8>>,
+    }
+
+    impl MockSession {
+        fn new(reads: impl IntoIterator<Item = (&'static [u8], ViStatus)>) -> MockSession {
+            MockSession {
+                reads: reads.into_iter().collect(),
+                max_write: usize::MAX,
+                ..MockSession::default()
+            }
+        }
+    }
+
+    impl VisaSession for MockSession {
+        fn read(&mut self, buf: &mut [u8]) -> (ViStatus, usize) {
+            self.read_lens.push(buf.len());
+            match self.reads.pop_front() {
+                Some((bytes, status)) => {
+                    buf[..bytes.len()].copy_from_slice(bytes);
+                    (status, bytes.len())
+                }
+                None => (VI_ERROR_TMO, 0),
+            }
+        }
+        fn write(&mut self, buf: &[u8]) -> (ViStatus, usize) {
+            if self.write_status < 0 {
+                return (self.write_status, 0);
+            }
+            let len = buf.len().min(self.max_write);
+            self.writes.push(buf[..len].to_vec());
+            (VI_SUCCESS, len)
+        }
+    }
+
+    #[test]
+    fn end_indicator_terminates_the_response() {
+        // END without a newline
+        let mut visa = VisaIo::new(MockSession::new([(&b"1999.0"[..], VI_SUCCESS)]));
+        let mut decoder = Decoder::new(&mut visa);
+        assert_eq!(SystemVersionQuery.decode(&mut decoder).unwrap(), 1999.0);
+        decoder.finish().unwrap();
+
+        // END with a newline
+        let mut visa = VisaIo::new(MockSession::new([(&b"1999.0\n"[..], VI_SUCCESS)]));
+        let mut decoder = Decoder::new(&mut visa);
+        assert_eq!(SystemVersionQuery.decode(&mut decoder).unwrap(), 1999.0);
+        decoder.finish().unwrap();
+        assert_matches!(visa.read_byte(), Err(err) if err.is_timeout());
+
+        // END without data
+        let mut visa = VisaIo::new(MockSession::new([(&b""[..], VI_SUCCESS)]));
+        assert_matches!(visa.read_byte_with_end(), Ok((b'\n', true)));
+    }
+
+    #[test]
+    fn chunked_reads() {
+        let mut visa = VisaIo::with_read_buffer_len(
+            MockSession::new([
+                (&b"ACME,"[..], VI_SUCCESS_MAX_CNT),
+                (&b"PSU1,"[..], VI_SUCCESS_MAX_CNT),
+                (&b"0,1.0"[..], VI_SUCCESS),
+                (&b"#13\n\0"[..], VI_SUCCESS_MAX_CNT),
+                (&b"\n\n"[..], VI_SUCCESS_TERM_CHAR),
+            ]),
+            5,
+        );
+        let mut decoder = Decoder::new(&mut visa);
+        let identification = IdentificationQuery.decode(&mut decoder).unwrap();
+        assert_eq!(identification.model, "PSU1");
+        assert_eq!(identification.firmware_level, "1.0");
+        decoder.finish().unwrap();
+
+        // The next response starts with a new read, and a newline in a block doesn't end it
+        let mut decoder = Decoder::new(&mut visa);
+        assert_eq!(Vec::<u8>::decode(&mut decoder).unwrap(), b"\n\0\n");
+        decoder.finish().unwrap();
+        assert_eq!(visa.session().read_lens, [5; 5]);
+        assert!(visa.session().reads.is_empty());
+    }
+
+    #[test]
+    fn program_messages_are_written_whole() {
+        let mut session = MockSession::new([]);
+        session.max_write = 8;
+        let mut visa = VisaIo::new(session);
+        let mut encoder = Encoder::new(&mut visa);
+        StatusOperationEnable(42).encode(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+        let writes = &visa.session().writes;
+        assert_eq!(writes.len(), 3);
+        assert_eq!(writes.concat(), b":STAT:OPER:ENAB 42\n");
+    }
+
+    #[test]
+    fn status_codes() {
+        let mut visa = VisaIo::new(MockSession::new([(&b""[..], VI_ERROR_CONN_LOST)]));
+        let err = visa.read_byte().unwrap_err();
+        assert!(!err.is_timeout());
+        assert_matches!(&err, Error::Io(err) if err.kind() == std::io::ErrorKind::ConnectionAborted);
+        assert_eq!(err.to_string(), "VISA error 0xbfff00a6");
+
+        let mut session = MockSession::new([]);
+        session.write_status = VI_ERROR_TMO;
+        let mut visa = VisaIo::new(session);
+        let mut encoder = Encoder::new(&mut visa);
+        let err = StatusOperationEnable(42)
+            .encode(&mut encoder)
+            .and_then(|_| encoder.finish().map(|_| ()))
+            .unwrap_err();
+        assert!(err.is_timeout());
+        assert_eq!(err.to_string(), "VISA error 0xbfff0015");
+    }
+}
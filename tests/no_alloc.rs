@@ -0,0 +1,97 @@
+// SPDX-FileCopyrightText: 2019-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Decoding into stack buffers, without using the `alloc` feature
+//!
+//! Run with `cargo test --no-default-features` to check that none of this requires `alloc`.
+
+use matches::assert_matches;
+use red_sculpin::{
+    buffer::ArrayBuffer,
+    decode::{Boundary, DecodeError, Decoder, FeedDecoder},
+    encode::Encoder,
+    ieee::message::{ClearStatus, StatusByteQuery},
+    scpi::{message::SystemVersionQuery, types::Coupling},
+    Command, Query, ResponseData,
+};
+
+#[test]
+fn status_byte_query() {
+    let mut encoder = Encoder::new(ArrayBuffer::<16>::new());
+    ClearStatus.encode(&mut encoder).unwrap();
+    StatusByteQuery.encode(&mut encoder).unwrap();
+    let output = encoder.finish().unwrap();
+    assert_eq!(output.as_slice(), b"*CLS;*STB?\n");
+
+    let mut decoder = Decoder::new(&b"+64\n"[..]);
+    assert_eq!(StatusByteQuery.decode(&mut decoder), Ok(64));
+    assert!(decoder.finish().unwrap().is_empty());
+}
+
+#[test]
+fn system_version_query() {
+    let mut decoder = Decoder::new(&b"1999.0\n"[..]);
+    assert_eq!(SystemVersionQuery.decode(&mut decoder), Ok(1999.0));
+    assert!(decoder.finish().unwrap().is_empty());
+}
+
+#[test]
+fn text_and_blocks_into_stack_buffers() {
+    let mut decoder = Decoder::new(&b"VOLT,\"Hello, \"\"world\"\"\",#15\x00\x01\x02\x03\x04\n"[..]);
+
+    let mut characters = ArrayBuffer::<12>::new();
+    decoder.begin_response_data().unwrap();
+    decoder.decode_characters(&mut characters).unwrap();
+    assert_eq!(characters.as_slice(), b"VOLT");
+
+    let mut text = ArrayBuffer::<32>::new();
+    decoder.begin_response_data().unwrap();
+    decoder.decode_string(&mut text).unwrap();
+    assert_eq!(text.as_slice(), b"Hello, \"world\"");
+
+    let mut block = ArrayBuffer::<8>::new();
+    decoder.begin_response_data().unwrap();
+    decoder.decode_arbitrary_block(&mut block).unwrap();
+    assert_eq!(block.as_slice(), [0, 1, 2, 3, 4]);
+    assert!(decoder.finish().unwrap().is_empty());
+}
+
+#[test]
+fn character_response_data() {
+    assert_eq!(
+        Coupling::from_response_bytes(b"GND\n"),
+        Ok(Coupling::Ground)
+    );
+    assert_matches!(
+        Coupling::from_response_bytes(b"HOLD\n"),
+        Err(DecodeError::UnknownCharacters(_))
+    );
+    // Collected into a 64-byte buffer without `alloc`
+    #[cfg(not(feature = "alloc"))]
+    assert_eq!(
+        Coupling::from_response_bytes(&[b'A'; 65]),
+        Err(DecodeError::BufferOverflow)
+    );
+}
+
+#[test]
+fn stack_buffer_overflow_is_reported() {
+    let mut decoder = Decoder::new(&b"\"too long\"\n"[..]);
+    let mut text = ArrayBuffer::<4>::new();
+    decoder.begin_response_data().unwrap();
+    assert_eq!(
+        decoder.decode_string(&mut text),
+        Err(DecodeError::BufferOverflow)
+    );
+}
+
+#[test]
+fn feed_decoder_with_stack_buffer() {
+    let mut buf = [0; 8];
+    let mut decoder = FeedDecoder::new(&mut buf);
+    assert_eq!(decoder.feed(b"+64").boundary, None);
+    assert_eq!(decoder.feed(b"\n").boundary, Some(Boundary::End));
+    assert_eq!(decoder.decode::<u8>(), Ok(64));
+    assert!(decoder.is_at_end());
+}